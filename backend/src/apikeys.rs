@@ -0,0 +1,343 @@
+use std::{collections::BTreeMap, io::ErrorKind, path::PathBuf};
+
+use axum::{
+    Json,
+    extract::{Path as RoutePath, State},
+    http::{HeaderMap, StatusCode, header::AUTHORIZATION},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    ApiError, AppState, encode_hex, non_empty,
+    rbac::{Role, RoleTokens},
+};
+
+const KEY_PREFIX: &str = "td_";
+const MAX_NAME_CHARS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum KeySource {
+    Config,
+    Admin,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredApiKey {
+    #[serde(skip_serializing)]
+    key_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    daily_limit: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_download_mb: Option<u64>,
+    created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_used_at: Option<DateTime<Utc>>,
+    source: KeySource,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ApiKeyFile {
+    #[serde(default)]
+    keys: BTreeMap<String, PersistedApiKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PersistedApiKey {
+    key_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    daily_limit: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_download_mb: Option<u64>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ApiKeys {
+    path: PathBuf,
+    keys: Mutex<BTreeMap<String, StoredApiKey>>,
+}
+
+// Resolved per request; the limits replace the IP-based defaults in admit_download.
+#[derive(Debug, Clone)]
+pub(crate) struct ApiKeyGrant {
+    pub(crate) name: String,
+    pub(crate) daily_limit: Option<usize>,
+    pub(crate) max_download_bytes: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateApiKeyRequest {
+    name: String,
+    daily_limit: Option<usize>,
+    max_download_mb: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct CreatedApiKey {
+    name: String,
+    key: String,
+    #[serde(flatten)]
+    details: StoredApiKey,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiKeyReport {
+    name: String,
+    #[serde(flatten)]
+    details: StoredApiKey,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ApiKeyList {
+    keys: Vec<ApiKeyReport>,
+}
+
+fn hash_key(key: &str) -> String {
+    encode_hex(&Sha256::digest(key.trim().as_bytes()))
+}
+
+fn normalize_name(value: &str) -> Result<String, ApiError> {
+    let name = value.trim().to_ascii_lowercase();
+    if name.is_empty()
+        || name.len() > MAX_NAME_CHARS
+        || !name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || matches!(character, '-' | '_'))
+    {
+        return Err(ApiError::bad_request(format!(
+            "Nombre de clave invalido: usa hasta {MAX_NAME_CHARS} letras, numeros, '-' o '_'."
+        )));
+    }
+    Ok(name)
+}
+
+pub(crate) fn tenant(name: &str) -> String {
+    format!("apikey:{name}")
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .and_then(non_empty)
+}
+
+impl ApiKeys {
+    pub(crate) async fn from_env(path: PathBuf) -> Result<Self, ApiError> {
+        let stored: ApiKeyFile = match tokio::fs::read_to_string(&path).await {
+            Ok(content) if content.trim().is_empty() => ApiKeyFile::default(),
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|error| ApiError::internal(format!("Claves de API invalidas: {error}")))?,
+            Err(error) if error.kind() == ErrorKind::NotFound => ApiKeyFile::default(),
+            Err(error) => {
+                return Err(ApiError::internal(format!(
+                    "No se pudieron leer las claves de API: {error}"
+                )));
+            }
+        };
+        let mut keys = stored
+            .keys
+            .into_iter()
+            .map(|(name, key)| {
+                (
+                    name,
+                    StoredApiKey {
+                        key_hash: key.key_hash,
+                        daily_limit: key.daily_limit,
+                        max_download_mb: key.max_download_mb,
+                        created_at: key.created_at,
+                        last_used_at: None,
+                        source: KeySource::Admin,
+                    },
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let now = Utc::now();
+        for entry in crate::read_list_env_raw("API_KEYS") {
+            let mut parts = entry.split('|').map(str::trim);
+            let (Some(Ok(name)), Some(key)) = (
+                parts.next().map(normalize_name),
+                parts.next().and_then(non_empty),
+            ) else {
+                warn!(
+                    "Entrada invalida en API_KEYS (usa nombre|clave|limite_diario|max_mb): {:?}",
+                    entry.split('|').next().unwrap_or_default()
+                );
+                continue;
+            };
+            let mut number = || {
+                parts
+                    .next()
+                    .and_then(|value| value.parse::<u64>().ok())
+                    .filter(|value| *value > 0)
+            };
+            let daily_limit = number().map(|value| value as usize);
+            let max_download_mb = number();
+            keys.insert(
+                name,
+                StoredApiKey {
+                    key_hash: hash_key(key),
+                    daily_limit,
+                    max_download_mb,
+                    created_at: now,
+                    last_used_at: None,
+                    source: KeySource::Config,
+                },
+            );
+        }
+        if !keys.is_empty() {
+            info!("Claves de API habilitadas: {}.", keys.len());
+        }
+
+        Ok(Self {
+            path,
+            keys: Mutex::new(keys),
+        })
+    }
+
+    // Role tokens also travel as Bearer; only a token that is neither is rejected.
+    pub(crate) async fn resolve(
+        &self,
+        roles: &RoleTokens,
+        headers: &HeaderMap,
+    ) -> Result<Option<ApiKeyGrant>, ApiError> {
+        let Some(token) = bearer_token(headers) else {
+            return Ok(None);
+        };
+        let hash = hash_key(token);
+        let mut keys = self.keys.lock().await;
+        if keys.is_empty() {
+            return Ok(None);
+        }
+        let Some((name, key)) = keys.iter_mut().find(|(_, key)| key.key_hash == hash) else {
+            if roles.resolve(headers) == Role::Anonymous {
+                return Err(ApiError::invalid_api_key());
+            }
+            return Ok(None);
+        };
+        key.last_used_at = Some(Utc::now());
+        Ok(Some(ApiKeyGrant {
+            name: name.clone(),
+            daily_limit: key.daily_limit,
+            max_download_bytes: key.max_download_mb.map(|mb| mb.saturating_mul(1024 * 1024)),
+        }))
+    }
+
+    async fn save(&self, keys: &BTreeMap<String, StoredApiKey>) -> Result<(), ApiError> {
+        let file = ApiKeyFile {
+            keys: keys
+                .iter()
+                .filter(|(_, key)| key.source == KeySource::Admin)
+                .map(|(name, key)| {
+                    (
+                        name.clone(),
+                        PersistedApiKey {
+                            key_hash: key.key_hash.clone(),
+                            daily_limit: key.daily_limit,
+                            max_download_mb: key.max_download_mb,
+                            created_at: key.created_at,
+                        },
+                    )
+                })
+                .collect(),
+        };
+        let payload = crate::lowmem::to_json_vec(&file).map_err(|error| {
+            ApiError::internal(format!(
+                "No se pudieron serializar las claves de API: {error}"
+            ))
+        })?;
+        tokio::fs::write(&self.path, payload)
+            .await
+            .map_err(|error| {
+                ApiError::internal(format!("No se pudieron guardar las claves de API: {error}"))
+            })
+    }
+}
+
+pub(crate) async fn list_api_keys(State(state): State<AppState>) -> Json<ApiKeyList> {
+    let keys = state
+        .api_keys
+        .keys
+        .lock()
+        .await
+        .iter()
+        .map(|(name, details)| ApiKeyReport {
+            name: name.clone(),
+            details: details.clone(),
+        })
+        .collect();
+    Json(ApiKeyList { keys })
+}
+
+pub(crate) async fn create_api_key(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> Result<(StatusCode, Json<CreatedApiKey>), ApiError> {
+    let name = normalize_name(&payload.name)?;
+    let key = format!(
+        "{KEY_PREFIX}{}{}",
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    );
+    let details = StoredApiKey {
+        key_hash: hash_key(&key),
+        daily_limit: payload.daily_limit.filter(|limit| *limit > 0),
+        max_download_mb: payload.max_download_mb.filter(|mb| *mb > 0),
+        created_at: Utc::now(),
+        last_used_at: None,
+        source: KeySource::Admin,
+    };
+
+    let mut keys = state.api_keys.keys.lock().await;
+    if keys.contains_key(&name) {
+        return Err(ApiError::bad_request(format!(
+            "Ya existe una clave de API llamada {name}."
+        )));
+    }
+    keys.insert(name.clone(), details.clone());
+    if let Err(error) = state.api_keys.save(&keys).await {
+        keys.remove(&name);
+        return Err(error);
+    }
+    info!("Clave de API {name} creada.");
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedApiKey { name, key, details }),
+    ))
+}
+
+pub(crate) async fn revoke_api_key(
+    State(state): State<AppState>,
+    RoutePath(name): RoutePath<String>,
+) -> Result<StatusCode, ApiError> {
+    let name = normalize_name(&name)?;
+    let mut keys = state.api_keys.keys.lock().await;
+    match keys.get(&name).map(|key| key.source) {
+        None => Err(ApiError::not_found(format!(
+            "No existe una clave de API llamada {name}."
+        ))),
+        Some(KeySource::Config) => Err(ApiError::bad_request(format!(
+            "La clave {name} viene de API_KEYS; quitala de la configuracion y reinicia."
+        ))),
+        Some(KeySource::Admin) => {
+            let removed = keys.remove(&name);
+            if let Err(error) = state.api_keys.save(&keys).await {
+                if let Some(removed) = removed {
+                    keys.insert(name, removed);
+                }
+                return Err(error);
+            }
+            info!("Clave de API {name} revocada.");
+            Ok(StatusCode::NO_CONTENT)
+        }
+    }
+}
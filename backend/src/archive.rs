@@ -0,0 +1,228 @@
+use std::path::{Path, PathBuf};
+
+use axum::body::Bytes;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::mpsc,
+};
+
+const LOCAL_HEADER_BYTES: u64 = 30;
+const CENTRAL_HEADER_BYTES: u64 = 46;
+const END_OF_CENTRAL_DIRECTORY_BYTES: u64 = 22;
+const READ_CHUNK_BYTES: usize = 64 * 1024;
+const VERSION_NEEDED: u16 = 20;
+const FLAG_UTF8_NAMES: u16 = 1 << 11;
+const METHOD_STORED: u16 = 0;
+
+static CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0_u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut value = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            value = if value & 1 == 1 {
+                0xEDB8_8320 ^ (value >> 1)
+            } else {
+                value >> 1
+            };
+            bit += 1;
+        }
+        table[index] = value;
+        index += 1;
+    }
+    table
+}
+
+fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(crc, |crc, byte| {
+        CRC32_TABLE[((crc ^ u32::from(*byte)) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ArchiveEntry {
+    pub(crate) name: String,
+    pub(crate) path: PathBuf,
+    pub(crate) size: u64,
+}
+
+// Stored entries without ZIP64: media barely compresses and fixed sizes give an exact Content-Length.
+pub(crate) fn archive_len(entries: &[ArchiveEntry]) -> Option<u64> {
+    if entries.len() > usize::from(u16::MAX) {
+        return None;
+    }
+    let mut offset = 0_u64;
+    let mut central = 0_u64;
+    for entry in entries {
+        let name = entry.name.len() as u64;
+        if entry.size > u64::from(u32::MAX) || offset > u64::from(u32::MAX) {
+            return None;
+        }
+        offset += LOCAL_HEADER_BYTES + name + entry.size;
+        central += CENTRAL_HEADER_BYTES + name;
+    }
+    if offset + central > u64::from(u32::MAX) {
+        return None;
+    }
+    Some(offset + central + END_OF_CENTRAL_DIRECTORY_BYTES)
+}
+
+fn dos_timestamp(at: DateTime<Utc>) -> (u16, u16) {
+    let time = (at.hour() << 11) | (at.minute() << 5) | (at.second() / 2);
+    let date = ((at.year().clamp(1980, 2107) as u32 - 1980) << 9) | (at.month() << 5) | at.day();
+    (time as u16, date as u16)
+}
+
+struct CentralRecord {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+pub(crate) async fn write_archive(
+    entries: Vec<ArchiveEntry>,
+    sender: &mpsc::Sender<Result<Bytes, std::io::Error>>,
+) -> Result<(), std::io::Error> {
+    let (time, date) = dos_timestamp(Utc::now());
+    let mut records = Vec::with_capacity(entries.len());
+    let mut offset = 0_u32;
+
+    for entry in entries {
+        let crc = file_crc32(&entry).await?;
+        let size = u32::try_from(entry.size).map_err(|_| archive_too_large())?;
+        let mut header = Vec::with_capacity(LOCAL_HEADER_BYTES as usize + entry.name.len());
+        header.extend_from_slice(&0x0403_4b50_u32.to_le_bytes());
+        header.extend_from_slice(&VERSION_NEEDED.to_le_bytes());
+        header.extend_from_slice(&FLAG_UTF8_NAMES.to_le_bytes());
+        header.extend_from_slice(&METHOD_STORED.to_le_bytes());
+        header.extend_from_slice(&time.to_le_bytes());
+        header.extend_from_slice(&date.to_le_bytes());
+        header.extend_from_slice(&crc.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0_u16.to_le_bytes());
+        header.extend_from_slice(entry.name.as_bytes());
+        let header_len = header.len() as u32;
+        send(sender, header).await?;
+        send_file(&entry, sender).await?;
+
+        records.push(CentralRecord {
+            name: entry.name,
+            crc,
+            size,
+            offset,
+        });
+        offset = offset
+            .checked_add(header_len)
+            .and_then(|offset| offset.checked_add(size))
+            .ok_or_else(archive_too_large)?;
+    }
+
+    let mut directory = Vec::new();
+    for record in &records {
+        directory.extend_from_slice(&0x0201_4b50_u32.to_le_bytes());
+        directory.extend_from_slice(&VERSION_NEEDED.to_le_bytes());
+        directory.extend_from_slice(&VERSION_NEEDED.to_le_bytes());
+        directory.extend_from_slice(&FLAG_UTF8_NAMES.to_le_bytes());
+        directory.extend_from_slice(&METHOD_STORED.to_le_bytes());
+        directory.extend_from_slice(&time.to_le_bytes());
+        directory.extend_from_slice(&date.to_le_bytes());
+        directory.extend_from_slice(&record.crc.to_le_bytes());
+        directory.extend_from_slice(&record.size.to_le_bytes());
+        directory.extend_from_slice(&record.size.to_le_bytes());
+        directory.extend_from_slice(&(record.name.len() as u16).to_le_bytes());
+        directory.extend_from_slice(&[0; 12]);
+        directory.extend_from_slice(&record.offset.to_le_bytes());
+        directory.extend_from_slice(record.name.as_bytes());
+    }
+    let count = records.len() as u16;
+    let directory_len = directory.len() as u32;
+    directory.extend_from_slice(&0x0605_4b50_u32.to_le_bytes());
+    directory.extend_from_slice(&[0; 4]);
+    directory.extend_from_slice(&count.to_le_bytes());
+    directory.extend_from_slice(&count.to_le_bytes());
+    directory.extend_from_slice(&directory_len.to_le_bytes());
+    directory.extend_from_slice(&offset.to_le_bytes());
+    directory.extend_from_slice(&0_u16.to_le_bytes());
+    send(sender, directory).await
+}
+
+pub(crate) async fn write_archive_file(
+    entries: Vec<ArchiveEntry>,
+    output: &Path,
+) -> Result<(), std::io::Error> {
+    let mut file = tokio::fs::File::create(output).await?;
+    let (sender, mut receiver) = mpsc::channel::<Result<Bytes, std::io::Error>>(8);
+    let writer = async {
+        while let Some(chunk) = receiver.recv().await {
+            file.write_all(&chunk?).await?;
+        }
+        file.flush().await
+    };
+    let (written, flushed) = tokio::join!(
+        async {
+            let result = write_archive(entries, &sender).await;
+            drop(sender);
+            result
+        },
+        writer
+    );
+    written.and(flushed)
+}
+
+async fn file_crc32(entry: &ArchiveEntry) -> Result<u32, std::io::Error> {
+    let mut file = tokio::fs::File::open(&entry.path).await?;
+    let mut buffer = vec![0_u8; crate::lowmem::chunk_bytes(READ_CHUNK_BYTES)];
+    let mut crc = 0xFFFF_FFFF_u32;
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(!crc);
+        }
+        crc = crc32_update(crc, &buffer[..read]);
+    }
+}
+
+async fn send_file(
+    entry: &ArchiveEntry,
+    sender: &mpsc::Sender<Result<Bytes, std::io::Error>>,
+) -> Result<(), std::io::Error> {
+    let mut file = tokio::fs::File::open(&entry.path).await?;
+    let mut buffer = vec![0_u8; crate::lowmem::chunk_bytes(READ_CHUNK_BYTES)];
+    let mut remaining = entry.size;
+    while remaining > 0 {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "el archivo cambio de tamano durante el empaquetado",
+            ));
+        }
+        let read = read.min(remaining as usize);
+        remaining -= read as u64;
+        send(sender, buffer[..read].to_vec()).await?;
+    }
+    Ok(())
+}
+
+async fn send(
+    sender: &mpsc::Sender<Result<Bytes, std::io::Error>>,
+    chunk: Vec<u8>,
+) -> Result<(), std::io::Error> {
+    sender.send(Ok(Bytes::from(chunk))).await.map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::BrokenPipe,
+            "el cliente cerro la conexion",
+        )
+    })
+}
+
+fn archive_too_large() -> std::io::Error {
+    std::io::Error::other("el archivo ZIP supera 4 GiB")
+}
@@ -0,0 +1,669 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::ErrorKind,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, Path as RoutePath, State},
+    http::HeaderMap,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{io::AsyncReadExt, sync::Mutex};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    ApiError, AppState, ArtifactSpec, DownloadMode, FormatHints, client_ip_for_request, encode_hex,
+    is_supported_download_url, non_empty, produce_artifact, schedule_artifact_release,
+};
+
+const HASH_READ_BUFFER_BYTES: usize = 256 * 1024;
+const DEFAULT_PREFETCH_TTL_HOURS: u64 = 24;
+const MAX_PREFETCH_TTL_HOURS: u64 = 7 * 24;
+const RELEASED_ARTIFACT_RETENTION_DAYS: i64 = 30;
+const SHARED_REFERENCES_DIR: &str = "refs";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ArtifactReference {
+    filename: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ArtifactEntry {
+    size: u64,
+    filename: String,
+    created_at: DateTime<Utc>,
+    #[serde(default)]
+    references: HashMap<Uuid, ArtifactReference>,
+    #[serde(default)]
+    sources: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    released_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ArtifactAvailability {
+    Available,
+    Released,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ArtifactHashReport {
+    sha256: String,
+    status: ArtifactAvailability,
+    size_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    released_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct StoredArtifact {
+    pub(crate) hash: String,
+    pub(crate) path: PathBuf,
+    pub(crate) filename: String,
+    pub(crate) size: u64,
+    pub(crate) reused: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PrefetchRequest {
+    url: String,
+    mode: DownloadMode,
+    format_id: Option<String>,
+    has_audio: Option<bool>,
+    ttl_hours: Option<u64>,
+    #[serde(flatten)]
+    hints: FormatHints,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PrefetchResponse {
+    job_id: Uuid,
+    artifact_hash: String,
+    filename: String,
+    size: u64,
+    cached: bool,
+    expires_at: DateTime<Utc>,
+}
+
+// Held while a node changes the shared references of a blob; closing the file drops the flock.
+struct SharedLock {
+    _file: std::fs::File,
+}
+
+#[derive(Debug)]
+pub(crate) struct ArtifactStore {
+    dir: PathBuf,
+    index_path: PathBuf,
+    index: Mutex<HashMap<String, ArtifactEntry>>,
+}
+
+impl ArtifactStore {
+    pub(crate) async fn open(dir: PathBuf, index_path: PathBuf) -> Result<Self, ApiError> {
+        tokio::fs::create_dir_all(dir.join(SHARED_REFERENCES_DIR))
+            .await
+            .map_err(|error| {
+                ApiError::internal(format!(
+                    "No se pudo crear la carpeta de artefactos: {error}"
+                ))
+            })?;
+
+        let index = match tokio::fs::read_to_string(&index_path).await {
+            Ok(content) if content.trim().is_empty() => HashMap::new(),
+            Ok(content) => serde_json::from_str(&content).map_err(|error| {
+                ApiError::internal(format!("Indice de artefactos invalido: {error}"))
+            })?,
+            Err(error) if error.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(error) => {
+                return Err(ApiError::internal(format!(
+                    "No se pudo leer el indice de artefactos: {error}"
+                )));
+            }
+        };
+
+        let store = Self {
+            dir,
+            index_path,
+            index: Mutex::new(index),
+        };
+        store.release_expired().await;
+        Ok(store)
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(&hash[..2]).join(hash)
+    }
+
+    // ARTIFACTS_DIR can be shared by several nodes, each with its own index. Every reference also
+    // leaves a marker next to the blobs, and a blob is only unlinked once no node has a live one.
+    fn shared_references(&self, hash: &str) -> PathBuf {
+        self.dir.join(SHARED_REFERENCES_DIR).join(hash)
+    }
+
+    async fn lock_shared(&self, hash: &str) -> Result<SharedLock, String> {
+        let path = self
+            .dir
+            .join(SHARED_REFERENCES_DIR)
+            .join(format!("{}.lock", &hash[..2]));
+        let file = tokio::task::spawn_blocking(move || {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)?;
+            #[cfg(unix)]
+            {
+                use std::os::fd::AsRawFd;
+                // SAFETY: flock only takes an advisory lock on a descriptor owned by `file`.
+                if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(file)
+        })
+        .await
+        .map_err(|error| error.to_string())?
+        .map_err(|error| error.to_string())?;
+        Ok(SharedLock { _file: file })
+    }
+
+    async fn add_shared_reference(&self, hash: &str, job_id: Uuid, expires_at: DateTime<Utc>) {
+        let dir = self.shared_references(hash);
+        let result = async {
+            tokio::fs::create_dir_all(&dir).await?;
+            tokio::fs::write(dir.join(job_id.to_string()), expires_at.to_rfc3339()).await
+        }
+        .await;
+        if let Err(error) = result {
+            warn!("No se pudo registrar la referencia compartida del artefacto {hash}: {error}");
+        }
+    }
+
+    // Drops the job's marker and any expired one; true once no node references the blob.
+    async fn release_shared_reference(&self, hash: &str, job_id: Option<Uuid>) -> bool {
+        let dir = self.shared_references(hash);
+        if let Some(job_id) = job_id {
+            let _ = tokio::fs::remove_file(dir.join(job_id.to_string())).await;
+        }
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == ErrorKind::NotFound => return true,
+            Err(error) => {
+                warn!("No se pudieron leer las referencias del artefacto {hash}: {error}");
+                return false;
+            }
+        };
+        let now = Utc::now();
+        let mut referenced = false;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let expires_at = tokio::fs::read_to_string(entry.path())
+                .await
+                .ok()
+                .and_then(|content| DateTime::parse_from_rfc3339(content.trim()).ok());
+            if expires_at.is_some_and(|expires_at| expires_at > now) {
+                referenced = true;
+            } else {
+                let _ = tokio::fs::remove_file(entry.path()).await;
+            }
+        }
+        if !referenced {
+            let _ = tokio::fs::remove_dir(&dir).await;
+        }
+        !referenced
+    }
+
+    async fn release_blob(&self, hash: &str, job_id: Option<Uuid>, unused_here: bool) {
+        match self.lock_shared(hash).await {
+            Ok(_lock) => {
+                if self.release_shared_reference(hash, job_id).await && unused_here {
+                    self.remove_blob(hash).await;
+                }
+            }
+            Err(error) => {
+                warn!("No se pudo bloquear el artefacto {hash}; se conserva el archivo: {error}")
+            }
+        }
+    }
+
+    pub(crate) async fn ingest(
+        &self,
+        source: &Path,
+        job_id: Uuid,
+        filename: &str,
+        expires_at: DateTime<Utc>,
+        source_key: Option<&str>,
+    ) -> Result<StoredArtifact, ApiError> {
+        let (hash, size) = hash_file(source).await?;
+
+        let snapshot = {
+            let mut index = self.index.lock().await;
+            let _lock = self.lock_shared(&hash).await.map_err(|error| {
+                ApiError::internal(format!("No se pudo bloquear el artefacto: {error}"))
+            })?;
+            self.place_blob(source, &hash, size).await?;
+            self.add_shared_reference(&hash, job_id, expires_at).await;
+            Self::register(
+                &mut index,
+                &hash,
+                size,
+                job_id,
+                ArtifactReference {
+                    filename: filename.to_string(),
+                    expires_at,
+                },
+                source_key,
+            );
+            index.clone()
+        };
+        self.persist(&snapshot).await;
+
+        Ok(StoredArtifact {
+            path: self.blob_path(&hash),
+            hash,
+            filename: filename.to_string(),
+            size,
+            reused: false,
+        })
+    }
+
+    pub(crate) async fn store_blob(&self, source: &Path) -> Result<(String, u64), ApiError> {
+        let (hash, size) = hash_file(source).await?;
+        let _index = self.index.lock().await;
+        self.place_blob(source, &hash, size).await?;
+        Ok((hash, size))
+    }
+
+    pub(crate) async fn adopt(
+        &self,
+        hash: &str,
+        size: u64,
+        job_id: Uuid,
+        filename: &str,
+        expires_at: DateTime<Utc>,
+        source_key: Option<&str>,
+    ) -> Result<StoredArtifact, ApiError> {
+        if !is_artifact_hash(hash) {
+            return Err(ApiError::internal("El worker devolvio un hash invalido."));
+        }
+        let blob_path = self.blob_path(hash);
+
+        let snapshot = {
+            let mut index = self.index.lock().await;
+            let _lock = self.lock_shared(hash).await.map_err(|error| {
+                ApiError::internal(format!("No se pudo bloquear el artefacto: {error}"))
+            })?;
+            let present = tokio::fs::metadata(&blob_path)
+                .await
+                .is_ok_and(|metadata| metadata.len() == size);
+            if !present {
+                return Err(ApiError::internal(
+                    "El artefacto del worker no esta en el almacenamiento compartido.",
+                ));
+            }
+            self.add_shared_reference(hash, job_id, expires_at).await;
+            Self::register(
+                &mut index,
+                hash,
+                size,
+                job_id,
+                ArtifactReference {
+                    filename: filename.to_string(),
+                    expires_at,
+                },
+                source_key,
+            );
+            index.clone()
+        };
+        self.persist(&snapshot).await;
+
+        Ok(StoredArtifact {
+            hash: hash.to_string(),
+            path: blob_path,
+            filename: filename.to_string(),
+            size,
+            reused: false,
+        })
+    }
+
+    async fn place_blob(&self, source: &Path, hash: &str, size: u64) -> Result<(), ApiError> {
+        let blob_path = self.blob_path(hash);
+        let deduplicated = tokio::fs::metadata(&blob_path)
+            .await
+            .is_ok_and(|metadata| metadata.len() == size);
+
+        if deduplicated {
+            let _ = tokio::fs::remove_file(source).await;
+            info!("Artefacto {hash} deduplicado");
+            return Ok(());
+        }
+        if let Some(parent) = blob_path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|error| {
+                ApiError::internal(format!("No se pudo preparar el artefacto: {error}"))
+            })?;
+        }
+        move_file(source, &blob_path).await
+    }
+
+    fn register(
+        index: &mut HashMap<String, ArtifactEntry>,
+        hash: &str,
+        size: u64,
+        job_id: Uuid,
+        reference: ArtifactReference,
+        source_key: Option<&str>,
+    ) {
+        let entry = index
+            .entry(hash.to_string())
+            .or_insert_with(|| ArtifactEntry {
+                size,
+                filename: reference.filename.clone(),
+                created_at: Utc::now(),
+                references: HashMap::new(),
+                sources: BTreeSet::new(),
+                released_at: None,
+            });
+        entry.released_at = None;
+        if let Some(source_key) = source_key {
+            entry.sources.insert(source_key.to_string());
+        }
+        entry.references.insert(job_id, reference);
+    }
+
+    pub(crate) async fn acquire_cached(
+        &self,
+        source_key: &str,
+        job_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Option<StoredArtifact> {
+        let (artifact, snapshot) = {
+            let mut index = self.index.lock().await;
+            let hash = index
+                .iter()
+                .find(|(_, entry)| {
+                    !entry.references.is_empty() && entry.sources.contains(source_key)
+                })
+                .map(|(hash, _)| hash.clone())?;
+            let path = self.blob_path(&hash);
+            let _lock = self.lock_shared(&hash).await.ok()?;
+            if tokio::fs::metadata(&path).await.is_err() {
+                return None;
+            }
+            self.add_shared_reference(&hash, job_id, expires_at).await;
+
+            let entry = index.get_mut(&hash)?;
+            entry.references.insert(
+                job_id,
+                ArtifactReference {
+                    filename: entry.filename.clone(),
+                    expires_at,
+                },
+            );
+            let artifact = StoredArtifact {
+                hash,
+                path,
+                filename: entry.filename.clone(),
+                size: entry.size,
+                reused: true,
+            };
+            (artifact, index.clone())
+        };
+        self.persist(&snapshot).await;
+        Some(artifact)
+    }
+
+    // Deduplicated blobs keep each job's own filename; links without a job fall back to the first one.
+    pub(crate) async fn lookup(&self, hash: &str, job_id: Option<Uuid>) -> Option<StoredArtifact> {
+        if !is_artifact_hash(hash) {
+            return None;
+        }
+
+        let index = self.index.lock().await;
+        let entry = index
+            .get(hash)
+            .filter(|entry| !entry.references.is_empty())?;
+        let filename = job_id
+            .and_then(|job_id| entry.references.get(&job_id))
+            .map_or(&entry.filename, |reference| &reference.filename);
+        Some(StoredArtifact {
+            hash: hash.to_string(),
+            path: self.blob_path(hash),
+            filename: filename.clone(),
+            size: entry.size,
+            reused: true,
+        })
+    }
+
+    pub(crate) async fn blob_at(&self, hash: &str, size: u64) -> Option<PathBuf> {
+        if !is_artifact_hash(hash) {
+            return None;
+        }
+        let path = self.blob_path(hash);
+        tokio::fs::metadata(&path)
+            .await
+            .is_ok_and(|metadata| metadata.len() == size)
+            .then_some(path)
+    }
+
+    pub(crate) async fn release(&self, hash: &str, job_id: Uuid) {
+        let snapshot = {
+            let mut index = self.index.lock().await;
+            let Some(entry) = index.get_mut(hash) else {
+                return;
+            };
+            entry.references.remove(&job_id);
+            let unused = entry.references.is_empty();
+            if unused && entry.released_at.is_none() {
+                entry.released_at = Some(Utc::now());
+                entry.sources.clear();
+            }
+            self.release_blob(hash, Some(job_id), unused).await;
+            index.clone()
+        };
+        self.persist(&snapshot).await;
+    }
+
+    pub(crate) async fn release_expired(&self) {
+        let now = Utc::now();
+        let snapshot = {
+            let mut index = self.index.lock().await;
+            let mut orphaned = Vec::new();
+            for (hash, entry) in index.iter_mut() {
+                entry
+                    .references
+                    .retain(|_, reference| reference.expires_at > now);
+                if entry.references.is_empty() && entry.released_at.is_none() {
+                    entry.released_at = Some(now);
+                    entry.sources.clear();
+                    orphaned.push(hash.clone());
+                }
+            }
+            for hash in orphaned {
+                self.release_blob(&hash, None, true).await;
+            }
+            let retention = Duration::days(RELEASED_ARTIFACT_RETENTION_DAYS);
+            index.retain(|_, entry| {
+                entry
+                    .released_at
+                    .is_none_or(|released_at| now - released_at < retention)
+            });
+            index.clone()
+        };
+        self.persist(&snapshot).await;
+    }
+
+    async fn report(&self, hash: &str) -> Option<ArtifactHashReport> {
+        let index = self.index.lock().await;
+        let entry = index.get(hash)?;
+        let released = entry.references.is_empty();
+        Some(ArtifactHashReport {
+            sha256: hash.to_string(),
+            status: if released {
+                ArtifactAvailability::Released
+            } else {
+                ArtifactAvailability::Available
+            },
+            size_bytes: entry.size,
+            expires_at: entry
+                .references
+                .values()
+                .map(|reference| reference.expires_at)
+                .max(),
+            released_at: entry.released_at.filter(|_| released),
+        })
+    }
+
+    async fn remove_blob(&self, hash: &str) {
+        if let Err(error) = tokio::fs::remove_file(self.blob_path(hash)).await
+            && error.kind() != ErrorKind::NotFound
+        {
+            warn!("No se pudo eliminar artefacto {hash}: {error}");
+        }
+    }
+
+    async fn persist(&self, index: &HashMap<String, ArtifactEntry>) {
+        let result = async {
+            let content = crate::lowmem::to_json_vec(index).map_err(|error| error.to_string())?;
+            tokio::fs::write(&self.index_path, content)
+                .await
+                .map_err(|error| error.to_string())
+        }
+        .await;
+        if let Err(error) = result {
+            warn!("No se pudo guardar el indice de artefactos: {error}");
+        }
+    }
+}
+
+pub(crate) fn is_artifact_hash(value: &str) -> bool {
+    value.len() == 64 && value.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+pub(crate) async fn hash_file(path: &Path) -> Result<(String, u64), ApiError> {
+    let mut file = tokio::fs::File::open(path).await.map_err(|error| {
+        ApiError::internal(format!("No se pudo leer el archivo temporal: {error}"))
+    })?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0_u8; HASH_READ_BUFFER_BYTES];
+    let mut size = 0_u64;
+
+    loop {
+        let read = file.read(&mut buffer).await.map_err(|error| {
+            ApiError::internal(format!("No se pudo leer el archivo temporal: {error}"))
+        })?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+
+    Ok((encode_hex(&hasher.finalize()), size))
+}
+
+async fn move_file(source: &Path, destination: &Path) -> Result<(), ApiError> {
+    if tokio::fs::rename(source, destination).await.is_ok() {
+        return Ok(());
+    }
+
+    tokio::fs::copy(source, destination)
+        .await
+        .map_err(|error| ApiError::internal(format!("No se pudo guardar el artefacto: {error}")))?;
+    let _ = tokio::fs::remove_file(source).await;
+    Ok(())
+}
+
+pub(crate) async fn prefetch_artifact(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<PrefetchRequest>,
+) -> Result<Json<PrefetchResponse>, ApiError> {
+    let url = payload.url.trim();
+    if url.is_empty() || !is_supported_download_url(url, &state.extra_supported_domains) {
+        return Err(ApiError::bad_request("URL no soportada para pre-carga."));
+    }
+
+    let ttl_hours = payload
+        .ttl_hours
+        .filter(|hours| *hours > 0)
+        .unwrap_or(DEFAULT_PREFETCH_TTL_HOURS)
+        .min(MAX_PREFETCH_TTL_HOURS);
+    let spec = ArtifactSpec {
+        url,
+        mode: payload.mode,
+        format_id: payload.format_id.as_deref().and_then(non_empty),
+        has_audio: payload.has_audio.unwrap_or(false),
+        hints: payload.hints,
+        container: None,
+        subtitle_languages: &[],
+        codec_profile: None,
+        clip: None,
+        split_chapters: false,
+        extra_args: &[],
+        sponsorblock_remove: &[],
+        embed_metadata: false,
+        embed_thumbnail: true,
+        audio_tags: None,
+        sidecars: None,
+        ip_family: state.ip_family,
+        max_download_bytes: state.config.max_download_bytes,
+        retention_seconds: ttl_hours * 60 * 60,
+    };
+
+    let client_ip = client_ip_for_request(&state, &headers, addr);
+    let job = state.jobs.create(Uuid::new_v4(), &client_ip).await?;
+
+    let artifact = match produce_artifact(&state, &job, &spec).await {
+        Ok(artifact) => artifact,
+        Err(error) => {
+            job.fail(&error.message);
+            return Err(error);
+        }
+    };
+    job.complete(&artifact.filename);
+    info!(
+        "Pre-carga de {url} lista: artefacto {} ({} bytes, cache={})",
+        artifact.hash, artifact.size, artifact.reused
+    );
+    schedule_artifact_release(
+        &state,
+        artifact.hash.clone(),
+        job.job_id(),
+        spec.retention_seconds,
+    );
+
+    Ok(Json(PrefetchResponse {
+        job_id: job.job_id(),
+        artifact_hash: artifact.hash,
+        filename: artifact.filename,
+        size: artifact.size,
+        cached: artifact.reused,
+        expires_at: Utc::now() + chrono::Duration::seconds(spec.retention_seconds as i64),
+    }))
+}
+
+pub(crate) async fn get_artifact_by_hash(
+    State(state): State<AppState>,
+    RoutePath(hash): RoutePath<String>,
+) -> Result<Json<ArtifactHashReport>, ApiError> {
+    let hash = hash.trim().to_ascii_lowercase();
+    if !is_artifact_hash(&hash) {
+        return Err(ApiError::bad_request(
+            "El hash debe ser un SHA-256 en hexadecimal (64 caracteres).",
+        ));
+    }
+    state.artifacts.report(&hash).await.map(Json).ok_or_else(|| {
+        ApiError::not_found(format!(
+            "No hay registro de un artefacto con ese hash en los ultimos {RELEASED_ARTIFACT_RETENTION_DAYS} dias."
+        ))
+    })
+}
@@ -0,0 +1,568 @@
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{COOKIE, LOCATION, SET_COOKIE},
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    ApiError, AppState, entitlements::Tier, non_empty, public_base_url, rbac::Role, sign_value,
+    verify_signature,
+};
+
+const SESSION_COOKIE: &str = "td_session";
+const LOGIN_COOKIE: &str = "td_oidc";
+const LOGIN_COOKIE_MINUTES: i64 = 10;
+const DEFAULT_SESSION_HOURS: i64 = 12;
+const DEFAULT_SCOPES: &str = "openid email profile";
+const DEFAULT_ROLE_CLAIM: &str = "groups";
+const ID_TOKEN_LEEWAY_SECONDS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SameSite {
+    Lax,
+    Strict,
+    None,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    #[serde(default)]
+    userinfo_endpoint: Option<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct OidcAuth {
+    issuer_url: String,
+    client_id: String,
+    client_secret: String,
+    redirect_url: Option<String>,
+    post_login_url: Option<String>,
+    scopes: String,
+    role_claim: String,
+    role_map: Vec<(String, Role)>,
+    default_role: Role,
+    require_login: bool,
+    session_hours: i64,
+    same_site: SameSite,
+    provider: OnceCell<ProviderMetadata>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LoginAttempt {
+    state: String,
+    nonce: String,
+    verifier: String,
+    redirect_url: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Session {
+    pub(crate) sub: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    pub(crate) role: Role,
+    pub(crate) expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CallbackQuery {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    state: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+    #[serde(default)]
+    access_token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SessionResponse {
+    authenticated: bool,
+    login_required: bool,
+    role: Role,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tier: Option<Tier>,
+}
+
+impl OidcAuth {
+    pub(crate) fn from_env() -> Option<Self> {
+        let read = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| non_empty(&value).map(ToString::to_string))
+        };
+        let issuer_url = read("OIDC_ISSUER_URL")?.trim_end_matches('/').to_string();
+        let Some(client_id) = read("OIDC_CLIENT_ID") else {
+            warn!("OIDC_ISSUER_URL configurado sin OIDC_CLIENT_ID: login OIDC deshabilitado.");
+            return None;
+        };
+
+        let mut role_map = Vec::new();
+        for entry in crate::read_list_env_raw("OIDC_ROLE_MAP") {
+            let parsed = entry.rsplit_once(':').and_then(|(claim, role)| {
+                Some((non_empty(claim)?.to_string(), Role::parse(role)?))
+            });
+            match parsed {
+                Some(binding) => role_map.push(binding),
+                None => warn!("Entrada invalida en OIDC_ROLE_MAP (usa valor:rol): {entry:?}"),
+            }
+        }
+        let same_site = match read("AUTH_COOKIE_SAME_SITE")
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "strict" => SameSite::Strict,
+            "none" => SameSite::None,
+            _ => SameSite::Lax,
+        };
+
+        Some(Self {
+            issuer_url,
+            client_id,
+            client_secret: read("OIDC_CLIENT_SECRET").unwrap_or_default(),
+            redirect_url: read("OIDC_REDIRECT_URL"),
+            post_login_url: read("OIDC_POST_LOGIN_URL"),
+            scopes: read("OIDC_SCOPES").unwrap_or_else(|| DEFAULT_SCOPES.to_string()),
+            role_claim: read("OIDC_ROLE_CLAIM").unwrap_or_else(|| DEFAULT_ROLE_CLAIM.to_string()),
+            role_map,
+            default_role: read("OIDC_DEFAULT_ROLE")
+                .and_then(|role| Role::parse(&role))
+                .unwrap_or(Role::User),
+            require_login: crate::read_bool_env("OIDC_REQUIRE_LOGIN").unwrap_or(false),
+            session_hours: crate::read_usize_env("AUTH_SESSION_HOURS")
+                .filter(|value| *value > 0)
+                .map_or(DEFAULT_SESSION_HOURS, |value| value as i64),
+            same_site,
+            provider: OnceCell::new(),
+        })
+    }
+
+    pub(crate) fn issuer(&self) -> &str {
+        &self.issuer_url
+    }
+
+    async fn provider(&self, client: &reqwest::Client) -> Result<&ProviderMetadata, ApiError> {
+        self.provider
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.issuer_url);
+                let metadata = client
+                    .get(&url)
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .map_err(|error| {
+                        ApiError::internal(format!(
+                            "No se pudo consultar el proveedor OIDC: {error}"
+                        ))
+                    })?
+                    .json::<ProviderMetadata>()
+                    .await
+                    .map_err(|error| {
+                        ApiError::internal(format!("Configuracion OIDC invalida: {error}"))
+                    })?;
+                if metadata.issuer.trim_end_matches('/') != self.issuer_url {
+                    return Err(ApiError::internal(
+                        "El issuer del proveedor OIDC no coincide con OIDC_ISSUER_URL.",
+                    ));
+                }
+                Ok(metadata)
+            })
+            .await
+    }
+
+    fn callback_url(&self, state: &AppState, headers: &HeaderMap) -> String {
+        self.redirect_url
+            .clone()
+            .unwrap_or_else(|| format!("{}/api/auth/callback", public_base_url(state, headers)))
+    }
+
+    fn resolve_role(&self, claims: &serde_json::Value) -> Role {
+        let claim = self
+            .role_claim
+            .split('.')
+            .try_fold(claims, |value, segment| value.get(segment));
+        let values = match claim {
+            Some(serde_json::Value::String(value)) => vec![value.as_str()],
+            Some(serde_json::Value::Array(items)) => {
+                items.iter().filter_map(serde_json::Value::as_str).collect()
+            }
+            _ => Vec::new(),
+        };
+
+        self.role_map
+            .iter()
+            .filter(|(claim_value, _)| values.contains(&claim_value.as_str()))
+            .map(|(_, role)| *role)
+            .max()
+            .map_or(self.default_role, |role| role.max(self.default_role))
+    }
+
+    fn cookie(&self, name: &str, value: &str, path: &str, max_age: i64, secure: bool) -> String {
+        let same_site = match self.same_site {
+            SameSite::Lax => "Lax",
+            SameSite::Strict => "Strict",
+            SameSite::None => "None",
+        };
+        let secure = if secure || self.same_site == SameSite::None {
+            "; Secure"
+        } else {
+            ""
+        };
+        format!(
+            "{name}={value}; Path={path}; Max-Age={max_age}; HttpOnly; SameSite={same_site}{secure}"
+        )
+    }
+}
+
+pub(crate) fn session_from_headers(state: &AppState, headers: &HeaderMap) -> Option<Session> {
+    state.auth.as_ref()?;
+    read_signed_cookie::<Session>(state, headers, SESSION_COOKIE)
+        .filter(|session| session.expires_at > Utc::now())
+}
+
+pub(crate) fn session_role(state: &AppState, headers: &HeaderMap) -> Role {
+    session_from_headers(state, headers).map_or(Role::Anonymous, |session| session.role)
+}
+
+pub(crate) async fn require_login(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let required = state.auth.as_ref().is_some_and(|auth| auth.require_login);
+    if required
+        && state.roles.resolve(request.headers()) == Role::Anonymous
+        && session_from_headers(&state, request.headers()).is_none()
+        && !matches!(
+            state
+                .api_keys
+                .resolve(&state.roles, request.headers())
+                .await,
+            Ok(Some(_))
+        )
+    {
+        return ApiError::unauthorized("Inicia sesion para usar el descargador.").into_response();
+    }
+
+    next.run(request).await
+}
+
+pub(crate) async fn start_login(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let auth = enabled(&state)?;
+    let provider = auth.provider(&state.http_client).await?;
+    let redirect_url = auth.callback_url(&state, &headers);
+    let attempt = LoginAttempt {
+        state: Uuid::new_v4().simple().to_string(),
+        nonce: Uuid::new_v4().simple().to_string(),
+        verifier: format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+        redirect_url,
+        expires_at: Utc::now() + chrono::Duration::minutes(LOGIN_COOKIE_MINUTES),
+    };
+
+    let mut location = Url::parse(&provider.authorization_endpoint)
+        .map_err(|_| ApiError::internal("authorization_endpoint OIDC invalido."))?;
+    location
+        .query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &auth.client_id)
+        .append_pair("redirect_uri", &attempt.redirect_url)
+        .append_pair("scope", &auth.scopes)
+        .append_pair("state", &attempt.state)
+        .append_pair("nonce", &attempt.nonce)
+        .append_pair(
+            "code_challenge",
+            &URL_SAFE_NO_PAD.encode(Sha256::digest(attempt.verifier.as_bytes())),
+        )
+        .append_pair("code_challenge_method", "S256");
+
+    let secure = attempt.redirect_url.starts_with("https://");
+    let cookie = auth.cookie(
+        LOGIN_COOKIE,
+        &sign_cookie(&state, LOGIN_COOKIE, &attempt)?,
+        "/api/auth",
+        LOGIN_COOKIE_MINUTES * 60,
+        secure,
+    );
+    Ok(redirect_with_cookies(location.as_str(), &[cookie]))
+}
+
+pub(crate) async fn complete_login(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<CallbackQuery>,
+) -> Result<Response, ApiError> {
+    let auth = enabled(&state)?;
+    if let Some(error) = query.error.as_deref() {
+        return Err(ApiError::unauthorized(format!(
+            "El proveedor de identidad rechazo el login: {error}"
+        )));
+    }
+    let attempt = read_signed_cookie::<LoginAttempt>(&state, &headers, LOGIN_COOKIE)
+        .filter(|attempt| attempt.expires_at > Utc::now())
+        .ok_or_else(|| ApiError::unauthorized("La sesion de login expiro. Intenta de nuevo."))?;
+    let (Some(code), Some(returned_state)) = (query.code.as_deref(), query.state.as_deref()) else {
+        return Err(ApiError::bad_request(
+            "Faltan parametros de respuesta OIDC.",
+        ));
+    };
+    if returned_state != attempt.state {
+        return Err(ApiError::unauthorized(
+            "El parametro state de OIDC no coincide.",
+        ));
+    }
+
+    let provider = auth.provider(&state.http_client).await?;
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", attempt.redirect_url.as_str()),
+        ("client_id", auth.client_id.as_str()),
+        ("code_verifier", attempt.verifier.as_str()),
+    ];
+    if !auth.client_secret.is_empty() {
+        form.push(("client_secret", auth.client_secret.as_str()));
+    }
+    let tokens = state
+        .http_client
+        .post(&provider.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|error| {
+            ApiError::unauthorized(format!("No se pudo canjear el codigo OIDC: {error}"))
+        })?
+        .json::<TokenResponse>()
+        .await
+        .map_err(|error| {
+            ApiError::unauthorized(format!("Respuesta de token OIDC invalida: {error}"))
+        })?;
+
+    let mut claims = validate_id_token(auth, provider, &tokens.id_token, &attempt.nonce)?;
+    if let (Some(userinfo_endpoint), Some(access_token), serde_json::Value::Object(merged)) = (
+        provider.userinfo_endpoint.as_deref(),
+        tokens.access_token.as_deref(),
+        &mut claims,
+    ) && let Ok(response) = state
+        .http_client
+        .get(userinfo_endpoint)
+        .bearer_auth(access_token)
+        .send()
+        .await
+        && let Ok(serde_json::Value::Object(userinfo)) = response.json::<serde_json::Value>().await
+        && userinfo.get("sub") == merged.get("sub")
+    {
+        for (key, value) in userinfo {
+            merged.entry(key).or_insert(value);
+        }
+    }
+
+    let claim = |name: &str| {
+        claims
+            .get(name)
+            .and_then(serde_json::Value::as_str)
+            .map(ToString::to_string)
+    };
+    let session = Session {
+        sub: claim("sub").ok_or_else(|| ApiError::unauthorized("El id_token no incluye sub."))?,
+        email: claim("email"),
+        name: claim("name").or_else(|| claim("preferred_username")),
+        role: auth.resolve_role(&claims),
+        expires_at: Utc::now() + chrono::Duration::hours(auth.session_hours),
+    };
+    info!("Login OIDC de {} con rol {:?}", session.sub, session.role);
+
+    let secure = attempt.redirect_url.starts_with("https://");
+    let cookies = [
+        auth.cookie(
+            SESSION_COOKIE,
+            &sign_cookie(&state, SESSION_COOKIE, &session)?,
+            "/",
+            auth.session_hours * 3600,
+            secure,
+        ),
+        auth.cookie(LOGIN_COOKIE, "", "/api/auth", 0, secure),
+    ];
+    let target = auth
+        .post_login_url
+        .clone()
+        .unwrap_or_else(|| format!("{}/", public_base_url(&state, &headers)));
+    Ok(redirect_with_cookies(&target, &cookies))
+}
+
+pub(crate) async fn get_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<SessionResponse>, ApiError> {
+    let auth = enabled(&state)?;
+    let session = session_from_headers(&state, &headers);
+    let tier = match (&state.entitlements, &session) {
+        (Some(entitlements), Some(session)) => {
+            Some(if entitlements.premium_for(session).await.is_some() {
+                Tier::Premium
+            } else {
+                Tier::Free
+            })
+        }
+        _ => None,
+    };
+
+    Ok(Json(SessionResponse {
+        authenticated: session.is_some(),
+        login_required: auth.require_login,
+        role: session
+            .as_ref()
+            .map_or(Role::Anonymous, |session| session.role),
+        subject: session.as_ref().map(|session| session.sub.clone()),
+        email: session.as_ref().and_then(|session| session.email.clone()),
+        name: session.as_ref().and_then(|session| session.name.clone()),
+        expires_at: session.map(|session| session.expires_at),
+        tier,
+    }))
+}
+
+pub(crate) async fn logout(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let auth = enabled(&state)?;
+    let secure = auth.callback_url(&state, &headers).starts_with("https://");
+    let cookie = auth.cookie(SESSION_COOKIE, "", "/", 0, secure);
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    if let Ok(value) = HeaderValue::from_str(&cookie) {
+        response.headers_mut().append(SET_COOKIE, value);
+    }
+    Ok(response)
+}
+
+fn enabled(state: &AppState) -> Result<&OidcAuth, ApiError> {
+    state
+        .auth
+        .as_deref()
+        .ok_or_else(|| ApiError::not_found("Login OIDC deshabilitado."))
+}
+
+// The id_token only ever comes straight from the token endpoint over TLS in exchange for our
+// PKCE code, so (per OIDC Core 3.1.3.7) the TLS server check stands in for the JWS signature.
+fn validate_id_token(
+    auth: &OidcAuth,
+    provider: &ProviderMetadata,
+    id_token: &str,
+    nonce: &str,
+) -> Result<serde_json::Value, ApiError> {
+    let invalid = || ApiError::unauthorized("id_token OIDC invalido.");
+    let payload = id_token.split('.').nth(1).ok_or_else(invalid)?;
+    let claims = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
+        .ok_or_else(invalid)?;
+
+    let issuer_matches =
+        claims.get("iss").and_then(serde_json::Value::as_str) == Some(provider.issuer.as_str());
+    let audience_matches = match claims.get("aud") {
+        Some(serde_json::Value::String(audience)) => *audience == auth.client_id,
+        Some(serde_json::Value::Array(audiences)) => audiences
+            .iter()
+            .any(|audience| audience.as_str() == Some(auth.client_id.as_str())),
+        _ => false,
+    };
+    let not_expired = claims
+        .get("exp")
+        .and_then(serde_json::Value::as_i64)
+        .is_some_and(|exp| exp + ID_TOKEN_LEEWAY_SECONDS > Utc::now().timestamp());
+    let nonce_matches = claims.get("nonce").and_then(serde_json::Value::as_str) == Some(nonce);
+    if !(issuer_matches && audience_matches && not_expired && nonce_matches) {
+        warn!(
+            "id_token OIDC rechazado (iss={issuer_matches}, aud={audience_matches}, exp={not_expired}, nonce={nonce_matches})"
+        );
+        return Err(invalid());
+    }
+
+    Ok(claims)
+}
+
+fn sign_cookie<T: Serialize>(state: &AppState, name: &str, value: &T) -> Result<String, ApiError> {
+    let payload = serde_json::to_vec(value)
+        .map(|bytes| URL_SAFE_NO_PAD.encode(bytes))
+        .map_err(|error| ApiError::internal(format!("No se pudo firmar la sesion: {error}")))?;
+    let signature = sign_value(&state.signing_secret, &format!("{name}:{payload}"));
+    Ok(format!("{payload}.{signature}"))
+}
+
+fn read_signed_cookie<T: DeserializeOwned>(
+    state: &AppState,
+    headers: &HeaderMap,
+    name: &str,
+) -> Option<T> {
+    let raw = headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)?;
+    let (payload, signature) = raw.rsplit_once('.')?;
+    if !verify_signature(
+        &state.signing_secret,
+        &format!("{name}:{payload}"),
+        signature,
+    ) {
+        return None;
+    }
+
+    let bytes = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn redirect_with_cookies(location: &str, cookies: &[String]) -> Response {
+    let mut response = StatusCode::FOUND.into_response();
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(location) {
+        headers.insert(LOCATION, value);
+    }
+    for cookie in cookies {
+        if let Ok(value) = HeaderValue::from_str(cookie) {
+            headers.append(SET_COOKIE, value);
+        }
+    }
+    response
+}
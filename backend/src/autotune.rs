@@ -0,0 +1,315 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use axum::{Json, extract::State};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::{sync::Semaphore, time::Duration};
+use tracing::{info, warn};
+
+use crate::{ApiError, AppState, read_bool_env, read_usize_env, system::PressureSample};
+
+const DEFAULT_INTERVAL_SECONDS: usize = 15;
+const DEFAULT_CPU_HIGH: f64 = 0.9;
+const DEFAULT_CPU_LOW: f64 = 0.6;
+const DEFAULT_MIN_MEMORY_PERCENT: f64 = 15.0;
+const DEFAULT_MIN_DISK_PERCENT: f64 = 10.0;
+const MAX_LOGGED_DECISIONS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pressure {
+    Cpu,
+    Memory,
+    Disk,
+}
+
+impl Pressure {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Cpu => "CPU",
+            Self::Memory => "memoria",
+            Self::Disk => "disco",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Decision {
+    at: DateTime<Utc>,
+    from: usize,
+    to: usize,
+    reason: String,
+    sample: PressureSample,
+}
+
+#[derive(Debug)]
+struct TunerState {
+    limit: usize,
+    // Permits still held by running jobs that must be dropped once they come back.
+    owed: usize,
+    last_sample: Option<PressureSample>,
+    decisions: VecDeque<Decision>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ConcurrencyTuner {
+    semaphore: Arc<Semaphore>,
+    min: usize,
+    max: usize,
+    interval: Duration,
+    cpu_high: f64,
+    cpu_low: f64,
+    min_memory_ratio: f64,
+    min_disk_ratio: f64,
+    state: std::sync::Mutex<TunerState>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TunerReport {
+    limit: usize,
+    min: usize,
+    max: usize,
+    in_use: usize,
+    pending_reduction: usize,
+    interval_seconds: u64,
+    thresholds: Thresholds,
+    last_sample: Option<PressureSample>,
+    decisions: Vec<Decision>,
+}
+
+#[derive(Debug, Serialize)]
+struct Thresholds {
+    cpu_high: f64,
+    cpu_low: f64,
+    min_memory_ratio: f64,
+    min_disk_ratio: f64,
+}
+
+fn read_f64_env(name: &str) -> Option<f64> {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .filter(|value| value.is_finite() && *value > 0.0)
+}
+
+impl ConcurrencyTuner {
+    pub(crate) fn from_env(semaphore: Arc<Semaphore>, initial: usize) -> Option<Self> {
+        if !read_bool_env("AUTO_CONCURRENCY").unwrap_or(false) {
+            return None;
+        }
+        let min = read_usize_env("AUTO_CONCURRENCY_MIN")
+            .filter(|value| *value > 0)
+            .unwrap_or(1);
+        let max = crate::lowmem::concurrency(
+            read_usize_env("AUTO_CONCURRENCY_MAX")
+                .filter(|value| *value > 0)
+                .unwrap_or(initial * 2)
+                .max(min),
+        );
+        let min = min.min(max);
+        let cpu_high = read_f64_env("AUTO_CONCURRENCY_CPU_HIGH").unwrap_or(DEFAULT_CPU_HIGH);
+        let tuner = Self {
+            semaphore,
+            min,
+            max,
+            interval: Duration::from_secs(
+                read_usize_env("AUTO_CONCURRENCY_INTERVAL_SECONDS")
+                    .filter(|value| *value > 0)
+                    .unwrap_or(DEFAULT_INTERVAL_SECONDS) as u64,
+            ),
+            cpu_high,
+            cpu_low: read_f64_env("AUTO_CONCURRENCY_CPU_LOW")
+                .unwrap_or(DEFAULT_CPU_LOW)
+                .min(cpu_high),
+            min_memory_ratio: read_f64_env("AUTO_CONCURRENCY_MIN_MEMORY_PERCENT")
+                .unwrap_or(DEFAULT_MIN_MEMORY_PERCENT)
+                / 100.0,
+            min_disk_ratio: read_f64_env("AUTO_CONCURRENCY_MIN_DISK_PERCENT")
+                .unwrap_or(DEFAULT_MIN_DISK_PERCENT)
+                / 100.0,
+            state: std::sync::Mutex::new(TunerState {
+                limit: initial,
+                owed: 0,
+                last_sample: None,
+                decisions: VecDeque::new(),
+            }),
+        };
+        let clamped = initial.clamp(min, max);
+        if clamped != initial {
+            tuner.resize(
+                initial,
+                clamped,
+                "limites de AUTO_CONCURRENCY".to_string(),
+                None,
+            );
+        }
+        info!(
+            "Concurrencia de descargas automatica entre {min} y {max} (inicial {clamped}, cada {:?}).",
+            tuner.interval
+        );
+        Some(tuner)
+    }
+
+    fn pressures(&self, sample: &PressureSample) -> Vec<Pressure> {
+        let mut pressures = Vec::new();
+        if sample
+            .cpu_load_per_core
+            .is_some_and(|load| load > self.cpu_high)
+        {
+            pressures.push(Pressure::Cpu);
+        }
+        if sample
+            .memory_available_ratio
+            .is_some_and(|ratio| ratio < self.min_memory_ratio)
+        {
+            pressures.push(Pressure::Memory);
+        }
+        if sample
+            .disk_available_ratio
+            .is_some_and(|ratio| ratio < self.min_disk_ratio)
+        {
+            pressures.push(Pressure::Disk);
+        }
+        pressures
+    }
+
+    // Grows only with spare headroom on every signal and jobs actually waiting for a slot.
+    fn has_headroom(&self, sample: &PressureSample) -> bool {
+        sample
+            .cpu_load_per_core
+            .is_none_or(|load| load < self.cpu_low)
+            && sample
+                .memory_available_ratio
+                .is_none_or(|ratio| ratio >= self.min_memory_ratio * 2.0)
+            && sample
+                .disk_available_ratio
+                .is_none_or(|ratio| ratio >= self.min_disk_ratio)
+    }
+
+    fn settle_debt(&self, state: &mut TunerState) {
+        if state.owed > 0 {
+            state.owed -= self.semaphore.forget_permits(state.owed);
+        }
+    }
+
+    fn resize(&self, from: usize, to: usize, reason: String, sample: Option<PressureSample>) {
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        if to > from {
+            let grow = to - from;
+            let cancelled = grow.min(state.owed);
+            state.owed -= cancelled;
+            self.semaphore.add_permits(grow - cancelled);
+        } else {
+            state.owed += from - to;
+            self.settle_debt(&mut state);
+        }
+        state.limit = to;
+        info!("Concurrencia de descargas {from} -> {to}: {reason}.");
+        if let Some(sample) = sample {
+            state.decisions.push_back(Decision {
+                at: Utc::now(),
+                from,
+                to,
+                reason,
+                sample,
+            });
+            if state.decisions.len() > MAX_LOGGED_DECISIONS {
+                state.decisions.pop_front();
+            }
+        }
+    }
+
+    fn evaluate(&self, sample: PressureSample, waiting: usize) {
+        let limit = {
+            let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+            state.last_sample = Some(sample);
+            self.settle_debt(&mut state);
+            state.limit
+        };
+        let pressures = self.pressures(&sample);
+        if !pressures.is_empty() {
+            if limit > self.min {
+                self.resize(
+                    limit,
+                    limit - 1,
+                    format!(
+                        "presion de {}",
+                        pressures
+                            .iter()
+                            .map(|pressure| pressure.label())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    Some(sample),
+                );
+            }
+        } else if waiting > 0 && limit < self.max && self.has_headroom(&sample) {
+            self.resize(
+                limit,
+                limit + 1,
+                format!("{waiting} descarga(s) en cola con recursos libres"),
+                Some(sample),
+            );
+        }
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.state
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .limit
+    }
+
+    fn report(&self) -> TunerReport {
+        let state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        TunerReport {
+            limit: state.limit,
+            min: self.min,
+            max: self.max,
+            in_use: (state.limit + state.owed).saturating_sub(self.semaphore.available_permits()),
+            pending_reduction: state.owed,
+            interval_seconds: self.interval.as_secs(),
+            thresholds: Thresholds {
+                cpu_high: self.cpu_high,
+                cpu_low: self.cpu_low,
+                min_memory_ratio: self.min_memory_ratio,
+                min_disk_ratio: self.min_disk_ratio,
+            },
+            last_sample: state.last_sample,
+            decisions: state.decisions.iter().rev().cloned().collect(),
+        }
+    }
+}
+
+pub(crate) async fn run_tuner(state: AppState) {
+    let Some(tuner) = state.concurrency.clone() else {
+        return;
+    };
+    let mut ticker = tokio::time::interval(tuner.interval);
+    loop {
+        ticker.tick().await;
+        let monitor = state.system.clone();
+        let sample = match tokio::task::spawn_blocking(move || monitor.pressure("transfer")).await {
+            Ok(sample) => sample,
+            Err(error) => {
+                warn!("No se pudo medir la carga del sistema: {error}");
+                continue;
+            }
+        };
+        let (waiting, _) = state.jobs.state_counts().await;
+        tuner.evaluate(sample, waiting);
+    }
+}
+
+pub(crate) async fn get_concurrency_report(
+    State(state): State<AppState>,
+) -> Result<Json<TunerReport>, ApiError> {
+    state
+        .concurrency
+        .as_deref()
+        .map(|tuner| Json(tuner.report()))
+        .ok_or_else(|| {
+            ApiError::not_found(
+                "El ajuste automatico de concurrencia esta deshabilitado (AUTO_CONCURRENCY).",
+            )
+        })
+}
@@ -0,0 +1,197 @@
+use std::{collections::HashMap, net::IpAddr};
+
+use axum::{
+    Json,
+    extract::{Path as RoutePath, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+use tracing::info;
+
+use crate::{ApiError, AppState, normalize_optional_text, redis::RedisStore};
+
+const MAX_REASON_CHARS: usize = 500;
+const MAX_BAN_HOURS: u64 = 24 * 365 * 10;
+const REDIS_BAN_KIND: &str = "ban";
+const REDIS_BANS_IMPORTED: (&str, &str) = ("meta", "bans-imported-at");
+
+pub(crate) type BanMap = HashMap<String, IpBan>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct IpBan {
+    ip: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct BanRequest {
+    ip: String,
+    reason: Option<String>,
+    hours: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct BanList {
+    bans: Vec<IpBan>,
+}
+
+impl IpBan {
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+
+    // Permanent bans are stored without a TTL so Redis never drops them.
+    fn redis_ttl(&self, now: DateTime<Utc>) -> Option<Duration> {
+        self.expires_at
+            .map(|expires_at| (expires_at - now).to_std().unwrap_or_default())
+    }
+}
+
+// Same textual form as client_ip_for_request, so "::ffff:1.2.3.4" and "1.2.3.4" are one ban.
+fn normalize_ip(value: &str) -> Result<String, ApiError> {
+    value
+        .trim()
+        .parse::<IpAddr>()
+        .map(|ip| ip.to_canonical().to_string())
+        .map_err(|_| ApiError::bad_request(format!("IP invalida: {}.", value.trim())))
+}
+
+pub(crate) async fn ensure_not_banned(state: &AppState, client_ip: &str) -> Result<(), ApiError> {
+    let Ok(ip) = normalize_ip(client_ip) else {
+        return Ok(());
+    };
+    let now = Utc::now();
+    // With Redis every instance sees bans and unbans made elsewhere.
+    let ban = match &state.redis {
+        Some(redis) => redis.get_json::<IpBan>(REDIS_BAN_KIND, &ip, false).await?,
+        None => state.bans.lock().await.get(&ip).cloned(),
+    };
+    match ban.filter(|ban| ban.is_active(now)) {
+        Some(ban) => Err(ApiError::ip_banned(ban.expires_at)),
+        None => Ok(()),
+    }
+}
+
+// Copies bans saved before Redis was enabled. Later bans already went through Redis, so
+// republishing them would undo unbans made on other instances; one in Redis may be newer.
+pub(crate) async fn publish_stored_bans(redis: &RedisStore, bans: &BanMap) -> Result<(), ApiError> {
+    let now = Utc::now();
+    let (kind, id) = REDIS_BANS_IMPORTED;
+    let imported_at = match redis.get_json::<DateTime<Utc>>(kind, id, false).await? {
+        Some(imported_at) => imported_at,
+        None => {
+            redis.put_json(kind, id, &now, None).await?;
+            now
+        }
+    };
+    let mut published = 0;
+    for ban in bans
+        .values()
+        .filter(|ban| ban.is_active(now) && ban.created_at < imported_at)
+    {
+        if redis
+            .get_json::<IpBan>(REDIS_BAN_KIND, &ban.ip, false)
+            .await?
+            .is_none()
+        {
+            redis
+                .put_json(REDIS_BAN_KIND, &ban.ip, ban, ban.redis_ttl(now))
+                .await?;
+            published += 1;
+        }
+    }
+    if published > 0 {
+        info!("{published} bloqueo(s) de IP guardados copiados a Redis.");
+    }
+    Ok(())
+}
+
+pub(crate) async fn list_bans(State(state): State<AppState>) -> Result<Json<BanList>, ApiError> {
+    let now = Utc::now();
+    // Same source as ensure_not_banned, so the list matches what is enforced.
+    let bans = match &state.redis {
+        Some(redis) => redis.list_json::<IpBan>(REDIS_BAN_KIND).await?,
+        None => state.bans.lock().await.values().cloned().collect(),
+    };
+    let mut bans = bans
+        .into_iter()
+        .filter(|ban| ban.is_active(now))
+        .collect::<Vec<_>>();
+    bans.sort_by_key(|ban| std::cmp::Reverse(ban.created_at));
+    Ok(Json(BanList { bans }))
+}
+
+pub(crate) async fn create_ban(
+    State(state): State<AppState>,
+    Json(payload): Json<BanRequest>,
+) -> Result<(StatusCode, Json<IpBan>), ApiError> {
+    let ip = normalize_ip(&payload.ip)?;
+    let hours = payload.hours.filter(|hours| *hours > 0);
+    if hours.is_some_and(|hours| hours > MAX_BAN_HOURS) {
+        return Err(ApiError::bad_request(format!(
+            "hours no puede superar {MAX_BAN_HOURS}; omitelo para un bloqueo permanente."
+        )));
+    }
+    let reason = payload
+        .reason
+        .and_then(normalize_optional_text)
+        .map(|reason| reason.chars().take(MAX_REASON_CHARS).collect::<String>());
+    let now = Utc::now();
+    let ban = IpBan {
+        ip: ip.clone(),
+        reason,
+        created_at: now,
+        expires_at: hours.map(|hours| now + chrono::Duration::hours(hours as i64)),
+    };
+
+    if let Some(redis) = &state.redis {
+        redis
+            .put_json(REDIS_BAN_KIND, &ip, &ban, ban.redis_ttl(now))
+            .await?;
+    }
+    {
+        let mut bans = state.bans.lock().await;
+        bans.retain(|_, existing| existing.is_active(now));
+        bans.insert(ip.clone(), ban.clone());
+        state.storage.bans_changed(&bans).await?;
+    }
+    info!(
+        "IP {ip} bloqueada{}.",
+        ban.expires_at
+            .map(|expires_at| format!(" hasta {}", expires_at.to_rfc3339()))
+            .unwrap_or_default()
+    );
+    Ok((StatusCode::CREATED, Json(ban)))
+}
+
+pub(crate) async fn remove_ban(
+    State(state): State<AppState>,
+    RoutePath(ip): RoutePath<String>,
+) -> Result<StatusCode, ApiError> {
+    let ip = normalize_ip(&ip)?;
+    let removed_remote = match &state.redis {
+        Some(redis) => redis.delete(REDIS_BAN_KIND, &ip).await?,
+        None => false,
+    };
+    let removed_local = {
+        let mut bans = state.bans.lock().await;
+        let removed = bans.remove(&ip).is_some();
+        if removed {
+            state.storage.bans_changed(&bans).await?;
+        }
+        removed
+    };
+    if !removed_local && !removed_remote {
+        return Err(ApiError::not_found(format!(
+            "La IP {ip} no esta bloqueada."
+        )));
+    }
+    info!("IP {ip} desbloqueada.");
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,283 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::ErrorKind,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    ApiError, AppState, artifacts::StoredArtifact, build_attachment_headers,
+    delivery::ServedHandler, storage::append_line, workers::WORKER_JOB_OWNER,
+};
+
+const PUBLIC_ACCOUNT: &str = "public";
+const MAX_TRACKED_ARTIFACTS: usize = 10_000;
+const BYTES_PER_GB: f64 = 1_000_000_000.0;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UsageRecord {
+    at: DateTime<Utc>,
+    account: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    job_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    bytes_downloaded: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    bytes_served: u64,
+    #[serde(default, skip_serializing_if = "is_zero_f64")]
+    cpu_seconds: f64,
+    #[serde(default, skip_serializing_if = "is_zero_f64")]
+    storage_byte_hours: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct AccountUsage {
+    account: String,
+    jobs: u64,
+    bytes_downloaded: u64,
+    bytes_served: u64,
+    cpu_seconds: f64,
+    storage_gb_hours: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct UsageExport {
+    month: String,
+    generated_at: DateTime<Utc>,
+    accounts: Vec<AccountUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct UsageExportQuery {
+    month: Option<String>,
+    format: Option<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct UsageLedger {
+    dir: PathBuf,
+    owners: Mutex<HashMap<String, (String, Uuid)>>,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+fn is_zero_f64(value: &f64) -> bool {
+    *value == 0.0
+}
+
+// Anonymous visitors are billed together; embed sites (and any future tenant) by their id.
+pub(crate) fn account_for(owner: &str) -> Option<String> {
+    match owner {
+        WORKER_JOB_OWNER => None,
+        owner if owner.starts_with("embed:") => Some(owner.to_string()),
+        _ => Some(PUBLIC_ACCOUNT.to_string()),
+    }
+}
+
+fn parse_month(value: Option<&str>) -> Result<String, ApiError> {
+    let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Ok(Utc::now().format("%Y-%m").to_string());
+    };
+    NaiveDate::parse_from_str(&format!("{value}-01"), "%Y-%m-%d")
+        .map(|date| date.format("%Y-%m").to_string())
+        .map_err(|_| ApiError::bad_request("month debe tener el formato AAAA-MM."))
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl UsageLedger {
+    pub(crate) async fn open(dir: PathBuf) -> Result<Self, ApiError> {
+        tokio::fs::create_dir_all(&dir).await.map_err(|error| {
+            ApiError::internal(format!(
+                "No se pudo preparar la carpeta de consumo {}: {error}",
+                dir.display()
+            ))
+        })?;
+        info!("Contabilidad de consumo habilitada en {}", dir.display());
+        Ok(Self {
+            dir,
+            owners: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn append(&self, record: UsageRecord) {
+        let path = self
+            .dir
+            .join(format!("{}.jsonl", record.at.format("%Y-%m")));
+        if let Err(error) = append_line(&path, &record).await {
+            warn!(
+                "No se pudo registrar el consumo de {}: {error}",
+                record.account
+            );
+        }
+    }
+
+    pub(crate) async fn record_job(
+        &self,
+        owner: &str,
+        job_id: Uuid,
+        artifact: &StoredArtifact,
+        cpu_seconds: f64,
+        retention_seconds: u64,
+    ) {
+        let Some(account) = account_for(owner) else {
+            return;
+        };
+        {
+            let mut owners = self.owners.lock().await;
+            if owners.len() >= MAX_TRACKED_ARTIFACTS && !owners.contains_key(&artifact.hash) {
+                owners.clear();
+            }
+            owners.insert(artifact.hash.clone(), (account.clone(), job_id));
+        }
+        self.append(UsageRecord {
+            at: Utc::now(),
+            account,
+            job_id: Some(job_id),
+            bytes_downloaded: if artifact.reused { 0 } else { artifact.size },
+            cpu_seconds,
+            storage_byte_hours: artifact.size as f64 * retention_seconds as f64 / 3600.0,
+            ..UsageRecord::default()
+        })
+        .await;
+    }
+
+    pub(crate) fn served_handler(
+        self: &Arc<Self>,
+        account: String,
+        job_id: Option<Uuid>,
+    ) -> ServedHandler {
+        let ledger = Arc::clone(self);
+        Box::new(move |bytes| {
+            if bytes == 0 {
+                return;
+            }
+            tokio::spawn(async move {
+                ledger
+                    .append(UsageRecord {
+                        at: Utc::now(),
+                        account,
+                        job_id,
+                        bytes_served: bytes,
+                        ..UsageRecord::default()
+                    })
+                    .await;
+            });
+        })
+    }
+
+    pub(crate) async fn artifact_served_handler(
+        self: &Arc<Self>,
+        artifact_hash: &str,
+    ) -> Option<ServedHandler> {
+        let (account, job_id) = self.owners.lock().await.get(artifact_hash).cloned()?;
+        Some(self.served_handler(account, Some(job_id)))
+    }
+
+    async fn export(&self, month: String) -> Result<UsageExport, ApiError> {
+        let path = self.dir.join(format!("{month}.jsonl"));
+        let contents = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+            Err(error) => {
+                return Err(ApiError::internal(format!(
+                    "No se pudo leer el consumo de {month}: {error}"
+                )));
+            }
+        };
+
+        let mut accounts = BTreeMap::<String, AccountUsage>::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let Ok(record) = serde_json::from_str::<UsageRecord>(line) else {
+                warn!("Linea de consumo invalida ignorada en {}", path.display());
+                continue;
+            };
+            let usage = accounts
+                .entry(record.account.clone())
+                .or_insert_with(|| AccountUsage {
+                    account: record.account.clone(),
+                    ..AccountUsage::default()
+                });
+            if record.job_id.is_some() && record.bytes_served == 0 {
+                usage.jobs += 1;
+            }
+            usage.bytes_downloaded += record.bytes_downloaded;
+            usage.bytes_served += record.bytes_served;
+            usage.cpu_seconds += record.cpu_seconds;
+            usage.storage_gb_hours += record.storage_byte_hours / BYTES_PER_GB;
+        }
+
+        Ok(UsageExport {
+            month,
+            generated_at: Utc::now(),
+            accounts: accounts.into_values().collect(),
+        })
+    }
+}
+
+impl UsageExport {
+    fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "month,account,jobs,bytes_downloaded,bytes_served,cpu_seconds,storage_gb_hours\n",
+        );
+        for usage in &self.accounts {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{:.3},{:.6}\n",
+                self.month,
+                csv_field(&usage.account),
+                usage.jobs,
+                usage.bytes_downloaded,
+                usage.bytes_served,
+                usage.cpu_seconds,
+                usage.storage_gb_hours
+            ));
+        }
+        csv
+    }
+}
+
+pub(crate) async fn export_usage(
+    State(state): State<AppState>,
+    Query(query): Query<UsageExportQuery>,
+) -> Result<Response, ApiError> {
+    let ledger = state.usage.as_ref().ok_or_else(|| {
+        ApiError::not_found(
+            "La contabilidad de consumo esta deshabilitada (USAGE_ACCOUNTING_ENABLED).",
+        )
+    })?;
+    let month = parse_month(query.month.as_deref())?;
+    let export = ledger.export(month).await?;
+
+    match query.format.as_deref().unwrap_or("json") {
+        "json" => Ok(Json(export).into_response()),
+        "csv" => {
+            let csv = export.to_csv();
+            let headers = build_attachment_headers(
+                &format!("consumo-{}.csv", export.month),
+                "text/csv; charset=utf-8",
+                csv.len() as u64,
+            )?;
+            Ok((headers, csv).into_response())
+        }
+        _ => Err(ApiError::bad_request("format debe ser json o csv.")),
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use tokio::process::Command;
+
+use crate::{config::Config, extractor, ffmpeg, layout::DataLayout};
+
+const CHECK_COMMAND_TIMEOUT_SECONDS: u64 = 15;
+
+pub(crate) const USAGE: &str = "Uso: backend [opciones]
+
+Opciones:
+  --config <ruta>          Archivo TOML de configuracion (o CONFIG_PATH)
+  --port <puerto>          Puerto de escucha (PORT)
+  --data-dir <ruta>        Directorio de datos persistentes (DATA_DIR)
+  --transfer-dir <ruta>    Directorio de descargas temporales (TRANSFER_DIR)
+  --max-concurrent <n>     Descargas simultaneas (MAX_CONCURRENT_DOWNLOADS)
+  --trust-proxy[=<bool>]   Confiar en cabeceras X-Forwarded-For (TRUST_PROXY_HEADERS)
+  --check                  Valida la configuracion y las herramientas, y termina
+  -h, --help               Muestra esta ayuda
+
+Las opciones tienen prioridad sobre las variables de entorno y el archivo TOML.";
+
+#[derive(Debug, Default)]
+pub(crate) struct Cli {
+    pub(crate) config: Option<PathBuf>,
+    pub(crate) check: bool,
+    port: Option<u16>,
+    data_dir: Option<PathBuf>,
+    transfer_dir: Option<PathBuf>,
+    max_concurrent: Option<usize>,
+    trust_proxy: Option<bool>,
+}
+
+fn parse_bool(flag: &str, value: &str) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("{flag} espera true o false, no {value:?}")),
+    }
+}
+
+impl Cli {
+    pub(crate) fn parse(args: impl IntoIterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut cli = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = |name: &str| {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .filter(|value| !value.is_empty())
+                    .ok_or_else(|| format!("{name} necesita un valor"))
+            };
+            match flag.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--check" => cli.check = true,
+                "--config" => cli.config = Some(PathBuf::from(value("--config")?)),
+                "--port" => {
+                    let port = value("--port")?;
+                    cli.port = Some(
+                        port.parse::<u16>()
+                            .ok()
+                            .filter(|port| *port > 0)
+                            .ok_or_else(|| format!("--port invalido: {port}"))?,
+                    );
+                }
+                "--data-dir" => cli.data_dir = Some(PathBuf::from(value("--data-dir")?)),
+                "--transfer-dir" => {
+                    cli.transfer_dir = Some(PathBuf::from(value("--transfer-dir")?));
+                }
+                "--max-concurrent" => {
+                    let max = value("--max-concurrent")?;
+                    cli.max_concurrent = Some(
+                        max.parse::<usize>()
+                            .ok()
+                            .filter(|max| *max > 0)
+                            .ok_or_else(|| format!("--max-concurrent invalido: {max}"))?,
+                    );
+                }
+                "--trust-proxy" => {
+                    cli.trust_proxy = Some(match &inline {
+                        Some(value) => parse_bool("--trust-proxy", value)?,
+                        None => true,
+                    });
+                }
+                _ => return Err(format!("Opcion desconocida: {arg}")),
+            }
+        }
+        Ok(Some(cli))
+    }
+
+    pub(crate) fn apply_overrides(&self) {
+        let mut overrides = Vec::new();
+        if let Some(port) = self.port {
+            match std::env::var("APP_ADDR")
+                .ok()
+                .and_then(|addr| addr.trim().parse::<SocketAddr>().ok())
+            {
+                Some(mut addr) => {
+                    addr.set_port(port);
+                    overrides.push(("APP_ADDR", addr.to_string()));
+                }
+                None => overrides.push(("PORT", port.to_string())),
+            }
+        }
+        let path = |path: &Path| path.to_string_lossy().into_owned();
+        if let Some(dir) = &self.data_dir {
+            overrides.push(("DATA_DIR", path(dir)));
+        }
+        if let Some(dir) = &self.transfer_dir {
+            overrides.push(("TRANSFER_DIR", path(dir)));
+        }
+        if let Some(max) = self.max_concurrent {
+            overrides.push(("MAX_CONCURRENT_DOWNLOADS", max.to_string()));
+        }
+        if let Some(trust) = self.trust_proxy {
+            overrides.push(("TRUST_PROXY_HEADERS", trust.to_string()));
+        }
+        for (name, value) in overrides {
+            // SAFETY: called from main before the tokio runtime or any other thread starts.
+            unsafe { std::env::set_var(name, value) };
+        }
+    }
+}
+
+async fn tool_version(binary: &str, flag: &str) -> Result<String, String> {
+    let output = tokio::time::timeout(
+        Duration::from_secs(CHECK_COMMAND_TIMEOUT_SECONDS),
+        Command::new(binary).arg(flag).kill_on_drop(true).output(),
+    )
+    .await
+    .map_err(|_| format!("{binary} no respondio en {CHECK_COMMAND_TIMEOUT_SECONDS} s"))?
+    .map_err(|error| format!("no se pudo ejecutar {binary}: {error}"))?;
+    if !output.status.success() {
+        return Err(format!("{binary} {flag} termino con {}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string())
+}
+
+async fn encoder_status(binary: &str, encoder: &str) -> Result<String, String> {
+    let output = tokio::time::timeout(
+        Duration::from_secs(CHECK_COMMAND_TIMEOUT_SECONDS),
+        Command::new(binary)
+            .args(["-hide_banner", "-encoders"])
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| format!("{binary} no respondio en {CHECK_COMMAND_TIMEOUT_SECONDS} s"))?
+    .map_err(|error| format!("no se pudo ejecutar {binary}: {error}"))?;
+    let listed = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .any(|line| line.split_whitespace().nth(1) == Some(encoder));
+    if listed {
+        Ok(encoder.to_string())
+    } else {
+        Err(format!(
+            "{binary} no incluye el codificador {encoder}; se usara libx264"
+        ))
+    }
+}
+
+fn directory_status(path: &Path) -> Result<String, String> {
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .ok_or_else(|| format!("{} no tiene un directorio padre existente", path.display()))?;
+    let metadata = std::fs::metadata(existing)
+        .map_err(|error| format!("no se pudo leer {}: {error}", existing.display()))?;
+    if !metadata.is_dir() {
+        return Err(format!("{} no es un directorio", existing.display()));
+    }
+    if metadata.permissions().readonly() {
+        return Err(format!("{} es de solo lectura", existing.display()));
+    }
+    Ok(if existing == path {
+        path.display().to_string()
+    } else {
+        format!("{} (se creara al arrancar)", path.display())
+    })
+}
+
+pub(crate) async fn run_check() -> bool {
+    let mut failures = 0;
+    let mut report = |result: Result<String, String>, label: &str| match result {
+        Ok(detail) => println!("OK     {label}: {detail}"),
+        Err(error) => {
+            failures += 1;
+            println!("ERROR  {label}: {error}");
+        }
+    };
+
+    report(
+        Config::from_env()
+            .map(|_| "valores dentro de rango".to_string())
+            .map_err(|error| error.message),
+        "limites",
+    );
+    report(
+        crate::load_allowed_origins(std::iter::empty())
+            .map(|origins| format!("{} origen(es)", origins.len()))
+            .map_err(|error| error.message),
+        "CORS",
+    );
+    let addr = crate::resolve_bind_addr();
+    report(
+        match tokio::net::lookup_host(&addr).await {
+            Ok(mut resolved) => match resolved.next() {
+                Some(_) => Ok(addr.clone()),
+                None => Err(format!("{addr} no resuelve a ninguna direccion")),
+            },
+            Err(error) => Err(format!("{addr} invalida: {error}")),
+        },
+        "direccion",
+    );
+
+    let layout = DataLayout::from_env();
+    report(directory_status(&layout.data_dir), "datos");
+    report(directory_status(&layout.transfer_dir), "transferencias");
+    report(directory_status(&layout.artifact_dir), "artefactos");
+
+    let (stable, candidate) = extractor::configured_binaries();
+    report(tool_version(&stable, "--version").await, "yt-dlp");
+    if let Some(candidate) = candidate {
+        report(
+            tool_version(&candidate, "--version").await,
+            "yt-dlp candidato",
+        );
+    }
+    report(
+        tool_version(&ffmpeg::ffmpeg_binary(), "-version").await,
+        "ffmpeg",
+    );
+    if let Some(encoder) = ffmpeg::hw_encoder() {
+        report(
+            encoder_status(&ffmpeg::ffmpeg_binary(), encoder.accel().encoder_name()).await,
+            "aceleracion por hardware",
+        );
+    }
+
+    if failures == 0 {
+        println!("Configuracion valida.");
+    } else {
+        println!("{failures} comprobacion(es) fallaron.");
+    }
+    failures == 0
+}
@@ -0,0 +1,431 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    io::ErrorKind,
+    net::SocketAddr,
+    path::PathBuf,
+};
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, Query, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header::USER_AGENT},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, sync::Mutex};
+use tracing::{Instrument, Span, info, info_span, warn};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{ApiError, AppState, client_ip_for_request, read_bool_env, read_usize_env};
+
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+pub(crate) const MAX_REPORT_BYTES: usize = 32 * 1024;
+const DEFAULT_REPORTS_PER_HOUR: usize = 20;
+const DEFAULT_MAX_STORED_KB: usize = 512;
+const DEFAULT_LIST_LIMIT: usize = 100;
+const MAX_LIST_LIMIT: usize = 500;
+const MAX_MESSAGE_CHARS: usize = 1000;
+
+tokio::task_local! {
+    static CURRENT_REQUEST_ID: String;
+}
+const MAX_STACK_CHARS: usize = 8000;
+const MAX_FIELD_CHARS: usize = 300;
+const MAX_REQUEST_ID_CHARS: usize = 64;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ClientErrorKind {
+    Script,
+    Request,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ClientErrorReport {
+    kind: ClientErrorKind,
+    message: String,
+    #[serde(default)]
+    stack: Option<String>,
+    #[serde(default)]
+    page: Option<String>,
+    #[serde(default)]
+    endpoint: Option<String>,
+    #[serde(default)]
+    method: Option<String>,
+    #[serde(default)]
+    status: Option<u16>,
+    #[serde(default)]
+    request_id: Option<String>,
+    #[serde(default)]
+    job_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ClientErrorRecord {
+    id: Uuid,
+    at: DateTime<Utc>,
+    ip: String,
+    kind: ClientErrorKind,
+    message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stack: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    page: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    endpoint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    job_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_agent: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct StoredErrors {
+    entries: VecDeque<(ClientErrorRecord, usize)>,
+    bytes: usize,
+}
+
+#[derive(Debug)]
+pub(crate) struct ClientErrorLog {
+    path: PathBuf,
+    max_bytes: usize,
+    per_hour: usize,
+    stored: Mutex<StoredErrors>,
+    recent: Mutex<HashMap<String, Vec<DateTime<Utc>>>>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ClientErrorAccepted {
+    id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ClientErrorQuery {
+    limit: Option<usize>,
+    kind: Option<ClientErrorKind>,
+    request_id: Option<String>,
+    job_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ClientErrorList {
+    total: usize,
+    stored_bytes: usize,
+    max_bytes: usize,
+    errors: Vec<ClientErrorRecord>,
+}
+
+fn valid_request_id(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_CHARS
+        && value
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || matches!(character, '-' | '_'))
+}
+
+pub(crate) async fn assign_request_id(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| valid_request_id(value))
+        .map_or_else(|| Uuid::new_v4().simple().to_string(), ToString::to_string);
+    let span = info_span!("request", id = %request_id);
+    let mut response = CURRENT_REQUEST_ID
+        .scope(request_id.clone(), next.run(request).instrument(span))
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    response
+}
+
+pub(crate) fn current_request_id() -> Option<String> {
+    CURRENT_REQUEST_ID
+        .try_with(Clone::clone)
+        .ok()
+        .filter(|id| !id.is_empty())
+}
+
+// Background jobs outlive the request; keep its id in their logs and history entries.
+pub(crate) fn with_request_context<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let span = Span::current();
+    let request_id = current_request_id().unwrap_or_default();
+    CURRENT_REQUEST_ID.scope(request_id, future.instrument(span))
+}
+
+fn clip(value: &str, max_chars: usize, keep_newlines: bool) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|character| {
+            if character.is_control() && !(keep_newlines && character == '\n') {
+                ' '
+            } else {
+                character
+            }
+        })
+        .take(max_chars)
+        .collect()
+}
+
+fn optional(value: Option<String>, max_chars: usize) -> Option<String> {
+    value
+        .map(|value| clip(&value, max_chars, false))
+        .filter(|value| !value.is_empty())
+}
+
+fn without_query(value: Option<String>) -> Option<String> {
+    optional(value, MAX_FIELD_CHARS).map(|value| match Url::parse(&value) {
+        Ok(mut url) => {
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url.set_query(None);
+            url.set_fragment(None);
+            url.to_string()
+        }
+        Err(_) => value
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+    })
+}
+
+impl StoredErrors {
+    fn push(&mut self, record: ClientErrorRecord, size: usize, max_bytes: usize) -> bool {
+        self.entries.push_back((record, size));
+        self.bytes += size;
+        if self.bytes <= max_bytes {
+            return false;
+        }
+        while self.bytes > max_bytes * 3 / 4 {
+            let Some((_, dropped)) = self.entries.pop_front() else {
+                break;
+            };
+            self.bytes -= dropped;
+        }
+        true
+    }
+
+    fn contents(&self) -> String {
+        self.entries
+            .iter()
+            .filter_map(|(record, _)| serde_json::to_string(record).ok())
+            .map(|line| line + "\n")
+            .collect()
+    }
+}
+
+impl ClientErrorLog {
+    pub(crate) async fn from_env(path: PathBuf) -> Result<Option<Self>, ApiError> {
+        if !read_bool_env("CLIENT_ERRORS_ENABLED").unwrap_or(true) {
+            return Ok(None);
+        }
+        let per_hour = read_usize_env("CLIENT_ERRORS_PER_HOUR")
+            .filter(|value| *value > 0)
+            .unwrap_or(DEFAULT_REPORTS_PER_HOUR);
+        let max_bytes = read_usize_env("CLIENT_ERRORS_MAX_KB")
+            .filter(|value| *value > 0)
+            .unwrap_or(DEFAULT_MAX_STORED_KB)
+            * 1024;
+
+        let mut stored = StoredErrors::default();
+        let mut trimmed = false;
+        match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => {
+                for line in contents.lines() {
+                    match serde_json::from_str::<ClientErrorRecord>(line) {
+                        Ok(record) => trimmed |= stored.push(record, line.len() + 1, max_bytes),
+                        Err(_) => trimmed = true,
+                    }
+                }
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => {}
+            Err(error) => {
+                return Err(ApiError::internal(format!(
+                    "No se pudo abrir el registro de errores del cliente: {error}"
+                )));
+            }
+        }
+        let log = Self {
+            path,
+            max_bytes,
+            per_hour,
+            stored: Mutex::new(stored),
+            recent: Mutex::default(),
+        };
+        if trimmed {
+            let stored = log.stored.lock().await;
+            log.rewrite(&stored).await;
+        }
+        Ok(Some(log))
+    }
+
+    async fn rewrite(&self, stored: &StoredErrors) {
+        if let Err(error) = tokio::fs::write(&self.path, stored.contents()).await {
+            warn!("No se pudo compactar el registro de errores del cliente: {error}");
+        }
+    }
+
+    async fn allow(&self, ip: &str, now: DateTime<Utc>) -> Result<(), ApiError> {
+        let window_start = now - chrono::Duration::hours(1);
+        let mut recent = self.recent.lock().await;
+        recent.retain(|_, reports| {
+            reports.retain(|at| *at > window_start);
+            !reports.is_empty()
+        });
+        let reports = recent.entry(ip.to_string()).or_default();
+        if reports.len() >= self.per_hour {
+            let retry_after = reports
+                .first()
+                .map_or(3600, |first| (*first - window_start).num_seconds().max(1));
+            return Err(ApiError::client_errors_rate_limited(retry_after as u64));
+        }
+        reports.push(now);
+        Ok(())
+    }
+
+    async fn store(&self, record: ClientErrorRecord) {
+        let Ok(line) = serde_json::to_string(&record) else {
+            return;
+        };
+        let size = line.len() + 1;
+        let mut stored = self.stored.lock().await;
+        if stored.push(record, size, self.max_bytes) {
+            self.rewrite(&stored).await;
+            return;
+        }
+        let result = async {
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await?;
+            file.write_all(format!("{line}\n").as_bytes()).await
+        }
+        .await;
+        if let Err(error) = result {
+            warn!("No se pudo guardar el error del cliente: {error}");
+        }
+    }
+}
+
+fn enabled(state: &AppState) -> Result<&ClientErrorLog, ApiError> {
+    state
+        .client_errors
+        .as_deref()
+        .ok_or_else(|| ApiError::not_found("Los reportes de errores estan deshabilitados."))
+}
+
+pub(crate) async fn report_client_error(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(report): Json<ClientErrorReport>,
+) -> Result<Response, ApiError> {
+    let log = enabled(&state)?;
+    let message = clip(&report.message, MAX_MESSAGE_CHARS, false);
+    if message.is_empty() {
+        return Err(ApiError::bad_request(
+            "El reporte de error necesita un mensaje.",
+        ));
+    }
+    let client_ip = client_ip_for_request(&state, &headers, addr);
+    let now = Utc::now();
+    log.allow(&client_ip, now).await?;
+
+    let record = ClientErrorRecord {
+        id: Uuid::new_v4(),
+        at: now,
+        ip: client_ip,
+        kind: report.kind,
+        message,
+        stack: report
+            .stack
+            .map(|stack| clip(&stack, MAX_STACK_CHARS, true))
+            .filter(|stack| !stack.is_empty()),
+        page: without_query(report.page),
+        endpoint: without_query(report.endpoint),
+        method: optional(report.method, 10).map(|method| method.to_ascii_uppercase()),
+        status: report.status,
+        request_id: report
+            .request_id
+            .map(|value| value.trim().to_string())
+            .filter(|value| valid_request_id(value)),
+        job_id: report.job_id,
+        user_agent: optional(
+            headers
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(ToString::to_string),
+            MAX_FIELD_CHARS,
+        ),
+    };
+    info!(
+        "Error del cliente {} ({:?}) request_id={} job_id={}: {}",
+        record.id,
+        record.kind,
+        record.request_id.as_deref().unwrap_or("-"),
+        record
+            .job_id
+            .map_or_else(|| "-".to_string(), |job_id| job_id.to_string()),
+        record.message
+    );
+    let id = record.id;
+    log.store(record).await;
+
+    Ok((StatusCode::ACCEPTED, Json(ClientErrorAccepted { id })).into_response())
+}
+
+pub(crate) async fn list_client_errors(
+    State(state): State<AppState>,
+    Query(query): Query<ClientErrorQuery>,
+) -> Result<Json<ClientErrorList>, ApiError> {
+    let log = enabled(&state)?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let stored = log.stored.lock().await;
+    let errors = stored
+        .entries
+        .iter()
+        .rev()
+        .map(|(record, _)| record)
+        .filter(|record| query.kind.is_none_or(|kind| record.kind == kind))
+        .filter(|record| {
+            query
+                .request_id
+                .as_deref()
+                .is_none_or(|request_id| record.request_id.as_deref() == Some(request_id))
+        })
+        .filter(|record| {
+            query
+                .job_id
+                .is_none_or(|job_id| record.job_id == Some(job_id))
+        })
+        .take(limit)
+        .cloned()
+        .collect();
+
+    Ok(Json(ClientErrorList {
+        total: stored.entries.len(),
+        stored_bytes: stored.bytes,
+        max_bytes: log.max_bytes,
+        errors,
+    }))
+}
@@ -0,0 +1,227 @@
+use axum::http::{
+    HeaderMap,
+    header::{ACCEPT, USER_AGENT},
+};
+use serde::{Deserialize, Serialize};
+
+const CODECS_MARKER: &str = "__codecs__";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CodecProfile {
+    client: String,
+    block_av1: bool,
+    block_vp9: bool,
+    block_opus: bool,
+    #[serde(default)]
+    strict: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CodecDecision {
+    client: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    video_codec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio_codec: Option<String>,
+    pub(crate) transcoded_video: bool,
+    pub(crate) transcoded_audio: bool,
+}
+
+impl CodecDecision {
+    pub(crate) fn transcodes(&self) -> bool {
+        self.transcoded_video || self.transcoded_audio
+    }
+}
+
+fn version_after(user_agent: &str, token: &str) -> Option<u32> {
+    let start = user_agent.find(token)? + token.len();
+    user_agent[start..]
+        .split(|character: char| !character.is_ascii_digit())
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn profile(client: String, block_av1: bool, block_vp9: bool, block_opus: bool) -> CodecProfile {
+    CodecProfile {
+        client,
+        block_av1,
+        block_vp9,
+        block_opus,
+        strict: false,
+    }
+}
+
+fn from_accept(headers: &HeaderMap) -> Option<CodecProfile> {
+    let accept = headers.get(ACCEPT)?.to_str().ok()?.to_ascii_lowercase();
+    let codecs: Vec<String> = accept
+        .split(';')
+        .filter_map(|parameter| parameter.trim().strip_prefix("codecs="))
+        .flat_map(|list| list.trim_matches('"').split(',').map(str::trim))
+        .filter(|codec| !codec.is_empty())
+        .map(ToString::to_string)
+        .collect();
+    if codecs.is_empty() {
+        return None;
+    }
+    let accepts = |prefixes: &[&str]| {
+        codecs
+            .iter()
+            .any(|codec| prefixes.iter().any(|prefix| codec.starts_with(prefix)))
+    };
+    Some(profile(
+        "accept".to_string(),
+        !accepts(&["av01", "av1"]),
+        !accepts(&["vp09", "vp9"]),
+        !accepts(&["opus"]),
+    ))
+}
+
+fn from_user_agent(headers: &HeaderMap) -> Option<CodecProfile> {
+    let user_agent = headers.get(USER_AGENT)?.to_str().ok()?;
+    if user_agent.contains("Trident/") || user_agent.contains("MSIE ") {
+        return Some(profile("ie".to_string(), true, true, true));
+    }
+    if let Some(version) = version_after(user_agent, "Edge/") {
+        return Some(profile(format!("edge-legacy {version}"), true, false, true));
+    }
+    if user_agent.contains("iPhone") || user_agent.contains("iPad") {
+        let version = version_after(user_agent, " OS ")?;
+        return Some(profile(
+            format!("ios {version}"),
+            version < 17,
+            version < 14,
+            version < 17,
+        ));
+    }
+    if let Some(version) = version_after(user_agent, "Firefox/") {
+        return Some(profile(
+            format!("firefox {version}"),
+            version < 67,
+            false,
+            false,
+        ));
+    }
+    if let Some(version) =
+        version_after(user_agent, "Edg/").or_else(|| version_after(user_agent, "Chrome/"))
+    {
+        return Some(profile(
+            format!("chrome {version}"),
+            version < 70,
+            false,
+            false,
+        ));
+    }
+    if user_agent.contains("Safari/") {
+        let version = version_after(user_agent, "Version/")?;
+        return Some(profile(
+            format!("safari {version}"),
+            version < 17,
+            version < 14,
+            version < 17,
+        ));
+    }
+    None
+}
+
+pub(crate) fn profile_for_request(headers: &HeaderMap) -> Option<CodecProfile> {
+    from_accept(headers)
+        .or_else(|| from_user_agent(headers))
+        .filter(|profile| profile.block_av1 || profile.block_vp9 || profile.block_opus)
+}
+
+impl CodecProfile {
+    pub(crate) fn h264_aac() -> Self {
+        Self {
+            strict: true,
+            ..profile("compatibility".to_string(), true, true, true)
+        }
+    }
+
+    pub(crate) fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub(crate) fn cache_key(&self) -> String {
+        if self.strict {
+            return "h264-aac".to_string();
+        }
+        [
+            (self.block_av1, "av1"),
+            (self.block_vp9, "vp9"),
+            (self.block_opus, "opus"),
+        ]
+        .into_iter()
+        .filter(|(blocked, _)| *blocked)
+        .map(|(_, codec)| codec)
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+
+    fn blocks_video(&self, codec: &str) -> bool {
+        let codec = codec.to_ascii_lowercase();
+        if self.strict {
+            return !(codec.starts_with("avc1") || codec == "h264");
+        }
+        (self.block_av1 && (codec.starts_with("av01") || codec == "av1"))
+            || (self.block_vp9 && (codec.starts_with("vp09") || codec.starts_with("vp9")))
+    }
+
+    fn blocks_audio(&self, codec: &str) -> bool {
+        let codec = codec.to_ascii_lowercase();
+        if self.strict {
+            return !(codec.starts_with("mp4a") || codec == "aac");
+        }
+        self.block_opus && codec == "opus"
+    }
+
+    pub(crate) fn decide(
+        &self,
+        video_codec: Option<String>,
+        audio_codec: Option<String>,
+    ) -> CodecDecision {
+        CodecDecision {
+            client: self.client.clone(),
+            transcoded_video: video_codec
+                .as_deref()
+                .is_some_and(|codec| self.blocks_video(codec)),
+            transcoded_audio: audio_codec
+                .as_deref()
+                .is_some_and(|codec| self.blocks_audio(codec)),
+            video_codec,
+            audio_codec,
+        }
+    }
+}
+
+pub(crate) fn print_codecs_args() -> [String; 2] {
+    [
+        "--print".to_string(),
+        format!("after_move:{CODECS_MARKER} %(vcodec)s|%(acodec)s"),
+    ]
+}
+
+pub(crate) fn compatible_selector(filter: &str) -> String {
+    format!("bv[vcodec^=avc1]{filter}+ba[acodec^=mp4a]/b[vcodec^=avc1]{filter}")
+}
+
+pub(crate) fn is_codecs_line(line: &str) -> bool {
+    line.starts_with(CODECS_MARKER)
+}
+
+pub(crate) fn parse_printed_codecs(stdout: &[u8]) -> (Option<String>, Option<String>) {
+    let stdout = String::from_utf8_lossy(stdout);
+    let Some((video, audio)) = stdout
+        .lines()
+        .map(str::trim)
+        .find_map(|line| line.strip_prefix(CODECS_MARKER))
+        .and_then(|codecs| codecs.trim().split_once('|'))
+    else {
+        return (None, None);
+    };
+    let known = |codec: &str| {
+        let codec = codec.trim();
+        (!codec.is_empty() && !matches!(codec, "none" | "NA")).then(|| codec.to_string())
+    };
+    (known(video), known(audio))
+}
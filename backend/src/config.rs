@@ -0,0 +1,120 @@
+use serde::Serialize;
+use tracing::info;
+
+use crate::ApiError;
+
+const DEFAULT_DOWNLOAD_LIMIT_PER_DAY: u64 = 10;
+const DEFAULT_DOWNLOAD_WINDOW_HOURS: u64 = 24;
+const DEFAULT_MAX_DOWNLOAD_MB: u64 = 250;
+const DEFAULT_SNAPSHOT_MAX_DOWNLOAD_MB: u64 = 1024;
+const DEFAULT_YT_DLP_TIMEOUT_SECONDS: u64 = 180;
+const DEFAULT_HISTORY_PER_IP_LIMIT: u64 = 10;
+const DEFAULT_HISTORY_MAX_ENTRIES: u64 = 2_000;
+const DEFAULT_FORMATS_CACHE_TTL_SECONDS: u64 = 10 * 60;
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Config {
+    pub(crate) download_limit_per_day: usize,
+    pub(crate) download_window_hours: i64,
+    pub(crate) max_download_bytes: u64,
+    pub(crate) snapshot_max_download_bytes: u64,
+    pub(crate) yt_dlp_timeout_seconds: u64,
+    pub(crate) history_per_ip_limit: usize,
+    pub(crate) history_max_entries: usize,
+    pub(crate) formats_cache_ttl_seconds: i64,
+}
+
+fn read_bounded(name: &str, default: u64, min: u64, max: u64) -> Result<u64, String> {
+    let Some(value) = std::env::var(name)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+    else {
+        return Ok(default);
+    };
+    let parsed = value
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| format!("{name}={value:?} no es un entero positivo"))?;
+    if !(min..=max).contains(&parsed) {
+        return Err(format!("{name}={parsed} debe estar entre {min} y {max}"));
+    }
+    Ok(parsed)
+}
+
+impl Config {
+    pub(crate) fn from_env() -> Result<Self, ApiError> {
+        let mut errors = Vec::new();
+        let mut read = |name, default, min, max| {
+            read_bounded(name, default, min, max).unwrap_or_else(|error| {
+                errors.push(error);
+                default
+            })
+        };
+        let config = Self {
+            download_limit_per_day: read(
+                "DOWNLOAD_LIMIT_PER_DAY",
+                DEFAULT_DOWNLOAD_LIMIT_PER_DAY,
+                1,
+                100_000,
+            ) as usize,
+            download_window_hours: read(
+                "DOWNLOAD_WINDOW_HOURS",
+                DEFAULT_DOWNLOAD_WINDOW_HOURS,
+                1,
+                24 * 30,
+            ) as i64,
+            max_download_bytes: read("MAX_DOWNLOAD_MB", DEFAULT_MAX_DOWNLOAD_MB, 1, 1024 * 1024)
+                * 1024
+                * 1024,
+            snapshot_max_download_bytes: read(
+                "SNAPSHOT_MAX_DOWNLOAD_MB",
+                DEFAULT_SNAPSHOT_MAX_DOWNLOAD_MB,
+                1,
+                4 * 1024,
+            ) * 1024
+                * 1024,
+            yt_dlp_timeout_seconds: read(
+                "YT_DLP_TIMEOUT_SECONDS",
+                DEFAULT_YT_DLP_TIMEOUT_SECONDS,
+                10,
+                24 * 60 * 60,
+            ),
+            history_per_ip_limit: read(
+                "HISTORY_PER_IP_LIMIT",
+                DEFAULT_HISTORY_PER_IP_LIMIT,
+                1,
+                1_000,
+            ) as usize,
+            history_max_entries: read(
+                "HISTORY_MAX_ENTRIES",
+                DEFAULT_HISTORY_MAX_ENTRIES,
+                1,
+                1_000_000,
+            ) as usize,
+            formats_cache_ttl_seconds: read(
+                "FORMATS_CACHE_TTL_SECONDS",
+                DEFAULT_FORMATS_CACHE_TTL_SECONDS,
+                0,
+                24 * 60 * 60,
+            ) as i64,
+        };
+        if !errors.is_empty() {
+            return Err(ApiError::internal(format!(
+                "Configuracion invalida: {}.",
+                errors.join("; ")
+            )));
+        }
+        info!(
+            "Limites: {} descargas cada {} h, {} MB por archivo ({} MB en snapshot), yt-dlp {} s, historial {} por IP y {} en total, formatos en cache {} s.",
+            config.download_limit_per_day,
+            config.download_window_hours,
+            config.max_download_bytes / 1_048_576,
+            config.snapshot_max_download_bytes / 1_048_576,
+            config.yt_dlp_timeout_seconds,
+            config.history_per_ip_limit,
+            config.history_max_entries,
+            config.formats_cache_ttl_seconds
+        );
+        Ok(config)
+    }
+}
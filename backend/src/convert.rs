@@ -0,0 +1,560 @@
+use std::{net::SocketAddr, path::Path};
+
+use axum::{
+    body::{Body, BodyDataStream},
+    extract::{ConnectInfo, State},
+    http::{
+        HeaderMap,
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+    },
+    response::Response,
+};
+use chrono::Utc;
+use futures_util::StreamExt;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    ApiError, AppState, DOWNLOAD_JOB_RETENTION_SECONDS, DownloadMode, DownloadRequest, JobDir,
+    admit_download, apply_audio_format, apply_audio_tags, apply_compatibility, apply_container,
+    auth, bans, client_identity_for_request, client_ip_for_request, deliver_artifact, ffmpeg,
+    jobs::{JobHandle, JobPhase, UPLOAD_CONVERT_PHASES, UPLOAD_TRANSCODE_PHASES},
+    postprocess, sanitize_ascii_filename,
+};
+
+const MAX_OPTIONS_BYTES: usize = 16 * 1024;
+const MAX_PART_HEADER_BYTES: usize = 8 * 1024;
+const MAX_STEM_CHARS: usize = 120;
+const UPLOAD_EXTENSION: &str = "upload";
+const REJECTED_DEMUXERS: [&str; 7] = [
+    "hls",
+    "dash",
+    "concat",
+    "image2",
+    "image2pipe",
+    "sdp",
+    "ffmetadata",
+];
+
+struct PartHeaders {
+    name: String,
+    filename: Option<String>,
+}
+
+// Streams one multipart/form-data body; file parts go straight to disk instead of memory.
+struct MultipartReader {
+    stream: BodyDataStream,
+    buffer: Vec<u8>,
+    delimiter: Vec<u8>,
+    received: u64,
+    finished: bool,
+}
+
+impl MultipartReader {
+    fn new(body: Body, boundary: &str) -> Self {
+        Self {
+            stream: body.into_data_stream(),
+            // The leading CRLF lets the first boundary match like every later one.
+            buffer: b"\r\n".to_vec(),
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            received: 0,
+            finished: false,
+        }
+    }
+
+    async fn fill(&mut self) -> Result<bool, ApiError> {
+        match self.stream.next().await {
+            Some(Ok(chunk)) => {
+                self.received += chunk.len() as u64;
+                self.buffer.extend_from_slice(&chunk);
+                Ok(true)
+            }
+            Some(Err(error)) => Err(ApiError::bad_request(format!(
+                "No se pudo recibir el archivo: {error}"
+            ))),
+            None => Ok(false),
+        }
+    }
+
+    fn find(&self, needle: &[u8]) -> Option<usize> {
+        self.buffer
+            .windows(needle.len())
+            .position(|window| window == needle)
+    }
+
+    async fn next_part(&mut self) -> Result<Option<PartHeaders>, ApiError> {
+        if self.finished {
+            return Ok(None);
+        }
+        let start = loop {
+            if let Some(position) = self.find(&self.delimiter) {
+                break position + self.delimiter.len();
+            }
+            let keep = self.buffer.len().min(self.delimiter.len());
+            self.buffer.drain(..self.buffer.len() - keep);
+            if !self.fill().await? {
+                return Err(incomplete());
+            }
+        };
+        while self.buffer.len() < start + 2 {
+            if !self.fill().await? {
+                return Err(incomplete());
+            }
+        }
+        if &self.buffer[start..start + 2] == b"--" {
+            self.finished = true;
+            return Ok(None);
+        }
+        self.buffer.drain(..start);
+        let end = loop {
+            if let Some(position) = self.find(b"\r\n\r\n") {
+                break position;
+            }
+            if self.buffer.len() > MAX_PART_HEADER_BYTES || !self.fill().await? {
+                return Err(incomplete());
+            }
+        };
+        let raw = String::from_utf8_lossy(&self.buffer[..end]).into_owned();
+        self.buffer.drain(..end + 4);
+
+        let disposition = raw
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-disposition"))
+            .map(|(_, value)| value.to_string())
+            .unwrap_or_default();
+        let parameter = |key: &str| {
+            disposition.split(';').find_map(|part| {
+                let (name, value) = part.split_once('=')?;
+                (name.trim().eq_ignore_ascii_case(key))
+                    .then(|| value.trim().trim_matches('"').to_string())
+            })
+        };
+        Ok(Some(PartHeaders {
+            name: parameter("name").unwrap_or_default(),
+            filename: parameter("filename"),
+        }))
+    }
+
+    // Returns the next slice of the current part, or None once its closing boundary is reached.
+    async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, ApiError> {
+        loop {
+            if let Some(position) = self.find(&self.delimiter) {
+                if position == 0 {
+                    return Ok(None);
+                }
+                return Ok(Some(self.buffer.drain(..position).collect()));
+            }
+            if self.buffer.len() > self.delimiter.len() {
+                let safe = self.buffer.len() - self.delimiter.len();
+                return Ok(Some(self.buffer.drain(..safe).collect()));
+            }
+            if !self.fill().await? {
+                return Err(incomplete());
+            }
+        }
+    }
+
+    async fn read_text(&mut self, limit: usize) -> Result<String, ApiError> {
+        let mut text = Vec::new();
+        while let Some(chunk) = self.next_chunk().await? {
+            text.extend_from_slice(&chunk);
+            if text.len() > limit {
+                return Err(ApiError::bad_request(format!(
+                    "El campo options supera {} KB.",
+                    limit / 1024
+                )));
+            }
+        }
+        String::from_utf8(text)
+            .map_err(|_| ApiError::bad_request("El campo options debe ser texto UTF-8."))
+    }
+}
+
+fn incomplete() -> ApiError {
+    ApiError::bad_request("El formulario multipart esta incompleto o mal formado.")
+}
+
+fn multipart_boundary(headers: &HeaderMap) -> Option<String> {
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+    let (kind, parameters) = content_type.split_once(';')?;
+    if !kind.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    parameters.split(';').find_map(|part| {
+        let (name, value) = part.split_once('=')?;
+        let value = value.trim().trim_matches('"');
+        (name.trim().eq_ignore_ascii_case("boundary") && !value.is_empty())
+            .then(|| value.to_string())
+    })
+}
+
+// Keeps the uploaded name recognizable while making it safe as a path component.
+fn upload_filename(filename: &str) -> String {
+    let name = Path::new(filename)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension))
+            if !stem.is_empty()
+                && (1..=5).contains(&extension.len())
+                && extension
+                    .chars()
+                    .all(|character| character.is_ascii_alphanumeric()) =>
+        {
+            (stem, extension.to_ascii_lowercase())
+        }
+        _ => (name, "bin".to_string()),
+    };
+    let stem = sanitize_ascii_filename(stem)
+        .trim_start_matches('.')
+        .chars()
+        .take(MAX_STEM_CHARS)
+        .collect::<String>();
+    let stem = if stem.is_empty() || stem == "download.bin" {
+        "upload"
+    } else {
+        &stem
+    };
+    format!("{stem}.{extension}")
+}
+
+fn parse_options(text: &str, filename: &str) -> Result<DownloadRequest, ApiError> {
+    let mut options = if text.trim().is_empty() {
+        serde_json::Map::new()
+    } else {
+        match serde_json::from_str::<serde_json::Value>(text) {
+            Ok(serde_json::Value::Object(options)) => options,
+            _ => {
+                return Err(ApiError::bad_request(
+                    "options debe ser un objeto JSON con los mismos campos que /api/download.",
+                ));
+            }
+        }
+    };
+    if !options.contains_key("mode") {
+        let mode = if options.contains_key("audio_format") {
+            "audio"
+        } else {
+            "video"
+        };
+        options.insert("mode".to_string(), mode.into());
+    }
+    options.insert("url".to_string(), format!("upload:{filename}").into());
+    serde_json::from_value(serde_json::Value::Object(options))
+        .map_err(|error| ApiError::bad_request(format!("options invalido: {error}")))
+}
+
+fn validate_options(payload: &DownloadRequest) -> Result<(), ApiError> {
+    let unsupported = [
+        ("playlist", payload.playlist),
+        ("async", payload.respond_async),
+        ("format_id", payload.format_id.is_some()),
+        ("streams", payload.streams.is_some()),
+        ("split_chapters", payload.split_chapters),
+        ("embed_subtitles", payload.embed_subtitles.is_some()),
+        ("start_time", payload.start_time.is_some()),
+        ("end_time", payload.end_time.is_some()),
+        ("sponsorblock", payload.sponsorblock.is_some()),
+        ("extra_args", payload.extra_args.is_some()),
+        ("sidecars", payload.sidecars.is_some()),
+        ("snapshot", payload.snapshot),
+        ("warc", payload.warc),
+    ];
+    if let Some((field, _)) = unsupported.iter().find(|(_, present)| *present) {
+        return Err(ApiError::bad_request(format!(
+            "{field} no se aplica a archivos subidos."
+        )));
+    }
+    if matches!(payload.mode, DownloadMode::Video)
+        && payload.container.is_none()
+        && !payload.compatibility
+    {
+        return Err(ApiError::bad_request(
+            "Indica container o compatibility para convertir video, o audio_format para extraer el audio.",
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) async fn convert_upload(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, ApiError> {
+    let client_ip = client_ip_for_request(&state, &headers, addr);
+    bans::ensure_not_banned(&state, &client_ip).await?;
+    let boundary = multipart_boundary(&headers).ok_or_else(|| {
+        ApiError::bad_request("Envia el archivo como multipart/form-data en el campo file.")
+    })?;
+    let expected_bytes = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+
+    let mut reader = MultipartReader::new(body, &boundary);
+    let mut options = String::new();
+    let filename = loop {
+        let Some(part) = reader.next_part().await? else {
+            return Err(ApiError::bad_request(
+                "Falta el archivo: envialo en el campo file, despues de options.",
+            ));
+        };
+        match (part.name.as_str(), part.filename) {
+            ("file", Some(filename)) => break filename,
+            ("options", None) => options = reader.read_text(MAX_OPTIONS_BYTES).await?,
+            _ => while reader.next_chunk().await?.is_some() {},
+        }
+    };
+
+    let upload_name = upload_filename(&filename);
+    let mut payload = parse_options(&options, &upload_name)?;
+    validate_options(&payload)?;
+    let requested_container = payload.container.take();
+    apply_container(&mut payload, requested_container)?;
+    apply_audio_format(&mut payload)?;
+    apply_audio_tags(&mut payload)?;
+    apply_compatibility(&mut payload)?;
+    payload.title.get_or_insert(filename);
+    payload.format_label.get_or_insert_with(|| {
+        format!(
+            "Conversion a {}",
+            payload.container.as_deref().unwrap_or("mp3")
+        )
+    });
+    if let Some(entitlements) = &state.entitlements
+        && let Some(session) = auth::session_from_headers(&state, &headers)
+    {
+        payload.premium = entitlements.premium_for(&session).await;
+    }
+    payload.api_key = state.api_keys.resolve(&state.roles, &headers).await?;
+    if payload.signed_link {
+        payload.link_base = crate::public_base_url(&state, &headers);
+    }
+    let url = payload.url.clone();
+    let requester = client_identity_for_request(&state, &headers, addr);
+    payload.remote_ip = client_ip;
+
+    let job = state
+        .jobs
+        .create(
+            payload.job_id.unwrap_or_else(Uuid::new_v4),
+            &payload.remote_ip,
+        )
+        .await?;
+    if payload.premium.is_some() {
+        job.prioritize();
+    }
+    let abandon = job.abandon_on_drop();
+    let requested_at = Utc::now();
+    let result = async {
+        // Anti-bot and quota are settled before the upload is read, so a rejected client costs no disk.
+        let limits = admit_download(&state, &requester, &url, &payload).await?;
+        let produced = produce_upload_artifact(
+            &state,
+            &job,
+            &payload,
+            &mut reader,
+            &upload_name,
+            expected_bytes,
+            limits.max_download_bytes,
+        )
+        .await;
+        deliver_artifact(
+            &state,
+            &requester,
+            &url,
+            &payload,
+            &job,
+            requested_at,
+            produced,
+        )
+        .await
+    }
+    .await;
+    abandon.disarm();
+    if let Err(error) = &result {
+        job.fail(&error.message);
+    }
+    result
+}
+
+async fn produce_upload_artifact(
+    state: &AppState,
+    job: &JobHandle,
+    payload: &DownloadRequest,
+    reader: &mut MultipartReader,
+    upload_name: &str,
+    expected_bytes: Option<u64>,
+    max_bytes: u64,
+) -> Result<crate::StoredArtifact, ApiError> {
+    let job_id = job.job_id();
+    let transcodes = payload.codec_profile.is_some();
+    job.running(if transcodes {
+        UPLOAD_TRANSCODE_PHASES
+    } else {
+        UPLOAD_CONVERT_PHASES
+    });
+    let job_dir = JobDir::new(state.transfer_dir.join(job_id.to_string()));
+    tokio::fs::create_dir_all(job_dir.path())
+        .await
+        .map_err(|error| {
+            ApiError::internal(format!(
+                "No se pudo preparar la conversion temporal: {error}"
+            ))
+        })?;
+    // The client's extension must not pick the demuxer, so the upload is stored under a neutral one.
+    let input = job_dir
+        .path()
+        .join(Path::new(upload_name).with_extension(UPLOAD_EXTENSION));
+    let result = receive_and_convert(
+        state,
+        job,
+        payload,
+        reader,
+        &input,
+        expected_bytes,
+        max_bytes,
+    )
+    .await;
+    job_dir.remove().await;
+    result
+}
+
+async fn receive_and_convert(
+    state: &AppState,
+    job: &JobHandle,
+    payload: &DownloadRequest,
+    reader: &mut MultipartReader,
+    input: &Path,
+    expected_bytes: Option<u64>,
+    max_bytes: u64,
+) -> Result<crate::StoredArtifact, ApiError> {
+    let job_id = job.job_id();
+    let transcodes = payload.codec_profile.is_some();
+    let received_at_start = reader.received;
+    let mut file = tokio::fs::File::create(input).await.map_err(|error| {
+        ApiError::internal(format!("No se pudo guardar el archivo subido: {error}"))
+    })?;
+    let mut written = 0u64;
+    while let Some(chunk) = reader.next_chunk().await? {
+        written += chunk.len() as u64;
+        if written > max_bytes {
+            return Err(ApiError::file_too_large(max_bytes));
+        }
+        file.write_all(&chunk).await.map_err(|error| {
+            ApiError::internal(format!("No se pudo guardar el archivo subido: {error}"))
+        })?;
+        if let Some(total) = expected_bytes.filter(|total| *total > received_at_start) {
+            let fraction =
+                (reader.received - received_at_start) as f64 / (total - received_at_start) as f64;
+            job.progress(JobPhase::Download, fraction.min(1.0));
+        }
+    }
+    file.flush().await.map_err(|error| {
+        ApiError::internal(format!("No se pudo guardar el archivo subido: {error}"))
+    })?;
+    drop(file);
+    if written == 0 {
+        return Err(ApiError::bad_request("El archivo subido esta vacio."));
+    }
+    job.progress(JobPhase::Download, 1.0);
+    info!("Job {job_id}: {written} bytes recibidos para convertir.");
+    let probe = ffmpeg::probe(input).await?;
+    // Playlist and pattern demuxers would make ffmpeg open other files named inside the upload.
+    if let Some(format) = probe.format.as_deref().filter(|format| {
+        format
+            .split(',')
+            .any(|demuxer| REJECTED_DEMUXERS.contains(&demuxer.trim()))
+    }) {
+        warn!("Job {job_id}: subida rechazada con formato {format}.");
+        return Err(ApiError::bad_request(
+            "El archivo subido no es un video ni un audio (listas de reproduccion e imagenes no se convierten).",
+        ));
+    }
+    if probe.video.is_none() && probe.audio.is_none() {
+        return Err(ApiError::bad_request(
+            "El archivo subido no contiene pistas de audio ni de video.",
+        ));
+    }
+
+    let _permit = tokio::select! {
+        permit = state.download_semaphore.clone().acquire_owned() => permit
+            .map_err(|_| ApiError::internal("No se pudo reservar capacidad de conversion."))?,
+        () = job.cancelled() => return Err(ApiError::job_cancelled()),
+    };
+    let work = async {
+        let phase = if transcodes {
+            JobPhase::Transcode
+        } else {
+            JobPhase::Convert
+        };
+        let mut on_progress = |fraction: f64| job.progress(phase, fraction);
+        let tags = payload
+            .audio_tags
+            .as_ref()
+            .map(|tags| tags.pairs())
+            .unwrap_or_default();
+        match (&payload.mode, payload.container.as_deref()) {
+            (DownloadMode::Audio, container) => {
+                postprocess::convert_audio(
+                    input,
+                    container.unwrap_or("mp3"),
+                    None,
+                    &tags,
+                    None,
+                    &mut on_progress,
+                )
+                .await
+            }
+            (DownloadMode::Video, _) if transcodes => {
+                postprocess::transcode_compatible(input, true, true, &mut on_progress).await
+            }
+            (DownloadMode::Video, container) => {
+                postprocess::remux(input, container.unwrap_or("mp4"), &mut on_progress).await
+            }
+        }
+    };
+    let (converted, cpu_seconds) = tokio::select! {
+        (result, cpu_seconds) = ffmpeg::metered(work) => (result?, cpu_seconds),
+        () = job.cancelled() => {
+            info!("Conversion {job_id} cancelada por el usuario.");
+            return Err(ApiError::job_cancelled());
+        }
+    };
+
+    let size = tokio::fs::metadata(&converted)
+        .await
+        .map_err(|error| {
+            ApiError::internal(format!("No se pudo leer el archivo convertido: {error}"))
+        })?
+        .len();
+    if size > max_bytes {
+        return Err(ApiError::file_too_large(max_bytes));
+    }
+    let filename = converted
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("convertido.bin")
+        .to_string();
+    let expires_at = Utc::now() + chrono::Duration::seconds(DOWNLOAD_JOB_RETENTION_SECONDS as i64);
+    let artifact = state
+        .artifacts
+        .ingest(&converted, job_id, &filename, expires_at, None)
+        .await?;
+    if let Some(usage) = &state.usage {
+        usage
+            .record_job(
+                job.owner(),
+                job_id,
+                &artifact,
+                cpu_seconds,
+                DOWNLOAD_JOB_RETENTION_SECONDS,
+            )
+            .await;
+    }
+    Ok(artifact)
+}
@@ -0,0 +1,645 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::ErrorKind,
+    path::PathBuf,
+};
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Path as RoutePath, Query, State},
+};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{io::AsyncWriteExt, sync::Mutex};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{ApiError, AppState, encode_hex, throughput::platform_key};
+
+const MAX_CREDENTIAL_BYTES: u64 = 1024 * 1024;
+const MAX_CHUNK_BYTES: usize = 256 * 1024;
+const MAX_EXTRACTOR_ARG_LENGTH: usize = 512;
+const UPLOAD_TTL_MINUTES: i64 = 60;
+const KEPT_VERSIONS: usize = 10;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CredentialKind {
+    Cookies,
+    ExtractorArgs,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CredentialVersion {
+    version: u32,
+    uploaded_at: DateTime<Utc>,
+    bytes: u64,
+    sha256: String,
+    entries: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    expired_entries: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct CredentialSlot {
+    active: Option<u32>,
+    #[serde(default)]
+    versions: Vec<CredentialVersion>,
+}
+
+type CredentialIndex = BTreeMap<String, BTreeMap<CredentialKind, CredentialSlot>>;
+
+#[derive(Debug, Clone)]
+struct PendingUpload {
+    platform: String,
+    kind: CredentialKind,
+    total_bytes: u64,
+    received_bytes: u64,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub(crate) struct CredentialStore {
+    dir: PathBuf,
+    index: Mutex<CredentialIndex>,
+    uploads: Mutex<HashMap<Uuid, PendingUpload>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreateUploadRequest {
+    platform: String,
+    kind: CredentialKind,
+    total_bytes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ChunkQuery {
+    offset: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RollbackRequest {
+    version: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct UploadStatus {
+    upload_id: Uuid,
+    platform: String,
+    kind: CredentialKind,
+    total_bytes: u64,
+    received_bytes: u64,
+    max_chunk_bytes: usize,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SlotReport {
+    platform: String,
+    kind: CredentialKind,
+    #[serde(flatten)]
+    slot: CredentialSlot,
+}
+
+impl CredentialKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Cookies => "cookies",
+            Self::ExtractorArgs => "extractor_args",
+        }
+    }
+}
+
+impl CredentialSlot {
+    fn previous_version(&self) -> Option<u32> {
+        let active = self.active?;
+        self.versions
+            .iter()
+            .map(|entry| entry.version)
+            .filter(|version| *version < active)
+            .max()
+    }
+}
+
+impl PendingUpload {
+    fn status(&self, upload_id: Uuid) -> UploadStatus {
+        UploadStatus {
+            upload_id,
+            platform: self.platform.clone(),
+            kind: self.kind,
+            total_bytes: self.total_bytes,
+            received_bytes: self.received_bytes,
+            max_chunk_bytes: MAX_CHUNK_BYTES,
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+fn normalize_platform(value: &str) -> Result<String, ApiError> {
+    let platform = value.trim().to_ascii_lowercase();
+    let valid = !platform.is_empty()
+        && platform.len() <= 64
+        && platform
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '.' | '-' | '_'))
+        && !platform.starts_with('.');
+    if valid {
+        Ok(platform)
+    } else {
+        Err(ApiError::bad_request("Plataforma invalida."))
+    }
+}
+
+fn validate_cookies(
+    content: &str,
+    platform: &str,
+    now: DateTime<Utc>,
+) -> Result<CredentialVersion, String> {
+    let mut entries = 0;
+    let mut expired_entries = 0;
+    let mut expires_at: Option<DateTime<Utc>> = None;
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = line.split('\t').collect::<Vec<_>>();
+        let line_number = number + 1;
+        if fields.len() != 7 {
+            return Err(format!(
+                "Linea {line_number}: se esperaban 7 campos separados por tabulador (formato Netscape)."
+            ));
+        }
+        let flags_valid = [fields[1], fields[3]]
+            .iter()
+            .all(|flag| matches!(*flag, "TRUE" | "FALSE"));
+        if !flags_valid {
+            return Err(format!(
+                "Linea {line_number}: los campos de subdominio y seguridad deben ser TRUE o FALSE."
+            ));
+        }
+        let domain = fields[0].trim_start_matches('.');
+        if platform_key(&format!("https://{domain}/")) != platform {
+            return Err(format!(
+                "Linea {line_number}: la cookie de {domain} no corresponde a {platform}."
+            ));
+        }
+        let expiry = fields[4]
+            .parse::<i64>()
+            .map_err(|_| format!("Linea {line_number}: caducidad invalida."))?;
+
+        entries += 1;
+        if expiry > 0 {
+            let Some(expiry) = Utc.timestamp_opt(expiry, 0).single() else {
+                return Err(format!("Linea {line_number}: caducidad invalida."));
+            };
+            if expiry <= now {
+                expired_entries += 1;
+            } else {
+                expires_at = Some(expires_at.map_or(expiry, |earliest| earliest.min(expiry)));
+            }
+        }
+    }
+
+    if entries == 0 {
+        return Err("El archivo no contiene cookies en formato Netscape.".to_string());
+    }
+    if expired_entries == entries {
+        return Err("Todas las cookies del archivo estan caducadas.".to_string());
+    }
+
+    Ok(CredentialVersion {
+        version: 0,
+        uploaded_at: now,
+        bytes: 0,
+        sha256: String::new(),
+        entries,
+        expires_at,
+        expired_entries,
+    })
+}
+
+fn extractor_arg_lines(content: &str) -> impl Iterator<Item = &str> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+fn validate_extractor_args(content: &str, now: DateTime<Utc>) -> Result<CredentialVersion, String> {
+    let mut entries = 0;
+    for line in extractor_arg_lines(content) {
+        let valid = line.len() <= MAX_EXTRACTOR_ARG_LENGTH
+            && !line.chars().any(char::is_control)
+            && line.split_once(':').is_some_and(|(extractor, args)| {
+                !extractor.is_empty()
+                    && extractor
+                        .chars()
+                        .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | '-'))
+                    && args.contains('=')
+            });
+        if !valid {
+            return Err(format!(
+                "Argumento invalido {line:?}: usa extractor:clave=valor[;clave=valor]."
+            ));
+        }
+        entries += 1;
+    }
+
+    if entries == 0 {
+        return Err("El archivo no contiene argumentos de extractor.".to_string());
+    }
+
+    Ok(CredentialVersion {
+        version: 0,
+        uploaded_at: now,
+        bytes: 0,
+        sha256: String::new(),
+        entries,
+        expires_at: None,
+        expired_entries: 0,
+    })
+}
+
+impl CredentialStore {
+    pub(crate) async fn open(dir: PathBuf) -> Result<Self, ApiError> {
+        tokio::fs::create_dir_all(dir.join("uploads"))
+            .await
+            .map_err(|error| {
+                ApiError::internal(format!(
+                    "No se pudo crear la carpeta de credenciales: {error}"
+                ))
+            })?;
+
+        let index = match tokio::fs::read_to_string(dir.join("index.json")).await {
+            Ok(content) if content.trim().is_empty() => CredentialIndex::new(),
+            Ok(content) => serde_json::from_str(&content).map_err(|error| {
+                ApiError::internal(format!("Indice de credenciales invalido: {error}"))
+            })?,
+            Err(error) if error.kind() == ErrorKind::NotFound => CredentialIndex::new(),
+            Err(error) => {
+                return Err(ApiError::internal(format!(
+                    "No se pudo leer el indice de credenciales: {error}"
+                )));
+            }
+        };
+
+        Ok(Self {
+            dir,
+            index: Mutex::new(index),
+            uploads: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn version_path(&self, platform: &str, kind: CredentialKind, version: u32) -> PathBuf {
+        self.dir
+            .join(platform)
+            .join(kind.as_str())
+            .join(format!("v{version}.txt"))
+    }
+
+    fn active_path(&self, platform: &str, kind: CredentialKind) -> PathBuf {
+        self.dir
+            .join(platform)
+            .join(format!("{}.txt", kind.as_str()))
+    }
+
+    fn upload_path(&self, upload_id: Uuid) -> PathBuf {
+        self.dir.join("uploads").join(format!("{upload_id}.part"))
+    }
+
+    pub(crate) async fn args_for(&self, url: &str) -> Vec<String> {
+        let platform = platform_key(url);
+        let (cookies, extractor_args) = {
+            let index = self.index.lock().await;
+            let Some(slots) = index.get(&platform) else {
+                return Vec::new();
+            };
+            let is_active = |kind| slots.get(&kind).is_some_and(|slot| slot.active.is_some());
+            (
+                is_active(CredentialKind::Cookies),
+                is_active(CredentialKind::ExtractorArgs),
+            )
+        };
+
+        let mut args = Vec::new();
+        if cookies {
+            args.push("--cookies".to_string());
+            args.push(
+                self.active_path(&platform, CredentialKind::Cookies)
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+        }
+        if extractor_args {
+            match tokio::fs::read_to_string(
+                self.active_path(&platform, CredentialKind::ExtractorArgs),
+            )
+            .await
+            {
+                Ok(content) => {
+                    for line in extractor_arg_lines(&content) {
+                        args.push("--extractor-args".to_string());
+                        args.push(line.to_string());
+                    }
+                }
+                Err(error) => {
+                    warn!("No se pudieron leer los argumentos de extractor de {platform}: {error}");
+                }
+            }
+        }
+        args
+    }
+
+    async fn persist(&self, index: &CredentialIndex) -> Result<(), ApiError> {
+        let payload = crate::lowmem::to_json_vec(index).map_err(|error| {
+            ApiError::internal(format!(
+                "No se pudo serializar el indice de credenciales: {error}"
+            ))
+        })?;
+        tokio::fs::write(self.dir.join("index.json"), payload)
+            .await
+            .map_err(|error| {
+                ApiError::internal(format!(
+                    "No se pudo guardar el indice de credenciales: {error}"
+                ))
+            })
+    }
+
+    async fn activate(
+        &self,
+        platform: &str,
+        kind: CredentialKind,
+        version: u32,
+    ) -> Result<(), ApiError> {
+        tokio::fs::copy(
+            self.version_path(platform, kind, version),
+            self.active_path(platform, kind),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|error| {
+            ApiError::internal(format!(
+                "No se pudo activar la version {version} de {platform}: {error}"
+            ))
+        })
+    }
+
+    async fn discard_expired_uploads(&self) {
+        let now = Utc::now();
+        let expired = {
+            let mut uploads = self.uploads.lock().await;
+            let expired = uploads
+                .iter()
+                .filter(|(_, upload)| upload.expires_at <= now)
+                .map(|(upload_id, _)| *upload_id)
+                .collect::<Vec<_>>();
+            for upload_id in &expired {
+                uploads.remove(upload_id);
+            }
+            expired
+        };
+        for upload_id in expired {
+            let _ = tokio::fs::remove_file(self.upload_path(upload_id)).await;
+        }
+    }
+
+    async fn report(&self, platform: &str, kind: CredentialKind) -> SlotReport {
+        let slot = self
+            .index
+            .lock()
+            .await
+            .get(platform)
+            .and_then(|slots| slots.get(&kind))
+            .cloned()
+            .unwrap_or_default();
+        SlotReport {
+            platform: platform.to_string(),
+            kind,
+            slot,
+        }
+    }
+}
+
+pub(crate) async fn list_credentials(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<SlotReport>>, ApiError> {
+    let index = state.credentials.index.lock().await;
+    let reports = index
+        .iter()
+        .flat_map(|(platform, slots)| {
+            slots.iter().map(|(kind, slot)| SlotReport {
+                platform: platform.clone(),
+                kind: *kind,
+                slot: slot.clone(),
+            })
+        })
+        .collect();
+    Ok(Json(reports))
+}
+
+pub(crate) async fn create_upload(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateUploadRequest>,
+) -> Result<Json<UploadStatus>, ApiError> {
+    let platform = normalize_platform(&payload.platform)?;
+    if payload.total_bytes == 0 || payload.total_bytes > MAX_CREDENTIAL_BYTES {
+        return Err(ApiError::bad_request(format!(
+            "total_bytes debe estar entre 1 y {MAX_CREDENTIAL_BYTES}."
+        )));
+    }
+
+    let store = &state.credentials;
+    store.discard_expired_uploads().await;
+    let upload_id = Uuid::new_v4();
+    tokio::fs::write(store.upload_path(upload_id), b"")
+        .await
+        .map_err(|error| ApiError::internal(format!("No se pudo preparar la subida: {error}")))?;
+    let upload = PendingUpload {
+        platform,
+        kind: payload.kind,
+        total_bytes: payload.total_bytes,
+        received_bytes: 0,
+        expires_at: Utc::now() + Duration::minutes(UPLOAD_TTL_MINUTES),
+    };
+    let status = upload.status(upload_id);
+    store.uploads.lock().await.insert(upload_id, upload);
+    Ok(Json(status))
+}
+
+pub(crate) async fn get_upload(
+    State(state): State<AppState>,
+    RoutePath(upload_id): RoutePath<Uuid>,
+) -> Result<Json<UploadStatus>, ApiError> {
+    state
+        .credentials
+        .uploads
+        .lock()
+        .await
+        .get(&upload_id)
+        .map(|upload| Json(upload.status(upload_id)))
+        .ok_or_else(unknown_upload)
+}
+
+pub(crate) async fn upload_chunk(
+    State(state): State<AppState>,
+    RoutePath(upload_id): RoutePath<Uuid>,
+    Query(query): Query<ChunkQuery>,
+    chunk: Bytes,
+) -> Result<Json<UploadStatus>, ApiError> {
+    if chunk.is_empty() || chunk.len() > MAX_CHUNK_BYTES {
+        return Err(ApiError::bad_request(format!(
+            "Cada fragmento debe tener entre 1 y {MAX_CHUNK_BYTES} bytes."
+        )));
+    }
+
+    let store = &state.credentials;
+    let mut uploads = store.uploads.lock().await;
+    let upload = uploads.get_mut(&upload_id).ok_or_else(unknown_upload)?;
+    if query.offset != upload.received_bytes {
+        return Err(ApiError::upload_offset_mismatch(upload.received_bytes));
+    }
+    if upload.received_bytes + chunk.len() as u64 > upload.total_bytes {
+        return Err(ApiError::bad_request(
+            "El fragmento excede el tamano declarado de la subida.",
+        ));
+    }
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(store.upload_path(upload_id))
+        .await
+        .map_err(|error| ApiError::internal(format!("No se pudo abrir la subida: {error}")))?;
+    file.write_all(&chunk)
+        .await
+        .map_err(|error| ApiError::internal(format!("No se pudo guardar el fragmento: {error}")))?;
+    upload.received_bytes += chunk.len() as u64;
+    Ok(Json(upload.status(upload_id)))
+}
+
+pub(crate) async fn commit_upload(
+    State(state): State<AppState>,
+    RoutePath(upload_id): RoutePath<Uuid>,
+) -> Result<Json<SlotReport>, ApiError> {
+    let store = &state.credentials;
+    let upload = {
+        let mut uploads = store.uploads.lock().await;
+        let upload = uploads.get(&upload_id).ok_or_else(unknown_upload)?;
+        if upload.received_bytes != upload.total_bytes {
+            return Err(ApiError::bad_request(format!(
+                "Subida incompleta: {} de {} bytes recibidos.",
+                upload.received_bytes, upload.total_bytes
+            )));
+        }
+        uploads.remove(&upload_id).ok_or_else(unknown_upload)?
+    };
+
+    let part_path = store.upload_path(upload_id);
+    let content = tokio::fs::read(&part_path)
+        .await
+        .map_err(|error| ApiError::internal(format!("No se pudo leer la subida: {error}")));
+    let _ = tokio::fs::remove_file(&part_path).await;
+    let content = content?;
+    let text = std::str::from_utf8(&content)
+        .map_err(|_| ApiError::bad_request("El archivo debe estar codificado en UTF-8."))?;
+
+    let now = Utc::now();
+    let mut entry = match upload.kind {
+        CredentialKind::Cookies => validate_cookies(text, &upload.platform, now),
+        CredentialKind::ExtractorArgs => validate_extractor_args(text, now),
+    }
+    .map_err(ApiError::bad_request)?;
+    entry.bytes = content.len() as u64;
+    entry.sha256 = encode_hex(&Sha256::digest(&content));
+
+    let (platform, kind) = (upload.platform.as_str(), upload.kind);
+    let mut index = store.index.lock().await;
+    let slot = index
+        .entry(platform.to_string())
+        .or_default()
+        .entry(kind)
+        .or_default();
+    entry.version = slot
+        .versions
+        .iter()
+        .map(|version| version.version)
+        .max()
+        .unwrap_or_default()
+        + 1;
+
+    let version_path = store.version_path(platform, kind, entry.version);
+    if let Some(parent) = version_path.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(|error| {
+            ApiError::internal(format!(
+                "No se pudo crear la carpeta de credenciales: {error}"
+            ))
+        })?;
+    }
+    tokio::fs::write(&version_path, &content)
+        .await
+        .map_err(|error| {
+            ApiError::internal(format!("No se pudo guardar la nueva version: {error}"))
+        })?;
+    store.activate(platform, kind, entry.version).await?;
+
+    let version = entry.version;
+    slot.active = Some(version);
+    slot.versions.push(entry);
+    while slot.versions.len() > KEPT_VERSIONS {
+        let pruned = slot.versions.remove(0);
+        let _ = tokio::fs::remove_file(store.version_path(platform, kind, pruned.version)).await;
+    }
+    store.persist(&index).await?;
+    drop(index);
+
+    info!(
+        "Credenciales {} de {platform} actualizadas a la version {version}",
+        kind.as_str()
+    );
+    Ok(Json(store.report(platform, kind).await))
+}
+
+pub(crate) async fn rollback_credentials(
+    State(state): State<AppState>,
+    RoutePath((platform, kind)): RoutePath<(String, CredentialKind)>,
+    Json(payload): Json<RollbackRequest>,
+) -> Result<Json<SlotReport>, ApiError> {
+    let platform = normalize_platform(&platform)?;
+    let store = &state.credentials;
+    let mut index = store.index.lock().await;
+    let slot = index
+        .get_mut(&platform)
+        .and_then(|slots| slots.get_mut(&kind))
+        .ok_or_else(|| ApiError::not_found("No hay credenciales guardadas para esa plataforma."))?;
+    let target = payload
+        .version
+        .or_else(|| slot.previous_version())
+        .ok_or_else(|| ApiError::bad_request("No hay una version anterior a la que volver."))?;
+    if !slot.versions.iter().any(|entry| entry.version == target) {
+        return Err(ApiError::not_found(format!(
+            "La version {target} no existe o ya fue descartada."
+        )));
+    }
+
+    store.activate(&platform, kind, target).await?;
+    slot.active = Some(target);
+    store.persist(&index).await?;
+    drop(index);
+
+    info!(
+        "Credenciales {} de {platform} revertidas a la version {target}",
+        kind.as_str()
+    );
+    Ok(Json(store.report(&platform, kind).await))
+}
+
+fn unknown_upload() -> ApiError {
+    ApiError::not_found("No existe una subida con ese identificador o ya caduco.")
+}
@@ -0,0 +1,264 @@
+use std::{cmp::Reverse, collections::HashMap, sync::Arc};
+
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::State,
+};
+use chrono::{DateTime, Utc};
+use futures_util::stream;
+use serde::Serialize;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::{Mutex, OwnedSemaphorePermit, Semaphore, mpsc},
+    time::{Duration, Instant, timeout},
+};
+use tracing::info;
+
+use crate::{ApiError, AppState, stats::DownloadStats};
+
+const DEFAULT_SLOW_CLIENT_MIN_KBPS: u64 = 16;
+const DEFAULT_SLOW_CLIENT_GRACE_SECONDS: u64 = 30;
+const DEFAULT_MAX_CONCURRENT_STREAMS: usize = 32;
+const STREAM_CHUNK_BYTES: usize = 64 * 1024;
+const STREAM_CHANNEL_CHUNKS: usize = 4;
+const MAX_TRACKED_CLIENTS: usize = 5_000;
+const MAX_REPORTED_CLIENTS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamOutcome {
+    Completed,
+    Slow,
+    Disconnected,
+    Failed,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct ClientSpeed {
+    streams: u64,
+    completed: u64,
+    slow_aborts: u64,
+    disconnects: u64,
+    bytes: u64,
+    total_ms: u128,
+    last_bytes_per_second: u64,
+    last_seen: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct DeliveryMonitor {
+    min_bytes_per_second: u64,
+    grace: Duration,
+    max_streams: usize,
+    streams: Arc<Semaphore>,
+    clients: Mutex<HashMap<String, ClientSpeed>>,
+    stats: Arc<DownloadStats>,
+}
+
+pub(crate) type SlowClientHandler = Box<dyn FnOnce() + Send>;
+pub(crate) type DisconnectHandler = Box<dyn FnOnce() + Send>;
+pub(crate) type ServedHandler = Box<dyn FnOnce(u64) + Send>;
+
+#[derive(Debug, Serialize)]
+struct ClientSpeedReport {
+    client_ip: String,
+    avg_bytes_per_second: Option<u64>,
+    #[serde(flatten)]
+    speed: ClientSpeed,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct DeliveryReport {
+    min_bytes_per_second: u64,
+    grace_seconds: u64,
+    max_streams: usize,
+    active_streams: usize,
+    clients: Vec<ClientSpeedReport>,
+}
+
+impl DeliveryMonitor {
+    pub(crate) fn from_env(stats: Arc<DownloadStats>) -> Self {
+        let min_kbps = crate::read_usize_env("SLOW_CLIENT_MIN_KBPS")
+            .map_or(DEFAULT_SLOW_CLIENT_MIN_KBPS, |value| value as u64);
+        let grace_seconds = crate::read_usize_env("SLOW_CLIENT_GRACE_SECONDS")
+            .filter(|value| *value > 0)
+            .map_or(DEFAULT_SLOW_CLIENT_GRACE_SECONDS, |value| value as u64);
+        let max_streams = crate::read_usize_env("MAX_CONCURRENT_STREAMS")
+            .filter(|value| *value > 0)
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_STREAMS);
+
+        Self {
+            min_bytes_per_second: min_kbps * 1024,
+            grace: Duration::from_secs(grace_seconds),
+            max_streams,
+            streams: Arc::new(Semaphore::new(max_streams)),
+            clients: Mutex::new(HashMap::new()),
+            stats,
+        }
+    }
+
+    pub(crate) fn try_reserve_stream(&self) -> Option<OwnedSemaphorePermit> {
+        Arc::clone(&self.streams).try_acquire_owned().ok()
+    }
+
+    pub(crate) fn stream_file(
+        self: &Arc<Self>,
+        permit: OwnedSemaphorePermit,
+        file: impl AsyncRead + Unpin + Send + 'static,
+        client_ip: String,
+        on_slow: Option<SlowClientHandler>,
+        on_disconnect: Option<DisconnectHandler>,
+        on_served: Option<ServedHandler>,
+    ) -> Body {
+        let (sender, receiver) =
+            mpsc::channel(crate::lowmem::channel_chunks(STREAM_CHANNEL_CHUNKS));
+        let monitor = Arc::clone(self);
+
+        tokio::spawn(async move {
+            let started_at = Instant::now();
+            let enforce_floor = on_slow.is_some() && monitor.min_bytes_per_second > 0;
+            let (outcome, bytes) = monitor.pump(file, &sender, started_at, enforce_floor).await;
+            drop(sender);
+            drop(permit);
+
+            if outcome == StreamOutcome::Slow
+                && let Some(on_slow) = on_slow
+            {
+                info!(
+                    "Cliente {client_ip} demasiado lento ({} bytes en {:?}); se ofrece enlace firmado.",
+                    bytes,
+                    started_at.elapsed()
+                );
+                on_slow();
+            }
+            if outcome == StreamOutcome::Disconnected
+                && let Some(on_disconnect) = on_disconnect
+            {
+                info!(
+                    "Cliente {client_ip} cerro la conexion tras {bytes} bytes; se descarta la descarga."
+                );
+                on_disconnect();
+            }
+            if let Some(on_served) = on_served {
+                on_served(bytes);
+            }
+            monitor
+                .record(client_ip, outcome, bytes, started_at.elapsed())
+                .await;
+        });
+
+        Body::from_stream(stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|chunk| (chunk, receiver))
+        }))
+    }
+
+    async fn pump(
+        &self,
+        mut file: impl AsyncRead + Unpin,
+        sender: &mpsc::Sender<Result<Bytes, std::io::Error>>,
+        started_at: Instant,
+        enforce_floor: bool,
+    ) -> (StreamOutcome, u64) {
+        let mut buffer = vec![0_u8; crate::lowmem::chunk_bytes(STREAM_CHUNK_BYTES)];
+        let mut sent = 0_u64;
+
+        loop {
+            let read = match file.read(&mut buffer).await {
+                Ok(0) => return (StreamOutcome::Completed, sent),
+                Ok(read) => read,
+                Err(error) => {
+                    let _ = sender.send(Err(error)).await;
+                    return (StreamOutcome::Failed, sent);
+                }
+            };
+            let chunk = Ok(Bytes::copy_from_slice(&buffer[..read]));
+
+            if enforce_floor {
+                match timeout(self.grace, sender.send(chunk)).await {
+                    Err(_) => return (StreamOutcome::Slow, sent),
+                    Ok(Err(_)) => return (StreamOutcome::Disconnected, sent),
+                    Ok(Ok(())) => {}
+                }
+            } else if sender.send(chunk).await.is_err() {
+                return (StreamOutcome::Disconnected, sent);
+            }
+            sent += read as u64;
+
+            let elapsed = started_at.elapsed();
+            if enforce_floor
+                && elapsed >= self.grace
+                && bytes_per_second(sent, elapsed) < self.min_bytes_per_second
+            {
+                return (StreamOutcome::Slow, sent);
+            }
+        }
+    }
+
+    async fn record(
+        &self,
+        client_ip: String,
+        outcome: StreamOutcome,
+        bytes: u64,
+        elapsed: Duration,
+    ) {
+        self.stats.record_served(bytes).await;
+        let mut clients = self.clients.lock().await;
+        if clients.len() >= MAX_TRACKED_CLIENTS
+            && !clients.contains_key(&client_ip)
+            && let Some(oldest) = clients
+                .iter()
+                .min_by_key(|(_, speed)| speed.last_seen)
+                .map(|(ip, _)| ip.clone())
+        {
+            clients.remove(&oldest);
+        }
+
+        let speed = clients.entry(client_ip).or_default();
+        speed.streams += 1;
+        match outcome {
+            StreamOutcome::Completed => speed.completed += 1,
+            StreamOutcome::Slow => speed.slow_aborts += 1,
+            StreamOutcome::Disconnected | StreamOutcome::Failed => speed.disconnects += 1,
+        }
+        speed.bytes += bytes;
+        speed.total_ms += elapsed.as_millis();
+        speed.last_bytes_per_second = bytes_per_second(bytes, elapsed);
+        speed.last_seen = Some(Utc::now());
+    }
+
+    async fn report(&self) -> DeliveryReport {
+        let mut clients = self
+            .clients
+            .lock()
+            .await
+            .iter()
+            .map(|(client_ip, speed)| ClientSpeedReport {
+                client_ip: client_ip.clone(),
+                avg_bytes_per_second: (speed.total_ms > 0)
+                    .then(|| (u128::from(speed.bytes) * 1000 / speed.total_ms) as u64),
+                speed: speed.clone(),
+            })
+            .collect::<Vec<_>>();
+        clients.sort_by_key(|client| Reverse(client.speed.last_seen));
+        clients.truncate(MAX_REPORTED_CLIENTS);
+
+        DeliveryReport {
+            min_bytes_per_second: self.min_bytes_per_second,
+            grace_seconds: self.grace.as_secs(),
+            max_streams: self.max_streams,
+            active_streams: self.max_streams - self.streams.available_permits(),
+            clients,
+        }
+    }
+}
+
+fn bytes_per_second(bytes: u64, elapsed: Duration) -> u64 {
+    let elapsed_ms = elapsed.as_millis().max(1);
+    (u128::from(bytes) * 1000 / elapsed_ms) as u64
+}
+
+pub(crate) async fn get_delivery_report(
+    State(state): State<AppState>,
+) -> Result<Json<DeliveryReport>, ApiError> {
+    Ok(Json(state.delivery.report().await))
+}
@@ -0,0 +1,162 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use tokio::{
+    process::Command,
+    time::{Duration, sleep, timeout},
+};
+use tracing::{info, warn};
+
+use crate::{
+    ApiError, ArtifactSpec, DownloadMode, JobDir, LocalFile,
+    ffmpeg::{ffmpeg_binary, postprocess_timeout},
+    jobs::{JobHandle, JobPhase},
+    non_empty,
+};
+
+const SAMPLE_SECONDS: u32 = 5;
+const SIMULATED_STEPS: u32 = 10;
+const SIMULATED_STEP_MS: u64 = 250;
+
+#[derive(Debug)]
+pub(crate) struct DemoMode {
+    sample_dir: Option<PathBuf>,
+}
+
+fn sample_extension(mode: &DownloadMode) -> &'static str {
+    match mode {
+        DownloadMode::Video => "mp4",
+        DownloadMode::Audio => "mp3",
+    }
+}
+
+// Test pattern and tone rendered locally by ffmpeg; nothing is fetched from the network.
+fn sample_args(mode: &DownloadMode) -> Vec<String> {
+    let tone = format!("sine=frequency=440:duration={SAMPLE_SECONDS}");
+    let mut args = vec!["-f".to_string(), "lavfi".to_string()];
+    match mode {
+        DownloadMode::Video => args.extend([
+            "-i".to_string(),
+            format!("testsrc=size=640x360:rate=25:duration={SAMPLE_SECONDS}"),
+            "-f".to_string(),
+            "lavfi".to_string(),
+            "-i".to_string(),
+            tone,
+            "-c:v".to_string(),
+            "libx264".to_string(),
+            "-pix_fmt".to_string(),
+            "yuv420p".to_string(),
+            "-c:a".to_string(),
+            "aac".to_string(),
+            "-shortest".to_string(),
+        ]),
+        DownloadMode::Audio => args.extend([
+            "-i".to_string(),
+            tone,
+            "-c:a".to_string(),
+            "libmp3lame".to_string(),
+        ]),
+    }
+    args
+}
+
+impl DemoMode {
+    pub(crate) fn from_env() -> Option<Self> {
+        if !crate::read_bool_env("DEMO_MODE").unwrap_or(false) {
+            return None;
+        }
+        let sample_dir = std::env::var("DEMO_SAMPLE_DIR")
+            .ok()
+            .and_then(|value| non_empty(&value).map(PathBuf::from));
+        warn!(
+            "DEMO_MODE=true: las descargas se simulan y entregan un archivo de muestra; los metadatos siguen siendo reales."
+        );
+        Some(Self { sample_dir })
+    }
+
+    pub(crate) async fn produce_sample(
+        &self,
+        job: &JobHandle,
+        spec: &ArtifactSpec<'_>,
+        job_dir: JobDir,
+    ) -> Result<LocalFile, ApiError> {
+        for step in 1..=SIMULATED_STEPS {
+            tokio::select! {
+                () = sleep(Duration::from_millis(SIMULATED_STEP_MS)) => {}
+                () = job.cancelled() => return Err(ApiError::job_cancelled()),
+            }
+            job.progress(
+                JobPhase::Download,
+                f64::from(step) / f64::from(SIMULATED_STEPS),
+            );
+        }
+
+        let extension = sample_extension(&spec.mode);
+        let filename = format!("total-downloader-demo.{extension}");
+        let path = job_dir.path().join(&filename);
+        let configured = self
+            .sample_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("sample.{extension}")));
+        match configured {
+            Some(sample) => {
+                tokio::fs::copy(&sample, &path).await.map_err(|error| {
+                    ApiError::internal(format!(
+                        "No se pudo copiar la muestra {}: {error}",
+                        sample.display()
+                    ))
+                })?;
+            }
+            None => render_sample(&spec.mode, &path).await?,
+        }
+        info!("Descarga simulada en modo demo para {}", spec.url);
+
+        Ok(LocalFile {
+            path,
+            filename,
+            job_dir,
+            codecs: None,
+            cpu_seconds: 0.0,
+        })
+    }
+}
+
+async fn render_sample(mode: &DownloadMode, output: &Path) -> Result<(), ApiError> {
+    let mut command = Command::new(ffmpeg_binary());
+    command
+        .args(["-hide_banner", "-nostdin", "-y", "-loglevel", "error"])
+        .args(sample_args(mode))
+        .arg(output)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    crate::process_group::isolate(&mut command);
+    let child = command.spawn().map_err(|error| {
+        if error.kind() == ErrorKind::NotFound {
+            ApiError::internal(
+                "ffmpeg no esta instalado en el sistema. Instala ffmpeg o configura DEMO_SAMPLE_DIR.",
+            )
+        } else {
+            ApiError::internal(format!("No se pudo ejecutar ffmpeg: {error}"))
+        }
+    })?;
+    let output_result = timeout(postprocess_timeout(), child.wait_with_output())
+        .await
+        .map_err(|_| ApiError::internal("ffmpeg tardo demasiado en generar la muestra."))?
+        .map_err(|error| ApiError::internal(format!("ffmpeg fallo: {error}")))?;
+    if !output_result.status.success() {
+        let stderr = String::from_utf8_lossy(&output_result.stderr);
+        warn!(
+            "ffmpeg no pudo generar la muestra de demo: {}",
+            stderr.trim()
+        );
+        return Err(ApiError::internal(
+            "No se pudo generar el archivo de muestra del modo demo.",
+        ));
+    }
+    Ok(())
+}
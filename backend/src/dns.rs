@@ -0,0 +1,405 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+use reqwest::{
+    Url,
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::{ACCEPT, CONTENT_TYPE},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+    sync::Mutex,
+    time::{Duration, Instant, timeout},
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{ApiError, non_empty, read_list_env_raw};
+
+const DNS_PORT: u16 = 53;
+const DNS_TIMEOUT_SECONDS: u64 = 3;
+const MIN_CACHE_TTL_SECONDS: u32 = 30;
+const MAX_CACHE_TTL_SECONDS: u32 = 3600;
+const MAX_CACHED_NAMES: usize = 2048;
+const RECORD_A: u16 = 1;
+const RECORD_AAAA: u16 = 28;
+const DNS_MESSAGE_TYPE: &str = "application/dns-message";
+
+type DnsCache = HashMap<String, (Vec<IpAddr>, Instant)>;
+
+#[derive(Debug, Clone)]
+enum Upstream {
+    Https(Url),
+    Udp(SocketAddr),
+}
+
+#[derive(Debug)]
+struct Lookup {
+    addresses: Vec<IpAddr>,
+    ttl: u32,
+    truncated: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct DnsResolver {
+    upstreams: Arc<Vec<Upstream>>,
+    client: Option<reqwest::Client>,
+    cache: Arc<Mutex<DnsCache>>,
+}
+
+fn parse_server(value: &str) -> Option<SocketAddr> {
+    value.parse::<SocketAddr>().ok().or_else(|| {
+        value
+            .parse::<IpAddr>()
+            .ok()
+            .map(|ip| SocketAddr::new(ip, DNS_PORT))
+    })
+}
+
+fn encode_query(id: u16, name: &str, record_type: u16) -> Result<Vec<u8>, String> {
+    let mut packet = Vec::with_capacity(name.len() + 18);
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("nombre DNS invalido: {name}"));
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&record_type.to_be_bytes());
+    packet.extend_from_slice(&1_u16.to_be_bytes());
+    Ok(packet)
+}
+
+fn skip_name(packet: &[u8], mut position: usize) -> Option<usize> {
+    loop {
+        let length = *packet.get(position)?;
+        match length {
+            0 => return Some(position + 1),
+            length if length & 0xC0 == 0xC0 => return Some(position + 2),
+            length => position += 1 + usize::from(length),
+        }
+    }
+}
+
+fn read_u16(packet: &[u8], position: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([
+        *packet.get(position)?,
+        *packet.get(position + 1)?,
+    ]))
+}
+
+fn decode_response(packet: &[u8], id: u16, record_type: u16) -> Result<Lookup, String> {
+    let malformed = || "respuesta DNS malformada".to_string();
+    if read_u16(packet, 0).ok_or_else(malformed)? != id {
+        return Err("respuesta DNS con identificador inesperado".to_string());
+    }
+    let flags = read_u16(packet, 2).ok_or_else(malformed)?;
+    if flags & 0x8000 == 0 {
+        return Err(malformed());
+    }
+    let truncated = flags & 0x0200 != 0;
+    match flags & 0x000F {
+        0 | 3 => {}
+        code => return Err(format!("el servidor DNS respondio con codigo {code}")),
+    }
+
+    let questions = read_u16(packet, 4).ok_or_else(malformed)?;
+    let answers = read_u16(packet, 6).ok_or_else(malformed)?;
+    let mut position = 12;
+    for _ in 0..questions {
+        position = skip_name(packet, position).ok_or_else(malformed)? + 4;
+    }
+
+    let mut lookup = Lookup {
+        addresses: Vec::new(),
+        ttl: MAX_CACHE_TTL_SECONDS,
+        truncated,
+    };
+    for _ in 0..answers {
+        position = skip_name(packet, position).ok_or_else(malformed)?;
+        let kind = read_u16(packet, position).ok_or_else(malformed)?;
+        let ttl = packet
+            .get(position + 4..position + 8)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .ok_or_else(malformed)?;
+        let length = usize::from(read_u16(packet, position + 8).ok_or_else(malformed)?);
+        let data = packet
+            .get(position + 10..position + 10 + length)
+            .ok_or_else(malformed)?;
+        position += 10 + length;
+        if kind != record_type {
+            continue;
+        }
+        let address = match (kind, data.len()) {
+            (RECORD_A, 4) => IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3])),
+            (RECORD_AAAA, 16) => {
+                let mut octets = [0_u8; 16];
+                octets.copy_from_slice(data);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => continue,
+        };
+        lookup.addresses.push(address);
+        lookup.ttl = lookup.ttl.min(ttl);
+    }
+    Ok(lookup)
+}
+
+async fn exchange_udp(server: SocketAddr, packet: &[u8]) -> std::io::Result<Vec<u8>> {
+    let local = if server.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    socket.send(packet).await?;
+    let mut buffer = vec![0_u8; 4096];
+    let read = socket.recv(&mut buffer).await?;
+    buffer.truncate(read);
+    Ok(buffer)
+}
+
+async fn exchange_tcp(server: SocketAddr, packet: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect(server).await?;
+    let mut framed = (packet.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(packet);
+    stream.write_all(&framed).await?;
+    let mut length = [0_u8; 2];
+    stream.read_exact(&mut length).await?;
+    let mut buffer = vec![0_u8; usize::from(u16::from_be_bytes(length))];
+    stream.read_exact(&mut buffer).await?;
+    Ok(buffer)
+}
+
+impl DnsResolver {
+    pub(crate) fn from_env() -> Result<Option<Self>, ApiError> {
+        let mut servers = Vec::new();
+        for raw in read_list_env_raw("DNS_SERVERS") {
+            let server = parse_server(&raw).ok_or_else(|| {
+                ApiError::internal(format!(
+                    "DNS_SERVERS contiene una direccion invalida: {raw:?}"
+                ))
+            })?;
+            servers.push(Upstream::Udp(server));
+        }
+        let doh = match std::env::var("DNS_OVER_HTTPS_URL")
+            .ok()
+            .and_then(|value| non_empty(&value).map(ToString::to_string))
+        {
+            Some(value) => match Url::parse(&value) {
+                Ok(url) if url.scheme() == "https" && url.host_str().is_some() => Some(url),
+                _ => {
+                    return Err(ApiError::internal(format!(
+                        "DNS_OVER_HTTPS_URL debe ser una URL https: {value:?}"
+                    )));
+                }
+            },
+            None => None,
+        };
+        if servers.is_empty() && doh.is_none() {
+            return Ok(None);
+        }
+
+        let servers_only = Self {
+            upstreams: Arc::new(servers.clone()),
+            client: None,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        };
+        let Some(doh) = doh else {
+            info!("DNS propio: servidores {:?}", servers_only.servers());
+            return Ok(Some(servers_only));
+        };
+
+        // The DoH endpoint itself is resolved through DNS_SERVERS when given.
+        let mut builder =
+            reqwest::Client::builder().timeout(Duration::from_secs(DNS_TIMEOUT_SECONDS));
+        if !servers.is_empty() {
+            builder = builder.dns_resolver(Arc::new(servers_only));
+        }
+        let client = builder.build().map_err(|error| {
+            ApiError::internal(format!(
+                "No se pudo crear el cliente DNS-over-HTTPS: {error}"
+            ))
+        })?;
+        info!("DNS propio: DNS-over-HTTPS {doh}");
+        let mut upstreams = vec![Upstream::Https(doh)];
+        upstreams.extend(servers);
+        Ok(Some(Self {
+            upstreams: Arc::new(upstreams),
+            client: Some(client),
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }))
+    }
+
+    fn servers(&self) -> Vec<SocketAddr> {
+        self.upstreams
+            .iter()
+            .filter_map(|upstream| match upstream {
+                Upstream::Udp(server) => Some(*server),
+                Upstream::Https(_) => None,
+            })
+            .collect()
+    }
+
+    pub(crate) fn downloader_args(&self) -> Vec<String> {
+        let Some(aria2c) = std::env::var("ARIA2C_PATH")
+            .ok()
+            .and_then(|value| non_empty(&value).map(ToString::to_string))
+        else {
+            return Vec::new();
+        };
+        let servers = self
+            .servers()
+            .into_iter()
+            .filter(|server| server.port() == DNS_PORT)
+            .map(|server| server.ip().to_string())
+            .collect::<Vec<_>>();
+        if servers.is_empty() {
+            warn!("ARIA2C_PATH ignorado: aria2c solo admite DNS_SERVERS con el puerto 53.");
+            return Vec::new();
+        }
+        vec![
+            "--downloader".to_string(),
+            format!("http:{aria2c}"),
+            "--downloader-args".to_string(),
+            format!(
+                "aria2c:--async-dns=true --async-dns-server={}",
+                servers.join(",")
+            ),
+        ]
+    }
+
+    async fn query(
+        &self,
+        upstream: &Upstream,
+        name: &str,
+        record_type: u16,
+    ) -> Result<Lookup, String> {
+        let id = match upstream {
+            Upstream::Https(_) => 0,
+            Upstream::Udp(_) => Uuid::new_v4().as_u128() as u16,
+        };
+        let packet = encode_query(id, name, record_type)?;
+        let exchange = async {
+            match upstream {
+                Upstream::Https(url) => {
+                    let client = self.client.as_ref().ok_or("cliente DoH no disponible")?;
+                    let response = client
+                        .post(url.clone())
+                        .header(CONTENT_TYPE, DNS_MESSAGE_TYPE)
+                        .header(ACCEPT, DNS_MESSAGE_TYPE)
+                        .body(packet.clone())
+                        .send()
+                        .await
+                        .and_then(reqwest::Response::error_for_status)
+                        .map_err(|error| format!("DoH fallo: {error}"))?;
+                    let body = response
+                        .bytes()
+                        .await
+                        .map_err(|error| format!("DoH fallo: {error}"))?;
+                    decode_response(&body, id, record_type)
+                }
+                Upstream::Udp(server) => {
+                    let response = exchange_udp(*server, &packet)
+                        .await
+                        .map_err(|error| format!("{server}: {error}"))?;
+                    let lookup = decode_response(&response, id, record_type)?;
+                    if !lookup.truncated {
+                        return Ok(lookup);
+                    }
+                    let response = exchange_tcp(*server, &packet)
+                        .await
+                        .map_err(|error| format!("{server} (tcp): {error}"))?;
+                    decode_response(&response, id, record_type)
+                }
+            }
+        };
+        timeout(Duration::from_secs(DNS_TIMEOUT_SECONDS), exchange)
+            .await
+            .map_err(|_| format!("sin respuesta en {DNS_TIMEOUT_SECONDS} s"))?
+    }
+
+    async fn lookup(&self, name: &str) -> Result<Vec<IpAddr>, String> {
+        let name = name.to_ascii_lowercase();
+        let now = Instant::now();
+        if let Some((addresses, expires_at)) = self.cache.lock().await.get(&name)
+            && *expires_at > now
+        {
+            return Ok(addresses.clone());
+        }
+
+        let mut errors = Vec::new();
+        for upstream in self.upstreams.iter() {
+            let (v4, v6) = tokio::join!(
+                self.query(upstream, &name, RECORD_A),
+                self.query(upstream, &name, RECORD_AAAA)
+            );
+            let lookups = [v4, v6]
+                .into_iter()
+                .filter_map(|result| match result {
+                    Ok(lookup) => Some(lookup),
+                    Err(error) => {
+                        errors.push(error);
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            if lookups.is_empty() {
+                continue;
+            }
+            let ttl = lookups
+                .iter()
+                .filter(|lookup| !lookup.addresses.is_empty())
+                .map(|lookup| lookup.ttl)
+                .min()
+                .unwrap_or(MIN_CACHE_TTL_SECONDS)
+                .clamp(MIN_CACHE_TTL_SECONDS, MAX_CACHE_TTL_SECONDS);
+            let addresses = lookups
+                .into_iter()
+                .flat_map(|lookup| lookup.addresses)
+                .collect::<Vec<_>>();
+            if addresses.is_empty() {
+                return Err(format!("{name} no tiene direcciones"));
+            }
+
+            let mut cache = self.cache.lock().await;
+            if cache.len() >= MAX_CACHED_NAMES {
+                cache.retain(|_, (_, expires_at)| *expires_at > now);
+            }
+            if cache.len() < MAX_CACHED_NAMES {
+                cache.insert(
+                    name,
+                    (addresses.clone(), now + Duration::from_secs(u64::from(ttl))),
+                );
+            }
+            return Ok(addresses);
+        }
+        Err(format!("no se pudo resolver {name}: {}", errors.join("; ")))
+    }
+}
+
+impl Resolve for DnsResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addresses = resolver.lookup(name.as_str()).await.map_err(|error| {
+                warn!("DNS propio: {error}");
+                Box::<dyn std::error::Error + Send + Sync>::from(error)
+            })?;
+            let addrs: Addrs = Box::new(
+                addresses
+                    .into_iter()
+                    .map(|address| SocketAddr::new(address, 0)),
+            );
+            Ok(addrs)
+        })
+    }
+}
@@ -0,0 +1,260 @@
+use std::{collections::BTreeMap, io::ErrorKind, path::PathBuf};
+
+use axum::{
+    Json,
+    extract::{Path as RoutePath, State},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::{ApiError, AppState, url_domain};
+
+const MAX_RULES: usize = 200;
+const MAX_HEADERS_PER_RULE: usize = 20;
+const MAX_HEADER_VALUE_LENGTH: usize = 1024;
+const MAX_USER_AGENT_LENGTH: usize = 512;
+const RESERVED_HEADERS: &[&str] = &[
+    "host",
+    "content-length",
+    "transfer-encoding",
+    "connection",
+    "cookie",
+    "user-agent",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HeaderRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_agent: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, String>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct UpdateHeaderRuleRequest {
+    #[serde(default)]
+    user_agent: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct HeaderRuleReport {
+    domain: String,
+    #[serde(flatten)]
+    rule: HeaderRule,
+}
+
+#[derive(Debug)]
+pub(crate) struct DomainHeaders {
+    path: PathBuf,
+    rules: Mutex<BTreeMap<String, HeaderRule>>,
+}
+
+fn normalize_domain(value: &str) -> Result<String, ApiError> {
+    let domain = value
+        .trim()
+        .trim_end_matches('.')
+        .trim_start_matches("www.")
+        .to_ascii_lowercase();
+    let valid = !domain.is_empty()
+        && domain.len() <= 253
+        && domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && label
+                    .chars()
+                    .all(|character| character.is_ascii_alphanumeric() || character == '-')
+        });
+    if valid {
+        Ok(domain)
+    } else {
+        Err(ApiError::bad_request(format!(
+            "Dominio invalido: {value:?}. Usa por ejemplo vimeo.com."
+        )))
+    }
+}
+
+fn is_token_char(character: char) -> bool {
+    character.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(character)
+}
+
+fn clean_value(name: &str, value: &str, max_length: usize) -> Result<String, ApiError> {
+    let value = value.trim();
+    if value.is_empty() || value.len() > max_length {
+        return Err(ApiError::bad_request(format!(
+            "{name} debe tener entre 1 y {max_length} caracteres."
+        )));
+    }
+    if value.chars().any(char::is_control) {
+        return Err(ApiError::bad_request(format!(
+            "{name} no puede contener caracteres de control."
+        )));
+    }
+    Ok(value.to_string())
+}
+
+fn validate_rule(payload: UpdateHeaderRuleRequest) -> Result<HeaderRule, ApiError> {
+    if payload.headers.len() > MAX_HEADERS_PER_RULE {
+        return Err(ApiError::bad_request(format!(
+            "Como maximo {MAX_HEADERS_PER_RULE} cabeceras por dominio."
+        )));
+    }
+    let user_agent = payload
+        .user_agent
+        .filter(|value| !value.trim().is_empty())
+        .map(|value| clean_value("user_agent", &value, MAX_USER_AGENT_LENGTH))
+        .transpose()?;
+
+    let mut headers = BTreeMap::new();
+    for (name, value) in payload.headers {
+        let name = name.trim().to_string();
+        if name.is_empty() || !name.chars().all(is_token_char) {
+            return Err(ApiError::bad_request(format!(
+                "Nombre de cabecera invalido: {name:?}."
+            )));
+        }
+        if RESERVED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+            return Err(ApiError::bad_request(format!(
+                "La cabecera {name} no se puede configurar aqui; usa user_agent o las credenciales de la plataforma."
+            )));
+        }
+        let value = clean_value(&name, &value, MAX_HEADER_VALUE_LENGTH)?;
+        headers.insert(name, value);
+    }
+
+    if user_agent.is_none() && headers.is_empty() {
+        return Err(ApiError::bad_request(
+            "Indica user_agent o al menos una cabecera.",
+        ));
+    }
+    Ok(HeaderRule {
+        user_agent,
+        headers,
+        updated_at: Utc::now(),
+    })
+}
+
+impl DomainHeaders {
+    pub(crate) async fn open(path: PathBuf) -> Result<Self, ApiError> {
+        let rules = match tokio::fs::read_to_string(&path).await {
+            Ok(content) if content.trim().is_empty() => BTreeMap::new(),
+            Ok(content) => serde_json::from_str(&content).map_err(|error| {
+                ApiError::internal(format!("Cabeceras por dominio invalidas: {error}"))
+            })?,
+            Err(error) if error.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => {
+                return Err(ApiError::internal(format!(
+                    "No se pudieron leer las cabeceras por dominio: {error}"
+                )));
+            }
+        };
+        Ok(Self {
+            path,
+            rules: Mutex::new(rules),
+        })
+    }
+
+    pub(crate) async fn args_for(&self, url: &str) -> Vec<String> {
+        let domain = url_domain(url);
+        let rules = self.rules.lock().await;
+        let Some(rule) = rules
+            .iter()
+            .filter(|(rule, _)| domain == **rule || domain.ends_with(&format!(".{rule}")))
+            .max_by_key(|(rule, _)| rule.len())
+            .map(|(_, rule)| rule)
+        else {
+            return Vec::new();
+        };
+
+        let mut args = Vec::new();
+        if let Some(user_agent) = &rule.user_agent {
+            args.push("--user-agent".to_string());
+            args.push(user_agent.clone());
+        }
+        for (name, value) in &rule.headers {
+            args.push("--add-header".to_string());
+            args.push(format!("{name}:{value}"));
+        }
+        args
+    }
+
+    async fn persist(&self, rules: &BTreeMap<String, HeaderRule>) -> Result<(), ApiError> {
+        let payload = crate::lowmem::to_json_vec(rules).map_err(|error| {
+            ApiError::internal(format!(
+                "No se pudieron serializar las cabeceras por dominio: {error}"
+            ))
+        })?;
+        tokio::fs::write(&self.path, payload)
+            .await
+            .map_err(|error| {
+                ApiError::internal(format!(
+                    "No se pudieron guardar las cabeceras por dominio: {error}"
+                ))
+            })
+    }
+}
+
+pub(crate) async fn list_header_rules(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<HeaderRuleReport>>, ApiError> {
+    let rules = state.domain_headers.rules.lock().await;
+    Ok(Json(
+        rules
+            .iter()
+            .map(|(domain, rule)| HeaderRuleReport {
+                domain: domain.clone(),
+                rule: rule.clone(),
+            })
+            .collect(),
+    ))
+}
+
+pub(crate) async fn put_header_rule(
+    State(state): State<AppState>,
+    RoutePath(domain): RoutePath<String>,
+    Json(payload): Json<UpdateHeaderRuleRequest>,
+) -> Result<Json<HeaderRuleReport>, ApiError> {
+    let domain = normalize_domain(&domain)?;
+    let rule = validate_rule(payload)?;
+    let store = &state.domain_headers;
+    let mut rules = store.rules.lock().await;
+    if !rules.contains_key(&domain) && rules.len() >= MAX_RULES {
+        return Err(ApiError::bad_request(format!(
+            "Como maximo {MAX_RULES} dominios con cabeceras propias."
+        )));
+    }
+    rules.insert(domain.clone(), rule.clone());
+    store.persist(&rules).await?;
+    drop(rules);
+
+    info!(
+        "Cabeceras de {domain} actualizadas: user_agent={} cabeceras={:?}",
+        rule.user_agent.is_some(),
+        rule.headers.keys().collect::<Vec<_>>()
+    );
+    Ok(Json(HeaderRuleReport { domain, rule }))
+}
+
+pub(crate) async fn delete_header_rule(
+    State(state): State<AppState>,
+    RoutePath(domain): RoutePath<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let domain = normalize_domain(&domain)?;
+    let store = &state.domain_headers;
+    let mut rules = store.rules.lock().await;
+    if rules.remove(&domain).is_none() {
+        return Err(ApiError::not_found(
+            "No hay cabeceras configuradas para ese dominio.",
+        ));
+    }
+    store.persist(&rules).await?;
+    drop(rules);
+
+    info!("Cabeceras de {domain} eliminadas");
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
@@ -0,0 +1,269 @@
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+use crate::{ApiError, read_list_env, read_usize_env};
+
+const DEFAULT_SOURCE_BAN_AFTER: usize = 3;
+const DEFAULT_SOURCE_BAN_MINUTES: usize = 30;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum IpFamily {
+    #[default]
+    Any,
+    Ipv4,
+    Ipv6,
+}
+
+impl IpFamily {
+    pub(crate) fn from_env() -> Result<Self, ApiError> {
+        match std::env::var("IP_FAMILY")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "any" => Ok(Self::Any),
+            "ipv4" | "4" => Ok(Self::Ipv4),
+            "ipv6" | "6" => Ok(Self::Ipv6),
+            other => Err(ApiError::internal(format!(
+                "IP_FAMILY={other:?} no es valido. Usa any, ipv4 o ipv6."
+            ))),
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Any => "any",
+            Self::Ipv4 => "ipv4",
+            Self::Ipv6 => "ipv6",
+        }
+    }
+
+    pub(crate) fn args(self) -> Vec<String> {
+        match self {
+            Self::Any => Vec::new(),
+            Self::Ipv4 => vec!["--force-ipv4".to_string()],
+            Self::Ipv6 => vec!["--force-ipv6".to_string()],
+        }
+    }
+
+    pub(crate) fn local_address(self) -> Option<IpAddr> {
+        match self {
+            Self::Any => None,
+            Self::Ipv4 => Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            Self::Ipv6 => Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SourceHealth {
+    successes: u64,
+    failures: u64,
+    blocked_streak: BTreeMap<String, u32>,
+    banned_until: BTreeMap<String, (Instant, DateTime<Utc>)>,
+    unusable_until: Option<(Instant, DateTime<Utc>)>,
+}
+
+#[derive(Debug)]
+pub(crate) struct SourcePool {
+    addresses: Vec<IpAddr>,
+    next: AtomicUsize,
+    ban_after: u32,
+    ban_for: Duration,
+    health: Mutex<Vec<SourceHealth>>,
+}
+
+#[derive(Debug, Serialize)]
+struct SourceReport {
+    address: IpAddr,
+    successes: u64,
+    failures: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unusable_until: Option<DateTime<Utc>>,
+    banned: BTreeMap<String, DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SourcePoolReport {
+    ban_after_blocks: u32,
+    ban_minutes: u64,
+    addresses: Vec<SourceReport>,
+}
+
+// yt-dlp treats the last --force-ipv4/--force-ipv6 as the effective one.
+fn forced_family(args: &[String]) -> IpFamily {
+    args.iter()
+        .rev()
+        .find_map(|arg| match arg.as_str() {
+            "--force-ipv4" | "-4" => Some(IpFamily::Ipv4),
+            "--force-ipv6" | "-6" => Some(IpFamily::Ipv6),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) fn is_unusable_address_error(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    message.contains("cannot assign requested address") || message.contains("address not available")
+}
+
+impl SourcePool {
+    pub(crate) fn from_env() -> Self {
+        let mut addresses = Vec::new();
+        for value in read_list_env("SOURCE_ADDRESSES") {
+            match value.trim_matches(['[', ']']).parse::<IpAddr>() {
+                Ok(address) if address.is_unspecified() || address.is_multicast() => {
+                    warn!("SOURCE_ADDRESSES: {address} no es una direccion de origen; se ignora.");
+                }
+                Ok(address) if !addresses.contains(&address) => addresses.push(address),
+                Ok(_) => {}
+                Err(_) => warn!("SOURCE_ADDRESSES: {value:?} no es una IP valida; se ignora."),
+            }
+        }
+        if !addresses.is_empty() {
+            info!(
+                "Rotacion de IP de origen habilitada con {} direcciones.",
+                addresses.len()
+            );
+        }
+        let ban_after = read_usize_env("SOURCE_ADDRESS_BAN_AFTER")
+            .filter(|count| *count > 0)
+            .unwrap_or(DEFAULT_SOURCE_BAN_AFTER) as u32;
+        let minutes = read_usize_env("SOURCE_ADDRESS_BAN_MINUTES")
+            .filter(|minutes| *minutes > 0)
+            .unwrap_or(DEFAULT_SOURCE_BAN_MINUTES);
+
+        Self {
+            health: Mutex::new(addresses.iter().map(|_| SourceHealth::default()).collect()),
+            addresses,
+            next: AtomicUsize::new(0),
+            ban_after,
+            ban_for: Duration::from_secs(minutes as u64 * 60),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.addresses.is_empty()
+    }
+
+    pub(crate) fn can_rotate(&self) -> bool {
+        self.addresses.len() > 1
+    }
+
+    // Round-robin over the addresses of the forced family that are neither unusable nor
+    // banned for this platform; if every one is banned, the pool is skipped entirely.
+    pub(crate) async fn pick(&self, platform: &str, args: &[String]) -> Option<usize> {
+        if self.addresses.is_empty() {
+            return None;
+        }
+        let family = forced_family(args);
+        let now = Instant::now();
+        let health = self.health.lock().await;
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..self.addresses.len())
+            .map(|offset| (start + offset) % self.addresses.len())
+            .find(|index| {
+                let address = self.addresses[*index];
+                let state = &health[*index];
+                let family_ok = match family {
+                    IpFamily::Any => true,
+                    IpFamily::Ipv4 => address.is_ipv4(),
+                    IpFamily::Ipv6 => address.is_ipv6(),
+                };
+                family_ok
+                    && state.unusable_until.is_none_or(|(until, _)| until <= now)
+                    && state
+                        .banned_until
+                        .get(platform)
+                        .is_none_or(|(until, _)| *until <= now)
+            })
+    }
+
+    pub(crate) fn args(&self, index: usize) -> Vec<String> {
+        vec![
+            "--source-address".to_string(),
+            self.addresses[index].to_string(),
+        ]
+    }
+
+    pub(crate) async fn record(
+        &self,
+        index: usize,
+        platform: &str,
+        result: Result<(), &str>,
+        blocked: bool,
+    ) {
+        let address = self.addresses[index];
+        let mut health = self.health.lock().await;
+        let state = &mut health[index];
+        let Err(message) = result else {
+            state.successes += 1;
+            state.blocked_streak.remove(platform);
+            state.banned_until.remove(platform);
+            state.unusable_until = None;
+            return;
+        };
+        state.failures += 1;
+        let until = (Instant::now() + self.ban_for, Utc::now() + self.ban_for);
+        if is_unusable_address_error(message) {
+            warn!("La IP de origen {address} no esta disponible en este host; se aparta.");
+            state.unusable_until = Some(until);
+        } else if blocked {
+            let streak = state
+                .blocked_streak
+                .entry(platform.to_string())
+                .or_default();
+            *streak += 1;
+            if *streak >= self.ban_after {
+                warn!(
+                    "{platform} bloqueo la IP de origen {address} {streak} veces seguidas; se aparta {} min.",
+                    self.ban_for.as_secs() / 60
+                );
+                state.blocked_streak.remove(platform);
+                state.banned_until.insert(platform.to_string(), until);
+            }
+        }
+    }
+
+    pub(crate) async fn report(&self) -> SourcePoolReport {
+        let now = Instant::now();
+        let health = self.health.lock().await;
+        SourcePoolReport {
+            ban_after_blocks: self.ban_after,
+            ban_minutes: self.ban_for.as_secs() / 60,
+            addresses: self
+                .addresses
+                .iter()
+                .zip(health.iter())
+                .map(|(address, state)| SourceReport {
+                    address: *address,
+                    successes: state.successes,
+                    failures: state.failures,
+                    unusable_until: state
+                        .unusable_until
+                        .filter(|(until, _)| *until > now)
+                        .map(|(_, at)| at),
+                    banned: state
+                        .banned_until
+                        .iter()
+                        .filter(|(_, (until, _))| *until > now)
+                        .map(|(platform, (_, at))| (platform.clone(), *at))
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
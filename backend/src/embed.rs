@@ -0,0 +1,312 @@
+use std::net::SocketAddr;
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{ConnectInfo, Path as RoutePath, Query, State},
+    http::{HeaderMap, StatusCode, header::ORIGIN},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    ApiError, AppState, BackgroundDownload, DOWNLOAD_JOB_RETENTION_SECONDS, DownloadMode,
+    DownloadStatus, FormatHints, is_supported_download_url, non_empty, normalize_origin,
+    public_base_url, register_download_attempt,
+    request_signing::{
+        DEFAULT_MAX_SKEW_SECONDS, RequestSigner, SIGNATURE_HEADER, TIMESTAMP_HEADER,
+    },
+    run_background_download, sign_value, verify_signature,
+};
+
+const SITE_HEADER: &str = "x-td-embed-site";
+const DEFAULT_EMBED_DAILY_QUOTA: usize = 100;
+
+#[derive(Debug)]
+struct EmbedSite {
+    id: String,
+    signer: RequestSigner,
+    daily_quota: usize,
+    origins: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct EmbedSites {
+    sites: Vec<EmbedSite>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbedJobRequest {
+    url: String,
+    #[serde(default = "default_embed_mode")]
+    mode: DownloadMode,
+    format_id: Option<String>,
+    has_audio: Option<bool>,
+    #[serde(flatten)]
+    hints: FormatHints,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct EmbedJobResponse {
+    job_id: Uuid,
+    status_url: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EmbedStatusQuery {
+    site: String,
+    expires: i64,
+    sig: String,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbedSiteUsage {
+    site_id: String,
+    daily_quota: usize,
+    used_last_window: usize,
+    succeeded: usize,
+    failed: usize,
+    last_download_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct EmbedReport {
+    window_hours: i64,
+    sites: Vec<EmbedSiteUsage>,
+}
+
+fn default_embed_mode() -> DownloadMode {
+    DownloadMode::Video
+}
+
+fn tenant(site_id: &str) -> String {
+    format!("embed:{site_id}")
+}
+
+impl EmbedSites {
+    pub(crate) fn from_env() -> Self {
+        let mut sites = Vec::new();
+        for entry in crate::read_list_env_raw("EMBED_SITES") {
+            let mut parts = entry.split('|').map(str::trim);
+            let (Some(id), Some(secret)) = (
+                parts.next().and_then(non_empty),
+                parts.next().and_then(non_empty),
+            ) else {
+                warn!("Entrada invalida en EMBED_SITES (usa id|secreto|cuota|origenes): {entry:?}");
+                continue;
+            };
+            let daily_quota = parts
+                .next()
+                .and_then(|value| value.parse::<usize>().ok())
+                .filter(|value| *value > 0)
+                .unwrap_or(DEFAULT_EMBED_DAILY_QUOTA);
+            let origins = parts
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+                .filter_map(|origin| {
+                    let normalized = normalize_origin(origin);
+                    if normalized.is_none() {
+                        warn!("Origen invalido para el sitio embebido {id}: {origin}");
+                    }
+                    normalized
+                })
+                .collect();
+
+            sites.push(EmbedSite {
+                id: id.to_string(),
+                signer: RequestSigner::new(secret.as_bytes().to_vec(), DEFAULT_MAX_SKEW_SECONDS),
+                daily_quota,
+                origins,
+            });
+        }
+
+        Self { sites }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.sites.is_empty()
+    }
+
+    pub(crate) fn origins(&self) -> impl Iterator<Item = &str> {
+        self.sites
+            .iter()
+            .flat_map(|site| site.origins.iter().map(String::as_str))
+    }
+
+    fn find(&self, id: &str) -> Option<&EmbedSite> {
+        self.sites.iter().find(|site| site.id == id)
+    }
+}
+
+fn status_payload(site_id: &str, job_id: Uuid, expires: i64) -> String {
+    format!("embed-status:{site_id}:{job_id}:{expires}")
+}
+
+pub(crate) async fn create_embed_job(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let site = header(SITE_HEADER)
+        .and_then(|id| state.embed_sites.find(id.trim()))
+        .ok_or_else(|| ApiError::unauthorized("Sitio embebido desconocido."))?;
+    let (Some(timestamp), Some(signature)) = (header(TIMESTAMP_HEADER), header(SIGNATURE_HEADER))
+    else {
+        return Err(ApiError::invalid_signature(
+            "Falta la firma de la solicitud.",
+        ));
+    };
+    if let Err(reason) = site
+        .signer
+        .verify("POST", "/api/embed/jobs", timestamp, &body, signature)
+    {
+        debug!("Firma de embed rechazada para {}: {reason}", site.id);
+        return Err(ApiError::invalid_signature("Firma de solicitud invalida."));
+    }
+    if let Some(origin) = header(ORIGIN.as_str())
+        && !site.origins.is_empty()
+        && !normalize_origin(origin).is_some_and(|origin| site.origins.contains(&origin))
+    {
+        return Err(ApiError::forbidden("Origen no autorizado para este sitio."));
+    }
+
+    let payload = serde_json::from_slice::<EmbedJobRequest>(&body)
+        .map_err(|error| ApiError::bad_request(format!("Cuerpo de solicitud invalido: {error}")))?;
+    let url = payload.url.trim().to_string();
+    if url.is_empty() || !is_supported_download_url(&url, &state.extra_supported_domains) {
+        return Err(ApiError::bad_request("URL no soportada."));
+    }
+
+    let tenant = tenant(&site.id);
+    register_download_attempt(&state, &tenant, site.daily_quota).await?;
+
+    let job = state.jobs.create(Uuid::new_v4(), &tenant).await?;
+    let job_id = job.job_id();
+    let expires_at = Utc::now() + chrono::Duration::seconds(DOWNLOAD_JOB_RETENTION_SECONDS as i64);
+    if let Some(registry) = &state.registry {
+        registry.record_job(job_id, expires_at).await;
+    }
+
+    let base_url = public_base_url(&state, &headers);
+    let status_url = format!(
+        "{base_url}/api/embed/jobs/{job_id}?site={}&expires={}&sig={}",
+        urlencoding::encode(&site.id),
+        expires_at.timestamp(),
+        sign_value(
+            &state.signing_secret,
+            &status_payload(&site.id, job_id, expires_at.timestamp())
+        )
+    );
+    info!(
+        "Job embebido {job_id} creado para {} desde {}",
+        site.id,
+        addr.ip()
+    );
+
+    let download = BackgroundDownload {
+        requester: tenant,
+        remote_ip: String::new(),
+        url,
+        mode: payload.mode,
+        format_id: payload.format_id,
+        format_label: None,
+        has_audio: payload.has_audio.unwrap_or(false),
+        hints: payload.hints,
+        container: None,
+        subtitle_languages: Vec::new(),
+        codec_profile: None,
+        clip: None,
+        split_chapters: false,
+        extra_args: Vec::new(),
+        sponsorblock_remove: Vec::new(),
+        embed_metadata: state.embed_job_metadata,
+        embed_thumbnail: true,
+        audio_tags: None,
+        sidecars: None,
+        ip_family: state.ip_family,
+        max_download_bytes: state.config.max_download_bytes,
+        title: None,
+        thumbnail: None,
+        link_base: base_url,
+    };
+    tokio::spawn(crate::clienterrors::with_request_context(
+        run_background_download(state, job, download),
+    ));
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(EmbedJobResponse {
+            job_id,
+            status_url,
+            expires_at,
+        }),
+    )
+        .into_response())
+}
+
+pub(crate) async fn get_embed_job(
+    State(state): State<AppState>,
+    RoutePath(job_id): RoutePath<Uuid>,
+    Query(query): Query<EmbedStatusQuery>,
+) -> Result<Response, ApiError> {
+    let valid_signature = verify_signature(
+        &state.signing_secret,
+        &status_payload(&query.site, job_id, query.expires),
+        &query.sig,
+    );
+    if !valid_signature || query.expires < Utc::now().timestamp() {
+        return Err(ApiError::invalid_signature(
+            "El enlace de estado es invalido o expiro.",
+        ));
+    }
+
+    let mut receiver = state
+        .jobs
+        .subscribe(job_id, &tenant(&query.site))
+        .await
+        .ok_or_else(|| ApiError::not_found("No existe un job embebido con ese identificador."))?;
+    let snapshot = receiver.borrow_and_update().clone();
+    Ok(Json(snapshot).into_response())
+}
+
+pub(crate) async fn get_embed_report(
+    State(state): State<AppState>,
+) -> Result<Json<EmbedReport>, ApiError> {
+    let history = state.history.lock().await.clone();
+
+    let mut usage = Vec::new();
+    for site in &state.embed_sites.sites {
+        let tenant = tenant(&site.id);
+        let used_last_window = crate::downloads_in_window(&state, &tenant).await;
+        let entries = history
+            .iter()
+            .filter(|entry| entry.requester_ip == tenant)
+            .collect::<Vec<_>>();
+        usage.push(EmbedSiteUsage {
+            site_id: site.id.clone(),
+            daily_quota: site.daily_quota,
+            used_last_window,
+            succeeded: entries
+                .iter()
+                .filter(|entry| matches!(entry.status, DownloadStatus::Success))
+                .count(),
+            failed: entries
+                .iter()
+                .filter(|entry| matches!(entry.status, DownloadStatus::Failed))
+                .count(),
+            last_download_at: entries.iter().map(|entry| entry.created_at).max(),
+        });
+    }
+
+    Ok(Json(EmbedReport {
+        window_hours: state.config.download_window_hours,
+        sites: usage,
+    }))
+}
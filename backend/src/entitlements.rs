@@ -0,0 +1,492 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::ErrorKind,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{AcquireError, Mutex, OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+
+use crate::{
+    ApiError, AppState, auth::Session, check_signed_timestamp, non_empty, policy::PolicyLimits,
+    read_usize_env, verify_hmac,
+};
+
+const STRIPE_SIGNATURE_HEADER: &str = "stripe-signature";
+const BILLING_SIGNATURE_HEADER: &str = "x-billing-signature";
+const SIGNATURE_TOLERANCE_SECONDS: i64 = 300;
+const MAX_PROCESSED_EVENTS: usize = 2_000;
+const MAX_WEBHOOK_BYTES: usize = 256 * 1024;
+const DEFAULT_PREMIUM_DAILY_LIMIT: usize = 100;
+const DEFAULT_PREMIUM_MAX_DOWNLOAD_MB: usize = 2048;
+const DEFAULT_PREMIUM_PRIORITY_SLOTS: usize = 1;
+const MAX_PREMIUM_DAYS: i64 = 3650;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Tier {
+    Free,
+    Premium,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entitlement {
+    tier: Tier,
+    source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    customer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<DateTime<Utc>>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct EntitlementFile {
+    #[serde(default)]
+    subjects: BTreeMap<String, Entitlement>,
+    #[serde(default)]
+    customers: BTreeMap<String, String>,
+    #[serde(default)]
+    processed_events: VecDeque<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EntitlementEvent {
+    id: String,
+    subject: Option<String>,
+    email: Option<String>,
+    tier: Tier,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct EntitlementReport {
+    subject: String,
+    active: bool,
+    #[serde(flatten)]
+    entitlement: Entitlement,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct EntitlementsReport {
+    premium_limits: PolicyLimits,
+    priority_slots: usize,
+    priority_available: usize,
+    entitlements: Vec<EntitlementReport>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Entitlements {
+    path: PathBuf,
+    stripe_secret: Option<Vec<u8>>,
+    webhook_secret: Option<Vec<u8>>,
+    premium: PolicyLimits,
+    priority_slots: usize,
+    priority: Arc<Semaphore>,
+    store: Mutex<EntitlementFile>,
+}
+
+struct Change {
+    subject: String,
+    tier: Tier,
+    customer: Option<String>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+fn read_secret(name: &str) -> Option<Vec<u8>> {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| non_empty(&value).map(|secret| secret.as_bytes().to_vec()))
+}
+
+fn email_subject(email: &str) -> String {
+    format!("email:{}", email.trim().to_ascii_lowercase())
+}
+
+fn is_active(entitlement: &Entitlement, now: DateTime<Utc>) -> bool {
+    entitlement.tier == Tier::Premium && entitlement.expires_at.is_none_or(|at| at > now)
+}
+
+// Stripe style: "t=<unix>,v1=<hex hmac of '<t>.<body>'>", possibly with several v1 entries.
+fn verify_signature(secret: &[u8], header: &str, body: &[u8]) -> Result<(), &'static str> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = Some(value),
+            Some(("v1", value)) => signatures.push(value),
+            _ => {}
+        }
+    }
+    let timestamp = timestamp.ok_or("falta la marca de tiempo")?;
+    check_signed_timestamp(timestamp, SIGNATURE_TOLERANCE_SECONDS)?;
+
+    let mut payload = format!("{timestamp}.").into_bytes();
+    payload.extend_from_slice(body);
+    if signatures
+        .iter()
+        .any(|provided| verify_hmac(secret, &payload, provided))
+    {
+        Ok(())
+    } else {
+        Err("firma incorrecta")
+    }
+}
+
+fn check_signature(
+    secret: Option<&Vec<u8>>,
+    headers: &HeaderMap,
+    header: &str,
+    body: &[u8],
+) -> Result<(), ApiError> {
+    let secret = secret.ok_or_else(|| ApiError::not_found("Este webhook no esta configurado."))?;
+    if body.len() > MAX_WEBHOOK_BYTES {
+        return Err(ApiError::bad_request("El evento es demasiado grande."));
+    }
+    let provided = headers
+        .get(header)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    verify_signature(secret, provided, body).map_err(|reason| {
+        warn!("Webhook de facturacion rechazado: {reason}");
+        ApiError::unauthorized("Firma del webhook invalida.")
+    })
+}
+
+fn text<'a>(object: &'a Value, pointer: &str) -> Option<&'a str> {
+    object
+        .pointer(pointer)
+        .and_then(Value::as_str)
+        .and_then(non_empty)
+}
+
+fn metadata_tier(object: &Value) -> Tier {
+    match text(object, "/metadata/tier") {
+        Some("free") => Tier::Free,
+        _ => Tier::Premium,
+    }
+}
+
+impl Entitlements {
+    pub(crate) async fn from_env(path: PathBuf) -> Result<Option<Self>, ApiError> {
+        let stripe_secret = read_secret("STRIPE_WEBHOOK_SECRET");
+        let webhook_secret = read_secret("BILLING_WEBHOOK_SECRET");
+        if stripe_secret.is_none() && webhook_secret.is_none() {
+            return Ok(None);
+        }
+
+        let store = match tokio::fs::read_to_string(&path).await {
+            Ok(content) if content.trim().is_empty() => EntitlementFile::default(),
+            Ok(content) => serde_json::from_str(&content).map_err(|error| {
+                ApiError::internal(format!("Planes de usuario invalidos: {error}"))
+            })?,
+            Err(error) if error.kind() == ErrorKind::NotFound => EntitlementFile::default(),
+            Err(error) => {
+                return Err(ApiError::internal(format!(
+                    "No se pudieron leer los planes de usuario: {error}"
+                )));
+            }
+        };
+        let premium = PolicyLimits {
+            daily_limit: read_usize_env("PREMIUM_DAILY_LIMIT")
+                .filter(|limit| *limit > 0)
+                .unwrap_or(DEFAULT_PREMIUM_DAILY_LIMIT),
+            max_download_bytes: read_usize_env("PREMIUM_MAX_DOWNLOAD_MB")
+                .filter(|megabytes| *megabytes > 0)
+                .unwrap_or(DEFAULT_PREMIUM_MAX_DOWNLOAD_MB) as u64
+                * 1024
+                * 1024,
+        };
+        let priority_slots =
+            read_usize_env("PREMIUM_PRIORITY_SLOTS").unwrap_or(DEFAULT_PREMIUM_PRIORITY_SLOTS);
+        info!(
+            "Plan premium habilitado ({} usuarios con plan guardado, webhooks: stripe={} generico={}).",
+            store.subjects.len(),
+            stripe_secret.is_some(),
+            webhook_secret.is_some()
+        );
+
+        Ok(Some(Self {
+            path,
+            stripe_secret,
+            webhook_secret,
+            premium,
+            priority_slots,
+            priority: Arc::new(Semaphore::new(priority_slots)),
+            store: Mutex::new(store),
+        }))
+    }
+
+    pub(crate) async fn premium_for(&self, session: &Session) -> Option<PolicyLimits> {
+        let now = Utc::now();
+        let store = self.store.lock().await;
+        let by_subject = store.subjects.get(&session.sub);
+        let by_email = session
+            .email
+            .as_deref()
+            .and_then(|email| store.subjects.get(&email_subject(email)));
+        [by_subject, by_email]
+            .into_iter()
+            .flatten()
+            .any(|entitlement| is_active(entitlement, now))
+            .then_some(self.premium)
+    }
+
+    // Premium jobs race this lane against the shared download semaphore.
+    pub(crate) async fn priority_permit(&self) -> Result<OwnedSemaphorePermit, AcquireError> {
+        if self.priority_slots == 0 {
+            return std::future::pending().await;
+        }
+        Arc::clone(&self.priority).acquire_owned().await
+    }
+
+    async fn apply(
+        &self,
+        event_id: &str,
+        source: &str,
+        changes: Vec<Change>,
+    ) -> Result<bool, ApiError> {
+        let mut store = self.store.lock().await;
+        if store.processed_events.iter().any(|id| id == event_id) {
+            return Ok(false);
+        }
+        for change in changes {
+            if let Some(customer) = &change.customer {
+                store
+                    .customers
+                    .insert(customer.clone(), change.subject.clone());
+            }
+            let customer = change.customer.or_else(|| {
+                store
+                    .subjects
+                    .get(&change.subject)
+                    .and_then(|existing| existing.customer.clone())
+            });
+            info!(
+                "Plan de {} actualizado a {:?} por {source} (evento {event_id}).",
+                change.subject, change.tier
+            );
+            store.subjects.insert(
+                change.subject,
+                Entitlement {
+                    tier: change.tier,
+                    source: source.to_string(),
+                    customer,
+                    expires_at: change.expires_at,
+                    updated_at: Utc::now(),
+                },
+            );
+        }
+        store.processed_events.push_back(event_id.to_string());
+        while store.processed_events.len() > MAX_PROCESSED_EVENTS {
+            store.processed_events.pop_front();
+        }
+        self.persist(&store).await?;
+        Ok(true)
+    }
+
+    async fn stripe_changes(&self, event: &Value) -> Result<Vec<Change>, ApiError> {
+        let kind = text(event, "/type").unwrap_or_default();
+        let Some(object) = event.pointer("/data/object") else {
+            return Ok(Vec::new());
+        };
+        let customer = text(object, "/customer").map(ToString::to_string);
+
+        match kind {
+            "checkout.session.completed" => {
+                let subject = text(object, "/client_reference_id")
+                    .map(ToString::to_string)
+                    .or_else(|| {
+                        text(object, "/customer_details/email")
+                            .or_else(|| text(object, "/customer_email"))
+                            .map(email_subject)
+                    });
+                let Some(subject) = subject else {
+                    warn!(
+                        "checkout.session.completed sin client_reference_id ni email; se ignora."
+                    );
+                    return Ok(Vec::new());
+                };
+                // One-off payments can grant a fixed number of days through metadata.
+                let expires_at = match text(object, "/metadata/premium_days")
+                    .and_then(|days| days.parse::<i64>().ok())
+                    .filter(|days| *days > 0)
+                {
+                    Some(days) => Some(
+                        Duration::try_days(days.min(MAX_PREMIUM_DAYS))
+                            .and_then(|duration| Utc::now().checked_add_signed(duration))
+                            .ok_or_else(|| {
+                                ApiError::bad_request("metadata.premium_days esta fuera de rango.")
+                            })?,
+                    ),
+                    None => None,
+                };
+                Ok(vec![Change {
+                    subject,
+                    tier: metadata_tier(object),
+                    customer,
+                    expires_at,
+                }])
+            }
+            "customer.subscription.created"
+            | "customer.subscription.updated"
+            | "customer.subscription.deleted" => {
+                let known = match &customer {
+                    Some(customer) => self.store.lock().await.customers.get(customer).cloned(),
+                    None => None,
+                };
+                let Some(subject) =
+                    known.or_else(|| text(object, "/metadata/subject").map(ToString::to_string))
+                else {
+                    warn!("{kind} para un cliente desconocido; se ignora.");
+                    return Ok(Vec::new());
+                };
+                let active = kind != "customer.subscription.deleted"
+                    && matches!(text(object, "/status"), Some("active" | "trialing"));
+                let expires_at = object
+                    .pointer("/current_period_end")
+                    .and_then(Value::as_i64)
+                    .and_then(|seconds| DateTime::from_timestamp(seconds, 0));
+                Ok(vec![Change {
+                    subject,
+                    tier: if active {
+                        metadata_tier(object)
+                    } else {
+                        Tier::Free
+                    },
+                    customer,
+                    expires_at: if active { expires_at } else { None },
+                }])
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    async fn persist(&self, store: &EntitlementFile) -> Result<(), ApiError> {
+        let payload = crate::lowmem::to_json_vec(store).map_err(|error| {
+            ApiError::internal(format!(
+                "No se pudieron serializar los planes de usuario: {error}"
+            ))
+        })?;
+        tokio::fs::write(&self.path, payload)
+            .await
+            .map_err(|error| {
+                ApiError::internal(format!(
+                    "No se pudieron guardar los planes de usuario: {error}"
+                ))
+            })
+    }
+}
+
+fn enabled(state: &AppState) -> Result<&Arc<Entitlements>, ApiError> {
+    state.entitlements.as_ref().ok_or_else(|| {
+        ApiError::not_found(
+            "Los planes premium estan deshabilitados (STRIPE_WEBHOOK_SECRET o BILLING_WEBHOOK_SECRET).",
+        )
+    })
+}
+
+fn acknowledge(applied: bool) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "status": if applied { "ok" } else { "duplicate" }
+        })),
+    )
+}
+
+pub(crate) async fn stripe_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let entitlements = enabled(&state)?;
+    check_signature(
+        entitlements.stripe_secret.as_ref(),
+        &headers,
+        STRIPE_SIGNATURE_HEADER,
+        &body,
+    )?;
+    let event: Value = serde_json::from_slice(&body)
+        .map_err(|_| ApiError::bad_request("El evento de Stripe no es JSON valido."))?;
+    let event_id = text(&event, "/id")
+        .ok_or_else(|| ApiError::bad_request("El evento de Stripe no tiene id."))?;
+
+    let changes = entitlements.stripe_changes(&event).await?;
+    let applied = entitlements.apply(event_id, "stripe", changes).await?;
+    Ok(acknowledge(applied))
+}
+
+pub(crate) async fn entitlement_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let entitlements = enabled(&state)?;
+    check_signature(
+        entitlements.webhook_secret.as_ref(),
+        &headers,
+        BILLING_SIGNATURE_HEADER,
+        &body,
+    )?;
+    let event: EntitlementEvent = serde_json::from_slice(&body)
+        .map_err(|error| ApiError::bad_request(format!("Evento de plan invalido: {error}")))?;
+    let event_id =
+        non_empty(&event.id).ok_or_else(|| ApiError::bad_request("El evento necesita un id."))?;
+    let subject = event
+        .subject
+        .as_deref()
+        .and_then(non_empty)
+        .map(ToString::to_string)
+        .or_else(|| {
+            event
+                .email
+                .as_deref()
+                .and_then(non_empty)
+                .map(email_subject)
+        })
+        .ok_or_else(|| ApiError::bad_request("Indica subject o email."))?;
+
+    let change = Change {
+        subject,
+        tier: event.tier,
+        customer: None,
+        expires_at: event.expires_at,
+    };
+    let applied = entitlements
+        .apply(event_id, "webhook", vec![change])
+        .await?;
+    Ok(acknowledge(applied))
+}
+
+pub(crate) async fn list_entitlements(
+    State(state): State<AppState>,
+) -> Result<Json<EntitlementsReport>, ApiError> {
+    let entitlements = enabled(&state)?;
+    let now = Utc::now();
+    let store = entitlements.store.lock().await;
+    Ok(Json(EntitlementsReport {
+        premium_limits: entitlements.premium,
+        priority_slots: entitlements.priority_slots,
+        priority_available: entitlements.priority.available_permits(),
+        entitlements: store
+            .subjects
+            .iter()
+            .map(|(subject, entitlement)| EntitlementReport {
+                subject: subject.clone(),
+                active: is_active(entitlement, now),
+                entitlement: entitlement.clone(),
+            })
+            .collect(),
+    }))
+}
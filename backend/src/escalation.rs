@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use tokio::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+use url::Url;
+
+use crate::read_usize_env;
+
+const DEFAULT_ESCALATION_MEMORY_MINUTES: usize = 60;
+const PROXY_SCHEMES: [&str; 5] = ["http", "https", "socks4", "socks5", "socks5h"];
+const BLOCKING_MARKERS: [&str; 6] = [
+    "http error 403",
+    "http error 429",
+    "sign in to confirm",
+    "rate-limit",
+    "rate limit",
+    "blocked",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Rung {
+    Plain,
+    Impersonate,
+    Proxy,
+}
+
+pub(crate) const RUNGS: [Rung; 3] = [Rung::Plain, Rung::Impersonate, Rung::Proxy];
+
+#[derive(Debug, Default)]
+struct PlatformRungs {
+    preferred: Option<(Rung, Instant)>,
+    successes: BTreeMap<Rung, u64>,
+    failures: BTreeMap<Rung, u64>,
+}
+
+#[derive(Debug)]
+pub(crate) struct EscalationLadder {
+    proxy: Option<String>,
+    memory: Duration,
+    platforms: Mutex<BTreeMap<String, PlatformRungs>>,
+}
+
+#[derive(Debug, Serialize)]
+struct PlatformReport {
+    platform: String,
+    first_attempt: Rung,
+    successes: BTreeMap<Rung, u64>,
+    failures: BTreeMap<Rung, u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct EscalationReport {
+    proxy_configured: bool,
+    memory_minutes: u64,
+    platforms: Vec<PlatformReport>,
+}
+
+pub(crate) fn is_blocking_error(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    BLOCKING_MARKERS
+        .iter()
+        .any(|marker| message.contains(marker))
+}
+
+impl EscalationLadder {
+    pub(crate) fn from_env() -> Self {
+        let proxy = std::env::var("ESCALATION_PROXY_URL")
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .filter(|value| {
+                let valid =
+                    Url::parse(value).is_ok_and(|parsed| PROXY_SCHEMES.contains(&parsed.scheme()));
+                if !valid {
+                    warn!("ESCALATION_PROXY_URL no es un proxy http(s)/socks valido; se ignora.");
+                }
+                valid
+            });
+        if proxy.is_some() {
+            info!("Escalado por proxy habilitado para plataformas bloqueadas.");
+        }
+        let minutes = read_usize_env("ESCALATION_MEMORY_MINUTES")
+            .filter(|minutes| *minutes > 0)
+            .unwrap_or(DEFAULT_ESCALATION_MEMORY_MINUTES);
+
+        Self {
+            proxy,
+            memory: Duration::from_secs(minutes as u64 * 60),
+            platforms: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub(crate) fn proxy_args(&self) -> Option<Vec<String>> {
+        self.proxy
+            .as_ref()
+            .map(|proxy| vec!["--proxy".to_string(), proxy.clone()])
+    }
+
+    pub(crate) async fn first_rung(&self, platform: &str) -> Rung {
+        let platforms = self.platforms.lock().await;
+        platforms
+            .get(platform)
+            .and_then(|rungs| rungs.preferred)
+            .filter(|(_, until)| *until > Instant::now())
+            .map_or(Rung::Plain, |(rung, _)| rung)
+    }
+
+    pub(crate) async fn record(&self, platform: &str, rung: Rung, success: bool) {
+        let mut platforms = self.platforms.lock().await;
+        let rungs = platforms.entry(platform.to_string()).or_default();
+        if success {
+            *rungs.successes.entry(rung).or_default() += 1;
+            rungs.preferred = (rung != Rung::Plain).then(|| (rung, Instant::now() + self.memory));
+        } else {
+            *rungs.failures.entry(rung).or_default() += 1;
+            if rungs
+                .preferred
+                .is_some_and(|(preferred, _)| preferred == rung)
+            {
+                rungs.preferred = None;
+            }
+        }
+    }
+
+    pub(crate) async fn report(&self) -> EscalationReport {
+        let now = Instant::now();
+        let platforms = self
+            .platforms
+            .lock()
+            .await
+            .iter()
+            .map(|(platform, rungs)| PlatformReport {
+                platform: platform.clone(),
+                first_attempt: rungs
+                    .preferred
+                    .filter(|(_, until)| *until > now)
+                    .map_or(Rung::Plain, |(rung, _)| rung),
+                successes: rungs.successes.clone(),
+                failures: rungs.failures.clone(),
+            })
+            .collect();
+        EscalationReport {
+            proxy_configured: self.proxy.is_some(),
+            memory_minutes: self.memory.as_secs() / 60,
+            platforms,
+        }
+    }
+}
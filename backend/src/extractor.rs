@@ -0,0 +1,392 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{Json, extract::State};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::egress::{self, SourcePool, SourcePoolReport};
+use crate::escalation::{self, EscalationLadder, EscalationReport, RUNGS, Rung};
+use crate::impersonate::{Impersonation, ImpersonationReport};
+use crate::{
+    ApiError, AppState, credentials::CredentialStore, domain_headers::DomainHeaders, read_list_env,
+    run_extractor, run_extractor_streaming, throughput, url_domain,
+};
+
+const DEFAULT_YT_DLP_BINARY: &str = "yt-dlp";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RequestClass {
+    Metadata,
+    Download,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ExtractorChannel {
+    Stable,
+    Candidate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RoutingRules {
+    percent: u8,
+    domains: Vec<String>,
+    classes: Vec<RequestClass>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct ChannelMetrics {
+    success: u64,
+    failure: u64,
+    total_ms: u128,
+}
+
+#[derive(Debug)]
+pub(crate) struct ExtractorRouter {
+    stable: String,
+    candidate: Option<String>,
+    common_args: Vec<String>,
+    credentials: Arc<CredentialStore>,
+    domain_headers: Arc<DomainHeaders>,
+    impersonation: Impersonation,
+    escalation: EscalationLadder,
+    sources: SourcePool,
+    rules: Mutex<RoutingRules>,
+    metrics: Mutex<BTreeMap<(ExtractorChannel, RequestClass), ChannelMetrics>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChannelReport {
+    channel: ExtractorChannel,
+    class: RequestClass,
+    success: u64,
+    failure: u64,
+    success_rate: Option<f64>,
+    avg_ms: Option<u128>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ExtractorReport {
+    stable: String,
+    candidate: Option<String>,
+    rules: RoutingRules,
+    channels: Vec<ChannelReport>,
+    impersonation: ImpersonationReport,
+    escalation: EscalationReport,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_addresses: Option<SourcePoolReport>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct UpdateRoutingRequest {
+    percent: Option<u8>,
+    domains: Option<Vec<String>>,
+    classes: Option<Vec<RequestClass>>,
+}
+
+pub(crate) fn configured_binaries() -> (String, Option<String>) {
+    (
+        crate::binary_path_env("YT_DLP_STABLE_PATH")
+            .or_else(|| crate::binary_path_env("YT_DLP_PATH"))
+            .unwrap_or_else(|| DEFAULT_YT_DLP_BINARY.to_string()),
+        crate::binary_path_env("YT_DLP_CANDIDATE_PATH"),
+    )
+}
+
+impl ExtractorRouter {
+    pub(crate) fn from_env(
+        common_args: Vec<String>,
+        credentials: Arc<CredentialStore>,
+        domain_headers: Arc<DomainHeaders>,
+    ) -> Self {
+        let (stable, candidate) = configured_binaries();
+        let percent = crate::read_usize_env("YT_DLP_CANDIDATE_PERCENT")
+            .unwrap_or_default()
+            .min(100) as u8;
+        let domains = read_list_env("YT_DLP_CANDIDATE_DOMAINS");
+        let classes = read_list_env("YT_DLP_CANDIDATE_CLASSES")
+            .iter()
+            .filter_map(|value| match value.as_str() {
+                "metadata" => Some(RequestClass::Metadata),
+                "download" => Some(RequestClass::Download),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            stable,
+            candidate,
+            common_args,
+            credentials,
+            domain_headers,
+            impersonation: Impersonation::from_env(),
+            escalation: EscalationLadder::from_env(),
+            sources: SourcePool::from_env(),
+            rules: Mutex::new(RoutingRules {
+                percent,
+                domains,
+                classes: if classes.is_empty() {
+                    vec![RequestClass::Metadata, RequestClass::Download]
+                } else {
+                    classes
+                },
+            }),
+            metrics: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub(crate) fn stable(&self) -> &str {
+        &self.stable
+    }
+
+    pub(crate) fn candidate(&self) -> Option<&str> {
+        self.candidate.as_deref()
+    }
+
+    pub(crate) async fn detect_impersonation(&self) {
+        self.impersonation.detect(&self.stable).await;
+    }
+
+    pub(crate) async fn with_common_args(&self, url: &str, args: Vec<String>) -> Vec<String> {
+        self.common_args
+            .iter()
+            .cloned()
+            .chain(self.credentials.args_for(url).await)
+            .chain(self.domain_headers.args_for(url).await)
+            .chain(self.impersonation.args_for(url))
+            .chain(args)
+            .collect()
+    }
+
+    async fn select(&self, class: RequestClass, url: &str) -> (ExtractorChannel, &str) {
+        let Some(candidate) = self.candidate.as_deref() else {
+            return (ExtractorChannel::Stable, &self.stable);
+        };
+
+        let rules = self.rules.lock().await;
+        if !rules.classes.contains(&class) {
+            return (ExtractorChannel::Stable, &self.stable);
+        }
+
+        let domain = url_domain(url);
+        let domain_match = rules
+            .domains
+            .iter()
+            .any(|rule| domain == *rule || domain.ends_with(&format!(".{rule}")));
+        let sampled = (Uuid::new_v4().as_u128() % 100) < u128::from(rules.percent);
+
+        if domain_match || sampled {
+            (ExtractorChannel::Candidate, candidate)
+        } else {
+            (ExtractorChannel::Stable, &self.stable)
+        }
+    }
+
+    pub(crate) async fn run(
+        &self,
+        class: RequestClass,
+        url: &str,
+        args: Vec<String>,
+        time_limit: Duration,
+    ) -> Result<std::process::Output, ApiError> {
+        self.execute(class, url, args, time_limit, None).await
+    }
+
+    pub(crate) async fn run_with_progress(
+        &self,
+        class: RequestClass,
+        url: &str,
+        args: Vec<String>,
+        time_limit: Duration,
+        on_line: &mut (dyn FnMut(&str) + Send),
+    ) -> Result<std::process::Output, ApiError> {
+        self.execute(class, url, args, time_limit, Some(on_line))
+            .await
+    }
+
+    async fn attempts(&self, url: &str, platform: &str) -> Vec<(Rung, Vec<String>)> {
+        let first = self.escalation.first_rung(platform).await;
+        let mut attempts = Vec::new();
+        for rung in RUNGS.into_iter().filter(|rung| *rung >= first) {
+            let extra = match rung {
+                Rung::Plain => Some(Vec::new()),
+                Rung::Impersonate => self.impersonation.auto_args(url),
+                Rung::Proxy => self.escalation.proxy_args(),
+            };
+            if let Some(extra) = extra {
+                attempts.push((rung, extra));
+            }
+        }
+        if attempts.is_empty() {
+            attempts.push((Rung::Plain, Vec::new()));
+        }
+        attempts
+    }
+
+    async fn execute(
+        &self,
+        class: RequestClass,
+        url: &str,
+        args: Vec<String>,
+        time_limit: Duration,
+        mut on_line: Option<&mut (dyn FnMut(&str) + Send)>,
+    ) -> Result<std::process::Output, ApiError> {
+        let platform = throughput::platform_key(url);
+        let attempts = self.attempts(url, &platform).await;
+        let last = attempts.len() - 1;
+        let mut index = 0;
+        let mut source_retried = false;
+        while let Some((rung, extra)) = attempts.get(index).cloned() {
+            let (channel, program) = self.select(class, url).await;
+            let started_at = Instant::now();
+            let mut args = self
+                .with_common_args(url, extra.into_iter().chain(args.iter().cloned()).collect())
+                .await;
+            let source = self.sources.pick(&platform, &args).await;
+            if let Some(source) = source {
+                args.extend(self.sources.args(source));
+            }
+            let result = match on_line.as_deref_mut() {
+                Some(on_line) => run_extractor_streaming(program, args, time_limit, on_line).await,
+                None => run_extractor(program, args, time_limit).await,
+            };
+            self.record(channel, class, started_at, result.is_ok())
+                .await;
+            let blocked = result
+                .as_ref()
+                .is_err_and(|error| escalation::is_blocking_error(&error.message));
+            if let Some(source) = source {
+                let outcome = result
+                    .as_ref()
+                    .map(|_| ())
+                    .map_err(|error| error.message.as_str());
+                self.sources
+                    .record(source, &platform, outcome, blocked)
+                    .await;
+            }
+            if result.is_ok() || blocked {
+                self.escalation
+                    .record(&platform, rung, result.is_ok())
+                    .await;
+            }
+            let retry_source = source.is_some()
+                && self.sources.can_rotate()
+                && result.as_ref().is_err_and(|error| {
+                    blocked || egress::is_unusable_address_error(&error.message)
+                });
+            match result {
+                Err(_) if retry_source && !source_retried => {
+                    warn!("{platform} fallo con la IP de origen elegida; se reintenta con otra.");
+                    source_retried = true;
+                }
+                Err(_) if blocked && index < last => {
+                    warn!("{platform} bloqueo el intento {rung:?}; se escala al siguiente nivel.");
+                    index += 1;
+                }
+                result => return result,
+            }
+        }
+        Err(ApiError::internal(
+            "No quedan intentos de extraccion disponibles.",
+        ))
+    }
+
+    async fn record(
+        &self,
+        channel: ExtractorChannel,
+        class: RequestClass,
+        started_at: Instant,
+        success: bool,
+    ) {
+        let elapsed_ms = started_at.elapsed().as_millis();
+        let mut metrics = self.metrics.lock().await;
+        let entry = metrics.entry((channel, class)).or_default();
+        if success {
+            entry.success += 1;
+        } else {
+            entry.failure += 1;
+        }
+        entry.total_ms += elapsed_ms;
+    }
+
+    pub(crate) async fn report(&self) -> ExtractorReport {
+        let rules = self.rules.lock().await.clone();
+        let channels = self
+            .metrics
+            .lock()
+            .await
+            .iter()
+            .map(|((channel, class), metrics)| {
+                let total = metrics.success + metrics.failure;
+                ChannelReport {
+                    channel: *channel,
+                    class: *class,
+                    success: metrics.success,
+                    failure: metrics.failure,
+                    success_rate: (total > 0).then(|| metrics.success as f64 / total as f64),
+                    avg_ms: (total > 0).then(|| metrics.total_ms / u128::from(total)),
+                }
+            })
+            .collect();
+
+        ExtractorReport {
+            stable: self.stable.clone(),
+            candidate: self.candidate.clone(),
+            rules,
+            channels,
+            impersonation: self.impersonation.report(),
+            escalation: self.escalation.report().await,
+            source_addresses: if self.sources.is_enabled() {
+                Some(self.sources.report().await)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+pub(crate) async fn get_extractor_report(
+    State(state): State<AppState>,
+) -> Result<Json<ExtractorReport>, ApiError> {
+    Ok(Json(state.extractor.report().await))
+}
+
+pub(crate) async fn update_extractor_routing(
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateRoutingRequest>,
+) -> Result<Json<ExtractorReport>, ApiError> {
+    if state.extractor.candidate.is_none() {
+        return Err(ApiError::bad_request(
+            "No hay binario candidato configurado (YT_DLP_CANDIDATE_PATH).",
+        ));
+    }
+
+    {
+        let mut rules = state.extractor.rules.lock().await;
+        if let Some(percent) = payload.percent {
+            rules.percent = percent.min(100);
+        }
+        if let Some(domains) = payload.domains {
+            rules.domains = domains
+                .into_iter()
+                .map(|domain| domain.trim().to_ascii_lowercase())
+                .filter(|domain| !domain.is_empty())
+                .collect();
+        }
+        if let Some(classes) = payload.classes {
+            rules.classes = classes;
+        }
+        info!(
+            "Ruteo de yt-dlp candidato actualizado: {}% dominios={:?} clases={:?}",
+            rules.percent, rules.domains, rules.classes
+        );
+    }
+
+    Ok(Json(state.extractor.report().await))
+}
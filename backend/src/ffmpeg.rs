@@ -0,0 +1,428 @@
+use std::{
+    future::Future,
+    io::ErrorKind,
+    path::Path,
+    process::Stdio,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+    time::{Duration, timeout},
+};
+use tracing::{debug, info, warn};
+
+use crate::{ApiError, read_usize_env};
+
+const DEFAULT_FFMPEG_BINARY: &str = "ffmpeg";
+const DEFAULT_POSTPROCESS_TIMEOUT_SECONDS: usize = 180;
+const DEFAULT_TRANSCODE_TIMEOUT_SECONDS: usize = 30 * 60;
+const MAX_FFMPEG_ERROR_LINES: usize = 20;
+const PROBE_TIMEOUT_SECONDS: u64 = 30;
+// Inputs are always local files; playlists inside them must not reach the network.
+const INPUT_PROTOCOLS: &str = "file,pipe";
+const DEFAULT_VAAPI_DEVICE: &str = "/dev/dri/renderD128";
+const DEFAULT_HWACCEL_BITRATE: &str = "4M";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HwAccel {
+    V4l2m2m,
+    VideoToolbox,
+    Vaapi,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HwEncoder {
+    accel: HwAccel,
+    device: String,
+    bitrate: String,
+}
+
+tokio::task_local! {
+    static CPU_MILLIS: Arc<AtomicU64>;
+}
+
+// Runs `future` and returns the CPU time spent by every ffmpeg process it launched.
+pub(crate) async fn metered<F: Future>(future: F) -> (F::Output, f64) {
+    let meter = Arc::new(AtomicU64::new(0));
+    let output = CPU_MILLIS.scope(Arc::clone(&meter), future).await;
+    (output, meter.load(Ordering::Relaxed) as f64 / 1000.0)
+}
+
+#[cfg(target_os = "linux")]
+fn process_cpu_millis(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The command name may contain spaces; utime and stime are fields 14 and 15.
+    let mut fields = stat
+        .get(stat.rfind(')')? + 1..)?
+        .split_whitespace()
+        .skip(11);
+    let ticks = fields.next()?.parse::<u64>().ok()? + fields.next()?.parse::<u64>().ok()?;
+    // SAFETY: sysconf only reads a system constant.
+    let per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    (per_second > 0).then(|| ticks * 1000 / per_second as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_cpu_millis(_pid: u32) -> Option<u64> {
+    None
+}
+
+pub(crate) fn ffmpeg_binary() -> String {
+    crate::binary_path_env("FFMPEG_PATH").unwrap_or_else(|| DEFAULT_FFMPEG_BINARY.to_string())
+}
+
+impl HwAccel {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "v4l2m2m" | "v4l2" => Some(Self::V4l2m2m),
+            "videotoolbox" => Some(Self::VideoToolbox),
+            "vaapi" => Some(Self::Vaapi),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn encoder_name(self) -> &'static str {
+        match self {
+            Self::V4l2m2m => "h264_v4l2m2m",
+            Self::VideoToolbox => "h264_videotoolbox",
+            Self::Vaapi => "h264_vaapi",
+        }
+    }
+}
+
+impl HwEncoder {
+    fn from_env() -> Option<Self> {
+        let value = std::env::var("FFMPEG_HWACCEL").ok()?;
+        if matches!(
+            value.trim().to_ascii_lowercase().as_str(),
+            "" | "none" | "off"
+        ) {
+            return None;
+        }
+        let Some(accel) = HwAccel::parse(&value) else {
+            warn!(
+                "FFMPEG_HWACCEL={value} no es valido (v4l2m2m, videotoolbox o vaapi); se codifica por software."
+            );
+            return None;
+        };
+        let read = |name: &str, default: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| crate::non_empty(&value).map(ToString::to_string))
+                .unwrap_or_else(|| default.to_string())
+        };
+        let encoder = Self {
+            accel,
+            device: read("FFMPEG_HWACCEL_DEVICE", DEFAULT_VAAPI_DEVICE),
+            bitrate: read("FFMPEG_HWACCEL_BITRATE", DEFAULT_HWACCEL_BITRATE),
+        };
+        info!(
+            "Recodificacion por hardware con {}; si falla se reintenta por software.",
+            accel.encoder_name()
+        );
+        Some(encoder)
+    }
+
+    pub(crate) fn accel(&self) -> HwAccel {
+        self.accel
+    }
+
+    // Options that must precede `-i`.
+    pub(crate) fn input_args(&self) -> Vec<String> {
+        let args: Vec<&str> = match self.accel {
+            HwAccel::V4l2m2m => Vec::new(),
+            HwAccel::VideoToolbox => vec!["-hwaccel", "videotoolbox"],
+            HwAccel::Vaapi => vec!["-vaapi_device", &self.device],
+        };
+        args.into_iter().map(ToString::to_string).collect()
+    }
+
+    // H.264 in yuv420p like the software path; these encoders take a bitrate or qp instead of crf.
+    pub(crate) fn video_args(&self) -> Vec<String> {
+        let args: Vec<&str> = match self.accel {
+            HwAccel::V4l2m2m | HwAccel::VideoToolbox => vec![
+                "-c:v",
+                self.accel.encoder_name(),
+                "-b:v",
+                &self.bitrate,
+                "-pix_fmt",
+                "yuv420p",
+            ],
+            HwAccel::Vaapi => vec![
+                "-vf",
+                "format=nv12,hwupload",
+                "-c:v",
+                self.accel.encoder_name(),
+                "-qp",
+                "23",
+            ],
+        };
+        args.into_iter().map(ToString::to_string).collect()
+    }
+}
+
+pub(crate) fn hw_encoder() -> Option<&'static HwEncoder> {
+    static ENCODER: OnceLock<Option<HwEncoder>> = OnceLock::new();
+    ENCODER.get_or_init(HwEncoder::from_env).as_ref()
+}
+
+pub(crate) fn postprocess_timeout() -> Duration {
+    timeout_from_env(
+        "FFMPEG_TIMEOUT_SECONDS",
+        DEFAULT_POSTPROCESS_TIMEOUT_SECONDS,
+    )
+}
+
+pub(crate) fn transcode_timeout() -> Duration {
+    timeout_from_env(
+        "FFMPEG_TRANSCODE_TIMEOUT_SECONDS",
+        DEFAULT_TRANSCODE_TIMEOUT_SECONDS,
+    )
+}
+
+fn timeout_from_env(name: &str, default: usize) -> Duration {
+    let seconds = read_usize_env(name)
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(default);
+    Duration::from_secs(seconds as u64)
+}
+
+fn parse_ffmpeg_duration_us(line: &str) -> Option<u64> {
+    let rest = line.trim().strip_prefix("Duration:")?;
+    let timestamp = rest.split(',').next()?.trim();
+    let mut parts = timestamp.split(':');
+    let hours = parts.next()?.parse::<f64>().ok()?;
+    let minutes = parts.next()?.parse::<f64>().ok()?;
+    let seconds = parts.next()?.parse::<f64>().ok()?;
+    let total = (hours * 3600.0 + minutes * 60.0 + seconds) * 1_000_000.0;
+    (total > 0.0).then_some(total as u64)
+}
+
+// First video and audio stream as ffmpeg describes them; equal layouts can be concatenated without re-encoding.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct MediaProbe {
+    pub(crate) format: Option<String>,
+    pub(crate) duration_seconds: Option<f64>,
+    pub(crate) video: Option<String>,
+    pub(crate) audio: Option<String>,
+    pub(crate) dimensions: Option<(u32, u32)>,
+}
+
+fn stream_signature(description: &str, video: bool) -> String {
+    let fields = description.split(',').map(str::trim).collect::<Vec<_>>();
+    let codec = fields
+        .first()
+        .and_then(|field| field.split_whitespace().next())
+        .unwrap_or_default();
+    let details = fields.iter().filter(|field| {
+        if video {
+            field.split_whitespace().next().is_some_and(|size| {
+                size.split_once('x').is_some_and(|(width, height)| {
+                    width.parse::<u32>().is_ok() && height.parse::<u32>().is_ok()
+                })
+            }) || field.ends_with(" fps")
+        } else {
+            field.ends_with(" Hz") || matches!(**field, "mono" | "stereo" | "5.1" | "5.1(side)")
+        }
+    });
+    std::iter::once(codec)
+        .chain(details.map(|field| field.split(" [").next().unwrap_or(field)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parse_probe(stderr: &str) -> MediaProbe {
+    let mut probe = MediaProbe::default();
+    for line in stderr.lines() {
+        if probe.format.is_none()
+            && let Some(rest) = line.trim().strip_prefix("Input #0, ")
+        {
+            probe.format = rest
+                .split_once(", from ")
+                .map(|(format, _)| format.to_string());
+        }
+        if let Some(duration) = parse_ffmpeg_duration_us(line) {
+            probe
+                .duration_seconds
+                .get_or_insert(duration as f64 / 1_000_000.0);
+        }
+        let Some((_, description)) = line.trim().split_once("Stream #") else {
+            continue;
+        };
+        if let Some((_, video)) = description.split_once(": Video: ")
+            && probe.video.is_none()
+        {
+            let signature = stream_signature(video, true);
+            probe.dimensions = signature.split_whitespace().find_map(|field| {
+                let (width, height) = field.split_once('x')?;
+                Some((width.parse().ok()?, height.parse().ok()?))
+            });
+            probe.video = Some(signature);
+        } else if let Some((_, audio)) = description.split_once(": Audio: ")
+            && probe.audio.is_none()
+        {
+            probe.audio = Some(stream_signature(audio, false));
+        }
+    }
+    probe
+}
+
+pub(crate) async fn probe(input: &Path) -> Result<MediaProbe, ApiError> {
+    let mut command = Command::new(ffmpeg_binary());
+    command
+        .arg("-hide_banner")
+        .arg("-nostdin")
+        .args(["-protocol_whitelist", INPUT_PROTOCOLS])
+        .arg("-i")
+        .arg(input)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    crate::process_group::isolate(&mut command);
+    let output = timeout(Duration::from_secs(PROBE_TIMEOUT_SECONDS), command.output())
+        .await
+        .map_err(|_| ApiError::internal("ffmpeg no pudo analizar el archivo a tiempo."))?
+        .map_err(|error| {
+            if error.kind() == ErrorKind::NotFound {
+                ApiError::internal(
+                    "ffmpeg no esta instalado en el sistema. Instala ffmpeg y reinicia el backend.",
+                )
+            } else {
+                ApiError::internal(format!("No se pudo ejecutar ffmpeg: {error}"))
+            }
+        })?;
+    // Without an output ffmpeg always exits with an error; the stream listing is all we need.
+    Ok(parse_probe(&String::from_utf8_lossy(&output.stderr)))
+}
+
+pub(crate) async fn run(
+    input: &Path,
+    output: &Path,
+    args: Vec<String>,
+    time_limit: Duration,
+    on_progress: &mut (dyn FnMut(f64) + Send),
+) -> Result<(), ApiError> {
+    run_with_input_args(input, output, &[], args, time_limit, on_progress).await
+}
+
+pub(crate) async fn run_with_input_args(
+    input: &Path,
+    output: &Path,
+    input_args: &[String],
+    args: Vec<String>,
+    time_limit: Duration,
+    on_progress: &mut (dyn FnMut(f64) + Send),
+) -> Result<(), ApiError> {
+    let mut command = Command::new(ffmpeg_binary());
+    command
+        .arg("-hide_banner")
+        .arg("-nostdin")
+        .arg("-y")
+        .args(input_args)
+        .args(["-protocol_whitelist", INPUT_PROTOCOLS])
+        .arg("-i")
+        .arg(input)
+        .args(args)
+        .args(crate::lowmem::ffmpeg_args())
+        .args(["-progress", "pipe:1", "-nostats"])
+        .arg(output)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    crate::process_group::isolate(&mut command);
+    crate::priority::child_priority().apply(&mut command);
+    let mut child = command.spawn().map_err(|error| {
+        if error.kind() == ErrorKind::NotFound {
+            ApiError::internal(
+                "ffmpeg no esta instalado en el sistema. Instala ffmpeg y reinicia el backend.",
+            )
+        } else {
+            ApiError::internal(format!("No se pudo ejecutar ffmpeg: {error}"))
+        }
+    })?;
+    let mut group = crate::process_group::ProcessGroup::track(&child);
+    let pid = child.id();
+    let mut cpu_millis = 0;
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| ApiError::internal("No se pudo leer el progreso de ffmpeg."))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| ApiError::internal("No se pudo leer la salida de ffmpeg."))?;
+
+    let duration_us = Arc::new(AtomicU64::new(0));
+    let stderr_task = tokio::spawn({
+        let duration_us = Arc::clone(&duration_us);
+        async move {
+            let mut lines = BufReader::new(stderr).lines();
+            let mut tail = Vec::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                // Multi-input runs (covers, concat) print one duration per input.
+                if let Some(duration) = parse_ffmpeg_duration_us(&line) {
+                    duration_us.fetch_add(duration, Ordering::Relaxed);
+                }
+                tail.push(line);
+                if tail.len() > MAX_FFMPEG_ERROR_LINES {
+                    tail.remove(0);
+                }
+            }
+            tail
+        }
+    });
+
+    let execution = async {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let total = duration_us.load(Ordering::Relaxed);
+            if key == "progress"
+                && let Some(millis) = pid.and_then(process_cpu_millis)
+            {
+                cpu_millis = millis;
+            }
+            match key {
+                "out_time_us" | "out_time_ms" if total > 0 => {
+                    if let Ok(elapsed) = value.trim().parse::<u64>() {
+                        on_progress((elapsed as f64 / total as f64).clamp(0.0, 1.0));
+                    }
+                }
+                "progress" if value == "end" => on_progress(1.0),
+                _ => {}
+            }
+        }
+        child.wait().await
+    };
+
+    let status = timeout(time_limit, execution)
+        .await
+        .map_err(|_| ApiError::bad_request("El post-procesado excedio el tiempo limite."))?
+        .map_err(|error| ApiError::internal(format!("No se pudo ejecutar ffmpeg: {error}")))?;
+    group.disarm();
+    let tail = stderr_task.await.unwrap_or_default();
+    let _ = CPU_MILLIS.try_with(|meter| meter.fetch_add(cpu_millis, Ordering::Relaxed));
+
+    if !status.success() {
+        debug!("ffmpeg fallo procesando {:?}: {}", input, tail.join("\n"));
+        let _ = tokio::fs::remove_file(output).await;
+        return Err(ApiError::internal(format!(
+            "No se pudo post-procesar el archivo: {}",
+            tail.iter()
+                .map(|line| line.trim())
+                .rfind(|line| !line.is_empty())
+                .unwrap_or("ffmpeg termino con error")
+        )));
+    }
+
+    Ok(())
+}
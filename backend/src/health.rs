@@ -0,0 +1,196 @@
+use std::{collections::BTreeMap, path::Path, process::Stdio};
+
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::{
+    process::Command,
+    sync::Mutex,
+    time::{Duration, Instant, timeout},
+};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{AppState, ffmpeg::ffmpeg_binary};
+
+const CHECK_TIMEOUT_SECONDS: u64 = 10;
+const READINESS_CACHE_SECONDS: u64 = 15;
+const TURNSTILE_URL: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CheckResult {
+    status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ReadinessReport {
+    status: &'static str,
+    checked_at: DateTime<Utc>,
+    checks: BTreeMap<&'static str, CheckResult>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ReadinessCache {
+    last: Mutex<Option<(Instant, ReadinessReport)>>,
+}
+
+impl CheckResult {
+    fn ok(version: Option<String>, detail: Option<String>) -> Self {
+        Self {
+            status: CheckStatus::Ok,
+            version,
+            detail,
+        }
+    }
+
+    fn failed(detail: impl Into<String>) -> Self {
+        Self {
+            status: CheckStatus::Failed,
+            version: None,
+            detail: Some(detail.into()),
+        }
+    }
+
+    fn skipped(detail: &str) -> Self {
+        Self {
+            status: CheckStatus::Skipped,
+            version: None,
+            detail: Some(detail.to_string()),
+        }
+    }
+}
+
+async fn check_binary(program: &str, version_arg: &str) -> CheckResult {
+    let mut command = Command::new(program);
+    command
+        .arg(version_arg)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    let output = match timeout(Duration::from_secs(CHECK_TIMEOUT_SECONDS), command.output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(error)) => return CheckResult::failed(format!("{program}: {error}")),
+        Err(_) => {
+            return CheckResult::failed(format!(
+                "{program} no respondio en {CHECK_TIMEOUT_SECONDS} s"
+            ));
+        }
+    };
+    if !output.status.success() {
+        return CheckResult::failed(format!("{program} termino con {}", output.status));
+    }
+    let version = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty());
+    CheckResult::ok(version, Some(program.to_string()))
+}
+
+async fn check_writable(path: &Path) -> CheckResult {
+    let probe = path.join(format!(".health-{}", Uuid::new_v4().simple()));
+    match tokio::fs::write(&probe, b"ok").await {
+        Ok(()) => {
+            let _ = tokio::fs::remove_file(&probe).await;
+            CheckResult::ok(None, Some(path.display().to_string()))
+        }
+        Err(error) => CheckResult::failed(format!("{}: {error}", path.display())),
+    }
+}
+
+// Any HTTP answer means the endpoint is reachable; only network errors count as failures.
+async fn check_turnstile(state: &AppState) -> CheckResult {
+    if state.turnstile_secret_key.is_none() {
+        return CheckResult::skipped("TURNSTILE_SECRET_KEY no configurado");
+    }
+    match timeout(
+        Duration::from_secs(CHECK_TIMEOUT_SECONDS),
+        state.http_client.head(TURNSTILE_URL).send(),
+    )
+    .await
+    {
+        Ok(Ok(response)) => CheckResult::ok(None, Some(format!("HTTP {}", response.status()))),
+        Ok(Err(error)) => CheckResult::failed(format!("Turnstile inalcanzable: {error}")),
+        Err(_) => CheckResult::failed(format!(
+            "Turnstile no respondio en {CHECK_TIMEOUT_SECONDS} s"
+        )),
+    }
+}
+
+async fn run_checks(state: &AppState) -> ReadinessReport {
+    let directories = state.system.directories();
+    let ffmpeg_program = ffmpeg_binary();
+    let (yt_dlp, ffmpeg, turnstile, writable) = tokio::join!(
+        check_binary(state.extractor.stable(), "--version"),
+        check_binary(&ffmpeg_program, "-version"),
+        check_turnstile(state),
+        futures_util::future::join_all(directories.iter().map(|(_, path)| check_writable(path))),
+    );
+
+    let mut checks = BTreeMap::new();
+    checks.insert("yt_dlp", yt_dlp);
+    checks.insert("ffmpeg", ffmpeg);
+    checks.insert("turnstile", turnstile);
+    for ((name, _), result) in directories.iter().zip(writable) {
+        checks.insert(*name, result);
+    }
+
+    let degraded = checks
+        .values()
+        .any(|check| check.status == CheckStatus::Failed);
+    if degraded {
+        let failed = checks
+            .iter()
+            .filter(|(_, check)| check.status == CheckStatus::Failed)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        warn!("Comprobacion de disponibilidad degradada: {failed:?}");
+    }
+    ReadinessReport {
+        status: if degraded { "degraded" } else { "ok" },
+        checked_at: Utc::now(),
+        checks,
+    }
+}
+
+pub(crate) async fn readiness_report(state: &AppState) -> ReadinessReport {
+    let mut last = state.readiness.last.lock().await;
+    match last.as_ref() {
+        Some((at, report)) if at.elapsed() < Duration::from_secs(READINESS_CACHE_SECONDS) => {
+            report.clone()
+        }
+        _ => {
+            let report = run_checks(state).await;
+            *last = Some((Instant::now(), report.clone()));
+            report
+        }
+    }
+}
+
+pub(crate) async fn get_readiness(State(state): State<AppState>) -> Response {
+    let report = readiness_report(&state).await;
+    let status = if report.status == "ok" {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report)).into_response()
+}
@@ -0,0 +1,163 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{ApiError, AppState, DownloadMode, DownloadStatus, HistoryEntry, url_domain};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct HistoryFilter {
+    ip: Option<String>,
+    domain: Option<String>,
+    status: Option<DownloadStatus>,
+    mode: Option<DownloadMode>,
+    job_id: Option<Uuid>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AdminHistoryQuery {
+    #[serde(flatten)]
+    filter: HistoryFilter,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PurgeHistoryQuery {
+    #[serde(flatten)]
+    filter: HistoryFilter,
+    #[serde(default)]
+    all: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct AdminHistoryEntry {
+    // HistoryEntry never serializes the IP; admins need it to trace abuse.
+    requester_ip: String,
+    #[serde(flatten)]
+    entry: HistoryEntry,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct AdminHistoryPage {
+    total: usize,
+    matched: usize,
+    offset: usize,
+    limit: usize,
+    entries: Vec<AdminHistoryEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PurgeHistoryResult {
+    removed: usize,
+    remaining: usize,
+}
+
+impl HistoryFilter {
+    fn is_empty(&self) -> bool {
+        self.ip.is_none()
+            && self.domain.is_none()
+            && self.status.is_none()
+            && self.mode.is_none()
+            && self.job_id.is_none()
+            && self.since.is_none()
+            && self.until.is_none()
+    }
+
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        let domain = self.domain.as_deref().map(|domain| {
+            domain
+                .trim()
+                .trim_start_matches("www.")
+                .to_ascii_lowercase()
+        });
+        self.ip
+            .as_deref()
+            .is_none_or(|ip| entry.requester_ip == ip.trim())
+            && domain.is_none_or(|domain| {
+                let host = url_domain(&entry.url);
+                host == domain || host.ends_with(&format!(".{domain}"))
+            })
+            && self
+                .status
+                .as_ref()
+                .is_none_or(|status| entry.status == *status)
+            && self.mode.as_ref().is_none_or(|mode| entry.mode == *mode)
+            && self
+                .job_id
+                .is_none_or(|job_id| entry.job_id == Some(job_id))
+            && self.since.is_none_or(|since| entry.created_at >= since)
+            && self.until.is_none_or(|until| entry.created_at < until)
+    }
+}
+
+pub(crate) async fn list_history(
+    State(state): State<AppState>,
+    Query(query): Query<AdminHistoryQuery>,
+) -> Json<AdminHistoryPage> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0);
+    let history = state.history.lock().await;
+    let matching = history
+        .iter()
+        .filter(|entry| query.filter.matches(entry))
+        .collect::<Vec<_>>();
+    Json(AdminHistoryPage {
+        total: history.len(),
+        matched: matching.len(),
+        offset,
+        limit,
+        entries: matching
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|entry| AdminHistoryEntry {
+                requester_ip: entry.requester_ip.clone(),
+                entry: entry.clone(),
+            })
+            .collect(),
+    })
+}
+
+pub(crate) async fn purge_history(
+    State(state): State<AppState>,
+    Query(query): Query<PurgeHistoryQuery>,
+) -> Result<Json<PurgeHistoryResult>, ApiError> {
+    if query.filter.is_empty() && !query.all {
+        return Err(ApiError::bad_request(
+            "Indica al menos un filtro (ip, domain, status, mode, job_id, since, until) o all=true para vaciar todo el historial.",
+        ));
+    }
+
+    let mut history = state.history.lock().await;
+    let removed = history
+        .iter()
+        .filter(|entry| query.filter.matches(entry))
+        .map(|entry| entry.id)
+        .collect::<Vec<_>>();
+    let count = removed.len();
+    if count > 0 {
+        history.retain(|entry| !query.filter.matches(entry));
+        state.storage.history_removed(removed, &history).await?;
+    }
+    info!(
+        "Historial purgado por un administrador: {count} entrada(s) eliminada(s), {} restantes.",
+        history.len()
+    );
+    Ok(Json(PurgeHistoryResult {
+        removed: count,
+        remaining: history.len(),
+    }))
+}
@@ -0,0 +1,149 @@
+use std::{
+    process::Stdio,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde::Serialize;
+use tokio::{
+    process::Command,
+    time::{Duration, timeout},
+};
+use tracing::{info, warn};
+
+use crate::{read_list_env_raw, url_domain};
+
+const DEFAULT_AUTO_TARGET: &str = "chrome";
+const DETECT_TIMEOUT_SECONDS: u64 = 15;
+
+#[derive(Debug)]
+pub(crate) struct Impersonation {
+    targets: Vec<(String, String)>,
+    auto_target: Option<String>,
+    available: AtomicBool,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ImpersonationReport {
+    available: bool,
+    targets: Vec<String>,
+    auto_target: Option<String>,
+}
+
+fn valid_target(target: &str) -> bool {
+    !target.is_empty()
+        && !target.starts_with('-')
+        && target.chars().all(|character| {
+            character.is_ascii_alphanumeric() || matches!(character, '-' | '_' | '.' | ':')
+        })
+}
+
+impl Impersonation {
+    pub(crate) fn from_env() -> Self {
+        let mut targets = Vec::new();
+        for raw in read_list_env_raw("IMPERSONATE_TARGETS") {
+            match raw.split_once('=') {
+                Some((domain, target)) if valid_target(target.trim()) => targets.push((
+                    domain
+                        .trim()
+                        .trim_start_matches("www.")
+                        .to_ascii_lowercase(),
+                    target.trim().to_string(),
+                )),
+                _ => warn!("Entrada invalida en IMPERSONATE_TARGETS: {raw:?}"),
+            }
+        }
+        let auto_target = match std::env::var("IMPERSONATE_AUTO_TARGET") {
+            Ok(value) if value.trim().is_empty() => None,
+            Ok(value) if valid_target(value.trim()) => Some(value.trim().to_string()),
+            Ok(value) => {
+                warn!(
+                    "IMPERSONATE_AUTO_TARGET invalido: {value:?}. Se desactiva el modo automatico."
+                );
+                None
+            }
+            Err(_) => Some(DEFAULT_AUTO_TARGET.to_string()),
+        };
+
+        Self {
+            targets,
+            auto_target,
+            available: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) async fn detect(&self, program: &str) {
+        let mut command = Command::new(program);
+        command
+            .arg("--list-impersonate-targets")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        crate::process_group::isolate(&mut command);
+        let output = match command.spawn() {
+            Ok(child) => {
+                timeout(
+                    Duration::from_secs(DETECT_TIMEOUT_SECONDS),
+                    child.wait_with_output(),
+                )
+                .await
+            }
+            Err(error) => {
+                warn!("No se pudo comprobar la suplantacion de navegador de yt-dlp: {error}");
+                return;
+            }
+        };
+        let available = match output {
+            Ok(Ok(output)) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(str::trim)
+                .any(|line| line.ends_with("curl_cffi") && !line.contains("unavailable")),
+            _ => false,
+        };
+        self.available.store(available, Ordering::Relaxed);
+        if available {
+            info!("yt-dlp admite --impersonate (curl_cffi disponible).");
+        } else if !self.targets.is_empty() {
+            warn!(
+                "IMPERSONATE_TARGETS configurado pero yt-dlp no tiene curl_cffi; se ignora --impersonate."
+            );
+        }
+    }
+
+    fn configured_target(&self, url: &str) -> Option<&str> {
+        let domain = url_domain(url);
+        self.targets
+            .iter()
+            .find(|(rule, _)| domain == *rule || domain.ends_with(&format!(".{rule}")))
+            .map(|(_, target)| target.as_str())
+    }
+
+    pub(crate) fn args_for(&self, url: &str) -> Vec<String> {
+        match self.configured_target(url) {
+            Some(target) if self.available.load(Ordering::Relaxed) => {
+                vec!["--impersonate".to_string(), target.to_string()]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub(crate) fn auto_args(&self, url: &str) -> Option<Vec<String>> {
+        if !self.available.load(Ordering::Relaxed) || self.configured_target(url).is_some() {
+            return None;
+        }
+        self.auto_target
+            .as_ref()
+            .map(|target| vec!["--impersonate".to_string(), target.clone()])
+    }
+
+    pub(crate) fn report(&self) -> ImpersonationReport {
+        ImpersonationReport {
+            available: self.available.load(Ordering::Relaxed),
+            targets: self
+                .targets
+                .iter()
+                .map(|(domain, target)| format!("{domain}={target}"))
+                .collect(),
+            auto_target: self.auto_target.clone(),
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::{collections::VecDeque, net::SocketAddr, sync::Mutex};
+
+use axum::{
+    extract::{ConnectInfo, Path as RoutePath, State},
+    http::{HeaderMap, HeaderValue, Method, Uri},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use chrono::{DateTime, Utc};
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, watch};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{ApiError, AppState, client_ip_for_request, compat, jobs::JobSnapshot, postprocess};
+
+const MAX_LOG_LINES: usize = 200;
+const MAX_LOG_BYTES: usize = 64 * 1024;
+const MAX_LINE_CHARS: usize = 500;
+const LOG_BROADCAST_BUFFER: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JobLogLine {
+    pub(crate) seq: u64,
+    pub(crate) at: DateTime<Utc>,
+    pub(crate) line: String,
+}
+
+#[derive(Debug, Default)]
+struct LogBuffer {
+    lines: VecDeque<JobLogLine>,
+    bytes: usize,
+    next_seq: u64,
+}
+
+#[derive(Debug)]
+pub(crate) struct JobLog {
+    buffer: Mutex<LogBuffer>,
+    sender: broadcast::Sender<JobLogLine>,
+}
+
+pub(crate) struct LogFollower {
+    backlog: VecDeque<JobLogLine>,
+    lines: broadcast::Receiver<JobLogLine>,
+    job: watch::Receiver<JobSnapshot>,
+    last_seq: u64,
+}
+
+fn sanitize_token(token: &str) -> String {
+    let trimmed = token.trim_matches(|character| matches!(character, '"' | '\'' | ',' | ';'));
+    if trimmed.is_empty() {
+        return token.to_string();
+    }
+    let replacement = if trimmed.contains("://") {
+        match Url::parse(trimmed) {
+            Ok(mut url) => {
+                let _ = url.set_username("");
+                let _ = url.set_password(None);
+                url.set_query(None);
+                url.set_fragment(None);
+                url.to_string()
+            }
+            Err(_) => "[url]".to_string(),
+        }
+    } else if trimmed.len() > 1 && trimmed.starts_with('/') {
+        trimmed
+            .rsplit('/')
+            .find(|segment| !segment.is_empty())
+            .unwrap_or_default()
+            .to_string()
+    } else {
+        return token.to_string();
+    };
+    token.replacen(trimmed, &replacement, 1)
+}
+
+pub(crate) fn sanitize_line(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.is_empty() || postprocess::is_progress_line(line) || compat::is_codecs_line(line) {
+        return None;
+    }
+    let cleaned: String = line
+        .chars()
+        .map(|character| {
+            if character.is_control() {
+                ' '
+            } else {
+                character
+            }
+        })
+        .collect();
+    let sanitized = cleaned
+        .split(' ')
+        .map(sanitize_token)
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(sanitized.chars().take(MAX_LINE_CHARS).collect())
+}
+
+impl Default for JobLog {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(LOG_BROADCAST_BUFFER);
+        Self {
+            buffer: Mutex::default(),
+            sender,
+        }
+    }
+}
+
+impl JobLog {
+    pub(crate) fn push(&self, raw: &str) {
+        let Some(line) = sanitize_line(raw) else {
+            return;
+        };
+        let Ok(mut buffer) = self.buffer.lock() else {
+            return;
+        };
+        buffer.next_seq += 1;
+        let entry = JobLogLine {
+            seq: buffer.next_seq,
+            at: Utc::now(),
+            line,
+        };
+        buffer.bytes += entry.line.len();
+        buffer.lines.push_back(entry.clone());
+        while buffer.lines.len() > MAX_LOG_LINES || buffer.bytes > MAX_LOG_BYTES {
+            let Some(dropped) = buffer.lines.pop_front() else {
+                break;
+            };
+            buffer.bytes -= dropped.line.len();
+        }
+        let _ = self.sender.send(entry);
+    }
+
+    pub(crate) fn bytes(&self) -> usize {
+        self.buffer
+            .lock()
+            .map(|buffer| buffer.bytes + buffer.lines.len() * size_of::<JobLogLine>())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<JobLogLine> {
+        self.sender.subscribe()
+    }
+
+    pub(crate) fn follow(&self, job: watch::Receiver<JobSnapshot>, after: u64) -> LogFollower {
+        let lines = self.sender.subscribe();
+        let backlog = self
+            .buffer
+            .lock()
+            .map(|buffer| {
+                buffer
+                    .lines
+                    .iter()
+                    .filter(|line| line.seq > after)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        LogFollower {
+            backlog,
+            lines,
+            job,
+            last_seq: after,
+        }
+    }
+}
+
+impl LogFollower {
+    fn take(&mut self, line: JobLogLine) -> Option<JobLogLine> {
+        (line.seq > self.last_seq).then(|| {
+            self.last_seq = line.seq;
+            line
+        })
+    }
+
+    pub(crate) async fn next(&mut self) -> Option<JobLogLine> {
+        loop {
+            if let Some(line) = self.backlog.pop_front() {
+                if let Some(line) = self.take(line) {
+                    return Some(line);
+                }
+                continue;
+            }
+            if self.job.borrow_and_update().state().is_terminal() {
+                while let Ok(line) = self.lines.try_recv() {
+                    if let Some(line) = self.take(line) {
+                        return Some(line);
+                    }
+                }
+                return None;
+            }
+            tokio::select! {
+                line = self.lines.recv() => match line {
+                    Ok(line) => {
+                        if let Some(line) = self.take(line) {
+                            return Some(line);
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+                changed = self.job.changed() => {
+                    if changed.is_err() {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub(crate) async fn get_job_logs(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    RoutePath(job_id): RoutePath<Uuid>,
+    uri: Uri,
+) -> Result<Response, ApiError> {
+    let client_ip = client_ip_for_request(&state, &headers, addr);
+    let after = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or_default();
+    let Some(follower) = state.jobs.follow_logs(job_id, &client_ip, after).await else {
+        if let Some(registry) = &state.registry
+            && let Some(location) = registry.locate_job(job_id, &headers).await
+        {
+            return registry
+                .forward(&location, Method::GET, &uri, &headers, &client_ip)
+                .await;
+        }
+        return Err(ApiError::not_found(
+            "No existe una descarga con ese identificador.",
+        ));
+    };
+
+    let events = stream::unfold(follower, |mut follower| async move {
+        let line = follower.next().await?;
+        let event = Event::default()
+            .event("log")
+            .id(line.seq.to_string())
+            .json_data(&line);
+        Some((event, follower))
+    });
+
+    let mut response = Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response();
+    response
+        .headers_mut()
+        .insert("x-accel-buffering", HeaderValue::from_static("no"));
+    Ok(response)
+}
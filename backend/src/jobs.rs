@@ -0,0 +1,721 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, Path as RoutePath, Query, State},
+    http::{HeaderMap, HeaderValue, Method, Uri},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use chrono::{DateTime, Utc};
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{Mutex, broadcast, watch},
+    time::{Duration, timeout},
+};
+use uuid::Uuid;
+
+use crate::compat::CodecDecision;
+use crate::egress::IpFamily;
+use crate::joblog::{JobLog, JobLogLine, LogFollower};
+use crate::memory::{MemoryBudget, TrackedMap};
+use crate::{
+    ApiError, AppState, DOWNLOAD_JOB_RETENTION_SECONDS, JOB_POLL_RETRY_SECONDS,
+    client_ip_for_request, serve_artifact,
+};
+
+const MAX_LONG_POLL_SECONDS: u64 = 60;
+const JOB_CREATED_BUFFER: usize = 64;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum JobState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum JobPhase {
+    Extraction,
+    Download,
+    Merge,
+    Convert,
+    Transcode,
+}
+
+pub(crate) type PhasePlan = &'static [(JobPhase, f64)];
+
+pub(crate) const VIDEO_PHASES: PhasePlan = &[
+    (JobPhase::Extraction, 0.05),
+    (JobPhase::Download, 0.85),
+    (JobPhase::Merge, 0.10),
+];
+pub(crate) const TRANSCODE_VIDEO_PHASES: PhasePlan = &[
+    (JobPhase::Extraction, 0.05),
+    (JobPhase::Download, 0.45),
+    (JobPhase::Merge, 0.05),
+    (JobPhase::Transcode, 0.45),
+];
+pub(crate) const AUDIO_PHASES: PhasePlan = &[
+    (JobPhase::Extraction, 0.05),
+    (JobPhase::Download, 0.60),
+    (JobPhase::Convert, 0.35),
+];
+// Uploads report the transfer from the client as the download phase.
+pub(crate) const UPLOAD_CONVERT_PHASES: PhasePlan =
+    &[(JobPhase::Download, 0.60), (JobPhase::Convert, 0.40)];
+pub(crate) const UPLOAD_TRANSCODE_PHASES: PhasePlan =
+    &[(JobPhase::Download, 0.30), (JobPhase::Transcode, 0.70)];
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub(crate) struct TransferRate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) speed_bytes_per_second: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) eta_seconds: Option<u64>,
+}
+
+impl JobState {
+    pub(crate) fn is_terminal(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct JobSnapshot {
+    job_id: Uuid,
+    state: JobState,
+    version: u64,
+    updated_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phase: Option<JobPhase>,
+    progress: f64,
+    #[serde(flatten)]
+    transfer: TransferRate,
+    #[serde(skip)]
+    plan: PhasePlan,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    receipt_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codecs: Option<CodecDecision>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip_family: Option<IpFamily>,
+    #[serde(skip)]
+    artifact_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug)]
+struct JobRecord {
+    owner_ip: String,
+    sender: Arc<watch::Sender<JobSnapshot>>,
+    cancel: Arc<watch::Sender<bool>>,
+    logs: Arc<JobLog>,
+}
+
+#[derive(Debug)]
+pub(crate) struct JobRegistry {
+    jobs: Mutex<HashMap<Uuid, JobRecord>>,
+    created: broadcast::Sender<(Uuid, String)>,
+    memory: Arc<MemoryBudget>,
+}
+
+#[derive(Debug)]
+pub(crate) struct JobHandle {
+    job_id: Uuid,
+    owner: String,
+    registry: Arc<JobRegistry>,
+    sender: Arc<watch::Sender<JobSnapshot>>,
+    cancel: Arc<watch::Sender<bool>>,
+    logs: Arc<JobLog>,
+    priority: Arc<AtomicBool>,
+}
+
+#[derive(Debug)]
+pub(crate) struct AbandonGuard<'a> {
+    job: &'a JobHandle,
+    armed: bool,
+}
+
+impl AbandonGuard<'_> {
+    pub(crate) fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for AbandonGuard<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            self.job.update(
+                JobState::Cancelled,
+                None,
+                Some("El cliente cerro la conexion antes de terminar la descarga.".to_string()),
+            );
+            self.job.cancel.send_replace(true);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct JobStatusQuery {
+    wait: Option<u64>,
+    since: Option<u64>,
+}
+
+impl JobSnapshot {
+    pub(crate) fn state(&self) -> JobState {
+        self.state
+    }
+
+    pub(crate) fn phase(&self) -> Option<JobPhase> {
+        self.phase
+    }
+
+    pub(crate) fn progress(&self) -> f64 {
+        self.progress
+    }
+
+    pub(crate) fn transfer(&self) -> TransferRate {
+        self.transfer
+    }
+}
+
+impl JobRegistry {
+    pub(crate) fn new(memory: Arc<MemoryBudget>) -> Self {
+        let (created, _) = broadcast::channel(JOB_CREATED_BUFFER);
+        Self {
+            jobs: Mutex::default(),
+            created,
+            memory,
+        }
+    }
+
+    pub(crate) async fn create(
+        self: &Arc<Self>,
+        job_id: Uuid,
+        owner_ip: &str,
+    ) -> Result<JobHandle, ApiError> {
+        let mut jobs = self.jobs.lock().await;
+        if jobs.contains_key(&job_id) {
+            return Err(ApiError::bad_request("El job_id indicado ya esta en uso."));
+        }
+
+        let (sender, _) = watch::channel(JobSnapshot {
+            job_id,
+            state: JobState::Queued,
+            version: 0,
+            updated_at: Utc::now(),
+            phase: None,
+            progress: 0.0,
+            transfer: TransferRate::default(),
+            plan: VIDEO_PHASES,
+            filename: None,
+            file_url: None,
+            receipt_url: None,
+            codecs: None,
+            ip_family: None,
+            artifact_hash: None,
+            error: None,
+        });
+        let sender = Arc::new(sender);
+        let cancel = Arc::new(watch::channel(false).0);
+        let logs = Arc::new(JobLog::default());
+        jobs.insert(
+            job_id,
+            JobRecord {
+                owner_ip: owner_ip.to_string(),
+                sender: Arc::clone(&sender),
+                cancel: Arc::clone(&cancel),
+                logs: Arc::clone(&logs),
+            },
+        );
+        self.memory
+            .evict_lru(TrackedMap::Jobs, &mut jobs, |record| {
+                let snapshot = record.sender.borrow();
+                snapshot.state.is_terminal().then_some(snapshot.updated_at)
+            });
+        let _ = self.created.send((job_id, owner_ip.to_string()));
+
+        Ok(JobHandle {
+            job_id,
+            owner: owner_ip.to_string(),
+            registry: Arc::clone(self),
+            sender,
+            cancel,
+            logs,
+            priority: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub(crate) async fn active_count(&self) -> usize {
+        self.jobs
+            .lock()
+            .await
+            .values()
+            .filter(|record| !record.sender.borrow().state.is_terminal())
+            .count()
+    }
+
+    pub(crate) async fn state_counts(&self) -> (usize, usize) {
+        let jobs = self.jobs.lock().await;
+        jobs.values().fold((0, 0), |(queued, running), record| {
+            match record.sender.borrow().state {
+                JobState::Queued => (queued + 1, running),
+                JobState::Running => (queued, running + 1),
+                _ => (queued, running),
+            }
+        })
+    }
+
+    pub(crate) async fn memory_usage(&self) -> (usize, usize) {
+        let jobs = self.jobs.lock().await;
+        let bytes = jobs
+            .values()
+            .map(|record| {
+                size_of::<(Uuid, JobRecord)>()
+                    + size_of::<JobSnapshot>()
+                    + record.owner_ip.len()
+                    + record.logs.bytes()
+            })
+            .sum();
+        (jobs.len(), bytes)
+    }
+
+    pub(crate) fn watch_created(&self) -> broadcast::Receiver<(Uuid, String)> {
+        self.created.subscribe()
+    }
+
+    pub(crate) async fn active_for(
+        &self,
+        client_ip: &str,
+    ) -> Vec<(Uuid, watch::Receiver<JobSnapshot>)> {
+        self.jobs
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, record)| {
+                record.owner_ip == client_ip && !record.sender.borrow().state.is_terminal()
+            })
+            .map(|(job_id, record)| (*job_id, record.sender.subscribe()))
+            .collect()
+    }
+
+    pub(crate) async fn subscribe(
+        &self,
+        job_id: Uuid,
+        client_ip: &str,
+    ) -> Option<watch::Receiver<JobSnapshot>> {
+        self.jobs
+            .lock()
+            .await
+            .get(&job_id)
+            .filter(|record| record.owner_ip == client_ip)
+            .map(|record| record.sender.subscribe())
+    }
+
+    // Signed links already prove access, so unlike subscribe this ignores the owner.
+    pub(crate) async fn completed_artifact(&self, job_id: Uuid) -> Option<String> {
+        let jobs = self.jobs.lock().await;
+        let snapshot = jobs.get(&job_id)?.sender.borrow();
+        (snapshot.state == JobState::Completed)
+            .then(|| snapshot.artifact_hash.clone())
+            .flatten()
+    }
+
+    pub(crate) async fn follow_logs(
+        &self,
+        job_id: Uuid,
+        client_ip: &str,
+        after: u64,
+    ) -> Option<LogFollower> {
+        self.jobs
+            .lock()
+            .await
+            .get(&job_id)
+            .filter(|record| record.owner_ip == client_ip)
+            .map(|record| record.logs.follow(record.sender.subscribe(), after))
+    }
+
+    pub(crate) async fn cancel(&self, job_id: Uuid, client_ip: &str) -> Option<JobSnapshot> {
+        let jobs = self.jobs.lock().await;
+        let record = jobs
+            .get(&job_id)
+            .filter(|record| record.owner_ip == client_ip)?;
+        record.sender.send_if_modified(|snapshot| {
+            if snapshot.state.is_terminal() {
+                return false;
+            }
+            snapshot.state = JobState::Cancelled;
+            snapshot.transfer = TransferRate::default();
+            snapshot.error = Some("La descarga fue cancelada.".to_string());
+            snapshot.version += 1;
+            snapshot.updated_at = Utc::now();
+            record.cancel.send_replace(true);
+            true
+        });
+        Some(record.sender.borrow().clone())
+    }
+}
+
+impl JobHandle {
+    pub(crate) fn job_id(&self) -> Uuid {
+        self.job_id
+    }
+
+    pub(crate) fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    pub(crate) fn prioritize(&self) {
+        self.priority.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_prioritized(&self) -> bool {
+        self.priority.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn running(&self, plan: PhasePlan) {
+        self.sender.send_if_modified(|snapshot| {
+            snapshot.plan = plan;
+            false
+        });
+        self.update(JobState::Running, None, None);
+        self.progress(JobPhase::Extraction, 0.0);
+    }
+
+    pub(crate) fn progress(&self, phase: JobPhase, fraction: f64) {
+        self.sender.send_if_modified(|snapshot| {
+            if snapshot.state != JobState::Running {
+                return false;
+            }
+
+            let mut overall = 0.0;
+            for (planned, weight) in snapshot.plan {
+                if *planned == phase {
+                    overall += weight * fraction.clamp(0.0, 1.0);
+                    break;
+                }
+                overall += weight;
+            }
+            Self::advance(snapshot, phase, (overall * 1000.0).round() / 10.0)
+        });
+    }
+
+    pub(crate) fn mirror_progress(&self, phase: JobPhase, overall: f64) {
+        self.sender.send_if_modified(|snapshot| {
+            snapshot.state == JobState::Running
+                && Self::advance(snapshot, phase, overall.clamp(0.0, 100.0))
+        });
+    }
+
+    pub(crate) fn transfer(&self, rate: TransferRate) {
+        self.sender.send_if_modified(|snapshot| {
+            if snapshot.state == JobState::Running {
+                snapshot.transfer = rate;
+            }
+            false
+        });
+    }
+
+    pub(crate) fn watch(&self) -> watch::Receiver<JobSnapshot> {
+        self.sender.subscribe()
+    }
+
+    pub(crate) fn log(&self, line: &str) {
+        self.logs.push(line);
+    }
+
+    pub(crate) fn watch_logs(&self) -> broadcast::Receiver<JobLogLine> {
+        self.logs.subscribe()
+    }
+
+    pub(crate) async fn cancelled(&self) {
+        let mut receiver = self.cancel.subscribe();
+        if receiver.wait_for(|cancelled| *cancelled).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    fn advance(snapshot: &mut JobSnapshot, phase: JobPhase, overall: f64) -> bool {
+        let phase_changed = snapshot.phase != Some(phase);
+        if !phase_changed && overall < snapshot.progress + 1.0 {
+            return false;
+        }
+        if phase != JobPhase::Download {
+            snapshot.transfer = TransferRate::default();
+        }
+        snapshot.phase = Some(phase);
+        snapshot.progress = overall.max(snapshot.progress);
+        snapshot.version += 1;
+        snapshot.updated_at = Utc::now();
+        true
+    }
+
+    pub(crate) fn complete(&self, filename: &str) {
+        self.update(JobState::Completed, Some(filename.to_string()), None);
+    }
+
+    pub(crate) fn complete_artifact(&self, filename: &str, artifact_hash: &str) {
+        self.sender.send_modify(|snapshot| {
+            snapshot.artifact_hash = Some(artifact_hash.to_string());
+        });
+        self.complete(filename);
+    }
+
+    pub(crate) fn attach_receipt(&self, receipt_url: String) {
+        self.sender.send_if_modified(|snapshot| {
+            snapshot.receipt_url = Some(receipt_url);
+            false
+        });
+    }
+
+    pub(crate) fn attach_codec_decision(&self, decision: CodecDecision) {
+        self.sender.send_if_modified(|snapshot| {
+            snapshot.codecs = Some(decision);
+            false
+        });
+    }
+
+    pub(crate) fn attach_ip_family(&self, family: IpFamily) {
+        self.sender.send_if_modified(|snapshot| {
+            snapshot.ip_family = Some(family);
+            false
+        });
+    }
+
+    pub(crate) fn link_offer(&self, file_url: String) -> impl FnOnce() + Send + 'static {
+        let sender = Arc::clone(&self.sender);
+        move || {
+            sender.send_modify(|snapshot| {
+                snapshot.file_url = Some(file_url);
+                snapshot.version += 1;
+                snapshot.updated_at = Utc::now();
+            });
+        }
+    }
+
+    pub(crate) fn fail(&self, message: &str) {
+        self.update(JobState::Failed, None, Some(message.to_string()));
+    }
+
+    pub(crate) fn abandon_on_drop(&self) -> AbandonGuard<'_> {
+        AbandonGuard {
+            job: self,
+            armed: true,
+        }
+    }
+
+    fn update(&self, state: JobState, filename: Option<String>, error: Option<String>) {
+        self.sender.send_if_modified(|snapshot| {
+            if snapshot.state.is_terminal() {
+                return false;
+            }
+            snapshot.state = state;
+            if state == JobState::Completed {
+                snapshot.progress = 100.0;
+            }
+            if state.is_terminal() {
+                snapshot.transfer = TransferRate::default();
+            }
+            snapshot.version += 1;
+            snapshot.updated_at = Utc::now();
+            snapshot.filename = filename;
+            snapshot.error = error;
+            true
+        });
+    }
+}
+
+impl Drop for JobHandle {
+    fn drop(&mut self) {
+        self.fail("La descarga se interrumpio antes de terminar.");
+
+        let registry = Arc::clone(&self.registry);
+        let job_id = self.job_id;
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(DOWNLOAD_JOB_RETENTION_SECONDS)).await;
+            registry.jobs.lock().await.remove(&job_id);
+        });
+    }
+}
+
+pub(crate) async fn get_job_status(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    RoutePath(job_id): RoutePath<Uuid>,
+    Query(query): Query<JobStatusQuery>,
+    uri: Uri,
+) -> Result<Response, ApiError> {
+    let client_ip = client_ip_for_request(&state, &headers, addr);
+    let Some(mut receiver) = state.jobs.subscribe(job_id, &client_ip).await else {
+        if let Some(registry) = &state.registry
+            && let Some(location) = registry.locate_job(job_id, &headers).await
+        {
+            return registry
+                .forward(&location, Method::GET, &uri, &headers, &client_ip)
+                .await;
+        }
+        return Err(ApiError::not_found(
+            "No existe una descarga con ese identificador.",
+        ));
+    };
+
+    let current = receiver.borrow_and_update().clone();
+    let wait = query.wait.unwrap_or_default().min(MAX_LONG_POLL_SECONDS);
+    let since = query.since.unwrap_or(current.version);
+    if wait == 0 || current.version != since || current.state.is_terminal() {
+        return Ok(Json(current).into_response());
+    }
+
+    let _ = timeout(Duration::from_secs(wait), receiver.changed()).await;
+    Ok(Json(receiver.borrow().clone()).into_response())
+}
+
+pub(crate) async fn get_job_progress(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    RoutePath(job_id): RoutePath<Uuid>,
+    uri: Uri,
+) -> Result<Response, ApiError> {
+    let client_ip = client_ip_for_request(&state, &headers, addr);
+    let Some(receiver) = state.jobs.subscribe(job_id, &client_ip).await else {
+        if let Some(registry) = &state.registry
+            && let Some(location) = registry.locate_job(job_id, &headers).await
+        {
+            return registry
+                .forward(&location, Method::GET, &uri, &headers, &client_ip)
+                .await;
+        }
+        return Err(ApiError::not_found(
+            "No existe una descarga con ese identificador.",
+        ));
+    };
+
+    let events = stream::unfold(Some((receiver, true)), |cursor| async move {
+        let (mut receiver, first) = cursor?;
+        if !first && receiver.changed().await.is_err() {
+            return None;
+        }
+        let snapshot = receiver.borrow_and_update().clone();
+        let event = Event::default()
+            .event(if snapshot.state.is_terminal() {
+                snapshot.state.as_str()
+            } else {
+                "progress"
+            })
+            .id(snapshot.version.to_string())
+            .json_data(&snapshot);
+        let next = (!snapshot.state.is_terminal()).then_some((receiver, false));
+        Some((event, next))
+    });
+
+    let mut response = Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response();
+    response
+        .headers_mut()
+        .insert("x-accel-buffering", HeaderValue::from_static("no"));
+    Ok(response)
+}
+
+pub(crate) async fn cancel_job(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    RoutePath(job_id): RoutePath<Uuid>,
+    uri: Uri,
+) -> Result<Response, ApiError> {
+    let client_ip = client_ip_for_request(&state, &headers, addr);
+    let Some(snapshot) = state.jobs.cancel(job_id, &client_ip).await else {
+        if let Some(registry) = &state.registry
+            && let Some(location) = registry.locate_job(job_id, &headers).await
+        {
+            return registry
+                .forward(&location, Method::DELETE, &uri, &headers, &client_ip)
+                .await;
+        }
+        return Err(ApiError::not_found(
+            "No existe una descarga con ese identificador.",
+        ));
+    };
+
+    Ok(Json(snapshot).into_response())
+}
+
+pub(crate) async fn get_job_file(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    RoutePath(job_id): RoutePath<Uuid>,
+    uri: Uri,
+) -> Result<Response, ApiError> {
+    let client_ip = client_ip_for_request(&state, &headers, addr);
+    let Some(receiver) = state.jobs.subscribe(job_id, &client_ip).await else {
+        if let Some(registry) = &state.registry
+            && let Some(location) = registry.locate_job(job_id, &headers).await
+        {
+            return registry
+                .forward(&location, Method::GET, &uri, &headers, &client_ip)
+                .await;
+        }
+        return Err(ApiError::not_found(
+            "No existe una descarga con ese identificador.",
+        ));
+    };
+
+    let snapshot = receiver.borrow().clone();
+    match (snapshot.state, snapshot.artifact_hash) {
+        (JobState::Completed, Some(artifact_hash)) => {
+            serve_artifact(
+                &state,
+                &artifact_hash,
+                Some(job_id),
+                &headers,
+                &uri,
+                client_ip,
+            )
+            .await
+        }
+        (JobState::Cancelled, _) => Err(ApiError::job_cancelled()),
+        (JobState::Failed, _) => Err(ApiError::job_failed(
+            snapshot
+                .error
+                .unwrap_or_else(|| "La descarga fallo.".to_string()),
+        )),
+        (JobState::Completed, None) => Err(ApiError::not_found(
+            "Esta descarga se entrego directamente y no tiene archivo para recoger.",
+        )),
+        _ => Err(ApiError::job_pending(JOB_POLL_RETRY_SECONDS)),
+    }
+}
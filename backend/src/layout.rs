@@ -0,0 +1,246 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+use crate::{ApiError, non_empty};
+
+const MIGRATION_LOG_FILE: &str = "layout_migrations.jsonl";
+const APP_DIR_NAME: &str = "total-downloader";
+
+#[derive(Debug, Clone)]
+pub(crate) struct DataLayout {
+    pub(crate) data_dir: PathBuf,
+    pub(crate) transfer_dir: PathBuf,
+    pub(crate) artifact_dir: PathBuf,
+    source_root: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+struct MigrationRecord {
+    migrated_at: DateTime<Utc>,
+    from: String,
+    to: String,
+    moved_files: usize,
+    skipped_files: Vec<String>,
+}
+
+fn env_dir(name: &str) -> Option<PathBuf> {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| non_empty(&value).map(PathBuf::from))
+}
+
+fn source_root() -> Option<PathBuf> {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.join("Cargo.toml").is_file().then_some(root)
+}
+
+fn platform_dir(xdg_name: &str, home_fallback: &str, macos_fallback: &str) -> PathBuf {
+    if let Some(dir) = env_dir(xdg_name).filter(|dir| dir.is_absolute()) {
+        return dir.join(APP_DIR_NAME);
+    }
+    match env_dir("HOME") {
+        Some(home) if cfg!(target_os = "macos") => home.join(macos_fallback).join(APP_DIR_NAME),
+        Some(home) => home.join(home_fallback).join(APP_DIR_NAME),
+        None => std::env::temp_dir().join(APP_DIR_NAME),
+    }
+}
+
+async fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    match tokio::fs::rename(from, to).await {
+        Err(error) if error.kind() == ErrorKind::CrossesDevices => {
+            let staged = to.with_file_name(format!(
+                ".{}.migrating",
+                to.file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or("archivo")
+            ));
+            tokio::fs::copy(from, &staged).await?;
+            tokio::fs::File::open(&staged).await?.sync_all().await?;
+            tokio::fs::rename(&staged, to).await?;
+            tokio::fs::remove_file(from).await
+        }
+        result => result,
+    }
+}
+
+async fn move_tree(from: &Path, to: &Path) -> std::io::Result<MigrationRecord> {
+    let mut record = MigrationRecord {
+        migrated_at: Utc::now(),
+        from: from.to_string_lossy().into_owned(),
+        to: to.to_string_lossy().into_owned(),
+        moved_files: 0,
+        skipped_files: Vec::new(),
+    };
+    let mut pending = vec![(from.to_path_buf(), to.to_path_buf())];
+    let mut visited = Vec::new();
+    while let Some((source_dir, target_dir)) = pending.pop() {
+        tokio::fs::create_dir_all(&target_dir).await?;
+        let mut entries = tokio::fs::read_dir(&source_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let source = entry.path();
+            let target = target_dir.join(entry.file_name());
+            if entry.file_type().await?.is_dir() {
+                pending.push((source, target));
+            } else if tokio::fs::symlink_metadata(&target).await.is_ok() {
+                warn!(
+                    "Migracion: {} ya existe; se conserva y no se sobrescribe.",
+                    target.display()
+                );
+                record
+                    .skipped_files
+                    .push(source.to_string_lossy().into_owned());
+            } else {
+                move_file(&source, &target).await?;
+                record.moved_files += 1;
+            }
+        }
+        visited.push(source_dir);
+    }
+    for dir in visited.iter().rev() {
+        let _ = tokio::fs::remove_dir(dir).await;
+    }
+    Ok(record)
+}
+
+async fn is_empty_dir(path: &Path) -> bool {
+    match tokio::fs::read_dir(path).await {
+        Ok(mut entries) => entries.next_entry().await.ok().flatten().is_none(),
+        Err(_) => true,
+    }
+}
+
+impl DataLayout {
+    pub(crate) fn from_env() -> Self {
+        let source_root = source_root();
+        let (data_base, cache_base) = match &source_root {
+            Some(root) => (root.clone(), root.clone()),
+            None => (
+                platform_dir(
+                    "XDG_DATA_HOME",
+                    ".local/share",
+                    "Library/Application Support",
+                ),
+                platform_dir("XDG_CACHE_HOME", ".cache", "Library/Caches"),
+            ),
+        };
+        let transfer_name = if source_root.is_some() {
+            "temp_downloads"
+        } else {
+            "transfers"
+        };
+        let configured_data_dir = env_dir("DATA_DIR");
+        // A custom DATA_DIR takes the artifacts with it; only ARTIFACTS_DIR splits them off.
+        let artifact_dir = env_dir("ARTIFACTS_DIR").unwrap_or_else(|| match &configured_data_dir {
+            Some(data_dir) => data_dir.join("artifacts"),
+            None => data_base.join("artifacts"),
+        });
+        Self {
+            data_dir: configured_data_dir.unwrap_or_else(|| data_base.join("data")),
+            transfer_dir: env_dir("TRANSFER_DIR").unwrap_or_else(|| cache_base.join(transfer_name)),
+            artifact_dir,
+            source_root,
+        }
+    }
+
+    pub(crate) async fn create_dirs(&self) -> Result<(), ApiError> {
+        tokio::fs::create_dir_all(&self.data_dir)
+            .await
+            .map_err(|error| {
+                ApiError::internal(format!("No se pudo crear la carpeta de datos: {error}"))
+            })?;
+        tokio::fs::create_dir_all(&self.transfer_dir)
+            .await
+            .map_err(|error| {
+                ApiError::internal(format!(
+                    "No se pudo crear la carpeta temporal de descargas: {error}"
+                ))
+            })?;
+        info!(
+            "Datos en {}, descargas temporales en {}, artefactos en {}.",
+            self.data_dir.display(),
+            self.transfer_dir.display(),
+            self.artifact_dir.display()
+        );
+        Ok(())
+    }
+
+    pub(crate) async fn migrate_legacy(&self) -> Result<(), ApiError> {
+        let Some(root) = &self.source_root else {
+            return Ok(());
+        };
+        let moves = [
+            (root.join("data"), &self.data_dir),
+            (root.join("temp_downloads"), &self.transfer_dir),
+        ];
+        for (legacy, target) in moves {
+            if is_empty_dir(&legacy).await {
+                continue;
+            }
+            let (Ok(legacy), Ok(target)) = (
+                tokio::fs::canonicalize(&legacy).await,
+                tokio::fs::canonicalize(target).await,
+            ) else {
+                continue;
+            };
+            if legacy == target {
+                continue;
+            }
+            if target.starts_with(&legacy) || legacy.starts_with(&target) {
+                warn!(
+                    "No se migra {}: la carpeta nueva {} esta anidada en la antigua.",
+                    legacy.display(),
+                    target.display()
+                );
+                continue;
+            }
+
+            info!(
+                "Migrando datos de {} a {}...",
+                legacy.display(),
+                target.display()
+            );
+            let record = move_tree(&legacy, &target).await.map_err(|error| {
+                ApiError::internal(format!(
+                    "No se pudo migrar {} a {}: {error}",
+                    legacy.display(),
+                    target.display()
+                ))
+            })?;
+            info!(
+                "Migracion completada: {} archivos movidos, {} conservados en el origen.",
+                record.moved_files,
+                record.skipped_files.len()
+            );
+            if record.moved_files > 0 {
+                self.record_migration(&record).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn record_migration(&self, record: &MigrationRecord) {
+        let Ok(mut line) = serde_json::to_string(record) else {
+            return;
+        };
+        line.push('\n');
+        let result = async {
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.data_dir.join(MIGRATION_LOG_FILE))
+                .await?;
+            file.write_all(line.as_bytes()).await
+        }
+        .await;
+        if let Err(error) = result {
+            warn!("No se pudo registrar la migracion de carpetas: {error}");
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::ErrorKind,
+    path::PathBuf,
+};
+
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path as RoutePath, State},
+};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{ApiError, AppState, encode_hex, hmac_sha256, storage::append_line};
+
+pub(crate) const LEAK_TAG_METADATA_KEY: &str = "td_tag";
+const TAG_PREFIX: &str = "TDLEAK-";
+const TAG_HEX_LENGTH: usize = 16;
+const MAX_REPORTED_TAGS: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct LeakTagRecord {
+    tag: String,
+    job_id: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    client_ip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    filename: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct LeakVerification {
+    scanned_bytes: u64,
+    matches: Vec<LeakTagRecord>,
+    unknown_tags: Vec<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct LeakTags {
+    secret: Vec<u8>,
+    path: PathBuf,
+    keep_details: bool,
+    records: Mutex<HashMap<String, LeakTagRecord>>,
+}
+
+fn normalize_tag(value: &str) -> Option<String> {
+    let hex = value.trim();
+    let hex = hex.strip_prefix(TAG_PREFIX).unwrap_or(hex);
+    (hex.len() == TAG_HEX_LENGTH && hex.bytes().all(|byte| byte.is_ascii_hexdigit()))
+        .then(|| format!("{TAG_PREFIX}{}", hex.to_ascii_lowercase()))
+}
+
+fn scan_tags(buffer: &[u8], found: &mut BTreeSet<String>) {
+    let prefix = TAG_PREFIX.as_bytes();
+    let length = prefix.len() + TAG_HEX_LENGTH;
+    for window in buffer.windows(length) {
+        if found.len() >= MAX_REPORTED_TAGS {
+            return;
+        }
+        if window.starts_with(prefix)
+            && let Ok(text) = std::str::from_utf8(window)
+            && let Some(tag) = normalize_tag(text)
+        {
+            found.insert(tag);
+        }
+    }
+}
+
+// Inserts the tag before the extension: "Video-abc.mp4" -> "Video-abc.1f3a9c0d2b4e6f70.mp4".
+pub(crate) fn tagged_filename(filename: &str, tag: &str) -> String {
+    let short = tag.strip_prefix(TAG_PREFIX).unwrap_or(tag);
+    match filename.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{stem}.{short}.{extension}"),
+        _ => format!("{filename}.{short}"),
+    }
+}
+
+impl LeakTags {
+    pub(crate) async fn from_env(
+        path: PathBuf,
+        secret: &[u8],
+        keep_details: bool,
+    ) -> Result<Option<Self>, ApiError> {
+        if !crate::read_bool_env("LEAK_TAGS_ENABLED").unwrap_or(false) {
+            return Ok(None);
+        }
+        let contents = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+            Err(error) => {
+                return Err(ApiError::internal(format!(
+                    "No se pudieron leer las marcas de descarga: {error}"
+                )));
+            }
+        };
+        let mut records = HashMap::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str::<LeakTagRecord>(line) {
+                Ok(record) => {
+                    records.insert(record.tag.clone(), record);
+                }
+                Err(error) => warn!("Marca de descarga invalida ignorada: {error}"),
+            }
+        }
+        info!(
+            "Marcas de descarga habilitadas ({} registradas).",
+            records.len()
+        );
+        Ok(Some(Self {
+            secret: secret.to_vec(),
+            path,
+            keep_details,
+            records: Mutex::new(records),
+        }))
+    }
+
+    pub(crate) fn tag_for(&self, job_id: Uuid) -> String {
+        let digest = hmac_sha256(&self.secret, format!("leak:{job_id}").as_bytes());
+        format!("{TAG_PREFIX}{}", &encode_hex(&digest)[..TAG_HEX_LENGTH])
+    }
+
+    pub(crate) async fn record(
+        &self,
+        tag: &str,
+        job_id: Uuid,
+        client_ip: &str,
+        url: &str,
+        filename: &str,
+    ) {
+        let record = LeakTagRecord {
+            tag: tag.to_string(),
+            job_id,
+            client_ip: self.keep_details.then(|| client_ip.to_string()),
+            url: self.keep_details.then(|| url.to_string()),
+            filename: filename.to_string(),
+            created_at: Utc::now(),
+        };
+        if let Err(error) = append_line(&self.path, &record).await {
+            warn!("No se pudo registrar la marca {tag} del job {job_id}: {error}");
+        }
+        self.records.lock().await.insert(tag.to_string(), record);
+    }
+}
+
+fn enabled(state: &AppState) -> Result<&LeakTags, ApiError> {
+    state.leak_tags.as_deref().ok_or_else(|| {
+        ApiError::not_found("Las marcas de descarga estan deshabilitadas (LEAK_TAGS_ENABLED).")
+    })
+}
+
+pub(crate) async fn get_leak_tag(
+    State(state): State<AppState>,
+    RoutePath(tag): RoutePath<String>,
+) -> Result<Json<LeakTagRecord>, ApiError> {
+    let leak_tags = enabled(&state)?;
+    let tag = normalize_tag(&tag).ok_or_else(|| {
+        ApiError::bad_request(format!(
+            "La marca debe ser {TAG_PREFIX} seguido de {TAG_HEX_LENGTH} caracteres hexadecimales."
+        ))
+    })?;
+    leak_tags
+        .records
+        .lock()
+        .await
+        .get(&tag)
+        .cloned()
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("No hay ninguna descarga con esa marca."))
+}
+
+pub(crate) async fn verify_leaked_file(
+    State(state): State<AppState>,
+    body: Body,
+) -> Result<Json<LeakVerification>, ApiError> {
+    let leak_tags = enabled(&state)?;
+    let overlap = TAG_PREFIX.len() + TAG_HEX_LENGTH;
+    let mut stream = body.into_data_stream();
+    let mut carry = Vec::new();
+    let mut found = BTreeSet::new();
+    let mut scanned_bytes = 0u64;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|error| {
+            ApiError::bad_request(format!("No se pudo leer el archivo: {error}"))
+        })?;
+        scanned_bytes += chunk.len() as u64;
+        carry.extend_from_slice(&chunk);
+        scan_tags(&carry, &mut found);
+        let keep = carry.len().min(overlap - 1);
+        carry.drain(..carry.len() - keep);
+    }
+
+    let records = leak_tags.records.lock().await;
+    let (matches, unknown_tags) = found.into_iter().fold(
+        (Vec::new(), Vec::new()),
+        |(mut matches, mut unknown), tag| {
+            match records.get(&tag) {
+                Some(record) => matches.push(record.clone()),
+                None => unknown.push(tag),
+            }
+            (matches, unknown)
+        },
+    );
+    Ok(Json(LeakVerification {
+        scanned_bytes,
+        matches,
+        unknown_tags,
+    }))
+}
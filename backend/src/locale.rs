@@ -0,0 +1,162 @@
+use std::fmt;
+
+use axum::http::{HeaderMap, header::ACCEPT_LANGUAGE};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{ApiError, DownloadMode, DownloadRequest, read_bool_env};
+
+const DEFAULT_IGNORED_LANGUAGES: &str = "en";
+const DISABLED_VALUES: [&str; 3] = ["none", "off", "original"];
+
+// Primary ISO 639 subtag, kept inline so FormatHints stays Copy.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct Language {
+    code: [u8; 3],
+    len: u8,
+}
+
+#[derive(Debug)]
+pub(crate) struct LanguageDefaults {
+    enabled: bool,
+    subtitles: bool,
+    audio: bool,
+    ignored: Vec<Language>,
+}
+
+impl Language {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        let primary = value.trim().split(['-', '_']).next()?;
+        if !(2..=3).contains(&primary.len())
+            || !primary.bytes().all(|byte| byte.is_ascii_alphabetic())
+        {
+            return None;
+        }
+        let mut code = [0; 3];
+        for (slot, byte) in code.iter_mut().zip(primary.bytes()) {
+            *slot = byte.to_ascii_lowercase();
+        }
+        Some(Self {
+            code,
+            len: primary.len() as u8,
+        })
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.code[..usize::from(self.len)]).unwrap_or_default()
+    }
+
+    pub(crate) fn audio_filter(&self) -> String {
+        format!("[language^={}]", self.as_str())
+    }
+
+    fn subtitle_patterns(&self) -> Vec<String> {
+        vec![self.as_str().to_string(), format!("{}-.*", self.as_str())]
+    }
+}
+
+impl fmt::Debug for Language {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.as_str())
+    }
+}
+
+impl TryFrom<String> for Language {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value).ok_or_else(|| format!("Idioma invalido: {value}"))
+    }
+}
+
+impl From<Language> for String {
+    fn from(language: Language) -> Self {
+        language.as_str().to_string()
+    }
+}
+
+// Highest-weighted primary tag; "*" and q=0 entries never count as a preference.
+fn preferred_language(headers: &HeaderMap) -> Option<Language> {
+    let header = headers.get(ACCEPT_LANGUAGE)?.to_str().ok()?;
+    let mut ranked = header
+        .split(',')
+        .enumerate()
+        .filter_map(|(position, item)| {
+            let mut parts = item.split(';');
+            let language = Language::parse(parts.next()?)?;
+            let weight = parts
+                .find_map(|parameter| parameter.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |value| value.trim().parse::<f32>().ok())?;
+            (weight > 0.0).then_some((weight, position, language))
+        })
+        .collect::<Vec<_>>();
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+    ranked.first().map(|(_, _, language)| *language)
+}
+
+impl LanguageDefaults {
+    pub(crate) fn from_env() -> Self {
+        let enabled = read_bool_env("AUTO_LANGUAGE_ENABLED").unwrap_or(true);
+        let ignored = std::env::var("AUTO_LANGUAGE_IGNORE")
+            .unwrap_or_else(|_| DEFAULT_IGNORED_LANGUAGES.to_string())
+            .split(',')
+            .filter_map(Language::parse)
+            .collect::<Vec<_>>();
+        let defaults = Self {
+            enabled,
+            subtitles: read_bool_env("AUTO_LANGUAGE_SUBTITLES").unwrap_or(true),
+            audio: read_bool_env("AUTO_LANGUAGE_AUDIO").unwrap_or(true),
+            ignored,
+        };
+        if defaults.enabled {
+            info!(
+                "Idioma por defecto segun Accept-Language (subtitulos: {}, audio: {}, se ignora: {:?}).",
+                defaults.subtitles, defaults.audio, defaults.ignored
+            );
+        }
+        defaults
+    }
+
+    // An explicit `language` always wins; Accept-Language only fills in what the request left open.
+    pub(crate) fn apply(
+        &self,
+        payload: &mut DownloadRequest,
+        headers: &HeaderMap,
+    ) -> Result<(), ApiError> {
+        let requested = payload
+            .language
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty());
+        let explicit = requested.is_some();
+        let language = match requested {
+            Some(value) if DISABLED_VALUES.contains(&value.to_ascii_lowercase().as_str()) => {
+                return Ok(());
+            }
+            Some(value) => Some(Language::parse(value).ok_or_else(|| {
+                ApiError::bad_request(format!(
+                    "Idioma invalido: {value}. Usa un codigo como es, pt o fr, o none."
+                ))
+            })?),
+            None if self.enabled => {
+                preferred_language(headers).filter(|language| !self.ignored.contains(language))
+            }
+            None => None,
+        };
+        let Some(language) = language else {
+            return Ok(());
+        };
+
+        if (explicit || self.subtitles)
+            && payload.embed_subtitles.is_none()
+            && matches!(payload.mode, DownloadMode::Video)
+        {
+            payload.embed_subtitles = Some(language.subtitle_patterns());
+        }
+        if explicit || self.audio {
+            payload.hints.audio_language = payload.hints.audio_language.or(Some(language));
+        }
+        Ok(())
+    }
+}
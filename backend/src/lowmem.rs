@@ -0,0 +1,100 @@
+use std::sync::OnceLock;
+
+use serde::Serialize;
+use tracing::info;
+
+const LOW_MEMORY_CHUNK_BYTES: usize = 16 * 1024;
+const LOW_MEMORY_CHANNEL_CHUNKS: usize = 1;
+const LOW_MEMORY_TRACKED_ENTRIES: usize = 200;
+const MAX_COLLECTED_OUTPUT_BYTES: usize = 64 * 1024;
+
+pub(crate) fn low_memory() -> bool {
+    static LOW_MEMORY: OnceLock<bool> = OnceLock::new();
+    *LOW_MEMORY.get_or_init(|| {
+        let enabled = crate::read_bool_env("LOW_MEMORY").unwrap_or(false);
+        if enabled {
+            info!(
+                "Modo de poca memoria activo: una descarga a la vez, sin cache de formatos y buffers de {} KiB.",
+                LOW_MEMORY_CHUNK_BYTES / 1024
+            );
+        }
+        enabled
+    })
+}
+
+pub(crate) fn concurrency(configured: usize) -> usize {
+    if low_memory() { 1 } else { configured }
+}
+
+pub(crate) fn chunk_bytes(configured: usize) -> usize {
+    if low_memory() {
+        configured.min(LOW_MEMORY_CHUNK_BYTES)
+    } else {
+        configured
+    }
+}
+
+pub(crate) fn channel_chunks(configured: usize) -> usize {
+    if low_memory() {
+        LOW_MEMORY_CHANNEL_CHUNKS
+    } else {
+        configured
+    }
+}
+
+pub(crate) fn tracked_entries(configured: usize) -> usize {
+    if low_memory() {
+        configured.min(LOW_MEMORY_TRACKED_ENTRIES)
+    } else {
+        configured
+    }
+}
+
+// Pretty output roughly doubles the serialized size of large indexes; both forms load the same.
+pub(crate) fn to_json_vec<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<Vec<u8>> {
+    if low_memory() {
+        serde_json::to_vec(value)
+    } else {
+        serde_json::to_vec_pretty(value)
+    }
+}
+
+// yt-dlp keeps its read buffer small and fragments sequential; merges run single-threaded.
+pub(crate) fn extractor_args() -> Vec<String> {
+    if !low_memory() {
+        return Vec::new();
+    }
+    [
+        "--buffer-size",
+        "16K",
+        "--no-resize-buffer",
+        "--concurrent-fragments",
+        "1",
+        "--postprocessor-args",
+        "Merger:-threads 1",
+    ]
+    .map(ToString::to_string)
+    .to_vec()
+}
+
+pub(crate) fn ffmpeg_args() -> &'static [&'static str] {
+    if low_memory() {
+        &["-threads", "1"]
+    } else {
+        &[]
+    }
+}
+
+// Keeps only the tail of a child's output so a chatty extractor cannot grow the buffer unbounded.
+pub(crate) fn collect_line(buffer: &mut Vec<u8>, line: &str) {
+    buffer.extend_from_slice(line.as_bytes());
+    buffer.push(b'\n');
+    if low_memory() && buffer.len() > MAX_COLLECTED_OUTPUT_BYTES {
+        let excess = buffer.len() - MAX_COLLECTED_OUTPUT_BYTES / 2;
+        let cut = buffer[excess..]
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map_or(excess, |offset| excess + offset + 1);
+        buffer.drain(..cut);
+    }
+}
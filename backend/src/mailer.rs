@@ -0,0 +1,236 @@
+use std::sync::Arc;
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chrono::Utc;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    time::{Duration, timeout},
+};
+use tokio_rustls::{
+    TlsConnector,
+    rustls::{ClientConfig, RootCertStore, crypto::ring, pki_types::ServerName},
+};
+use uuid::Uuid;
+
+const DEFAULT_SMTP_PORT: u16 = 587;
+const SMTP_TIMEOUT_SECONDS: u64 = 20;
+const MAX_SMTP_REPLY_LINES: usize = 64;
+
+trait SmtpStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> SmtpStream for T {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SmtpSecurity {
+    StartTls,
+    Tls,
+    Plain,
+}
+
+#[derive(Debug)]
+pub(crate) struct SmtpMailer {
+    host: String,
+    port: u16,
+    security: SmtpSecurity,
+    credentials: Option<(String, String)>,
+    from: String,
+}
+
+struct SmtpSession {
+    stream: BufReader<Box<dyn SmtpStream>>,
+}
+
+impl SmtpMailer {
+    pub(crate) fn from_env() -> Option<Self> {
+        let read = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| crate::non_empty(&value).map(ToString::to_string))
+        };
+        let host = read("SMTP_HOST")?;
+        let from = read("SMTP_FROM")?;
+        let security = match read("SMTP_SECURITY")
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "tls" => SmtpSecurity::Tls,
+            "none" | "plain" => SmtpSecurity::Plain,
+            _ => SmtpSecurity::StartTls,
+        };
+        let port = read("SMTP_PORT")
+            .and_then(|value| value.parse::<u16>().ok())
+            .unwrap_or(if security == SmtpSecurity::Tls {
+                465
+            } else {
+                DEFAULT_SMTP_PORT
+            });
+        let credentials = read("SMTP_USERNAME").zip(read("SMTP_PASSWORD"));
+
+        Some(Self {
+            host,
+            port,
+            security,
+            credentials,
+            from,
+        })
+    }
+
+    pub(crate) async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), String> {
+        timeout(
+            Duration::from_secs(SMTP_TIMEOUT_SECONDS),
+            self.deliver(to, subject, body),
+        )
+        .await
+        .map_err(|_| "tiempo limite de SMTP excedido".to_string())?
+    }
+
+    async fn deliver(&self, to: &str, subject: &str, body: &str) -> Result<(), String> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .map_err(|error| format!("no se pudo conectar a {}: {error}", self.host))?;
+        let stream: Box<dyn SmtpStream> = if self.security == SmtpSecurity::Tls {
+            Box::new(self.wrap_tls(tcp).await?)
+        } else {
+            Box::new(tcp)
+        };
+        let mut session = SmtpSession {
+            stream: BufReader::new(stream),
+        };
+
+        session.expect(220).await?;
+        session
+            .command(&format!("EHLO {}", hello_name(&self.from)), 250)
+            .await?;
+        if self.security == SmtpSecurity::StartTls {
+            session.command("STARTTLS", 220).await?;
+            let inner = session.stream.into_inner();
+            session = SmtpSession {
+                stream: BufReader::new(Box::new(self.wrap_tls(inner).await?)),
+            };
+            session
+                .command(&format!("EHLO {}", hello_name(&self.from)), 250)
+                .await?;
+        }
+        if let Some((username, password)) = &self.credentials {
+            let token = BASE64.encode(format!("\0{username}\0{password}"));
+            session.command(&format!("AUTH PLAIN {token}"), 235).await?;
+        }
+
+        session
+            .command(&format!("MAIL FROM:<{}>", self.from), 250)
+            .await?;
+        session.command(&format!("RCPT TO:<{to}>"), 250).await?;
+        session.command("DATA", 354).await?;
+        session.write(&self.compose(to, subject, body)).await?;
+        session.command(".", 250).await?;
+        let _ = session.command("QUIT", 221).await;
+        Ok(())
+    }
+
+    async fn wrap_tls<S>(&self, stream: S) -> Result<tokio_rustls::client::TlsStream<S>, String>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|error| error.to_string())?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let server_name = ServerName::try_from(self.host.clone())
+            .map_err(|error| format!("host SMTP invalido: {error}"))?;
+
+        TlsConnector::from(Arc::new(config))
+            .connect(server_name, stream)
+            .await
+            .map_err(|error| format!("fallo el handshake TLS: {error}"))
+    }
+
+    fn compose(&self, to: &str, subject: &str, body: &str) -> String {
+        let encoded_subject = if subject.is_ascii() {
+            subject.to_string()
+        } else {
+            format!("=?UTF-8?B?{}?=", BASE64.encode(subject))
+        };
+        let mut message = format!(
+            "From: <{from}>\r\nTo: <{to}>\r\nSubject: {encoded_subject}\r\nDate: {date}\r\nMessage-ID: <{id}@{domain}>\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+            from = self.from,
+            date = Utc::now().to_rfc2822(),
+            id = Uuid::new_v4().simple(),
+            domain = hello_name(&self.from),
+        );
+        for line in body.lines() {
+            if line.starts_with('.') {
+                message.push('.');
+            }
+            message.push_str(line);
+            message.push_str("\r\n");
+        }
+        message
+    }
+}
+
+impl SmtpSession {
+    async fn write(&mut self, data: &str) -> Result<(), String> {
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(data.as_bytes())
+            .await
+            .map_err(|error| error.to_string())?;
+        stream.flush().await.map_err(|error| error.to_string())
+    }
+
+    async fn command(&mut self, line: &str, expected: u16) -> Result<(), String> {
+        self.write(&format!("{line}\r\n")).await?;
+        self.expect(expected).await
+    }
+
+    async fn expect(&mut self, expected: u16) -> Result<(), String> {
+        let mut last = String::new();
+        for _ in 0..MAX_SMTP_REPLY_LINES {
+            last.clear();
+            let read = self
+                .stream
+                .read_line(&mut last)
+                .await
+                .map_err(|error| error.to_string())?;
+            if read == 0 {
+                return Err("el servidor SMTP cerro la conexion".to_string());
+            }
+            if last.as_bytes().get(3) == Some(&b'-') {
+                continue;
+            }
+
+            let code = last.get(..3).and_then(|code| code.parse::<u16>().ok());
+            return match code {
+                Some(code) if code == expected || (expected == 250 && code == 251) => Ok(()),
+                _ => Err(format!("respuesta SMTP inesperada: {}", last.trim())),
+            };
+        }
+        Err("respuesta SMTP demasiado larga".to_string())
+    }
+}
+
+fn hello_name(from: &str) -> &str {
+    from.rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .filter(|domain| !domain.is_empty())
+        .unwrap_or("localhost")
+}
+
+pub(crate) fn normalize_email(value: &str) -> Option<String> {
+    let email = value.trim().to_ascii_lowercase();
+    let (local, domain) = email.split_once('@')?;
+    let valid = !local.is_empty()
+        && email.len() <= 254
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && email
+            .chars()
+            .all(|character| character.is_ascii_graphic() && !"<>()[],;:\\\"".contains(character))
+        && !domain.contains('@');
+    valid.then_some(email)
+}
@@ -1,78 +1,302 @@
+mod apikeys;
+mod archive;
+mod artifacts;
+mod auth;
+mod autotune;
+mod bans;
+mod billing;
+mod cli;
+mod clienterrors;
+mod compat;
+mod config;
+mod convert;
+mod credentials;
+mod delivery;
+mod demo;
+mod dns;
+mod domain_headers;
+mod egress;
+mod embed;
+mod entitlements;
+mod escalation;
+mod extractor;
+mod ffmpeg;
+mod health;
+mod history;
+mod impersonate;
+mod joblog;
+mod jobs;
+mod layout;
+mod leaktags;
+mod locale;
+mod lowmem;
+mod mailer;
+mod memory;
+mod merge;
+mod passthrough;
+mod playlist;
+mod plugins;
+mod policy;
+mod postprocess;
+mod presets;
+mod priority;
+mod problem;
+mod process_group;
+mod promo;
+mod quota;
+mod rbac;
+mod receipts;
+mod redis;
+mod registry;
+mod request_signing;
+mod sessions;
+mod settings;
+mod shadow;
+mod sidecars;
+mod sniff;
+mod stats;
+mod storage;
+mod streams;
+mod supervisor;
+mod support;
+mod system;
+mod telemetry;
+mod throughput;
+mod thumbnail;
+mod verification;
+mod warc;
+mod websocket;
+mod workers;
+
 use std::{
     cmp::Ordering,
     collections::{HashMap, HashSet},
     io::ErrorKind,
     net::SocketAddr,
     path::{Path, PathBuf},
+    process::Stdio,
     sync::Arc,
 };
 
 use axum::{
     Json, Router,
     body::Body,
-    extract::{ConnectInfo, State},
+    extract::{ConnectInfo, DefaultBodyLimit, Path as RoutePath, Query, Request, State},
     http::{
-        HeaderMap, HeaderName, HeaderValue, Method, StatusCode,
-        header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER},
+        HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri,
+        header::{
+            ACCEPT_RANGES, AUTHORIZATION, CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_LENGTH,
+            CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE,
+            LAST_MODIFIED, RANGE, RETRY_AFTER,
+        },
     },
+    middleware::{self, Next},
     response::{IntoResponse, Response},
-    routing::{get, post},
+    routing::{delete, get, post, put},
 };
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use ring::hmac;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader},
     net::TcpListener,
     process::Command,
     sync::{Mutex, Semaphore},
     time::{Duration, timeout},
 };
-use tokio_util::io::ReaderStream;
-use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_http::cors::{AllowHeaders, AllowOrigin, Any, CorsLayer};
 use tracing::{debug, info, warn};
 use url::Url;
 use uuid::Uuid;
 
+use crate::apikeys::{ApiKeyGrant, ApiKeys};
+use crate::archive::{ArchiveEntry, write_archive_file};
+use crate::artifacts::{ArtifactStore, StoredArtifact};
+use crate::auth::{OidcAuth, require_login};
+use crate::autotune::ConcurrencyTuner;
+use crate::bans::BanMap;
+use crate::billing::UsageLedger;
+use crate::cli::Cli;
+use crate::clienterrors::ClientErrorLog;
+use crate::compat::{CodecDecision, CodecProfile};
+use crate::config::Config;
+use crate::credentials::CredentialStore;
+use crate::delivery::DeliveryMonitor;
+use crate::demo::DemoMode;
+use crate::dns::DnsResolver;
+use crate::domain_headers::DomainHeaders;
+use crate::egress::IpFamily;
+use crate::embed::EmbedSites;
+use crate::entitlements::Entitlements;
+use crate::extractor::{ExtractorRouter, RequestClass};
+use crate::health::ReadinessCache;
+use crate::jobs::{
+    AUDIO_PHASES, JobHandle, JobPhase, JobRegistry, PhasePlan, TRANSCODE_VIDEO_PHASES, VIDEO_PHASES,
+};
+use crate::layout::DataLayout;
+use crate::leaktags::{LEAK_TAG_METADATA_KEY, LeakTags, tagged_filename};
+use crate::locale::{Language, LanguageDefaults};
+use crate::memory::{MemoryBudget, TrackedMap};
+use crate::passthrough::ExtraArgsPolicy;
+use crate::playlist::PlaylistLimits;
+use crate::policy::{ClientReputation, PolicyHook, PolicyInput, PolicyLimits};
+use crate::postprocess::AudioTags;
+use crate::presets::{AUDIO_CONTAINERS, DownloadPreset, PresetCatalog, VIDEO_CONTAINERS};
+use crate::process_group::ProcessGroup;
+use crate::promo::{PromoStore, active_boost_for, load_promo_store, redeem_promo_code};
+use crate::quota::{QuotaPolicy, QuotaSchedule};
+use crate::rbac::{Role, RoleTokens};
+use crate::receipts::{ReceiptDetails, ReceiptSigner, issue_receipt};
+use crate::redis::RedisStore;
+use crate::registry::{ArtifactRoute, NodeRegistry};
+use crate::request_signing::{RequestSigner, require_signed_request};
+use crate::sessions::AnonSessions;
+use crate::shadow::{ExtractionSummary, ShadowExtractor};
+use crate::sidecars::SidecarRequest;
+use crate::stats::DownloadStats;
+use crate::storage::Storage;
+use crate::streams::StreamSelection;
+use crate::supervisor::{RestartPolicy, TaskSupervisor};
+use crate::system::SystemMonitor;
+use crate::telemetry::Telemetry;
+use crate::throughput::ThroughputStats;
+use crate::verification::{EmailVerification, verified_daily_limit_for};
+use crate::workers::{DispatchError, WorkerJobRequest, WorkerPool};
+
 #[derive(Clone)]
 struct AppState {
     history: Arc<Mutex<Vec<HistoryEntry>>>,
-    history_path: PathBuf,
+    history_enabled: bool,
     rate_limits: Arc<Mutex<RateLimitMap>>,
-    rate_limit_path: PathBuf,
+    bans: Arc<Mutex<BanMap>>,
+    storage: Arc<dyn Storage>,
     anti_bot_challenges: Arc<Mutex<AntiBotChallengeMap>>,
+    redis: Option<Arc<RedisStore>>,
+    usage: Option<Arc<UsageLedger>>,
+    entitlements: Option<Arc<Entitlements>>,
+    api_keys: Arc<ApiKeys>,
+    demo: Option<Arc<DemoMode>>,
+    leak_tags: Option<Arc<LeakTags>>,
     download_semaphore: Arc<Semaphore>,
+    concurrency: Option<Arc<ConcurrencyTuner>>,
+    metadata_semaphore: Arc<Semaphore>,
+    metadata_timeout: Duration,
     trust_proxy_headers: bool,
     turnstile_secret_key: Option<String>,
     http_client: reqwest::Client,
     transfer_dir: PathBuf,
+    signing_secret: Arc<Vec<u8>>,
+    public_base_url: Option<String>,
+    roles: Arc<RoleTokens>,
+    auth: Option<Arc<OidcAuth>>,
+    sessions: Option<Arc<AnonSessions>>,
+    promo: Arc<Mutex<PromoStore>>,
+    promo_path: PathBuf,
+    promo_audit_path: PathBuf,
+    policy_hook: Option<Arc<PolicyHook>>,
+    shadow_extractor: Option<Arc<ShadowExtractor>>,
+    extractor: Arc<ExtractorRouter>,
+    plugin_dirs: Arc<Vec<PathBuf>>,
+    extra_supported_domains: Arc<Vec<String>>,
+    formats_cache: Arc<Mutex<HashMap<String, CachedFormats>>>,
+    jobs: Arc<JobRegistry>,
+    config: Arc<Config>,
+    memory: Arc<MemoryBudget>,
+    system: Arc<SystemMonitor>,
+    readiness: Arc<ReadinessCache>,
+    supervisor: Arc<TaskSupervisor>,
+    embed_job_metadata: bool,
+    signed_link_ttl_seconds: u64,
+    codec_compat: bool,
+    snapshot_warc: bool,
+    ip_family: IpFamily,
+    sponsorblock: bool,
+    artifacts: Arc<ArtifactStore>,
+    delivery: Arc<DeliveryMonitor>,
+    presets: Arc<PresetCatalog>,
+    languages: Arc<LanguageDefaults>,
+    extra_args: Arc<ExtraArgsPolicy>,
+    telemetry: Option<Arc<Telemetry>>,
+    client_errors: Option<Arc<ClientErrorLog>>,
+    playlist: Arc<PlaylistLimits>,
+    workers: Option<Arc<WorkerPool>>,
+    worker_secret: Option<String>,
+    registry: Option<Arc<NodeRegistry>>,
+    request_signer: Option<Arc<RequestSigner>>,
+    email_verification: Option<Arc<EmailVerification>>,
+    allowed_origins: Arc<HashSet<String>>,
+    embed_sites: Arc<EmbedSites>,
+    throughput: Arc<ThroughputStats>,
+    stats: Arc<DownloadStats>,
+    credentials: Arc<CredentialStore>,
+    domain_headers: Arc<DomainHeaders>,
+    receipts: Option<Arc<ReceiptSigner>>,
+    quota: Arc<QuotaSchedule>,
 }
 
 type RateLimitMap = HashMap<String, Vec<DateTime<Utc>>>;
 type AntiBotChallengeMap = HashMap<String, AntiBotChallenge>;
 
-const DOWNLOAD_LIMIT_PER_DAY: usize = 10;
-const DOWNLOAD_WINDOW_HOURS: i64 = 24;
 const ANTIBOT_DIFFICULTY_HEX_PREFIX: usize = 3;
+const ANTIBOT_MAX_DIFFICULTY_HEX_PREFIX: usize = 5;
+const ANTIBOT_MAX_SUBMIT_DELAY_SECONDS: i64 = 10 * 60;
 const ANTIBOT_CHALLENGE_TTL_SECONDS: i64 = 5 * 60;
 const ANTIBOT_MIN_ELAPSED_MS: u64 = 900;
-const MAX_ANTIBOT_CHALLENGES: usize = 20_000;
 const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 3;
-const YT_DLP_TIMEOUT_SECONDS: u64 = 180;
-const MAX_DOWNLOAD_BYTES: u64 = 250 * 1024 * 1024;
 const TURNSTILE_TIMEOUT_SECONDS: u64 = 10;
 const DOWNLOAD_JOB_RETENTION_SECONDS: u64 = 20 * 60;
+const DEFAULT_SIGNED_LINK_TTL_SECONDS: u64 = 5 * 60;
+const JOB_POLL_RETRY_SECONDS: u64 = 2;
 const STALE_DOWNLOAD_JOB_SECONDS: u64 = 2 * 60 * 60;
-const HISTORY_PER_IP_LIMIT: usize = 10;
-const HISTORY_MAX_ENTRIES: usize = 2_000;
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
+const PERIODIC_CLEANUP_SECONDS: u64 = 10 * 60;
+const STREAM_RETRY_AFTER_SECONDS: u64 = 5;
+const DEFAULT_MAX_CONCURRENT_METADATA: usize = 2;
+const DEFAULT_METADATA_TIMEOUT_SECONDS: u64 = 45;
+const METADATA_QUEUE_WAIT_MS: u64 = 5_000;
+const METADATA_RETRY_AFTER_SECONDS: u64 = 10;
+const WORKER_RETRY_AFTER_SECONDS: u64 = 15;
+const AUTOMATIC_VIDEO_SELECTOR: &str = "bestvideo+bestaudio/best";
+const AUTOMATIC_AUDIO_SELECTOR: &str = "bestaudio";
+const MAX_SUBTITLE_LANGUAGES: usize = 8;
+const MAX_LOGGED_STDERR_LINES: usize = 40;
+const SPONSORBLOCK_CATEGORIES: [&str; 10] = [
+    "sponsor",
+    "intro",
+    "outro",
+    "selfpromo",
+    "preview",
+    "filler",
+    "interaction",
+    "music_offtopic",
+    "chapter",
+    "all",
+];
+const SUPPORTED_DOMAINS: [&str; 14] = [
+    "youtube.com",
+    "youtu.be",
+    "x.com",
+    "twitter.com",
+    "facebook.com",
+    "fb.watch",
+    "instagram.com",
+    "bsky.app",
+    "tiktok.com",
+    "vm.tiktok.com",
+    "vt.tiktok.com",
+    "m.youtube.com",
+    "music.youtube.com",
+    "m.facebook.com",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 enum DownloadMode {
     Video,
     Audio,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 enum DownloadStatus {
     Success,
@@ -85,6 +309,8 @@ struct HistoryEntry {
     created_at: DateTime<Utc>,
     #[serde(default, skip_serializing)]
     requester_ip: String,
+    #[serde(default, skip_serializing)]
+    remote_ip: String,
     url: String,
     title: Option<String>,
     thumbnail: Option<String>,
@@ -93,6 +319,16 @@ struct HistoryEntry {
     status: DownloadStatus,
     saved_path: Option<String>,
     error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    job_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    artifact_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    clip: Option<ClipRange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duration_seconds: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -100,21 +336,99 @@ struct FormatsRequest {
     url: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone)]
+struct CachedFormats {
+    response: Arc<FormatsResponse>,
+    fetched_at: DateTime<Utc>,
+    last_used: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 struct FormatsResponse {
     title: String,
+    #[serde(skip)]
+    source_title: Option<String>,
     thumbnail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_seconds: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uploader: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upload_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    view_count: Option<u64>,
     video_options: Vec<FormatOption>,
     audio_options: Vec<FormatOption>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 struct FormatOption {
     format_id: String,
     label: String,
     resolution: Option<String>,
     ext: String,
     has_audio: bool,
+    #[serde(flatten)]
+    details: FormatDetails,
+    #[serde(skip)]
+    size_bytes: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    merged_size_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    estimated_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct FormatDetails {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fps: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filesize_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bitrate_kbps: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vcodec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acodec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format_note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct FormatsResponseV2<'a> {
+    api_version: u8,
+    title: Option<&'a str>,
+    thumbnail: Option<&'a str>,
+    duration_seconds: Option<f64>,
+    uploader: Option<&'a str>,
+    upload_date: Option<&'a str>,
+    view_count: Option<u64>,
+    video_options: Vec<FormatOptionV2<'a>>,
+    audio_options: Vec<FormatOptionV2<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct FormatOptionV2<'a> {
+    format_id: &'a str,
+    automatic: bool,
+    ext: &'a str,
+    has_audio: bool,
+    #[serde(flatten)]
+    details: &'a FormatDetails,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    merged_size_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    estimated_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ApiVersion {
+    V1,
+    V2,
 }
 
 #[derive(Debug, Deserialize)]
@@ -131,6 +445,176 @@ struct DownloadRequest {
     antibot_honey: Option<String>,
     antibot_elapsed_ms: Option<u64>,
     turnstile_token: Option<String>,
+    promo_code: Option<String>,
+    job_id: Option<Uuid>,
+    embed_metadata: Option<bool>,
+    #[serde(default, rename = "async")]
+    respond_async: bool,
+    #[serde(default)]
+    playlist: bool,
+    #[serde(default)]
+    signed_link: bool,
+    #[serde(skip)]
+    link_base: String,
+    #[serde(skip)]
+    remote_ip: String,
+    #[serde(flatten)]
+    hints: FormatHints,
+    preset: Option<String>,
+    embed_subtitles: Option<Vec<String>>,
+    language: Option<String>,
+    start_time: Option<ClipTime>,
+    end_time: Option<ClipTime>,
+    #[serde(skip)]
+    clip: Option<ClipRange>,
+    clips: Option<Vec<ClipRequest>>,
+    #[serde(skip)]
+    clip_ranges: Vec<ClipRange>,
+    #[serde(default)]
+    merge: bool,
+    #[serde(default)]
+    merge_reencode: bool,
+    #[serde(default)]
+    split_chapters: bool,
+    extra_args: Option<Vec<String>>,
+    sponsorblock: Option<SponsorBlockRequest>,
+    audio_format: Option<String>,
+    container: Option<String>,
+    #[serde(default)]
+    compatibility: bool,
+    #[serde(skip)]
+    codec_profile: Option<CodecProfile>,
+    #[serde(skip)]
+    premium: Option<PolicyLimits>,
+    #[serde(skip)]
+    api_key: Option<ApiKeyGrant>,
+    embed_thumbnail: Option<bool>,
+    audio_tags: Option<AudioTags>,
+    streams: Option<StreamSelection>,
+    sidecars: Option<SidecarRequest>,
+    #[serde(default)]
+    snapshot: bool,
+    #[serde(default)]
+    warc: bool,
+    ip_family: Option<IpFamily>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SponsorBlockRequest {
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+impl DownloadRequest {
+    fn sponsorblock_categories(&self) -> &[String] {
+        self.sponsorblock
+            .as_ref()
+            .map(|sponsorblock| sponsorblock.remove.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct FormatHints {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_height: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    audio_language: Option<Language>,
+}
+
+impl FormatHints {
+    fn filter(&self, mode: &DownloadMode) -> String {
+        let mut filter = String::new();
+        if let (DownloadMode::Video, Some(height)) =
+            (mode, self.max_height.filter(|height| *height > 0))
+        {
+            filter.push_str(&format!("[height<={height}]"));
+        }
+        if let Some(bytes) = self.max_bytes.filter(|bytes| *bytes > 0) {
+            filter.push_str(&format!("[filesize<=?{bytes}][filesize_approx<=?{bytes}]"));
+        }
+        filter
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ClipRequest {
+    start_time: Option<ClipTime>,
+    end_time: Option<ClipTime>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ClipTime {
+    Seconds(f64),
+    Timestamp(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct ClipRange {
+    start_seconds: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end_seconds: Option<f64>,
+}
+
+impl ClipTime {
+    fn seconds(&self) -> Option<f64> {
+        let seconds = match self {
+            Self::Seconds(seconds) => *seconds,
+            Self::Timestamp(value) => {
+                let parts: Vec<&str> = value.trim().split(':').collect();
+                if parts.len() > 3 || parts.iter().any(|part| part.is_empty()) {
+                    return None;
+                }
+                let (leading, rest) = parts.split_first()?;
+                let mut seconds = leading.parse::<f64>().ok()?;
+                for part in rest {
+                    let value = part.parse::<f64>().ok()?;
+                    if !(0.0..60.0).contains(&value) {
+                        return None;
+                    }
+                    seconds = seconds * 60.0 + value;
+                }
+                seconds
+            }
+        };
+        (seconds.is_finite() && seconds >= 0.0).then_some(seconds)
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Seconds(seconds) => seconds.to_string(),
+            Self::Timestamp(value) => value.clone(),
+        }
+    }
+}
+
+impl ClipRange {
+    fn section(&self) -> String {
+        match self.end_seconds {
+            Some(end) => format!("*{}-{end}", self.start_seconds),
+            None => format!("*{}-inf", self.start_seconds),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AsyncDownloadResponse {
+    job_id: Uuid,
+    status_url: String,
+    progress_url: String,
+    file_url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SignedLinkResponse {
+    job_id: Uuid,
+    file_url: String,
+    expires_at: DateTime<Utc>,
+    filename: String,
+    size_bytes: u64,
 }
 
 #[derive(Debug, Serialize)]
@@ -140,6 +624,8 @@ struct ErrorBody {
     code: Option<&'static str>,
     #[serde(skip_serializing_if = "Option::is_none")]
     retry_after_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
 #[derive(Debug)]
@@ -169,11 +655,11 @@ impl ApiError {
         }
     }
 
-    fn daily_limit_exceeded(retry_after_seconds: u64) -> Self {
+    fn daily_limit_exceeded(limit: usize, window_hours: i64, retry_after_seconds: u64) -> Self {
         Self {
             status: StatusCode::TOO_MANY_REQUESTS,
             message: format!(
-                "Has superado el limite de {DOWNLOAD_LIMIT_PER_DAY} descargas por IP en 24 horas."
+                "Has superado el limite de {limit} descargas por IP en {window_hours} horas."
             ),
             code: Some("DAILY_LIMIT_EXCEEDED"),
             retry_after_seconds: Some(retry_after_seconds),
@@ -188,6 +674,190 @@ impl ApiError {
             retry_after_seconds: None,
         }
     }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message: message.into(),
+            code: Some("NOT_FOUND"),
+            retry_after_seconds: None,
+        }
+    }
+
+    fn workers_unavailable() -> Self {
+        Self {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message:
+                "No hay workers disponibles para procesar la descarga. Reintenta en unos segundos."
+                    .to_string(),
+            code: Some("WORKERS_UNAVAILABLE"),
+            retry_after_seconds: Some(WORKER_RETRY_AFTER_SECONDS),
+        }
+    }
+
+    fn remote(status: u16, message: String) -> Self {
+        Self {
+            status: StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY),
+            message,
+            code: None,
+            retry_after_seconds: None,
+        }
+    }
+
+    fn ip_banned(expires_at: Option<DateTime<Utc>>) -> Self {
+        let message = match expires_at {
+            Some(expires_at) => format!(
+                "Tu IP esta bloqueada hasta {}.",
+                expires_at.format("%Y-%m-%d %H:%M UTC")
+            ),
+            None => "Tu IP esta bloqueada en este servicio.".to_string(),
+        };
+        Self {
+            status: StatusCode::FORBIDDEN,
+            message,
+            code: Some("IP_BANNED"),
+            retry_after_seconds: None,
+        }
+    }
+
+    fn forbidden(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            message: message.into(),
+            code: Some("FORBIDDEN"),
+            retry_after_seconds: None,
+        }
+    }
+
+    fn unauthorized(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::UNAUTHORIZED,
+            message: message.into(),
+            code: Some("UNAUTHORIZED"),
+            retry_after_seconds: None,
+        }
+    }
+
+    fn invalid_api_key() -> Self {
+        Self {
+            status: StatusCode::UNAUTHORIZED,
+            message: "La clave de API no es valida o fue revocada.".to_string(),
+            code: Some("INVALID_API_KEY"),
+            retry_after_seconds: None,
+        }
+    }
+
+    fn policy_denied(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            message: message.into(),
+            code: Some("POLICY_DENIED"),
+            retry_after_seconds: None,
+        }
+    }
+
+    fn streams_saturated(retry_after_seconds: u64) -> Self {
+        Self {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: "El servidor alcanzo el limite de transferencias simultaneas. Reintenta en unos segundos.".to_string(),
+            code: Some("STREAMS_SATURATED"),
+            retry_after_seconds: Some(retry_after_seconds),
+        }
+    }
+
+    fn metadata_saturated(retry_after_seconds: u64) -> Self {
+        Self {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: "Hay demasiadas consultas de formatos en curso. Reintenta en unos segundos."
+                .to_string(),
+            code: Some("METADATA_SATURATED"),
+            retry_after_seconds: Some(retry_after_seconds),
+        }
+    }
+
+    fn verification_cooldown(message: impl Into<String>, retry_after_seconds: u64) -> Self {
+        Self {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            message: message.into(),
+            code: Some("VERIFICATION_COOLDOWN"),
+            retry_after_seconds: Some(retry_after_seconds),
+        }
+    }
+
+    fn file_too_large(max_download_bytes: u64) -> Self {
+        let max_mb = max_download_bytes / 1_048_576;
+        Self {
+            status: StatusCode::PAYLOAD_TOO_LARGE,
+            message: format!("El archivo supera el limite permitido de {max_mb} MB."),
+            code: Some("FILE_TOO_LARGE"),
+            retry_after_seconds: None,
+        }
+    }
+
+    fn history_disabled() -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message: "El historial esta deshabilitado en este servidor.".to_string(),
+            code: Some("HISTORY_DISABLED"),
+            retry_after_seconds: None,
+        }
+    }
+
+    fn client_errors_rate_limited(retry_after_seconds: u64) -> Self {
+        Self {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            message: "Se enviaron demasiados reportes de error desde esta IP.".to_string(),
+            code: Some("CLIENT_ERRORS_RATE_LIMITED"),
+            retry_after_seconds: Some(retry_after_seconds),
+        }
+    }
+
+    fn job_pending(retry_after_seconds: u64) -> Self {
+        Self {
+            status: StatusCode::CONFLICT,
+            message: "La descarga todavia se esta procesando.".to_string(),
+            code: Some("JOB_PENDING"),
+            retry_after_seconds: Some(retry_after_seconds),
+        }
+    }
+
+    fn job_failed(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::CONFLICT,
+            message: message.into(),
+            code: Some("JOB_FAILED"),
+            retry_after_seconds: None,
+        }
+    }
+
+    fn job_cancelled() -> Self {
+        Self {
+            status: StatusCode::CONFLICT,
+            message: "La descarga fue cancelada.".to_string(),
+            code: Some("JOB_CANCELLED"),
+            retry_after_seconds: None,
+        }
+    }
+
+    fn upload_offset_mismatch(received_bytes: u64) -> Self {
+        Self {
+            status: StatusCode::CONFLICT,
+            message: format!(
+                "El fragmento no continua la subida; reanuda desde el byte {received_bytes}."
+            ),
+            code: Some("UPLOAD_OFFSET_MISMATCH"),
+            retry_after_seconds: None,
+        }
+    }
+
+    fn invalid_signature(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            message: message.into(),
+            code: Some("INVALID_SIGNATURE"),
+            retry_after_seconds: None,
+        }
+    }
 }
 
 impl IntoResponse for ApiError {
@@ -196,6 +866,7 @@ impl IntoResponse for ApiError {
             error: self.message,
             code: self.code,
             retry_after_seconds: self.retry_after_seconds,
+            request_id: clienterrors::current_request_id(),
         });
 
         let mut response = (self.status, body).into_response();
@@ -213,6 +884,10 @@ impl IntoResponse for ApiError {
 struct YtDlpVideoInfo {
     title: Option<String>,
     thumbnail: Option<String>,
+    duration: Option<f64>,
+    uploader: Option<String>,
+    upload_date: Option<String>,
+    view_count: Option<u64>,
     formats: Vec<YtDlpFormat>,
 }
 
@@ -225,25 +900,113 @@ struct YtDlpFormat {
     height: Option<u32>,
     fps: Option<f32>,
     format_note: Option<String>,
+    language: Option<String>,
     tbr: Option<f32>,
     filesize: Option<f64>,
     filesize_approx: Option<f64>,
     abr: Option<f32>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct AntiBotChallenge {
     nonce: String,
     created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    difficulty: usize,
     ip: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct AntiBotChallengeQuery {
+    submit_in_seconds: Option<i64>,
+    difficulty: Option<usize>,
+}
+
 #[derive(Debug, Serialize)]
 struct AntiBotChallengeResponse {
     challenge_id: String,
     nonce: String,
     difficulty: usize,
     expires_in_seconds: i64,
+    expires_at: DateTime<Utc>,
+    downloads_saturated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct AntiBotVerifyRequest {
+    challenge_id: String,
+    solution: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct AntiBotVerifyResponse {
+    valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_in_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct CapabilitiesResponse {
+    version: &'static str,
+    supported_domains: Vec<String>,
+    features: CapabilityFlags,
+    limits: CapabilityLimits,
+    presets: Vec<DownloadPreset>,
+    extra_args: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CapabilityFlags {
+    extractor_plugins: bool,
+    turnstile: bool,
+    history: bool,
+    history_feed: bool,
+    promo_codes: bool,
+    policy_hook: bool,
+    job_metadata: bool,
+    codec_compat: bool,
+    snapshot_warc: bool,
+    sponsorblock: bool,
+    remote_workers: bool,
+    request_signing: bool,
+    email_verification: bool,
+    oidc_login: bool,
+    embed_api: bool,
+    dynamic_quota: bool,
+    download_receipts: bool,
+    telemetry: bool,
+    client_errors: bool,
+    demo_mode: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct CapabilityLimits {
+    daily_downloads: usize,
+    window_hours: i64,
+    max_download_bytes: u64,
+    snapshot_max_download_bytes: u64,
+    quota_policy: QuotaPolicy,
+}
+
+#[derive(Debug, Serialize)]
+struct FeedTokenResponse {
+    token: String,
+    feed_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeedQuery {
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SignedFileQuery {
+    #[serde(default)]
+    job: Option<Uuid>,
+    expires: i64,
+    sig: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -253,8 +1016,20 @@ struct TurnstileVerifyResponse {
     error_codes: Vec<String>,
 }
 
-#[tokio::main]
-async fn main() {
+fn main() {
+    let cli = match Cli::parse(std::env::args().skip(1)) {
+        Ok(Some(cli)) => cli,
+        Ok(None) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("{error}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let settings = settings::apply_config_file(cli.config.clone());
+    cli.apply_overrides();
     tracing_subscriber::fmt()
         .with_env_filter(
             std::env::var("RUST_LOG")
@@ -262,44 +1037,213 @@ async fn main() {
         )
         .init();
 
-    if let Err(error) = run().await {
+    match settings {
+        Ok(Some(loaded)) => {
+            info!(
+                "Configuracion cargada desde {} ({} valores).",
+                loaded.path.display(),
+                loaded.applied.len()
+            );
+            if !loaded.overridden.is_empty() {
+                info!(
+                    "Variables de entorno con prioridad sobre el archivo: {}.",
+                    loaded.overridden.join(", ")
+                );
+            }
+        }
+        Ok(None) => {}
+        Err(error) => {
+            eprintln!("Server error: {}", error.message);
+            std::process::exit(1);
+        }
+    }
+
+    let result = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|error| ApiError::internal(format!("No se pudo iniciar el runtime: {error}")))
+        .and_then(|runtime| {
+            runtime.block_on(async {
+                if !cli.check {
+                    return run().await;
+                }
+                if cli::run_check().await {
+                    Ok(())
+                } else {
+                    std::process::exit(1);
+                }
+            })
+        });
+    if let Err(error) = result {
         eprintln!("Server error: {}", error.message);
         std::process::exit(1);
     }
 }
 
 async fn run() -> Result<(), ApiError> {
-    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-
-    let data_dir = root.join("data");
-    let transfer_dir = root.join("temp_downloads");
-    let history_path = data_dir.join("history.json");
-    let rate_limit_path = data_dir.join("rate_limits.json");
-
-    tokio::fs::create_dir_all(&data_dir)
-        .await
-        .map_err(|error| {
-            ApiError::internal(format!("No se pudo crear la carpeta de datos: {error}"))
-        })?;
-    tokio::fs::create_dir_all(&transfer_dir)
-        .await
-        .map_err(|error| {
-            ApiError::internal(format!(
-                "No se pudo crear la carpeta temporal de descargas: {error}"
-            ))
-        })?;
+    let config = Config::from_env()?;
 
-    let history = load_history(&history_path).await?;
-    let rate_limits = load_rate_limits(&rate_limit_path).await?;
-    let max_concurrent_downloads = read_usize_env("MAX_CONCURRENT_DOWNLOADS")
-        .filter(|value| *value > 0)
-        .unwrap_or(DEFAULT_MAX_CONCURRENT_DOWNLOADS);
+    let layout = DataLayout::from_env();
+    layout.create_dirs().await?;
+    layout.migrate_legacy().await?;
+    let DataLayout {
+        data_dir,
+        transfer_dir,
+        artifact_dir,
+        ..
+    } = layout;
+    let storage = storage::from_env(&data_dir)?;
+    let redis = RedisStore::from_env()?.map(Arc::new);
+    if let Some(redis) = &redis {
+        redis.ping().await?;
+    }
+    let usage = if read_bool_env("USAGE_ACCOUNTING_ENABLED").unwrap_or(false) {
+        Some(Arc::new(UsageLedger::open(data_dir.join("usage")).await?))
+    } else {
+        None
+    };
+    let promo_path = data_dir.join("promo_codes.json");
+    let promo_audit_path = data_dir.join("promo_audit.jsonl");
+    let verification_path = data_dir.join("verified_emails.json");
+    let throughput_path = data_dir.join("throughput.json");
+    let stats_path = data_dir.join("download_stats.json");
+    let client_errors_path = data_dir.join("client_errors.jsonl");
+    let credentials_dir = data_dir.join("credentials");
+    let artifact_index_path = data_dir.join("artifacts.json");
+    let domain_headers_path = data_dir.join("domain_headers.json");
+    let entitlements_path = data_dir.join("entitlements.json");
+    let api_keys_path = data_dir.join("api_keys.json");
+    let leak_tags_path = data_dir.join("leak_tags.jsonl");
+    let system = SystemMonitor::new(vec![
+        ("data", data_dir.clone()),
+        ("transfer", transfer_dir.clone()),
+        ("artifacts", artifact_dir.clone()),
+    ]);
+
+    let history_enabled = read_bool_env("HISTORY_ENABLED").unwrap_or(true);
+    let (history, rate_limits) = if history_enabled {
+        (
+            storage.load_history(&config).await?,
+            storage
+                .load_rate_limits(config.download_window_hours)
+                .await?,
+        )
+    } else {
+        info!("Historial deshabilitado: no se guardan URLs ni IPs en disco.");
+        (Vec::new(), HashMap::new())
+    };
+    let bans = storage.load_bans().await?;
+    if let Some(redis) = &redis {
+        bans::publish_stored_bans(redis, &bans).await?;
+    }
+    let promo_store = load_promo_store(&promo_path).await?;
+    let email_verification =
+        EmailVerification::from_env(verification_path, config.download_limit_per_day).await?;
+    if email_verification.is_some() {
+        info!("Verificacion por email habilitada.");
+    }
+    let client_errors = if history_enabled {
+        ClientErrorLog::from_env(client_errors_path).await?
+    } else {
+        None
+    };
+    let memory = Arc::new(MemoryBudget::from_env());
+    let artifacts = ArtifactStore::open(artifact_dir, artifact_index_path).await?;
+    let throughput = ThroughputStats::load(throughput_path).await?;
+    let stats = Arc::new(DownloadStats::load(stats_path).await?);
+    let credentials = Arc::new(CredentialStore::open(credentials_dir).await?);
+    let domain_headers = Arc::new(DomainHeaders::open(domain_headers_path).await?);
+    let receipts = ReceiptSigner::from_env(&data_dir, history_enabled)
+        .await?
+        .map(Arc::new);
+    let embed_sites = EmbedSites::from_env();
+    let allowed_origins = load_allowed_origins(embed_sites.origins())?;
+    let max_concurrent_downloads = lowmem::concurrency(
+        read_usize_env("MAX_CONCURRENT_DOWNLOADS")
+            .filter(|value| *value > 0)
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_DOWNLOADS),
+    );
+    let max_concurrent_metadata = lowmem::concurrency(
+        read_usize_env("MAX_CONCURRENT_METADATA")
+            .filter(|value| *value > 0)
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_METADATA),
+    );
+    let download_semaphore = Arc::new(Semaphore::new(max_concurrent_downloads));
+    let concurrency =
+        ConcurrencyTuner::from_env(Arc::clone(&download_semaphore), max_concurrent_downloads)
+            .map(Arc::new);
+    let metadata_timeout_seconds = read_usize_env("METADATA_TIMEOUT_SECONDS")
+        .filter(|value| *value > 0)
+        .map_or(DEFAULT_METADATA_TIMEOUT_SECONDS, |value| value as u64);
     let trust_proxy_headers = read_bool_env("TRUST_PROXY_HEADERS").unwrap_or(false);
+    let embed_job_metadata = read_bool_env("EMBED_JOB_METADATA").unwrap_or(false);
+    // Past the job retention the artifact is released, so a longer link would only 404.
+    let signed_link_ttl_seconds = read_usize_env("SIGNED_LINK_TTL_SECONDS")
+        .filter(|value| *value > 0)
+        .map_or(DEFAULT_SIGNED_LINK_TTL_SECONDS, |value| value as u64)
+        .min(DOWNLOAD_JOB_RETENTION_SECONDS);
+    let codec_compat = read_bool_env("CODEC_COMPAT_MODE").unwrap_or(false);
+    let snapshot_warc = read_bool_env("SNAPSHOT_WARC_ENABLED").unwrap_or(false);
+    let ip_family = IpFamily::from_env()?;
+    if ip_family != IpFamily::Any {
+        info!("Salida de red forzada a {}", ip_family.as_str());
+    }
+    let sponsorblock = read_bool_env("SPONSORBLOCK_ENABLED").unwrap_or(true);
     let turnstile_secret_key = std::env::var("TURNSTILE_SECRET_KEY")
         .ok()
         .and_then(|value| non_empty(&value).map(ToString::to_string));
-    let http_client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(TURNSTILE_TIMEOUT_SECONDS))
+    let signing_secret = match std::env::var("SIGNING_SECRET")
+        .ok()
+        .and_then(|value| non_empty(&value).map(ToString::to_string))
+    {
+        Some(secret) => secret.into_bytes(),
+        None => {
+            warn!(
+                "SIGNING_SECRET no configurado. Se usara una clave temporal y los enlaces firmados caducaran al reiniciar."
+            );
+            [Uuid::new_v4().into_bytes(), Uuid::new_v4().into_bytes()].concat()
+        }
+    };
+    let leak_tags = LeakTags::from_env(leak_tags_path, &signing_secret, history_enabled)
+        .await?
+        .map(Arc::new);
+    let public_base_url = std::env::var("PUBLIC_BASE_URL")
+        .ok()
+        .and_then(|value| non_empty(&value).map(|url| url.trim_end_matches('/').to_string()));
+    let registry = NodeRegistry::from_env(public_base_url.as_deref())
+        .await?
+        .map(Arc::new);
+    if let Some(registry) = &registry {
+        info!(
+            "Registro de nodos habilitado para el nodo {}",
+            registry.node_id()
+        );
+    }
+    let worker_secret = std::env::var("WORKER_SHARED_SECRET")
+        .ok()
+        .and_then(|value| non_empty(&value).map(ToString::to_string));
+    let workers = WorkerPool::from_env(worker_secret.as_deref())?.map(Arc::new);
+    if let Some(workers) = &workers {
+        info!(
+            "Workers remotos configurados: {:?} (respaldo local: {})",
+            workers.urls(),
+            workers.fallback_local()
+        );
+    }
+    let dns_resolver = DnsResolver::from_env()?;
+    let dns_downloader_args = dns_resolver
+        .as_ref()
+        .map(DnsResolver::downloader_args)
+        .unwrap_or_default();
+    let mut http_client =
+        reqwest::Client::builder().timeout(Duration::from_secs(TURNSTILE_TIMEOUT_SECONDS));
+    if let Some(resolver) = dns_resolver {
+        http_client = http_client.dns_resolver(Arc::new(resolver));
+    }
+    if let Some(local_address) = ip_family.local_address() {
+        http_client = http_client.local_address(local_address);
+    }
+    let http_client = http_client
         .build()
         .map_err(|error| ApiError::internal(format!("No se pudo crear cliente HTTP: {error}")))?;
 
@@ -308,6 +1252,57 @@ async fn run() -> Result<(), ApiError> {
             "TRUST_PROXY_HEADERS=false: se usara la IP del socket para limitar descargas y anti-bot."
         );
     }
+    let child_priority = priority::child_priority();
+    if child_priority.is_enabled() {
+        info!("Prioridad de procesos hijos: {child_priority:?}");
+    }
+    let policy_hook = PolicyHook::from_env().map(Arc::new);
+    if let Some(hook) = &policy_hook {
+        info!("Hook de politica habilitado: {:?}", hook.command());
+    }
+    let plugin_dirs = plugins::plugin_dirs_from_env();
+    let extra_supported_domains = read_list_env("YT_DLP_PLUGIN_DOMAINS");
+    if !plugin_dirs.is_empty() {
+        let discovered = plugins::discover_plugins(&plugin_dirs).await;
+        info!(
+            "Plugins de yt-dlp cargados desde {:?}: {} modulo(s), dominios extra {:?}",
+            plugin_dirs,
+            discovered.len(),
+            extra_supported_domains
+        );
+    }
+    let mut common_args = plugins::plugin_args(&plugin_dirs);
+    common_args.extend(dns_downloader_args);
+    common_args.extend(ip_family.args());
+    common_args.extend(lowmem::extractor_args());
+    let extractor = ExtractorRouter::from_env(
+        common_args,
+        Arc::clone(&credentials),
+        Arc::clone(&domain_headers),
+    );
+    if let Some(candidate) = extractor.candidate() {
+        info!("Binario candidato de yt-dlp configurado: {candidate}");
+    }
+    let shadow_extractor = ShadowExtractor::from_env().map(Arc::new);
+    if let Some(shadow) = &shadow_extractor {
+        info!(
+            "Modo shadow habilitado con {} para {}% de las consultas de metadatos.",
+            shadow.command(),
+            shadow.sample_percent()
+        );
+    }
+    let auth = OidcAuth::from_env().map(Arc::new);
+    if let Some(auth) = &auth {
+        info!("Login OIDC habilitado con el proveedor {}", auth.issuer());
+    }
+    let entitlements = Entitlements::from_env(entitlements_path)
+        .await?
+        .map(Arc::new);
+    if entitlements.is_some() && auth.is_none() {
+        warn!(
+            "Plan premium habilitado sin login OIDC: ningun visitante podra identificarse como premium."
+        );
+    }
     if turnstile_secret_key.is_some() {
         info!("Turnstile habilitado para verificacion anti-bot.");
     } else {
@@ -316,28 +1311,329 @@ async fn run() -> Result<(), ApiError> {
 
     let state = AppState {
         history: Arc::new(Mutex::new(history)),
-        history_path,
+        history_enabled,
         rate_limits: Arc::new(Mutex::new(rate_limits)),
-        rate_limit_path,
+        bans: Arc::new(Mutex::new(bans)),
+        storage,
         anti_bot_challenges: Arc::new(Mutex::new(HashMap::new())),
-        download_semaphore: Arc::new(Semaphore::new(max_concurrent_downloads)),
+        redis,
+        usage,
+        entitlements,
+        api_keys: Arc::new(ApiKeys::from_env(api_keys_path).await?),
+        demo: DemoMode::from_env().map(Arc::new),
+        leak_tags,
+        download_semaphore,
+        concurrency,
+        metadata_semaphore: Arc::new(Semaphore::new(max_concurrent_metadata)),
+        metadata_timeout: Duration::from_secs(metadata_timeout_seconds),
         trust_proxy_headers,
         turnstile_secret_key,
+        telemetry: Telemetry::from_env(http_client.clone()).map(Arc::new),
+        client_errors: client_errors.map(Arc::new),
         http_client,
         transfer_dir,
+        signing_secret: Arc::new(signing_secret),
+        public_base_url,
+        roles: Arc::new(RoleTokens::from_env()),
+        auth,
+        sessions: AnonSessions::from_env().map(Arc::new),
+        promo: Arc::new(Mutex::new(promo_store)),
+        promo_path,
+        promo_audit_path,
+        policy_hook,
+        shadow_extractor,
+        extractor: Arc::new(extractor),
+        plugin_dirs: Arc::new(plugin_dirs),
+        extra_supported_domains: Arc::new(extra_supported_domains),
+        formats_cache: Arc::new(Mutex::new(HashMap::new())),
+        jobs: Arc::new(JobRegistry::new(Arc::clone(&memory))),
+        memory,
+        system: Arc::new(system),
+        readiness: Arc::new(ReadinessCache::default()),
+        supervisor: Arc::new(TaskSupervisor::default()),
+        embed_job_metadata,
+        signed_link_ttl_seconds,
+        codec_compat,
+        snapshot_warc,
+        ip_family,
+        sponsorblock,
+        artifacts: Arc::new(artifacts),
+        delivery: Arc::new(DeliveryMonitor::from_env(Arc::clone(&stats))),
+        presets: Arc::new(PresetCatalog::from_env()),
+        languages: Arc::new(LanguageDefaults::from_env()),
+        extra_args: Arc::new(ExtraArgsPolicy::from_env()),
+        playlist: Arc::new(PlaylistLimits::from_env()),
+        workers,
+        worker_secret,
+        registry,
+        request_signer: RequestSigner::from_env().map(Arc::new),
+        email_verification: email_verification.map(Arc::new),
+        allowed_origins: Arc::new(allowed_origins),
+        embed_sites: Arc::new(embed_sites),
+        throughput: Arc::new(throughput),
+        stats,
+        credentials,
+        domain_headers,
+        receipts,
+        quota: Arc::new(QuotaSchedule::from_env(config.download_limit_per_day)),
+        config: Arc::new(config),
     };
 
     cleanup_stale_download_jobs(&state.transfer_dir, STALE_DOWNLOAD_JOB_SECONDS).await;
+    let supervisor = Arc::clone(&state.supervisor);
+    let extractor = Arc::clone(&state.extractor);
+    supervisor
+        .spawn("impersonation", RestartPolicy::OnPanic, move || {
+            let extractor = Arc::clone(&extractor);
+            async move { extractor.detect_impersonation().await }
+        })
+        .await;
+    if let Some(telemetry) = &state.telemetry {
+        let telemetry = Arc::clone(telemetry);
+        supervisor
+            .spawn("telemetry", RestartPolicy::Always, move || {
+                Arc::clone(&telemetry).run_reporter()
+            })
+            .await;
+    }
+    if state.concurrency.is_some() {
+        let tuner_state = state.clone();
+        supervisor
+            .spawn("concurrency", RestartPolicy::Always, move || {
+                autotune::run_tuner(tuner_state.clone())
+            })
+            .await;
+    }
+    let cleanup_state = state.clone();
+    supervisor
+        .spawn("cleanup", RestartPolicy::Always, move || {
+            run_periodic_cleanup(cleanup_state.clone())
+        })
+        .await;
+
+    let cors = build_cors_layer(state.auth.is_some(), Arc::clone(&state.allowed_origins));
 
-    let cors = build_cors_layer()?;
+    let signed = middleware::from_fn_with_state(state.clone(), require_signed_request);
+    let login = middleware::from_fn_with_state(state.clone(), require_login);
+    let moderator_only =
+        middleware::from_fn_with_state((state.clone(), Role::Moderator), rbac::require_role);
+    let admin_only =
+        middleware::from_fn_with_state((state.clone(), Role::Admin), rbac::require_role);
+
+    let history_routes = Router::new()
+        .route("/api/history", get(get_history).delete(clear_history))
+        .route("/api/history/feed-token", get(get_history_feed_token))
+        .route("/api/history/feed", get(get_history_feed))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_history,
+        ));
+    let moderator_routes = Router::new()
+        .route("/api/admin/shadow", get(shadow::get_shadow_report))
+        .route("/api/admin/extractor", get(extractor::get_extractor_report))
+        .route("/api/admin/plugins", get(plugins::list_plugins))
+        .route("/api/admin/delivery", get(delivery::get_delivery_report))
+        .route("/api/admin/embeds", get(embed::get_embed_report))
+        .route(
+            "/api/admin/telemetry",
+            get(telemetry::get_telemetry_preview),
+        )
+        .route(
+            "/api/admin/throughput",
+            get(throughput::get_throughput_report),
+        )
+        .route(
+            "/api/admin/client-errors",
+            get(clienterrors::list_client_errors),
+        )
+        .route("/api/admin/memory", get(memory::get_memory_report))
+        .route("/api/admin/system", get(system::get_system_report))
+        .route(
+            "/api/admin/concurrency",
+            get(autotune::get_concurrency_report),
+        )
+        .route("/api/admin/tasks", get(supervisor::get_task_report))
+        .route(
+            "/api/admin/bans",
+            get(bans::list_bans).post(bans::create_ban),
+        )
+        .route("/api/admin/bans/{ip}", delete(bans::remove_ban))
+        .route_layer(moderator_only);
+    let admin_routes = Router::new()
+        .route(
+            "/api/admin/promo-codes",
+            get(promo::list_promo_codes).post(promo::create_promo_code),
+        )
+        .route(
+            "/api/admin/extractor",
+            put(extractor::update_extractor_routing),
+        )
+        .route("/api/admin/prefetch", post(artifacts::prefetch_artifact))
+        .route("/api/admin/credentials", get(credentials::list_credentials))
+        .route(
+            "/api/admin/credentials/uploads",
+            post(credentials::create_upload),
+        )
+        .route(
+            "/api/admin/credentials/uploads/{upload_id}",
+            get(credentials::get_upload).put(credentials::upload_chunk),
+        )
+        .route(
+            "/api/admin/credentials/uploads/{upload_id}/commit",
+            post(credentials::commit_upload),
+        )
+        .route(
+            "/api/admin/credentials/{platform}/{kind}/rollback",
+            post(credentials::rollback_credentials),
+        )
+        .route("/api/admin/headers", get(domain_headers::list_header_rules))
+        .route(
+            "/api/admin/headers/{domain}",
+            put(domain_headers::put_header_rule).delete(domain_headers::delete_header_rule),
+        )
+        .route("/api/admin/billing/export", get(billing::export_usage))
+        .route("/api/admin/stats", get(stats::get_admin_stats))
+        .route(
+            "/api/admin/api-keys",
+            get(apikeys::list_api_keys).post(apikeys::create_api_key),
+        )
+        .route(
+            "/api/admin/api-keys/{name}",
+            delete(apikeys::revoke_api_key),
+        )
+        .route(
+            "/api/admin/history",
+            get(history::list_history)
+                .delete(history::purge_history)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    require_history,
+                )),
+        )
+        .route(
+            "/api/admin/support-bundle",
+            get(support::get_support_bundle),
+        )
+        .route(
+            "/api/admin/leak-tags/verify",
+            post(leaktags::verify_leaked_file).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/api/admin/leak-tags/{tag}", get(leaktags::get_leak_tag))
+        .route(
+            "/api/admin/entitlements",
+            get(entitlements::list_entitlements),
+        )
+        .route_layer(admin_only);
 
     let app = Router::new()
         .route("/api/health", get(health))
+        .route("/api/health/ready", get(health::get_readiness))
+        .route("/api/capabilities", get(get_capabilities))
         .route("/api/antibot/challenge", get(create_antibot_challenge))
-        .route("/api/formats", post(fetch_formats))
-        .route("/api/download", post(start_download))
-        .route("/api/history", get(get_history).delete(clear_history))
-        .with_state(state)
+        .route("/api/antibot/verify", post(verify_antibot_challenge))
+        .route(
+            "/api/formats",
+            get(fetch_formats_by_query)
+                .post(fetch_formats)
+                .layer(signed.clone())
+                .layer(login.clone()),
+        )
+        .route(
+            "/api/v1/formats",
+            get(fetch_formats_by_query)
+                .post(fetch_formats)
+                .layer(signed.clone())
+                .layer(login.clone()),
+        )
+        .route(
+            "/api/v2/formats",
+            get(fetch_formats_v2_by_query)
+                .post(fetch_formats_v2)
+                .layer(signed.clone())
+                .layer(login.clone()),
+        )
+        .route(
+            "/api/thumbnail",
+            get(thumbnail::fetch_thumbnail_by_query)
+                .post(thumbnail::fetch_thumbnail)
+                .layer(signed.clone())
+                .layer(login.clone()),
+        )
+        .route(
+            "/api/download",
+            post(start_download)
+                .layer(signed.clone())
+                .layer(login.clone()),
+        )
+        .route(
+            "/api/convert",
+            post(convert::convert_upload)
+                .layer(DefaultBodyLimit::disable())
+                .layer(login),
+        )
+        .route(
+            "/api/client-errors",
+            post(clienterrors::report_client_error)
+                .layer(DefaultBodyLimit::max(clienterrors::MAX_REPORT_BYTES))
+                .layer(signed),
+        )
+        .route("/api/download/{job_id}", delete(jobs::cancel_job))
+        .route("/api/download/{job_id}/status", get(jobs::get_job_status))
+        .route(
+            "/api/download/{job_id}/progress",
+            get(jobs::get_job_progress),
+        )
+        .route("/api/download/{job_id}/logs", get(joblog::get_job_logs))
+        .route("/api/download/{job_id}/file", get(jobs::get_job_file))
+        .route("/api/ws", get(websocket::job_updates_socket))
+        .route(
+            "/api/receipts/public-key",
+            get(receipts::get_receipt_public_key),
+        )
+        .route("/api/receipts/{receipt_id}", get(receipts::get_receipt))
+        .route("/api/files/{artifact_hash}", get(download_signed_file))
+        .route(
+            "/api/artifacts/by-hash/{sha256}",
+            get(artifacts::get_artifact_by_hash),
+        )
+        .route("/api/promo/redeem", post(promo::redeem_promo))
+        .route(
+            "/api/verify/email",
+            post(verification::request_email_verification),
+        )
+        .route(
+            "/api/verify/email/confirm",
+            get(verification::confirm_email_verification),
+        )
+        .route(
+            "/api/verify/email/status",
+            get(verification::get_verification_status),
+        )
+        .route("/api/worker/produce", post(workers::produce_on_worker))
+        .route("/api/embed/jobs", post(embed::create_embed_job))
+        .route("/api/embed/jobs/{job_id}", get(embed::get_embed_job))
+        .route("/api/auth/login", get(auth::start_login))
+        .route("/api/auth/callback", get(auth::complete_login))
+        .route("/api/auth/session", get(auth::get_session))
+        .route("/api/auth/logout", post(auth::logout))
+        .route(
+            "/api/billing/stripe/webhook",
+            post(entitlements::stripe_webhook),
+        )
+        .route(
+            "/api/billing/entitlements/webhook",
+            post(entitlements::entitlement_webhook),
+        )
+        .merge(history_routes)
+        .merge(moderator_routes)
+        .merge(admin_routes)
+        .with_state(state.clone())
+        .layer(middleware::from_fn(problem::negotiate_problem_json))
+        .layer(middleware::from_fn(clienterrors::assign_request_id))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            sessions::issue_session,
+        ))
         .layer(cors);
 
     let addr = resolve_bind_addr();
@@ -347,34 +1643,91 @@ async fn run() -> Result<(), ApiError> {
 
     info!("Backend listo en http://{addr}");
 
-    axum::serve(
+    let served = axum::serve(
         listener,
         app.into_make_service_with_connect_info::<SocketAddr>(),
     )
+    .with_graceful_shutdown(shutdown_signal())
     .await
-    .map_err(|error| ApiError::internal(format!("Error del servidor HTTP: {error}")))
+    .map_err(|error| ApiError::internal(format!("Error del servidor HTTP: {error}")));
+    info!("Deteniendo tareas en segundo plano...");
+    supervisor.shutdown().await;
+    state.stats.flush().await;
+    served
 }
 
 async fn health() -> Json<serde_json::Value> {
     Json(serde_json::json!({"status": "ok"}))
 }
 
+async fn get_capabilities(State(state): State<AppState>) -> Json<CapabilitiesResponse> {
+    let now = Utc::now();
+    let active_jobs = state.jobs.active_count().await;
+    Json(CapabilitiesResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        supported_domains: SUPPORTED_DOMAINS
+            .iter()
+            .map(ToString::to_string)
+            .chain(state.extra_supported_domains.iter().cloned())
+            .collect(),
+        features: CapabilityFlags {
+            extractor_plugins: !state.plugin_dirs.is_empty(),
+            turnstile: state.turnstile_secret_key.is_some(),
+            history: state.history_enabled,
+            history_feed: state.history_enabled,
+            promo_codes: true,
+            policy_hook: state.policy_hook.is_some(),
+            job_metadata: state.embed_job_metadata,
+            codec_compat: state.codec_compat,
+            snapshot_warc: state.snapshot_warc,
+            sponsorblock: state.sponsorblock,
+            remote_workers: state.workers.is_some(),
+            request_signing: state.request_signer.is_some(),
+            email_verification: state.email_verification.is_some(),
+            oidc_login: state.auth.is_some(),
+            embed_api: !state.embed_sites.is_empty(),
+            dynamic_quota: state.quota.is_dynamic(),
+            download_receipts: state.receipts.is_some(),
+            telemetry: state.telemetry.is_some(),
+            client_errors: state.client_errors.is_some(),
+            demo_mode: state.demo.is_some(),
+        },
+        limits: CapabilityLimits {
+            daily_downloads: state.quota.effective_limit(now, active_jobs),
+            window_hours: state.config.download_window_hours,
+            max_download_bytes: state.config.max_download_bytes,
+            snapshot_max_download_bytes: state.config.snapshot_max_download_bytes,
+            quota_policy: state.quota.describe(now, active_jobs),
+        },
+        presets: state.presets.list(),
+        extra_args: state.extra_args.options(),
+    })
+}
+
+async fn require_history(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if !state.history_enabled {
+        return ApiError::history_disabled().into_response();
+    }
+    next.run(request).await
+}
+
 async fn get_history(
     State(state): State<AppState>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-) -> Result<Json<Vec<HistoryEntry>>, ApiError> {
-    let client_ip = client_ip_for_request(&state, &headers, addr);
+) -> Result<Response, ApiError> {
+    let client_ip = client_identity_for_request(&state, &headers, addr);
     let history = state
         .history
         .lock()
         .await
         .iter()
         .filter(|entry| entry.requester_ip == client_ip)
-        .take(HISTORY_PER_IP_LIMIT)
+        .take(state.config.history_per_ip_limit)
         .cloned()
-        .collect();
-    Ok(Json(history))
+        .collect::<Vec<_>>();
+    let last_modified = history.iter().map(|entry| entry.created_at).max();
+    conditional_json_response(&headers, &history, last_modified)
 }
 
 async fn clear_history(
@@ -382,339 +1735,2203 @@ async fn clear_history(
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
 ) -> Result<Json<serde_json::Value>, ApiError> {
-    let client_ip = client_ip_for_request(&state, &headers, addr);
+    let client_ip = client_identity_for_request(&state, &headers, addr);
+
+    let mut history = state.history.lock().await;
+    let removed = history
+        .iter()
+        .filter(|entry| entry.requester_ip == client_ip)
+        .map(|entry| entry.id)
+        .collect::<Vec<_>>();
+    history.retain(|entry| entry.requester_ip != client_ip);
+    state.storage.history_removed(removed, &history).await?;
+    Ok(Json(serde_json::json!({ "status": "ok" })))
+}
+
+async fn get_history_feed_token(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Json<FeedTokenResponse>, ApiError> {
+    let client_ip = client_identity_for_request(&state, &headers, addr);
+    let token = build_feed_token(&state.signing_secret, &client_ip);
+    let feed_url = format!(
+        "{}/api/history/feed?token={token}",
+        public_base_url(&state, &headers)
+    );
+
+    Ok(Json(FeedTokenResponse { token, feed_url }))
+}
+
+async fn get_history_feed(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<FeedQuery>,
+) -> Result<Response, ApiError> {
+    let client_ip = verify_feed_token(&state.signing_secret, &query.token)
+        .ok_or_else(|| ApiError::invalid_signature("Token de feed invalido."))?;
+
+    let entries = state
+        .history
+        .lock()
+        .await
+        .iter()
+        .filter(|entry| entry.requester_ip == client_ip)
+        .take(state.config.history_per_ip_limit)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let base_url = public_base_url(&state, &headers);
+    let feed_url = format!("{base_url}/api/history/feed?token={}", query.token);
+    let feed = build_history_feed(&state, &entries, &base_url, &feed_url, Utc::now());
+
+    Ok((
+        [(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/atom+xml; charset=utf-8"),
+        )],
+        feed,
+    )
+        .into_response())
+}
 
-    let snapshot = {
-        let mut history = state.history.lock().await;
-        history.retain(|entry| entry.requester_ip != client_ip);
-        history.clone()
+async fn download_signed_file(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request_headers: HeaderMap,
+    RoutePath(file_key): RoutePath<String>,
+    Query(query): Query<SignedFileQuery>,
+    uri: Uri,
+) -> Result<Response, ApiError> {
+    if query.expires < Utc::now().timestamp() {
+        return Err(ApiError::invalid_signature("El enlace de descarga expiro."));
+    }
+    let signed_key = match query.job {
+        Some(job_id) => format!("{file_key}:{job_id}"),
+        None => file_key.clone(),
     };
+    if !verify_signature(
+        &state.signing_secret,
+        &file_signature_payload(&signed_key, query.expires),
+        &query.sig,
+    ) {
+        return Err(ApiError::invalid_signature("Firma de descarga invalida."));
+    }
 
-    persist_history(&state.history_path, &snapshot).await?;
-    Ok(Json(serde_json::json!({ "status": "ok" })))
+    let client_ip = client_ip_for_request(&state, &request_headers, addr);
+    // Links from signed_link name the job; fallback and async links name the artifact hash and the job.
+    let (artifact_hash, job_id) = match file_key.parse::<Uuid>() {
+        Ok(job_id) => match state.jobs.completed_artifact(job_id).await {
+            Some(artifact_hash) => (artifact_hash, Some(job_id)),
+            None => match &state.registry {
+                Some(registry) => match registry.locate_job(job_id, &request_headers).await {
+                    Some(location) => {
+                        return registry
+                            .forward(&location, Method::GET, &uri, &request_headers, &client_ip)
+                            .await;
+                    }
+                    None => return Err(artifact_gone_error()),
+                },
+                None => return Err(artifact_gone_error()),
+            },
+        },
+        Err(_) => (file_key, query.job),
+    };
+    serve_artifact(
+        &state,
+        &artifact_hash,
+        job_id,
+        &request_headers,
+        &uri,
+        client_ip,
+    )
+    .await
+}
+
+async fn serve_artifact(
+    state: &AppState,
+    artifact_hash: &str,
+    job_id: Option<Uuid>,
+    request_headers: &HeaderMap,
+    uri: &Uri,
+    client_ip: String,
+) -> Result<Response, ApiError> {
+    let artifact = match state.artifacts.lookup(artifact_hash, job_id).await {
+        Some(artifact) => artifact,
+        None => match &state.registry {
+            Some(registry) => match registry
+                .route_artifact(&state.artifacts, artifact_hash, job_id, request_headers)
+                .await
+            {
+                Some(ArtifactRoute::Local(artifact)) => artifact,
+                Some(ArtifactRoute::Remote(location)) => {
+                    return registry
+                        .forward(&location, Method::GET, uri, request_headers, &client_ip)
+                        .await;
+                }
+                None => return Err(artifact_gone_error()),
+            },
+            None => return Err(artifact_gone_error()),
+        },
+    };
+    // Artifacts are content addressed, so the hash is a strong validator for If-Range.
+    let etag = format!("\"{}\"", artifact.hash);
+    let (status, offset, length) = match requested_byte_range(request_headers, &etag, artifact.size)
+    {
+        ByteRange::Full => (StatusCode::OK, 0, artifact.size),
+        ByteRange::Partial(start, end) => (StatusCode::PARTIAL_CONTENT, start, end - start + 1),
+        ByteRange::Unsatisfiable => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(CONTENT_RANGE, format!("bytes */{}", artifact.size))],
+            )
+                .into_response());
+        }
+    };
+    let stream_permit = state
+        .delivery
+        .try_reserve_stream()
+        .ok_or_else(|| ApiError::streams_saturated(STREAM_RETRY_AFTER_SECONDS))?;
+    let open = async {
+        let mut file = tokio::fs::File::open(&artifact.path).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+        Ok::<_, std::io::Error>(file)
+    };
+    let file = open.await.map_err(|error| {
+        if error.kind() == ErrorKind::NotFound {
+            artifact_gone_error()
+        } else {
+            ApiError::internal(format!("No se pudo leer el archivo: {error}"))
+        }
+    })?;
+
+    let mut headers = build_attachment_headers(
+        &artifact.filename,
+        sniff::content_type_for_file(&artifact.path, &artifact.filename).await,
+        length,
+    )?;
+    headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        headers.insert(ETAG, value);
+    }
+    if status == StatusCode::PARTIAL_CONTENT
+        && let Ok(value) = HeaderValue::from_str(&format!(
+            "bytes {offset}-{}/{}",
+            offset + length - 1,
+            artifact.size
+        ))
+    {
+        headers.insert(CONTENT_RANGE, value);
+    }
+    let on_served = match &state.usage {
+        Some(usage) => usage.artifact_served_handler(artifact_hash).await,
+        None => None,
+    };
+    let body = state.delivery.stream_file(
+        stream_permit,
+        file.take(length),
+        client_ip,
+        None,
+        None,
+        on_served,
+    );
+    Ok((status, headers, body).into_response())
+}
+
+enum ByteRange {
+    Full,
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+// Only a single "bytes=" range is honored; anything else gets the whole file, as RFC 9110 allows.
+fn requested_byte_range(headers: &HeaderMap, etag: &str, size: u64) -> ByteRange {
+    let Some(spec) = headers
+        .get(RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().strip_prefix("bytes="))
+        .filter(|spec| !spec.contains(','))
+    else {
+        return ByteRange::Full;
+    };
+    if headers
+        .get(IF_RANGE)
+        .is_some_and(|validator| validator.as_bytes() != etag.as_bytes())
+    {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return ByteRange::Full;
+    };
+    let last = size.saturating_sub(1);
+    let bounds = match (start.trim(), end.trim()) {
+        ("", suffix) => suffix
+            .parse::<u64>()
+            .ok()
+            .filter(|length| *length > 0)
+            .map(|length| (size.saturating_sub(length), last)),
+        (start, "") => start.parse::<u64>().ok().map(|start| (start, last)),
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => Some((start, end.min(last))),
+            _ => return ByteRange::Full,
+        },
+    };
+    match bounds {
+        Some((start, end)) if size > 0 && start <= end => ByteRange::Partial(start, end),
+        Some(_) => ByteRange::Unsatisfiable,
+        None => ByteRange::Full,
+    }
+}
+
+fn download_link_expiry() -> DateTime<Utc> {
+    Utc::now() + chrono::Duration::seconds(DOWNLOAD_JOB_RETENTION_SECONDS as i64)
+}
+
+fn artifact_gone_error() -> ApiError {
+    ApiError::not_found("El archivo ya no esta disponible en el servidor.")
 }
 
 async fn create_antibot_challenge(
     State(state): State<AppState>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
+    Query(query): Query<AntiBotChallengeQuery>,
 ) -> Result<Json<AntiBotChallengeResponse>, ApiError> {
-    let client_ip = client_ip_for_request(&state, &headers, addr);
+    let client_ip = client_identity_for_request(&state, &headers, addr);
     let now = Utc::now();
     let challenge_id = Uuid::new_v4().to_string();
     let nonce = Uuid::new_v4().simple().to_string();
-
-    {
+    let ttl_seconds = ANTIBOT_CHALLENGE_TTL_SECONDS
+        + query
+            .submit_in_seconds
+            .unwrap_or_default()
+            .clamp(0, ANTIBOT_MAX_SUBMIT_DELAY_SECONDS);
+    let expires_at = now + chrono::Duration::seconds(ttl_seconds);
+    let downloads_saturated = state.download_semaphore.available_permits() == 0;
+    let difficulty = query
+        .difficulty
+        .unwrap_or_default()
+        .max(ANTIBOT_DIFFICULTY_HEX_PREFIX + usize::from(downloads_saturated))
+        .min(ANTIBOT_MAX_DIFFICULTY_HEX_PREFIX);
+
+    let challenge = AntiBotChallenge {
+        nonce: nonce.clone(),
+        created_at: now,
+        expires_at,
+        difficulty,
+        ip: client_ip,
+    };
+    if let Some(redis) = &state.redis {
+        redis
+            .put_json(
+                "challenge",
+                &challenge_id,
+                &challenge,
+                Some(Duration::from_secs(ttl_seconds as u64)),
+            )
+            .await?;
+    } else {
         let mut challenges = state.anti_bot_challenges.lock().await;
         prune_antibot_challenges(&mut challenges, now);
-        challenges.insert(
-            challenge_id.clone(),
-            AntiBotChallenge {
-                nonce: nonce.clone(),
-                created_at: now,
-                ip: client_ip,
-            },
-        );
-        trim_antibot_challenges(&mut challenges);
+        challenges.insert(challenge_id.clone(), challenge);
+        state
+            .memory
+            .evict_lru(TrackedMap::Challenges, &mut challenges, |challenge| {
+                Some(challenge.created_at)
+            });
     }
 
     Ok(Json(AntiBotChallengeResponse {
         challenge_id,
         nonce,
-        difficulty: ANTIBOT_DIFFICULTY_HEX_PREFIX,
-        expires_in_seconds: ANTIBOT_CHALLENGE_TTL_SECONDS,
+        difficulty,
+        expires_in_seconds: ttl_seconds,
+        expires_at,
+        downloads_saturated,
     }))
 }
 
+async fn verify_antibot_challenge(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<AntiBotVerifyRequest>,
+) -> Json<AntiBotVerifyResponse> {
+    let client_ip = client_ip_for_request(&state, &headers, addr);
+    let requester = client_identity_for_request(&state, &headers, addr);
+    let now = Utc::now();
+    let challenge = find_antibot_challenge(&state, payload.challenge_id.trim(), false)
+        .await
+        .unwrap_or_default()
+        .filter(|challenge| challenge.expires_at >= now);
+
+    let outcome = match challenge {
+        None => Err("expired"),
+        Some(challenge) if challenge.ip != requester && challenge.ip != client_ip => {
+            Err("origin_mismatch")
+        }
+        Some(challenge)
+            if !is_pow_solution_valid(
+                payload.challenge_id.trim(),
+                &challenge.nonce,
+                payload.solution,
+                challenge.difficulty,
+            ) =>
+        {
+            Err("invalid_solution")
+        }
+        Some(challenge) => Ok((challenge.expires_at - now).num_seconds().max(0)),
+    };
+
+    Json(match outcome {
+        Ok(expires_in_seconds) => AntiBotVerifyResponse {
+            valid: true,
+            reason: None,
+            expires_in_seconds: Some(expires_in_seconds),
+        },
+        Err(reason) => AntiBotVerifyResponse {
+            valid: false,
+            reason: Some(reason),
+            expires_in_seconds: None,
+        },
+    })
+}
+
 async fn fetch_formats(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<FormatsRequest>,
-) -> Result<Json<FormatsResponse>, ApiError> {
-    let url = payload.url.trim();
+) -> Result<Response, ApiError> {
+    formats_response(&state, addr, &headers, &payload.url, ApiVersion::V1).await
+}
+
+async fn fetch_formats_v2(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<FormatsRequest>,
+) -> Result<Response, ApiError> {
+    formats_response(&state, addr, &headers, &payload.url, ApiVersion::V2).await
+}
+
+async fn fetch_formats_by_query(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(payload): Query<FormatsRequest>,
+) -> Result<Response, ApiError> {
+    formats_response(&state, addr, &headers, &payload.url, ApiVersion::V1).await
+}
+
+async fn fetch_formats_v2_by_query(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(payload): Query<FormatsRequest>,
+) -> Result<Response, ApiError> {
+    formats_response(&state, addr, &headers, &payload.url, ApiVersion::V2).await
+}
+
+async fn formats_response(
+    state: &AppState,
+    addr: SocketAddr,
+    headers: &HeaderMap,
+    raw_url: &str,
+    version: ApiVersion,
+) -> Result<Response, ApiError> {
+    bans::ensure_not_banned(state, &client_ip_for_request(state, headers, addr)).await?;
+    let url = raw_url.trim();
     if url.is_empty() {
         return Err(ApiError::bad_request("Ingresa una URL valida."));
     }
-    if !is_supported_download_url(url) {
+    if !is_supported_download_url(url, &state.extra_supported_domains) {
         return Err(ApiError::bad_request(
             "URL no soportada. Usa una URL de X, Facebook, TikTok, YouTube, Instagram o Bluesky.",
         ));
     }
 
-    let output = match run_yt_dlp(vec![
-        "-J".to_string(),
-        "--no-playlist".to_string(),
-        "--no-warnings".to_string(),
-        url.to_string(),
-    ])
-    .await
+    evaluate_lookup_policy(
+        state,
+        &client_ip_for_request(state, headers, addr),
+        "formats",
+        url,
+    )
+    .await?;
+
+    let cached = cached_formats(state, url).await?;
+    let estimate = state.throughput.estimator(url).await;
+    let mut response = cached.response.as_ref().clone();
+    for option in response
+        .video_options
+        .iter_mut()
+        .chain(response.audio_options.iter_mut())
     {
-        Ok(output) => output,
-        Err(error) => {
-            if should_use_automatic_formats_fallback(url, &error.message) {
-                warn!(
-                    "yt-dlp fallo cargando metadatos para URL {:?}. Se devolvera fallback automatico. Error: {}",
-                    url, error.message
-                );
-                return Ok(Json(build_automatic_formats_response(url)));
-            }
-            return Err(error);
+        option.estimated_seconds = estimate(option.size_bytes);
+    }
+    match version {
+        ApiVersion::V1 => conditional_json_response(headers, &response, Some(cached.fetched_at)),
+        ApiVersion::V2 => {
+            conditional_json_response(headers, &response.v2(), Some(cached.fetched_at))
         }
-    };
+    }
+}
 
-    let info: YtDlpVideoInfo = match serde_json::from_slice(&output.stdout) {
-        Ok(info) => info,
+// Metadata lookups run yt-dlp too, so they pass the policy hook before reaching the extractor.
+async fn evaluate_lookup_policy(
+    state: &AppState,
+    client_ip: &str,
+    endpoint: &str,
+    url: &str,
+) -> Result<(), ApiError> {
+    let Some(hook) = &state.policy_hook else {
+        return Ok(());
+    };
+    let input = PolicyInput {
+        endpoint,
+        url,
+        domain: url_domain(url),
+        client_ip,
+        reputation: client_reputation(state, client_ip).await,
+        mode: None,
+        format_id: None,
+        limits: PolicyLimits {
+            daily_limit: state
+                .quota
+                .effective_limit(Utc::now(), state.jobs.active_count().await),
+            max_download_bytes: state.config.max_download_bytes,
+        },
+    };
+    hook.evaluate(&input).await.map(|_| ())
+}
+
+async fn cached_formats(state: &AppState, url: &str) -> Result<CachedFormats, ApiError> {
+    let now = Utc::now();
+    {
+        let mut cache = state.formats_cache.lock().await;
+        cache.retain(|_, cached| {
+            (now - cached.fetched_at).num_seconds() < state.config.formats_cache_ttl_seconds
+        });
+        if let Some(cached) = cache.get_mut(url) {
+            cached.last_used = now;
+            return Ok(cached.clone());
+        }
+    }
+
+    let (response, cacheable) = extract_formats(state, url).await?;
+    let cached = CachedFormats {
+        response: Arc::new(response),
+        fetched_at: now,
+        last_used: now,
+    };
+
+    if cacheable && !lowmem::low_memory() {
+        let mut cache = state.formats_cache.lock().await;
+        cache.insert(url.to_string(), cached.clone());
+        state
+            .memory
+            .evict_lru(TrackedMap::FormatsCache, &mut cache, |cached| {
+                Some(cached.last_used)
+            });
+    }
+
+    Ok(cached)
+}
+
+async fn extract_formats(state: &AppState, url: &str) -> Result<(FormatsResponse, bool), ApiError> {
+    let args = vec![
+        "-J".to_string(),
+        "--no-playlist".to_string(),
+        "--no-warnings".to_string(),
+        url.to_string(),
+    ];
+    let _metadata_permit = timeout(
+        Duration::from_millis(METADATA_QUEUE_WAIT_MS),
+        state.metadata_semaphore.clone().acquire_owned(),
+    )
+    .await
+    .map_err(|_| ApiError::metadata_saturated(METADATA_RETRY_AFTER_SECONDS))?
+    .map_err(|_| ApiError::internal("No se pudo reservar capacidad para metadatos."))?;
+    let started_at = tokio::time::Instant::now();
+    let result = state
+        .extractor
+        .run(
+            RequestClass::Metadata,
+            url,
+            args.clone(),
+            state.metadata_timeout,
+        )
+        .await;
+    if let Some(shadow) = &state.shadow_extractor
+        && shadow.should_sample()
+    {
+        let primary = ExtractionSummary::from_output(&result, started_at.elapsed().as_millis());
+        shadow.spawn(
+            url_domain(url),
+            state.extractor.with_common_args(url, args).await,
+            primary,
+            state.metadata_timeout,
+        );
+    }
+
+    let output = match result {
+        Ok(output) => output,
+        Err(error) => {
+            if should_use_automatic_formats_fallback(url, &error.message) {
+                warn!(
+                    "yt-dlp fallo cargando metadatos para URL {:?}. Se devolvera fallback automatico. Error: {}",
+                    url, error.message
+                );
+                return Ok((build_automatic_formats_response(url), false));
+            }
+            return Err(error);
+        }
+    };
+
+    let info: YtDlpVideoInfo = match serde_json::from_slice(&output.stdout) {
+        Ok(info) => info,
         Err(error) => {
             warn!(
                 "No se pudo interpretar JSON de yt-dlp para URL {:?}. Se devolvera fallback automatico. Error: {error}",
                 url
             );
-            return Ok(Json(build_automatic_formats_response(url)));
+            return Ok((build_automatic_formats_response(url), false));
         }
     };
 
-    let mut video_options = build_video_options(&info.formats);
     let mut audio_options = build_audio_options(&info.formats);
+    let best_audio_bytes = audio_options.first().and_then(|option| option.size_bytes);
+    let mut video_options = build_video_options(&info.formats, best_audio_bytes);
 
     if video_options.is_empty() {
         video_options.push(FormatOption {
-            format_id: "bestvideo+bestaudio/best".to_string(),
+            format_id: AUTOMATIC_VIDEO_SELECTOR.to_string(),
             label: "Mejor calidad automatica".to_string(),
             resolution: Some("Auto".to_string()),
             ext: "mp4".to_string(),
             has_audio: true,
+            details: FormatDetails::default(),
+            size_bytes: None,
+            merged_size_bytes: None,
+            estimated_seconds: None,
         });
     }
 
     if audio_options.is_empty() {
         audio_options.push(FormatOption {
-            format_id: "bestaudio".to_string(),
+            format_id: AUTOMATIC_AUDIO_SELECTOR.to_string(),
             label: "Mejor audio disponible".to_string(),
             resolution: None,
             ext: "mp3".to_string(),
             has_audio: true,
+            details: FormatDetails::default(),
+            size_bytes: None,
+            merged_size_bytes: None,
+            estimated_seconds: None,
         });
     }
 
-    Ok(Json(FormatsResponse {
-        title: info
-            .title
-            .filter(|value| !value.trim().is_empty())
-            .unwrap_or_else(|| "Sin titulo".to_string()),
-        thumbnail: info.thumbnail,
-        video_options,
-        audio_options,
-    }))
+    let source_title = info.title.filter(|value| !value.trim().is_empty());
+    Ok((
+        FormatsResponse {
+            title: source_title
+                .clone()
+                .unwrap_or_else(|| "Sin titulo".to_string()),
+            source_title,
+            thumbnail: info.thumbnail,
+            duration_seconds: info.duration.filter(|duration| *duration > 0.0),
+            uploader: info.uploader.and_then(normalize_optional_text),
+            upload_date: info.upload_date.as_deref().and_then(format_upload_date),
+            view_count: info.view_count,
+            video_options,
+            audio_options,
+        },
+        true,
+    ))
+}
+
+async fn start_download(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(mut payload): Json<DownloadRequest>,
+) -> Result<Response, ApiError> {
+    let client_ip = client_ip_for_request(&state, &headers, addr);
+    bans::ensure_not_banned(&state, &client_ip).await?;
+    let requested_container = payload.container.take();
+    state.presets.apply(&mut payload)?;
+    state.languages.apply(&mut payload, &headers)?;
+    apply_container(&mut payload, requested_container)?;
+    apply_audio_format(&mut payload)?;
+    apply_audio_tags(&mut payload)?;
+    payload.embed_subtitles = normalize_subtitle_languages(payload.embed_subtitles.take())?;
+    payload.clip = normalize_clip(payload.start_time.take(), payload.end_time.take())?;
+    playlist::apply_batch(&mut payload, &state.playlist)?;
+    payload.extra_args = Some(state.extra_args.validate(payload.extra_args.take())?);
+    let sponsorblock_remove = normalize_sponsorblock(&state, payload.sponsorblock.take())?;
+    payload.sponsorblock = Some(SponsorBlockRequest {
+        remove: sponsorblock_remove,
+    });
+    if state.codec_compat {
+        payload.codec_profile = compat::profile_for_request(&headers);
+    }
+    apply_compatibility(&mut payload)?;
+    streams::apply_stream_selection(&mut payload)?;
+    sidecars::apply_sidecars(&mut payload, state.snapshot_warc)?;
+    if let Some(entitlements) = &state.entitlements
+        && let Some(session) = auth::session_from_headers(&state, &headers)
+    {
+        payload.premium = entitlements.premium_for(&session).await;
+    }
+    payload.api_key = state.api_keys.resolve(&state.roles, &headers).await?;
+    let url = payload.url.trim();
+    if url.is_empty() {
+        return Err(ApiError::bad_request(
+            "Ingresa una URL valida antes de descargar.",
+        ));
+    }
+    if !is_supported_download_url(url, &state.extra_supported_domains) {
+        return Err(ApiError::bad_request(
+            "URL no soportada. Usa una URL de X, Facebook, TikTok, YouTube, Instagram o Bluesky.",
+        ));
+    }
+
+    // Quota, history and promo codes follow the identity; bans, reputation and the hook use the IP.
+    let client_identity = client_identity_for_request(&state, &headers, addr);
+    payload.remote_ip = client_ip;
+    let job = state
+        .jobs
+        .create(
+            payload.job_id.unwrap_or_else(Uuid::new_v4),
+            &payload.remote_ip,
+        )
+        .await?;
+    if payload.premium.is_some() {
+        job.prioritize();
+    }
+    if let Some(registry) = &state.registry {
+        registry
+            .record_job(job.job_id(), download_link_expiry())
+            .await;
+    }
+    if payload.split_chapters && !matches!(payload.mode, DownloadMode::Audio) {
+        job.fail("La division por capitulos solo aplica al modo audio.");
+        return Err(ApiError::bad_request(
+            "La division por capitulos solo aplica al modo audio.",
+        ));
+    }
+    if payload.signed_link {
+        if payload.respond_async {
+            job.fail("signed_link no se puede combinar con async.");
+            return Err(ApiError::bad_request(
+                "signed_link no se puede combinar con async; el modo async ya entrega file_url.",
+            ));
+        }
+        payload.link_base = public_base_url(&state, &headers);
+    }
+    if payload.playlist || !payload.clip_ranges.is_empty() {
+        if payload.clip.is_some() {
+            job.fail("Los recortes por tiempo no se aplican a listas.");
+            return Err(ApiError::bad_request(
+                "Los recortes por tiempo no se aplican a listas.",
+            ));
+        }
+        // A merged batch ends as one artifact, so it can be handed out as a link.
+        if payload.respond_async || (payload.signed_link && !payload.merge) {
+            job.fail("Las listas solo se descargan de forma directa.");
+            return Err(ApiError::bad_request(
+                "Las listas solo se descargan de forma directa.",
+            ));
+        }
+        let abandon = job.abandon_on_drop();
+        let result =
+            playlist::run_playlist_download(&state, &client_identity, url, &payload, &job).await;
+        abandon.disarm();
+        record_telemetry(&state, url, result.as_ref().map(|_| ())).await;
+        if let Err(error) = &result {
+            job.fail(&error.message);
+        }
+        return result;
+    }
+    if payload.respond_async || prefers_async(&headers) {
+        return start_async_download(state, client_identity, url.to_string(), payload, job).await;
+    }
+    let abandon = job.abandon_on_drop();
+    let result = run_download(&state, &client_identity, url, &payload, &job).await;
+    abandon.disarm();
+    record_telemetry(&state, url, result.as_ref().map(|_| ())).await;
+    if let Err(error) = &result {
+        job.fail(&error.message);
+    }
+    result
+}
+
+async fn record_telemetry(state: &AppState, url: &str, result: Result<(), &ApiError>) {
+    state.stats.record_download(url, result.is_ok()).await;
+    if let Some(telemetry) = &state.telemetry {
+        telemetry.record(url, result).await;
+    }
+}
+
+fn apply_container(
+    payload: &mut DownloadRequest,
+    requested: Option<String>,
+) -> Result<(), ApiError> {
+    let Some(container) = requested
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| !value.is_empty())
+    else {
+        return Ok(());
+    };
+    if !matches!(payload.mode, DownloadMode::Video) {
+        return Err(ApiError::bad_request(
+            "container solo aplica al modo video; para audio usa audio_format.",
+        ));
+    }
+    if !VIDEO_CONTAINERS.contains(&container.as_str()) {
+        return Err(ApiError::bad_request(format!(
+            "Contenedor de video no soportado: {container}. Usa {}.",
+            VIDEO_CONTAINERS.join(", ")
+        )));
+    }
+    payload.container = Some(container);
+    Ok(())
+}
+
+fn apply_compatibility(payload: &mut DownloadRequest) -> Result<(), ApiError> {
+    if !payload.compatibility {
+        return Ok(());
+    }
+    if !matches!(payload.mode, DownloadMode::Video) {
+        return Err(ApiError::bad_request(
+            "compatibility solo aplica al modo video.",
+        ));
+    }
+    if let Some(container) = payload
+        .container
+        .as_deref()
+        .filter(|container| *container != "mp4")
+    {
+        return Err(ApiError::bad_request(format!(
+            "compatibility siempre entrega MP4; no se puede combinar con container {container}."
+        )));
+    }
+    payload.container = Some("mp4".to_string());
+    payload.codec_profile = Some(CodecProfile::h264_aac());
+    Ok(())
+}
+
+fn apply_audio_format(payload: &mut DownloadRequest) -> Result<(), ApiError> {
+    let Some(audio_format) = payload
+        .audio_format
+        .as_deref()
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| !value.is_empty())
+    else {
+        return Ok(());
+    };
+    if !matches!(payload.mode, DownloadMode::Audio) {
+        return Err(ApiError::bad_request(
+            "audio_format solo aplica al modo audio.",
+        ));
+    }
+    if !AUDIO_CONTAINERS.contains(&audio_format.as_str()) {
+        return Err(ApiError::bad_request(format!(
+            "Formato de audio no soportado: {audio_format}. Usa {}.",
+            AUDIO_CONTAINERS.join(", ")
+        )));
+    }
+    payload.container = Some(audio_format);
+    Ok(())
+}
+
+fn apply_audio_tags(payload: &mut DownloadRequest) -> Result<(), ApiError> {
+    let Some(tags) = payload.audio_tags.take() else {
+        return Ok(());
+    };
+    let Some(tags) = tags.normalize()? else {
+        return Ok(());
+    };
+    if !matches!(payload.mode, DownloadMode::Audio) {
+        return Err(ApiError::bad_request(
+            "audio_tags solo aplica al modo audio.",
+        ));
+    }
+    if payload.playlist {
+        return Err(ApiError::bad_request(
+            "audio_tags no se aplica a listas; cada pista conserva sus etiquetas.",
+        ));
+    }
+    payload.audio_tags = Some(tags);
+    Ok(())
+}
+
+fn normalize_sponsorblock(
+    state: &AppState,
+    request: Option<SponsorBlockRequest>,
+) -> Result<Vec<String>, ApiError> {
+    let categories = request.map(|request| request.remove).unwrap_or_default();
+    if categories.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !state.sponsorblock {
+        return Err(ApiError::bad_request(
+            "SponsorBlock esta deshabilitado en este servidor.",
+        ));
+    }
+    let mut normalized: Vec<String> = Vec::new();
+    for category in categories {
+        let category = category.trim().to_ascii_lowercase();
+        if !SPONSORBLOCK_CATEGORIES.contains(&category.as_str()) {
+            return Err(ApiError::bad_request(format!(
+                "Categoria de SponsorBlock invalida: {category}."
+            )));
+        }
+        if !normalized.contains(&category) {
+            normalized.push(category);
+        }
+    }
+    Ok(normalized)
+}
+
+fn normalize_clip(
+    start: Option<ClipTime>,
+    end: Option<ClipTime>,
+) -> Result<Option<ClipRange>, ApiError> {
+    let parse = |time: Option<ClipTime>| {
+        time.map(|time| {
+            time.seconds().ok_or_else(|| {
+                ApiError::bad_request(format!(
+                    "Tiempo de recorte invalido: {}. Usa segundos o HH:MM:SS.",
+                    time.describe()
+                ))
+            })
+        })
+        .transpose()
+    };
+    let start_seconds = parse(start)?.unwrap_or(0.0);
+    let end_seconds = parse(end)?;
+    if let Some(end) = end_seconds
+        && end <= start_seconds
+    {
+        return Err(ApiError::bad_request(
+            "El fin del recorte debe ser posterior al inicio.",
+        ));
+    }
+    if start_seconds == 0.0 && end_seconds.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(ClipRange {
+        start_seconds,
+        end_seconds,
+    }))
+}
+
+fn normalize_subtitle_languages(
+    languages: Option<Vec<String>>,
+) -> Result<Option<Vec<String>>, ApiError> {
+    let Some(languages) = languages else {
+        return Ok(None);
+    };
+    let mut normalized: Vec<String> = Vec::new();
+    for language in languages {
+        let language = language.trim();
+        if language.is_empty() {
+            continue;
+        }
+        let valid = language.len() <= 32
+            && language.chars().all(|character| {
+                character.is_ascii_alphanumeric() || matches!(character, '-' | '_' | '.' | '*')
+            });
+        if !valid {
+            return Err(ApiError::bad_request(format!(
+                "Idioma de subtitulos invalido: {language}."
+            )));
+        }
+        if !normalized.iter().any(|existing| existing == language) {
+            normalized.push(language.to_string());
+        }
+    }
+    if normalized.len() > MAX_SUBTITLE_LANGUAGES {
+        return Err(ApiError::bad_request(format!(
+            "Puedes incrustar como maximo {MAX_SUBTITLE_LANGUAGES} idiomas de subtitulos."
+        )));
+    }
+    Ok((!normalized.is_empty()).then_some(normalized))
+}
+
+fn prefers_async(headers: &HeaderMap) -> bool {
+    headers
+        .get_all("prefer")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|preference| preference.trim().eq_ignore_ascii_case("respond-async"))
+}
+
+async fn start_async_download(
+    state: AppState,
+    client_identity: String,
+    url: String,
+    payload: DownloadRequest,
+    job: JobHandle,
+) -> Result<Response, ApiError> {
+    let limits = match admit_download(&state, &client_identity, &url, &payload).await {
+        Ok(limits) => limits,
+        Err(error) => {
+            job.fail(&error.message);
+            return Err(error);
+        }
+    };
+
+    let job_id = job.job_id();
+    let download = BackgroundDownload {
+        requester: client_identity,
+        remote_ip: payload.remote_ip,
+        url,
+        mode: payload.mode,
+        format_id: payload.format_id,
+        format_label: payload.format_label,
+        has_audio: payload.has_audio.unwrap_or(false),
+        hints: payload.hints,
+        container: payload.container,
+        subtitle_languages: payload.embed_subtitles.unwrap_or_default(),
+        codec_profile: payload.codec_profile,
+        clip: payload.clip,
+        split_chapters: payload.split_chapters,
+        extra_args: payload.extra_args.unwrap_or_default(),
+        sponsorblock_remove: payload
+            .sponsorblock
+            .map(|sponsorblock| sponsorblock.remove)
+            .unwrap_or_default(),
+        embed_metadata: payload.embed_metadata.unwrap_or(state.embed_job_metadata),
+        embed_thumbnail: payload.embed_thumbnail.unwrap_or(true),
+        audio_tags: payload.audio_tags,
+        sidecars: payload.sidecars,
+        ip_family: payload.ip_family.unwrap_or(state.ip_family),
+        max_download_bytes: limits.max_download_bytes,
+        title: payload.title.and_then(normalize_optional_text),
+        thumbnail: payload.thumbnail.and_then(normalize_optional_text),
+        link_base: String::new(),
+    };
+    tokio::spawn(clienterrors::with_request_context(run_background_download(
+        state, job, download,
+    )));
+
+    let mut response = (
+        StatusCode::ACCEPTED,
+        Json(AsyncDownloadResponse {
+            job_id,
+            status_url: format!("/api/download/{job_id}/status"),
+            progress_url: format!("/api/download/{job_id}/progress"),
+            file_url: format!("/api/download/{job_id}/file"),
+        }),
+    )
+        .into_response();
+    if let Ok(value) = HeaderValue::from_str(&job_id.to_string()) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static("x-job-id"), value);
+    }
+    Ok(response)
+}
+
+async fn run_download(
+    state: &AppState,
+    client_identity: &str,
+    url: &str,
+    payload: &DownloadRequest,
+    job: &JobHandle,
+) -> Result<Response, ApiError> {
+    let requested_at = Utc::now();
+    let limits = admit_download(state, client_identity, url, payload).await?;
+
+    let spec = ArtifactSpec {
+        url,
+        mode: payload.mode.clone(),
+        format_id: payload.format_id.as_deref().and_then(non_empty),
+        has_audio: payload.has_audio.unwrap_or(false),
+        hints: payload.hints,
+        container: payload.container.as_deref(),
+        subtitle_languages: payload.embed_subtitles.as_deref().unwrap_or_default(),
+        codec_profile: payload.codec_profile.as_ref(),
+        clip: payload.clip,
+        split_chapters: payload.split_chapters,
+        extra_args: payload.extra_args.as_deref().unwrap_or_default(),
+        sponsorblock_remove: payload.sponsorblock_categories(),
+        embed_metadata: payload.embed_metadata.unwrap_or(state.embed_job_metadata),
+        embed_thumbnail: payload.embed_thumbnail.unwrap_or(true),
+        audio_tags: payload.audio_tags.as_ref(),
+        sidecars: payload.sidecars,
+        ip_family: payload.ip_family.unwrap_or(state.ip_family),
+        max_download_bytes: limits.max_download_bytes,
+        retention_seconds: DOWNLOAD_JOB_RETENTION_SECONDS,
+    };
+    let produced = produce_artifact(state, job, &spec).await;
+    deliver_artifact(
+        state,
+        client_identity,
+        url,
+        payload,
+        job,
+        requested_at,
+        produced,
+    )
+    .await
+}
+
+// Shared by URL downloads and uploaded conversions: history, receipt and either the bytes or a link.
+async fn deliver_artifact(
+    state: &AppState,
+    client_identity: &str,
+    url: &str,
+    payload: &DownloadRequest,
+    job: &JobHandle,
+    requested_at: DateTime<Utc>,
+    produced: Result<StoredArtifact, ApiError>,
+) -> Result<Response, ApiError> {
+    struct PreparedDownload {
+        // None when the client asked for a signed link instead of the bytes.
+        body: Option<Body>,
+        // No stream slot was free; the job hands out file_url instead.
+        saturated: bool,
+        filename: String,
+        content_type: &'static str,
+        content_length: u64,
+        artifact_hash: String,
+    }
+
+    let history_id = Uuid::new_v4();
+    let selected_format = payload
+        .format_label
+        .clone()
+        .or_else(|| payload.format_id.clone())
+        .unwrap_or_else(|| "Mejor calidad automatica".to_string());
+    let selected_title = payload.title.clone().and_then(normalize_optional_text);
+    let selected_thumbnail = payload.thumbnail.clone().and_then(normalize_optional_text);
+    let duration_seconds = cached_duration(state, url).await;
+    let job_id = job.job_id();
+
+    let preparation_result: Result<PreparedDownload, ApiError> = async {
+        let artifact = produced?;
+        if let Some(registry) = &state.registry {
+            registry
+                .record_artifact(job_id, &artifact, download_link_expiry())
+                .await;
+        }
+        if payload.signed_link {
+            return Ok(PreparedDownload {
+                body: None,
+                saturated: false,
+                content_type: sniff::content_type_for_file(&artifact.path, &artifact.filename)
+                    .await,
+                filename: artifact.filename,
+                content_length: artifact.size,
+                artifact_hash: artifact.hash,
+            });
+        }
+        let file = match tokio::fs::File::open(&artifact.path).await {
+            Ok(file) => file,
+            Err(error) => {
+                state.artifacts.release(&artifact.hash, job_id).await;
+                return Err(ApiError::internal(format!(
+                    "No se pudo leer el archivo temporal: {error}"
+                )));
+            }
+        };
+        let link_expires_at = Utc::now().timestamp() + DOWNLOAD_JOB_RETENTION_SECONDS as i64;
+        let file_url = build_signed_file_path(
+            &state.signing_secret,
+            &artifact.hash,
+            job_id,
+            link_expires_at,
+        );
+        let offer_link = job.link_offer(file_url);
+        let Some(stream_permit) = state.delivery.try_reserve_stream() else {
+            offer_link();
+            return Ok(PreparedDownload {
+                body: None,
+                saturated: true,
+                content_type: sniff::content_type_for_file(&artifact.path, &artifact.filename)
+                    .await,
+                filename: artifact.filename,
+                content_length: artifact.size,
+                artifact_hash: artifact.hash,
+            });
+        };
+        let on_disconnect = {
+            let state = state.clone();
+            let artifact_hash = artifact.hash.clone();
+            move || {
+                tokio::spawn(async move {
+                    mark_history_interrupted(&state, history_id).await;
+                    state.artifacts.release(&artifact_hash, job_id).await;
+                });
+            }
+        };
+        let body = state.delivery.stream_file(
+            stream_permit,
+            file,
+            client_identity.to_string(),
+            Some(Box::new(offer_link)),
+            Some(Box::new(on_disconnect)),
+            state.usage.as_ref().and_then(|usage| {
+                billing::account_for(job.owner())
+                    .map(|account| usage.served_handler(account, Some(job_id)))
+            }),
+        );
+
+        Ok(PreparedDownload {
+            body: Some(body),
+            saturated: false,
+            content_type: sniff::content_type_for_file(&artifact.path, &artifact.filename).await,
+            filename: artifact.filename,
+            content_length: artifact.size,
+            artifact_hash: artifact.hash,
+        })
+    }
+    .await;
+
+    match preparation_result {
+        Ok(prepared) => {
+            let entry = HistoryEntry {
+                id: history_id,
+                created_at: Utc::now(),
+                requester_ip: client_identity.to_string(),
+                remote_ip: payload.remote_ip.clone(),
+                url: url.to_string(),
+                title: selected_title,
+                thumbnail: selected_thumbnail,
+                mode: payload.mode.clone(),
+                format: selected_format.clone(),
+                status: DownloadStatus::Success,
+                saved_path: Some(prepared.filename.clone()),
+                error: None,
+                job_id: Some(job_id),
+                artifact_hash: Some(prepared.artifact_hash.clone()),
+                clip: payload.clip,
+                duration_seconds,
+                request_id: clienterrors::current_request_id(),
+            };
+
+            if let Err(error) = push_history(state, entry).await {
+                state
+                    .artifacts
+                    .release(&prepared.artifact_hash, job_id)
+                    .await;
+                return Err(error);
+            }
+
+            let mut headers = match prepared.body {
+                Some(_) => build_attachment_headers(
+                    &prepared.filename,
+                    prepared.content_type,
+                    prepared.content_length,
+                )?,
+                None => HeaderMap::new(),
+            };
+            if let Ok(value) = HeaderValue::from_str(&job_id.to_string()) {
+                headers.insert(HeaderName::from_static("x-job-id"), value);
+            }
+            let receipt = ReceiptDetails {
+                job_id,
+                url,
+                mode: payload.mode.clone(),
+                format_id: payload.format_id.as_deref().and_then(non_empty),
+                format: &selected_format,
+                filename: &prepared.filename,
+                size_bytes: prepared.content_length,
+                sha256: &prepared.artifact_hash,
+                requested_at,
+            };
+            if let Some(receipt_url) = issue_receipt(state, receipt).await {
+                if let Ok(value) = HeaderValue::from_str(&receipt_url) {
+                    headers.insert(HeaderName::from_static("x-receipt-url"), value);
+                }
+                job.attach_receipt(receipt_url);
+            }
+
+            schedule_artifact_release(
+                state,
+                prepared.artifact_hash.clone(),
+                job_id,
+                DOWNLOAD_JOB_RETENTION_SECONDS,
+            );
+            if prepared.saturated {
+                // The download itself succeeded: the client fetches it from the job's file_url.
+                job.complete(&prepared.filename);
+                let mut response =
+                    ApiError::streams_saturated(STREAM_RETRY_AFTER_SECONDS).into_response();
+                response.headers_mut().extend(headers);
+                return Ok(response);
+            }
+            let Some(body) = prepared.body else {
+                job.complete_artifact(&prepared.filename, &prepared.artifact_hash);
+                let expires_at =
+                    Utc::now() + chrono::Duration::seconds(state.signed_link_ttl_seconds as i64);
+                let file_url = format!(
+                    "{}{}",
+                    payload.link_base,
+                    build_signed_job_file_path(
+                        &state.signing_secret,
+                        job_id,
+                        expires_at.timestamp()
+                    )
+                );
+                (job.link_offer(file_url.clone()))();
+                return Ok((
+                    headers,
+                    Json(SignedLinkResponse {
+                        job_id,
+                        file_url,
+                        expires_at,
+                        filename: prepared.filename,
+                        size_bytes: prepared.content_length,
+                    }),
+                )
+                    .into_response());
+            };
+            job.complete(&prepared.filename);
+            Ok((headers, body).into_response())
+        }
+        Err(error) => {
+            let entry = HistoryEntry {
+                id: Uuid::new_v4(),
+                created_at: Utc::now(),
+                requester_ip: client_identity.to_string(),
+                remote_ip: payload.remote_ip.clone(),
+                url: url.to_string(),
+                title: selected_title,
+                thumbnail: selected_thumbnail,
+                mode: payload.mode.clone(),
+                format: selected_format,
+                status: DownloadStatus::Failed,
+                saved_path: None,
+                error: Some(error.message.clone()),
+                job_id: None,
+                artifact_hash: None,
+                clip: payload.clip,
+                duration_seconds,
+                request_id: clienterrors::current_request_id(),
+            };
+
+            push_history(state, entry).await?;
+            Err(error)
+        }
+    }
+}
+
+async fn admit_download(
+    state: &AppState,
+    client_identity: &str,
+    url: &str,
+    payload: &DownloadRequest,
+) -> Result<PolicyLimits, ApiError> {
+    let client_ip = payload.remote_ip.as_str();
+    // Integrations authenticate with their key instead of solving the anti-bot challenge.
+    if payload.api_key.is_none() {
+        verify_request_protection(state, client_identity, payload).await?;
+    }
+    if let Some(code) = payload.promo_code.as_deref().and_then(non_empty) {
+        redeem_promo_code(state, code, client_identity).await?;
+    }
+    let boost = active_boost_for(state, client_identity).await;
+    let base_limit = match payload.api_key.as_ref().and_then(|key| key.daily_limit) {
+        Some(limit) => limit,
+        None => {
+            let base_limit = verified_daily_limit_for(state, client_identity)
+                .await
+                .unwrap_or_else(|| state.quota.base_limit(Utc::now()));
+            state
+                .quota
+                .scale(base_limit, state.jobs.active_count().await)
+        }
+    };
+    let base_max_bytes = match payload
+        .api_key
+        .as_ref()
+        .and_then(|key| key.max_download_bytes)
+    {
+        Some(bytes) => bytes,
+        None if payload.snapshot => state.config.snapshot_max_download_bytes,
+        None => state.config.max_download_bytes,
+    };
+    let mut limits = PolicyLimits {
+        daily_limit: base_limit + boost.extra_downloads,
+        max_download_bytes: boost
+            .max_download_bytes
+            .map_or(base_max_bytes, |bytes| bytes.max(base_max_bytes)),
+    };
+    if let Some(premium) = payload.premium {
+        limits.daily_limit = limits.daily_limit.max(premium.daily_limit);
+        limits.max_download_bytes = limits.max_download_bytes.max(premium.max_download_bytes);
+    }
+    if let Some(hook) = &state.policy_hook {
+        let input = PolicyInput {
+            endpoint: "download",
+            url,
+            domain: url_domain(url),
+            client_ip,
+            reputation: client_reputation(state, client_ip).await,
+            mode: Some(match payload.mode {
+                DownloadMode::Video => "video",
+                DownloadMode::Audio => "audio",
+            }),
+            format_id: payload.format_id.as_deref(),
+            limits,
+        };
+        limits = hook.evaluate(&input).await?;
+    }
+    if payload.clip.is_none()
+        && let Some(format_id) = payload.format_id.as_deref().and_then(non_empty)
+        && let Some(bytes) = reported_format_bytes(state, url, format_id).await
+        && bytes > limits.max_download_bytes as f64
+    {
+        return Err(ApiError::file_too_large(limits.max_download_bytes));
+    }
+    // A key's quota is shared by every IP that uses it, like an embed site's.
+    let quota_subject = payload.api_key.as_ref().map_or_else(
+        || client_identity.to_string(),
+        |key| apikeys::tenant(&key.name),
+    );
+    let mut attempts = vec![(quota_subject.as_str(), limits.daily_limit)];
+    // Clearing the session cookie yields a fresh quota, so the IP keeps a wider shared cap.
+    if let Some(sessions) = &state.sessions
+        && payload.api_key.is_none()
+        && quota_subject != client_ip
+    {
+        attempts.push((
+            client_ip,
+            limits.daily_limit.saturating_mul(sessions.ip_limit_factor),
+        ));
+    }
+    register_download_attempts(state, &attempts).await?;
+    cleanup_stale_download_jobs(&state.transfer_dir, STALE_DOWNLOAD_JOB_SECONDS).await;
+    state.artifacts.release_expired().await;
+    if let Some(registry) = &state.registry {
+        registry.prune_expired().await;
+    }
+
+    Ok(limits)
+}
+
+struct BackgroundDownload {
+    requester: String,
+    remote_ip: String,
+    url: String,
+    mode: DownloadMode,
+    format_id: Option<String>,
+    format_label: Option<String>,
+    has_audio: bool,
+    hints: FormatHints,
+    container: Option<String>,
+    subtitle_languages: Vec<String>,
+    codec_profile: Option<CodecProfile>,
+    clip: Option<ClipRange>,
+    split_chapters: bool,
+    extra_args: Vec<String>,
+    sponsorblock_remove: Vec<String>,
+    embed_metadata: bool,
+    embed_thumbnail: bool,
+    audio_tags: Option<AudioTags>,
+    sidecars: Option<SidecarRequest>,
+    ip_family: IpFamily,
+    max_download_bytes: u64,
+    title: Option<String>,
+    thumbnail: Option<String>,
+    link_base: String,
+}
+
+async fn run_background_download(state: AppState, job: JobHandle, download: BackgroundDownload) {
+    let requested_at = Utc::now();
+    let spec = ArtifactSpec {
+        url: &download.url,
+        mode: download.mode.clone(),
+        format_id: download.format_id.as_deref().and_then(non_empty),
+        has_audio: download.has_audio,
+        hints: download.hints,
+        container: download.container.as_deref(),
+        subtitle_languages: &download.subtitle_languages,
+        codec_profile: download.codec_profile.as_ref(),
+        clip: download.clip,
+        split_chapters: download.split_chapters,
+        extra_args: &download.extra_args,
+        sponsorblock_remove: &download.sponsorblock_remove,
+        embed_metadata: download.embed_metadata,
+        embed_thumbnail: download.embed_thumbnail,
+        audio_tags: download.audio_tags.as_ref(),
+        sidecars: download.sidecars,
+        ip_family: download.ip_family,
+        max_download_bytes: download.max_download_bytes,
+        retention_seconds: DOWNLOAD_JOB_RETENTION_SECONDS,
+    };
+    let job_id = job.job_id();
+    let result = produce_artifact(&state, &job, &spec).await;
+    record_telemetry(&state, &download.url, result.as_ref().map(|_| ())).await;
+    let format = download
+        .format_label
+        .or(download.format_id.clone())
+        .unwrap_or_else(|| "Mejor calidad automatica".to_string());
+
+    let entry = HistoryEntry {
+        id: Uuid::new_v4(),
+        created_at: Utc::now(),
+        requester_ip: download.requester,
+        remote_ip: download.remote_ip,
+        url: download.url.clone(),
+        title: download.title,
+        thumbnail: download.thumbnail,
+        mode: download.mode.clone(),
+        format: format.clone(),
+        status: if result.is_ok() {
+            DownloadStatus::Success
+        } else {
+            DownloadStatus::Failed
+        },
+        saved_path: result
+            .as_ref()
+            .ok()
+            .map(|artifact| artifact.filename.clone()),
+        error: result.as_ref().err().map(|error| error.message.clone()),
+        job_id: result.is_ok().then_some(job_id),
+        artifact_hash: result.as_ref().ok().map(|artifact| artifact.hash.clone()),
+        clip: download.clip,
+        duration_seconds: cached_duration(&state, &download.url).await,
+        request_id: clienterrors::current_request_id(),
+    };
+    if let Err(error) = push_history(&state, entry).await {
+        warn!(
+            "No se pudo registrar la descarga {job_id} en el historial: {}",
+            error.message
+        );
+    }
+
+    match result {
+        Ok(artifact) => {
+            if let Some(registry) = &state.registry {
+                registry
+                    .record_artifact(job_id, &artifact, download_link_expiry())
+                    .await;
+            }
+            let link_expires_at = Utc::now().timestamp() + DOWNLOAD_JOB_RETENTION_SECONDS as i64;
+            let file_url = format!(
+                "{}{}",
+                download.link_base,
+                build_signed_file_path(
+                    &state.signing_secret,
+                    &artifact.hash,
+                    job_id,
+                    link_expires_at
+                )
+            );
+            let receipt = ReceiptDetails {
+                job_id,
+                url: &download.url,
+                mode: download.mode,
+                format_id: download.format_id.as_deref(),
+                format: &format,
+                filename: &artifact.filename,
+                size_bytes: artifact.size,
+                sha256: &artifact.hash,
+                requested_at,
+            };
+            if let Some(receipt_url) = issue_receipt(&state, receipt).await {
+                job.attach_receipt(receipt_url);
+            }
+            job.complete_artifact(&artifact.filename, &artifact.hash);
+            (job.link_offer(file_url))();
+            schedule_artifact_release(
+                &state,
+                artifact.hash,
+                job_id,
+                DOWNLOAD_JOB_RETENTION_SECONDS,
+            );
+        }
+        Err(error) => job.fail(&error.message),
+    }
+}
+
+struct ArtifactSpec<'a> {
+    url: &'a str,
+    mode: DownloadMode,
+    format_id: Option<&'a str>,
+    has_audio: bool,
+    hints: FormatHints,
+    container: Option<&'a str>,
+    subtitle_languages: &'a [String],
+    codec_profile: Option<&'a CodecProfile>,
+    clip: Option<ClipRange>,
+    split_chapters: bool,
+    extra_args: &'a [String],
+    sponsorblock_remove: &'a [String],
+    embed_metadata: bool,
+    embed_thumbnail: bool,
+    audio_tags: Option<&'a AudioTags>,
+    sidecars: Option<SidecarRequest>,
+    ip_family: IpFamily,
+    max_download_bytes: u64,
+    retention_seconds: u64,
+}
+
+impl ArtifactSpec<'_> {
+    fn format_selector(&self) -> String {
+        let format_id = self.format_id.filter(|format_id| {
+            !matches!(
+                *format_id,
+                AUTOMATIC_VIDEO_SELECTOR | AUTOMATIC_AUDIO_SELECTOR
+            )
+        });
+        let filter = self.hints.filter(&self.mode);
+        if let (Some(_), None) = (self.compat_profile(), format_id) {
+            return format!(
+                "{}/{}",
+                compat::compatible_selector(&filter),
+                self.base_selector(None, &filter)
+            );
+        }
+        self.base_selector(format_id, &filter)
+    }
+
+    // Dubbed tracks: try the requested audio language first, then fall back to the usual pick.
+    fn base_selector(&self, format_id: Option<&str>, filter: &str) -> String {
+        let selector = self.any_language_selector(format_id, filter);
+        let Some(language) = self
+            .hints
+            .audio_language
+            .map(|language| language.audio_filter())
+        else {
+            return selector;
+        };
+        let video = match self.container {
+            Some("mp4") => "[ext=mp4]",
+            Some("webm") => "[ext=webm]",
+            _ => "",
+        };
+        let preferred = match (&self.mode, format_id) {
+            (DownloadMode::Video, Some(_)) if self.has_audio => return selector,
+            (DownloadMode::Video, Some(format_id)) => format!("{format_id}+ba{language}"),
+            (DownloadMode::Video, None) => format!("bv{video}{filter}+ba{language}"),
+            (DownloadMode::Audio, Some(_)) => return selector,
+            (DownloadMode::Audio, None) => format!("ba{language}{filter}"),
+        };
+        format!("{preferred}/{selector}")
+    }
+
+    fn any_language_selector(&self, format_id: Option<&str>, filter: &str) -> String {
+        let native = match (&self.mode, self.container) {
+            (DownloadMode::Video, Some("mp4")) => Some(("[ext=mp4]", "[ext=m4a]")),
+            (DownloadMode::Video, Some("webm")) => Some(("[ext=webm]", "[ext=webm]")),
+            _ => None,
+        };
+        match (&self.mode, format_id) {
+            (DownloadMode::Video, Some(format_id)) if self.has_audio => format_id.to_string(),
+            (DownloadMode::Video, Some(format_id)) => match native {
+                Some((_, audio)) => {
+                    format!("{format_id}+bestaudio{audio}/{format_id}+bestaudio/best")
+                }
+                None => format!("{format_id}+bestaudio/best"),
+            },
+            (DownloadMode::Video, None) => match native {
+                Some((video, audio)) => {
+                    format!("bv{video}{filter}+ba{audio}/bv{filter}+ba/b{filter}")
+                }
+                None if filter.is_empty() => AUTOMATIC_VIDEO_SELECTOR.to_string(),
+                None => format!("bv{filter}+ba/b{filter}"),
+            },
+            (DownloadMode::Audio, Some(format_id)) => format_id.to_string(),
+            (DownloadMode::Audio, None) => {
+                let preferred = match self.container {
+                    Some("opus") => "[acodec^=opus]",
+                    Some("m4a") => "[ext=m4a]",
+                    _ => "",
+                };
+                if !preferred.is_empty() {
+                    format!("ba{preferred}{filter}/ba{filter}/b{filter}")
+                } else if filter.is_empty() {
+                    AUTOMATIC_AUDIO_SELECTOR.to_string()
+                } else {
+                    format!("ba{filter}/b{filter}")
+                }
+            }
+        }
+    }
+
+    fn compat_profile(&self) -> Option<&CodecProfile> {
+        self.codec_profile
+            .filter(|_| matches!(self.mode, DownloadMode::Video))
+    }
+
+    fn splits_chapters(&self) -> bool {
+        matches!(self.mode, DownloadMode::Audio) && self.split_chapters
+    }
+
+    fn embeds_thumbnail(&self) -> bool {
+        matches!(self.mode, DownloadMode::Audio) && self.embed_thumbnail
+    }
+
+    fn embeds_subtitles(&self) -> bool {
+        matches!(self.mode, DownloadMode::Video) && !self.subtitle_languages.is_empty()
+    }
+
+    fn phase_plan(&self) -> PhasePlan {
+        match self.mode {
+            DownloadMode::Video if self.compat_profile().is_some_and(CodecProfile::is_strict) => {
+                TRANSCODE_VIDEO_PHASES
+            }
+            DownloadMode::Video => VIDEO_PHASES,
+            DownloadMode::Audio => AUDIO_PHASES,
+        }
+    }
+
+    fn transcode_phase(&self) -> JobPhase {
+        if self.phase_plan() == TRANSCODE_VIDEO_PHASES {
+            JobPhase::Transcode
+        } else {
+            JobPhase::Merge
+        }
+    }
+
+    fn source_key(&self) -> String {
+        let mode = match self.mode {
+            DownloadMode::Video => "video",
+            DownloadMode::Audio => "audio",
+        };
+        let mut mode = match self.container {
+            Some(container) => format!("{mode}.{container}"),
+            None => mode.to_string(),
+        };
+        if self.embeds_subtitles() {
+            mode.push_str(&format!("+subs:{}", self.subtitle_languages.join(",")));
+        }
+        if let Some(profile) = self.compat_profile() {
+            mode.push_str(&format!("+compat:{}", profile.cache_key()));
+        }
+        if let Some(clip) = self.clip {
+            mode.push_str(&format!("+clip:{}", clip.section()));
+        }
+        if self.splits_chapters() {
+            mode.push_str("+chapters");
+        }
+        if self.embeds_thumbnail() {
+            mode.push_str("+cover");
+        }
+        if let Some(tags) = self.audio_tags {
+            mode.push_str(&format!("+tags:{}", tags.cache_key()));
+        }
+        if let Some(sidecars) = self.sidecars {
+            mode.push_str(&format!("+sidecars:{}", sidecars.cache_key()));
+        }
+        if !self.sponsorblock_remove.is_empty() {
+            mode.push_str(&format!(
+                "+sponsorblock:{}",
+                self.sponsorblock_remove.join(",")
+            ));
+        }
+        format!("{mode}|{}|{}", self.format_selector(), self.url)
+    }
 }
 
-async fn start_download(
-    State(state): State<AppState>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    headers: HeaderMap,
-    Json(payload): Json<DownloadRequest>,
-) -> Result<Response, ApiError> {
-    struct PreparedDownload {
-        body: Body,
-        filename: String,
-        content_type: &'static str,
-        content_length: u64,
-        job_dir: PathBuf,
+async fn produce_artifact(
+    state: &AppState,
+    job: &JobHandle,
+    spec: &ArtifactSpec<'_>,
+) -> Result<StoredArtifact, ApiError> {
+    let (artifact, cpu_seconds) = obtain_artifact(state, job, spec).await?;
+    if let Some(leak_tags) = state.leak_tags.as_ref().filter(|_| state.demo.is_none()) {
+        let tag = leak_tags.tag_for(job.job_id());
+        leak_tags
+            .record(
+                &tag,
+                job.job_id(),
+                job.owner(),
+                spec.url,
+                &artifact.filename,
+            )
+            .await;
+    }
+    if let Some(usage) = &state.usage {
+        usage
+            .record_job(
+                job.owner(),
+                job.job_id(),
+                &artifact,
+                cpu_seconds,
+                spec.retention_seconds,
+            )
+            .await;
     }
+    Ok(artifact)
+}
 
-    let url = payload.url.trim();
-    if url.is_empty() {
-        return Err(ApiError::bad_request(
-            "Ingresa una URL valida antes de descargar.",
-        ));
+async fn obtain_artifact(
+    state: &AppState,
+    job: &JobHandle,
+    spec: &ArtifactSpec<'_>,
+) -> Result<(StoredArtifact, f64), ApiError> {
+    let job_id = job.job_id();
+    let expires_at = Utc::now() + chrono::Duration::seconds(spec.retention_seconds as i64);
+
+    let leak_tag = state
+        .leak_tags
+        .as_ref()
+        .map(|leak_tags| leak_tags.tag_for(job_id));
+    // Demo samples and tagged files must never be shared under the real source URL.
+    let source_key =
+        (!spec.embed_metadata && state.demo.is_none() && leak_tag.is_none()).then(|| {
+            let key = spec.source_key();
+            if state.history_enabled {
+                key
+            } else {
+                encode_hex(&Sha256::digest(key.as_bytes()))
+            }
+        });
+    if let Some(source_key) = source_key.as_deref()
+        && let Some(artifact) = state
+            .artifacts
+            .acquire_cached(source_key, job_id, expires_at)
+            .await
+    {
+        job.running(spec.phase_plan());
+        if artifact.size > spec.max_download_bytes {
+            state.artifacts.release(&artifact.hash, job_id).await;
+            return Err(ApiError::file_too_large(spec.max_download_bytes));
+        }
+        info!("Artefacto en cache reutilizado para {}", spec.url);
+        return Ok((artifact, 0.0));
     }
-    if !is_supported_download_url(url) {
-        return Err(ApiError::bad_request(
-            "URL no soportada. Usa una URL de X, Facebook, TikTok, YouTube, Instagram o Bluesky.",
-        ));
+
+    job.attach_ip_family(spec.ip_family);
+    if let Some(workers) = &state.workers {
+        let started_at = tokio::time::Instant::now();
+        let request = WorkerJobRequest::from_spec(job_id, spec, leak_tag.as_deref());
+        let dispatched = tokio::select! {
+            dispatched = workers.dispatch(job, &request) => dispatched,
+            () = job.cancelled() => return Err(ApiError::job_cancelled()),
+        };
+        match dispatched {
+            Ok(produced) => {
+                state
+                    .throughput
+                    .record(spec.url, produced.size, started_at.elapsed())
+                    .await;
+                if let Some(decision) = produced.codecs {
+                    job.attach_codec_decision(decision);
+                }
+                return state
+                    .artifacts
+                    .adopt(
+                        &produced.hash,
+                        produced.size,
+                        job_id,
+                        &produced.filename,
+                        expires_at,
+                        source_key.as_deref(),
+                    )
+                    .await
+                    .map(|artifact| (artifact, produced.cpu_seconds));
+            }
+            Err(DispatchError::Job(error)) => return Err(error),
+            Err(DispatchError::Unavailable) if workers.fallback_local() => {
+                warn!("Ningun worker disponible; la descarga {job_id} se ejecuta localmente.");
+            }
+            Err(DispatchError::Unavailable) => return Err(ApiError::workers_unavailable()),
+        }
     }
 
-    let client_ip = client_ip_for_request(&state, &headers, addr);
-    verify_request_protection(&state, &client_ip, &payload).await?;
-    register_download_attempt(&state, &client_ip).await?;
-    let _download_permit = state
-        .download_semaphore
-        .clone()
-        .acquire_owned()
-        .await
-        .map_err(|_| ApiError::internal("No se pudo reservar capacidad de descarga."))?;
-    cleanup_stale_download_jobs(&state.transfer_dir, STALE_DOWNLOAD_JOB_SECONDS).await;
+    let produced = produce_local_file(state, job, spec, leak_tag.as_deref()).await?;
+    if let Some(decision) = produced.codecs.clone() {
+        job.attach_codec_decision(decision);
+    }
+    let result = state
+        .artifacts
+        .ingest(
+            &produced.path,
+            job_id,
+            &produced.filename,
+            expires_at,
+            source_key.as_deref(),
+        )
+        .await;
+    produced.job_dir.remove().await;
+    result.map(|artifact| (artifact, produced.cpu_seconds))
+}
 
-    let selected_format = payload
-        .format_label
-        .clone()
-        .or_else(|| payload.format_id.clone())
-        .unwrap_or_else(|| "Mejor calidad automatica".to_string());
-    let selected_title = payload.title.clone().and_then(normalize_optional_text);
-    let selected_thumbnail = payload.thumbnail.clone().and_then(normalize_optional_text);
+struct LocalFile {
+    path: PathBuf,
+    filename: String,
+    job_dir: JobDir,
+    codecs: Option<CodecDecision>,
+    cpu_seconds: f64,
+}
 
-    let job_dir = state.transfer_dir.join(Uuid::new_v4().to_string());
-    tokio::fs::create_dir_all(&job_dir).await.map_err(|error| {
-        ApiError::internal(format!("No se pudo preparar la descarga temporal: {error}"))
-    })?;
+async fn produce_local_file(
+    state: &AppState,
+    job: &JobHandle,
+    spec: &ArtifactSpec<'_>,
+    leak_tag: Option<&str>,
+) -> Result<LocalFile, ApiError> {
+    let job_id = job.job_id();
+    let priority_lane = async {
+        match state.entitlements.as_ref().filter(|_| job.is_prioritized()) {
+            Some(entitlements) => entitlements.priority_permit().await,
+            None => std::future::pending().await,
+        }
+    };
+    let _download_permit = tokio::select! {
+        permit = state.download_semaphore.clone().acquire_owned() => permit
+            .map_err(|_| ApiError::internal("No se pudo reservar capacidad de descarga."))?,
+        permit = priority_lane => permit
+            .map_err(|_| ApiError::internal("No se pudo reservar capacidad de descarga."))?,
+        () = job.cancelled() => return Err(ApiError::job_cancelled()),
+    };
+    job.running(spec.phase_plan());
+    let started_at = tokio::time::Instant::now();
 
-    let output_template = format!("{}/%(title).140B-%(id)s.%(ext)s", job_dir.to_string_lossy());
+    let job_dir = JobDir::new(state.transfer_dir.join(job_id.to_string()));
+    tokio::fs::create_dir_all(job_dir.path())
+        .await
+        .map_err(|error| {
+            ApiError::internal(format!("No se pudo preparar la descarga temporal: {error}"))
+        })?;
+    if let Some(demo) = &state.demo {
+        return demo.produce_sample(job, spec, job_dir).await;
+    }
 
+    let output_template = format!(
+        "{}/%(title).140B-%(id)s.%(ext)s",
+        job_dir.path().to_string_lossy()
+    );
     let mut args = vec![
         "--no-playlist".to_string(),
         "--no-warnings".to_string(),
         "--newline".to_string(),
+    ];
+    if spec.compat_profile().is_some() || matches!(spec.mode, DownloadMode::Audio) {
+        args.extend(compat::print_codecs_args());
+    }
+    args.extend([
         "--print".to_string(),
         "after_move:filepath".to_string(),
         "-o".to_string(),
         output_template,
-    ];
-
-    match payload.mode.clone() {
-        DownloadMode::Video => {
-            let selector = payload
-                .format_id
-                .as_deref()
-                .and_then(non_empty)
-                .map(|format_id| {
-                    if payload.has_audio.unwrap_or(false) {
-                        format_id.to_string()
+        "-f".to_string(),
+        spec.format_selector(),
+    ]);
+    if let (DownloadMode::Video, Some(container)) = (&spec.mode, spec.container) {
+        for flag in ["--merge-output-format", "--remux-video"] {
+            args.push(flag.to_string());
+            args.push(container.to_string());
+        }
+    }
+    if matches!(spec.mode, DownloadMode::Audio) {
+        args.push("--embed-metadata".to_string());
+    }
+    args.extend(streams::multistream_args(spec.format_id));
+    if spec.embeds_thumbnail() {
+        args.extend(postprocess::thumbnail_args(job_dir.path()));
+    }
+    if let Some(sidecars) = spec.sidecars {
+        args.extend(sidecars.args(job_dir.path()));
+    }
+    args.extend(spec.ip_family.args());
+    if let Some(clip) = spec.clip {
+        args.push("--download-sections".to_string());
+        args.push(clip.section());
+    }
+    let chapters_dir = job_dir.path().join("chapters");
+    if spec.splits_chapters() {
+        args.push("--split-chapters".to_string());
+        args.push("-o".to_string());
+        args.push(format!(
+            "chapter:{}/%(section_number)03d-%(section_title).100B.%(ext)s",
+            chapters_dir.to_string_lossy()
+        ));
+    }
+    if spec.embeds_subtitles() {
+        args.push("--embed-subs".to_string());
+        args.push("--sub-langs".to_string());
+        args.push(spec.subtitle_languages.join(","));
+    }
+    if !spec.sponsorblock_remove.is_empty() {
+        args.push("--sponsorblock-remove".to_string());
+        args.push(spec.sponsorblock_remove.join(","));
+    }
+    if !spec.extra_args.is_empty() {
+        info!(
+            "Job {job_id}: argumentos extra de yt-dlp {}",
+            spec.extra_args.join(" ")
+        );
+        args.extend(spec.extra_args.iter().cloned());
+    }
+    if spec.clip.is_none() {
+        args.extend([
+            "--max-filesize".to_string(),
+            spec.max_download_bytes.to_string(),
+        ]);
+    }
+    args.extend(postprocess::progress_args());
+    args.push(spec.url.to_string());
+    let time_limit = state
+        .throughput
+        .download_timeout(
+            spec.url,
+            expected_download_bytes(state, spec).await,
+            Duration::from_secs(state.config.yt_dlp_timeout_seconds),
+        )
+        .await;
+
+    let work = async {
+        let mut exceeds_max_filesize = false;
+        let output = state
+            .extractor
+            .run_with_progress(
+                RequestClass::Download,
+                spec.url,
+                args,
+                time_limit,
+                &mut |line| {
+                    if let Some((phase, fraction)) = postprocess::parse_progress_line(line) {
+                        if let Some(rate) = postprocess::parse_transfer_rate(line) {
+                            job.transfer(rate);
+                        }
+                        job.progress(phase, fraction);
                     } else {
-                        format!("{format_id}+bestaudio/best")
+                        exceeds_max_filesize |= line.contains("larger than max-filesize");
+                        job.log(line);
                     }
-                })
-                .unwrap_or_else(|| "bestvideo+bestaudio/best".to_string());
-
-            args.push("-f".to_string());
-            args.push(selector);
-        }
-        DownloadMode::Audio => {
-            let selector = payload
-                .format_id
-                .as_deref()
-                .and_then(non_empty)
-                .unwrap_or("bestaudio")
-                .to_string();
-
-            args.push("-f".to_string());
-            args.push(selector);
-            args.push("-x".to_string());
-            args.push("--audio-format".to_string());
-            args.push("mp3".to_string());
-            args.push("--audio-quality".to_string());
-            args.push("0".to_string());
+                },
+            )
+            .await?;
+        if exceeds_max_filesize {
+            return Err(ApiError::file_too_large(spec.max_download_bytes));
         }
-    }
-
-    args.push(url.to_string());
-
-    let preparation_result: Result<PreparedDownload, ApiError> = async {
-        let output = run_yt_dlp(args).await?;
         let printed_path = extract_printed_path(&output.stdout);
-        let resolved_path = resolve_downloaded_file(&job_dir, printed_path.as_deref()).await?;
+        let mut resolved_path =
+            resolve_downloaded_file(job_dir.path(), printed_path.as_deref()).await?;
+        let (video_codec, audio_codec) = compat::parse_printed_codecs(&output.stdout);
+        let source_audio_codec = audio_codec.clone();
+        let codecs = spec
+            .compat_profile()
+            .map(|profile| profile.decide(video_codec, audio_codec));
+        if let Some(decision) = codecs.as_ref().filter(|decision| decision.transcodes()) {
+            info!("Job {job_id}: transcodificando para {decision:?}");
+            let phase = spec.transcode_phase();
+            job.progress(phase, 0.0);
+            resolved_path = postprocess::transcode_compatible(
+                &resolved_path,
+                decision.transcoded_video,
+                decision.transcoded_audio,
+                &mut |fraction| {
+                    job.progress(phase, fraction);
+                },
+            )
+            .await?;
+        }
+        let mut tags = if spec.embed_metadata {
+            job_metadata_tags(spec.url, job_id, Utc::now())
+        } else {
+            Vec::new()
+        };
+        if let Some(overrides) = spec.audio_tags {
+            tags.extend(overrides.pairs());
+        }
+        if let Some(tag) = leak_tag {
+            tags.push((LEAK_TAG_METADATA_KEY, tag.to_string()));
+        }
+        let cover = if spec.embeds_thumbnail() {
+            postprocess::find_cover(job_dir.path()).await
+        } else {
+            None
+        };
+        let chapters = if spec.splits_chapters() {
+            list_chapter_files(&chapters_dir).await?
+        } else {
+            Vec::new()
+        };
+        if !chapters.is_empty() {
+            job.progress(JobPhase::Convert, 0.0);
+            resolved_path = package_chapters(
+                &resolved_path,
+                chapters,
+                spec.container.unwrap_or("mp3"),
+                source_audio_codec.as_deref(),
+                &tags,
+                cover.as_deref(),
+                job,
+            )
+            .await?;
+        } else if matches!(spec.mode, DownloadMode::Audio) {
+            job.progress(JobPhase::Convert, 0.0);
+            resolved_path = postprocess::convert_audio(
+                &resolved_path,
+                spec.container.unwrap_or("mp3"),
+                source_audio_codec.as_deref(),
+                &tags,
+                cover.as_deref(),
+                &mut |fraction| {
+                    job.progress(JobPhase::Convert, fraction);
+                },
+            )
+            .await?;
+        } else if !tags.is_empty() {
+            resolved_path = postprocess::embed_metadata(&resolved_path, &tags, None).await?;
+        }
+        if let Some(sidecars) = spec.sidecars {
+            resolved_path = sidecars
+                .package(&resolved_path, job_dir.path(), spec.url)
+                .await?;
+        }
 
         let filename = resolved_path
             .file_name()
             .and_then(|name| name.to_str())
             .map(ToString::to_string)
             .unwrap_or_else(|| "download.bin".to_string());
+        let filename = match leak_tag {
+            Some(tag) => tagged_filename(&filename, tag),
+            None => filename,
+        };
         let metadata = tokio::fs::metadata(&resolved_path).await.map_err(|error| {
             ApiError::internal(format!(
                 "No se pudo leer metadata del archivo temporal: {error}"
             ))
         })?;
-        if metadata.len() > MAX_DOWNLOAD_BYTES {
-            let max_mb = MAX_DOWNLOAD_BYTES / 1_048_576;
-            return Err(ApiError::bad_request(format!(
-                "El archivo supera el limite permitido de {max_mb} MB."
+        if metadata.len() > spec.max_download_bytes {
+            return Err(ApiError::file_too_large(spec.max_download_bytes));
+        }
+        state
+            .throughput
+            .record(spec.url, metadata.len(), started_at.elapsed())
+            .await;
+        Ok((resolved_path, filename, codecs))
+    };
+    let result = tokio::select! {
+        (result, cpu_seconds) = ffmpeg::metered(work) => {
+            result.map(|produced| (produced, cpu_seconds))
+        }
+        () = job.cancelled() => {
+            info!("Descarga {job_id} cancelada por el usuario.");
+            Err(ApiError::job_cancelled())
+        }
+    };
+
+    match result {
+        Ok(((path, filename, codecs), cpu_seconds)) => Ok(LocalFile {
+            path,
+            filename,
+            job_dir,
+            codecs,
+            cpu_seconds,
+        }),
+        Err(error) => {
+            job_dir.remove().await;
+            Err(error)
+        }
+    }
+}
+
+async fn list_chapter_files(chapters_dir: &Path) -> Result<Vec<PathBuf>, ApiError> {
+    let mut entries = match tokio::fs::read_dir(chapters_dir).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(ApiError::internal(format!(
+                "No se pudo abrir la carpeta de capitulos: {error}"
             )));
         }
+    };
+    let mut chapters = Vec::new();
+    while let Some(entry) = entries.next_entry().await.map_err(|error| {
+        ApiError::internal(format!("No se pudo leer la carpeta de capitulos: {error}"))
+    })? {
+        if entry.file_type().await.is_ok_and(|kind| kind.is_file()) {
+            chapters.push(entry.path());
+        }
+    }
+    chapters.sort();
+    Ok(chapters)
+}
 
-        let file = tokio::fs::File::open(&resolved_path)
+async fn package_chapters(
+    source: &Path,
+    chapters: Vec<PathBuf>,
+    audio_format: &str,
+    source_codec: Option<&str>,
+    tags: &[(&str, String)],
+    cover: Option<&Path>,
+    job: &JobHandle,
+) -> Result<PathBuf, ApiError> {
+    let total = chapters.len();
+    let mut entries = Vec::with_capacity(total);
+    for (index, chapter) in chapters.into_iter().enumerate() {
+        let converted = postprocess::convert_audio(
+            &chapter,
+            audio_format,
+            source_codec,
+            tags,
+            cover,
+            &mut |fraction| {
+                job.progress(JobPhase::Convert, (index as f64 + fraction) / total as f64);
+            },
+        )
+        .await?;
+        let size = tokio::fs::metadata(&converted)
             .await
             .map_err(|error| {
-                ApiError::internal(format!("No se pudo leer el archivo temporal: {error}"))
-            })?;
-        let stream = ReaderStream::new(file);
-        let body = Body::from_stream(stream);
-
-        Ok(PreparedDownload {
-            body,
-            filename: filename.clone(),
-            content_type: content_type_for_filename(&filename),
-            content_length: metadata.len(),
-            job_dir: job_dir.clone(),
-        })
+                ApiError::internal(format!("No se pudo leer un capitulo convertido: {error}"))
+            })?
+            .len();
+        let name = converted
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("capitulo")
+            .to_string();
+        entries.push(ArchiveEntry {
+            name,
+            path: converted,
+            size,
+        });
+    }
+    if archive::archive_len(&entries).is_none() {
+        return Err(ApiError::bad_request(
+            "Los capitulos superan el tamano maximo de un ZIP (4 GiB).",
+        ));
     }
-    .await;
-
-    match preparation_result {
-        Ok(prepared) => {
-            let entry = HistoryEntry {
-                id: Uuid::new_v4(),
-                created_at: Utc::now(),
-                requester_ip: client_ip.clone(),
-                url: url.to_string(),
-                title: selected_title,
-                thumbnail: selected_thumbnail,
-                mode: payload.mode,
-                format: selected_format,
-                status: DownloadStatus::Success,
-                saved_path: Some(prepared.filename.clone()),
-                error: None,
-            };
-
-            if let Err(error) = push_history(&state, entry).await {
-                cleanup_download_job(&prepared.job_dir).await;
-                return Err(error);
-            }
 
-            let mut headers = HeaderMap::new();
-            headers.insert(
-                CONTENT_TYPE,
-                HeaderValue::from_static(prepared.content_type),
-            );
-            headers.insert(
-                CONTENT_LENGTH,
-                HeaderValue::from_str(&prepared.content_length.to_string())
-                    .map_err(|_| ApiError::internal("No se pudo crear el tamano de descarga."))?,
-            );
+    let output = source.with_extension("zip");
+    write_archive_file(entries, &output)
+        .await
+        .map_err(|error| {
+            ApiError::internal(format!("No se pudo empaquetar los capitulos: {error}"))
+        })?;
+    let _ = tokio::fs::remove_file(source).await;
+    Ok(output)
+}
 
-            let content_disposition = build_content_disposition(&prepared.filename);
-            headers.insert(
-                CONTENT_DISPOSITION,
-                HeaderValue::from_str(&content_disposition)
-                    .map_err(|_| ApiError::internal("No se pudo crear la cabecera de descarga."))?,
-            );
+async fn cached_duration(state: &AppState, url: &str) -> Option<f64> {
+    let cache = state.formats_cache.lock().await;
+    cache.get(url)?.response.duration_seconds
+}
 
-            let safe_header_filename = sanitize_ascii_filename(&prepared.filename);
-            headers.insert(
-                HeaderName::from_static("x-download-filename"),
-                HeaderValue::from_str(&safe_header_filename)
-                    .map_err(|_| ApiError::internal("No se pudo crear el nombre del archivo."))?,
-            );
+fn format_upload_date(value: &str) -> Option<String> {
+    NaiveDate::parse_from_str(value.trim(), "%Y%m%d")
+        .ok()
+        .map(|date| date.format("%Y-%m-%d").to_string())
+}
 
-            schedule_cleanup_download_job(prepared.job_dir);
-            Ok((headers, prepared.body).into_response())
-        }
-        Err(error) => {
-            cleanup_download_job(&job_dir).await;
-            let entry = HistoryEntry {
-                id: Uuid::new_v4(),
-                created_at: Utc::now(),
-                requester_ip: client_ip,
-                url: url.to_string(),
-                title: selected_title,
-                thumbnail: selected_thumbnail,
-                mode: payload.mode,
-                format: selected_format,
-                status: DownloadStatus::Failed,
-                saved_path: None,
-                error: Some(error.message.clone()),
-            };
+async fn reported_format_bytes(state: &AppState, url: &str, format_id: &str) -> Option<f64> {
+    let cache = state.formats_cache.lock().await;
+    let response = &cache.get(url)?.response;
+    response
+        .video_options
+        .iter()
+        .chain(&response.audio_options)
+        .find(|option| option.format_id == format_id)
+        .and_then(|option| option.size_bytes)
+}
 
-            push_history(&state, entry).await?;
-            Err(error)
-        }
-    }
+async fn expected_download_bytes(state: &AppState, spec: &ArtifactSpec<'_>) -> Option<f64> {
+    reported_format_bytes(state, spec.url, spec.format_id?).await
 }
 
 fn extract_client_ip(headers: &HeaderMap) -> Option<String> {
@@ -751,6 +3968,31 @@ fn client_ip_for_request(state: &AppState, headers: &HeaderMap, addr: SocketAddr
     }
 }
 
+// Anonymous sessions separate visitors sharing an IP (CGNAT); bans and job ownership stay on the IP.
+fn client_identity_for_request(state: &AppState, headers: &HeaderMap, addr: SocketAddr) -> String {
+    sessions::subject(state, headers).unwrap_or_else(|| client_ip_for_request(state, headers, addr))
+}
+
+// Keys persisted per visitor; without history they are keyed hashes so no IP reaches disk.
+fn stored_identity(state: &AppState, identity: &str) -> String {
+    if state.history_enabled {
+        identity.to_string()
+    } else {
+        encode_hex(&hmac_sha256(&state.signing_secret, identity.as_bytes()))
+    }
+}
+
+fn bearer_matches(expected: &str, headers: &HeaderMap) -> bool {
+    let provided = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .unwrap_or_default();
+
+    constant_time_eq(expected.as_bytes(), provided.as_bytes())
+}
+
 fn read_bool_env(name: &str) -> Option<bool> {
     let value = std::env::var(name).ok()?;
     match value.trim().to_ascii_lowercase().as_str() {
@@ -766,6 +4008,27 @@ fn read_usize_env(name: &str) -> Option<usize> {
         .and_then(|value| value.trim().parse::<usize>().ok())
 }
 
+fn read_list_env(name: &str) -> Vec<String> {
+    read_list_env_raw(name)
+        .into_iter()
+        .map(|item| item.to_ascii_lowercase())
+        .collect()
+}
+
+fn read_list_env_raw(name: &str) -> Vec<String> {
+    std::env::var(name)
+        .ok()
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(ToString::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 fn resolve_bind_addr() -> String {
     if let Some(configured) = std::env::var("APP_ADDR")
         .ok()
@@ -784,7 +4047,9 @@ fn resolve_bind_addr() -> String {
     "127.0.0.1:8787".to_string()
 }
 
-fn build_cors_layer() -> Result<CorsLayer, ApiError> {
+fn load_allowed_origins<'a>(
+    extra_origins: impl Iterator<Item = &'a str>,
+) -> Result<HashSet<String>, ApiError> {
     let configured = std::env::var("ALLOWED_ORIGINS")
         .ok()
         .map(|value| {
@@ -817,7 +4082,13 @@ fn build_cors_layer() -> Result<CorsLayer, ApiError> {
             })
         })
         .collect::<Result<HashSet<_>, _>>()?;
-    let allowed_origins = Arc::new(normalized_origins);
+    Ok(normalized_origins
+        .into_iter()
+        .chain(extra_origins.map(ToString::to_string))
+        .collect())
+}
+
+fn build_cors_layer(allow_credentials: bool, allowed_origins: Arc<HashSet<String>>) -> CorsLayer {
     let allow_origin = AllowOrigin::predicate({
         let allowed_origins = Arc::clone(&allowed_origins);
         move |origin: &HeaderValue, _| {
@@ -839,14 +4110,34 @@ fn build_cors_layer() -> Result<CorsLayer, ApiError> {
         configured_origin_list
     );
 
-    Ok(CorsLayer::new()
-        .allow_origin(allow_origin)
-        .allow_methods([Method::GET, Method::POST, Method::DELETE])
-        .allow_headers(Any)
-        .expose_headers([
-            CONTENT_DISPOSITION,
-            HeaderName::from_static("x-download-filename"),
-        ]))
+    let layer = CorsLayer::new().allow_origin(allow_origin).allow_methods([
+        Method::GET,
+        Method::POST,
+        Method::PUT,
+        Method::DELETE,
+    ]);
+    let layer = if allow_credentials {
+        layer
+            .allow_headers(AllowHeaders::mirror_request())
+            .allow_credentials(true)
+    } else {
+        layer.allow_headers(Any)
+    };
+
+    layer.expose_headers([
+        ACCEPT_RANGES,
+        CONTENT_DISPOSITION,
+        CONTENT_RANGE,
+        ETAG,
+        LAST_MODIFIED,
+        HeaderName::from_static("x-download-filename"),
+        HeaderName::from_static("x-job-id"),
+        HeaderName::from_static(clienterrors::REQUEST_ID_HEADER),
+        HeaderName::from_static("x-receipt-url"),
+        HeaderName::from_static("x-playlist-entries"),
+        HeaderName::from_static("x-playlist-skipped"),
+        HeaderName::from_static(sessions::SESSION_HEADER),
+    ])
 }
 
 fn normalize_origin(value: &str) -> Option<String> {
@@ -873,36 +4164,80 @@ fn normalize_origin(value: &str) -> Option<String> {
     }
 }
 
-async fn register_download_attempt(state: &AppState, ip: &str) -> Result<(), ApiError> {
+async fn register_download_attempt(
+    state: &AppState,
+    ip: &str,
+    limit: usize,
+) -> Result<(), ApiError> {
+    register_download_attempts(state, &[(ip, limit)]).await
+}
+
+// Checks every limit first and records the attempt against all of them only when none is exhausted.
+async fn register_download_attempts(
+    state: &AppState,
+    attempts: &[(&str, usize)],
+) -> Result<(), ApiError> {
     let now = Utc::now();
-    let window_start = now - chrono::Duration::hours(DOWNLOAD_WINDOW_HOURS);
+    let window_start = now - chrono::Duration::hours(state.config.download_window_hours);
 
-    let (snapshot, retry_after_seconds) = {
+    let exhausted = if let Some(redis) = &state.redis {
+        redis
+            .register_attempts(attempts, now, state.config.download_window_hours)
+            .await?
+    } else {
         let mut rate_limits = state.rate_limits.lock().await;
-        let entries = rate_limits.entry(ip.to_string()).or_default();
-        entries.sort();
-        entries.retain(|timestamp| *timestamp > window_start);
-
-        let retry_after_seconds = if entries.len() >= DOWNLOAD_LIMIT_PER_DAY {
-            let reset_at = entries
-                .first()
-                .cloned()
-                .map(|value| value + chrono::Duration::hours(DOWNLOAD_WINDOW_HOURS))
-                .unwrap_or_else(|| now + chrono::Duration::hours(DOWNLOAD_WINDOW_HOURS));
-            Some((reset_at - now).num_seconds().max(1) as u64)
-        } else {
-            entries.push(now);
+        let mut exhausted = None;
+        for (index, (ip, limit)) in attempts.iter().enumerate() {
+            let entries = rate_limits.entry(ip.to_string()).or_default();
             entries.sort();
-            None
-        };
+            entries.retain(|timestamp| *timestamp > window_start);
+            if entries.len() >= *limit {
+                let reset_at = entries
+                    .first()
+                    .cloned()
+                    .map(|value| {
+                        value + chrono::Duration::hours(state.config.download_window_hours)
+                    })
+                    .unwrap_or_else(|| {
+                        now + chrono::Duration::hours(state.config.download_window_hours)
+                    });
+                exhausted = Some((index, (reset_at - now).num_seconds().max(1) as u64));
+                break;
+            }
+        }
+        if exhausted.is_none() {
+            for (ip, _) in attempts {
+                rate_limits.entry(ip.to_string()).or_default().push(now);
+            }
+        }
+        if rate_limits.len() > state.memory.ceiling(TrackedMap::RateLimits) {
+            rate_limits.retain(|_, timestamps| {
+                timestamps.iter().any(|timestamp| *timestamp > window_start)
+            });
+            state
+                .memory
+                .evict_lru(TrackedMap::RateLimits, &mut rate_limits, |timestamps| {
+                    Some(timestamps.last().copied())
+                });
+        }
 
-        (rate_limits.clone(), retry_after_seconds)
+        if state.history_enabled && exhausted.is_none() {
+            for (ip, _) in attempts {
+                state
+                    .storage
+                    .rate_limit_recorded(ip, now, &rate_limits)
+                    .await?;
+            }
+        }
+        exhausted
     };
 
-    persist_rate_limits(&state.rate_limit_path, &snapshot).await?;
-
-    if let Some(retry_after_seconds) = retry_after_seconds {
-        return Err(ApiError::daily_limit_exceeded(retry_after_seconds));
+    if let Some((index, retry_after_seconds)) = exhausted {
+        return Err(ApiError::daily_limit_exceeded(
+            attempts[index].1,
+            state.config.download_window_hours,
+            retry_after_seconds,
+        ));
     }
 
     Ok(())
@@ -934,7 +4269,10 @@ async fn verify_request_protection(
                     "Completa la verificacion anti-bot para continuar con la descarga.",
                 )
             })?;
-        verify_turnstile_token(state, token, client_ip).await
+        let remote_ip = Some(payload.remote_ip.as_str())
+            .filter(|ip| !ip.is_empty())
+            .unwrap_or(client_ip);
+        verify_turnstile_token(state, token, remote_ip).await
     } else {
         validate_antibot(state, client_ip, payload).await
     }
@@ -1028,22 +4366,28 @@ async fn validate_antibot(
         .antibot_solution
         .ok_or_else(|| ApiError::bot_check_failed("Falta solucion anti-bot."))?;
 
-    let challenge = {
-        let mut challenges = state.anti_bot_challenges.lock().await;
-        prune_antibot_challenges(&mut challenges, Utc::now());
-        challenges.remove(challenge_id)
-    }
-    .ok_or_else(|| {
-        ApiError::bot_check_failed("Challenge anti-bot invalido o expirado. Actualiza y reintenta.")
-    })?;
+    let challenge = find_antibot_challenge(state, challenge_id, true)
+        .await?
+        .filter(|challenge| challenge.expires_at >= Utc::now())
+        .ok_or_else(|| {
+            ApiError::bot_check_failed(
+                "Challenge anti-bot invalido o expirado. Actualiza y reintenta.",
+            )
+        })?;
 
-    if challenge.ip != client_ip {
+    // A challenge fetched before the session cookie existed is still bound to the IP.
+    if challenge.ip != client_ip && challenge.ip != payload.remote_ip {
         return Err(ApiError::bot_check_failed(
             "Challenge anti-bot no coincide con el origen de la solicitud.",
         ));
     }
 
-    if !is_pow_solution_valid(challenge_id, &challenge.nonce, solution) {
+    if !is_pow_solution_valid(
+        challenge_id,
+        &challenge.nonce,
+        solution,
+        challenge.difficulty,
+    ) {
         return Err(ApiError::bot_check_failed(
             "No se pudo validar la prueba anti-bot. Intenta nuevamente.",
         ));
@@ -1053,48 +4397,35 @@ async fn validate_antibot(
 }
 
 async fn push_history(state: &AppState, entry: HistoryEntry) -> Result<(), ApiError> {
-    let snapshot = {
-        let mut history = state.history.lock().await;
-        history.insert(0, entry);
-        trim_history_limits(&mut history);
-        history.clone()
-    };
-
-    persist_history(&state.history_path, &snapshot).await
-}
-
-async fn load_history(path: &Path) -> Result<Vec<HistoryEntry>, ApiError> {
-    match tokio::fs::read_to_string(path).await {
-        Ok(contents) => {
-            let mut entries: Vec<HistoryEntry> =
-                serde_json::from_str(&contents).map_err(|error| {
-                    ApiError::internal(format!("No se pudo leer el historial local: {error}"))
-                })?;
-            trim_history_limits(&mut entries);
-            Ok(entries)
-        }
-        Err(error) if error.kind() == ErrorKind::NotFound => Ok(Vec::new()),
-        Err(error) => Err(ApiError::internal(format!(
-            "No se pudo abrir el historial local: {error}"
-        ))),
+    if !state.history_enabled {
+        return Ok(());
     }
+    let mut history = state.history.lock().await;
+    history.insert(0, entry.clone());
+    trim_history_limits(&mut history, &state.config);
+    state.storage.history_pushed(&entry, &history).await
 }
 
-async fn persist_history(path: &Path, history: &[HistoryEntry]) -> Result<(), ApiError> {
-    let payload = serde_json::to_string_pretty(history).map_err(|error| {
-        ApiError::internal(format!("No se pudo serializar el historial local: {error}"))
-    })?;
-
-    tokio::fs::write(path, payload)
-        .await
-        .map_err(|error| ApiError::internal(format!("No se pudo guardar el historial: {error}")))
+async fn mark_history_interrupted(state: &AppState, history_id: Uuid) {
+    let mut history = state.history.lock().await;
+    let Some(entry) = history.iter_mut().find(|entry| entry.id == history_id) else {
+        return;
+    };
+    entry.status = DownloadStatus::Failed;
+    entry.error = Some("La descarga se interrumpio: el cliente cerro la conexion.".to_string());
+    entry.saved_path = None;
+    entry.artifact_hash = None;
+    let entry = entry.clone();
+    if let Err(error) = state.storage.history_updated(&entry, &history).await {
+        warn!("No se pudo actualizar el historial: {}", error.message);
+    }
 }
 
-fn trim_history_limits(entries: &mut Vec<HistoryEntry>) {
+pub(crate) fn trim_history_limits(entries: &mut Vec<HistoryEntry>, config: &Config) {
     let mut counters: HashMap<String, usize> = HashMap::new();
     entries.retain(|entry| {
         let counter = counters.entry(entry.requester_ip.clone()).or_insert(0);
-        if *counter >= HISTORY_PER_IP_LIMIT {
+        if *counter >= config.history_per_ip_limit {
             false
         } else {
             *counter += 1;
@@ -1102,48 +4433,13 @@ fn trim_history_limits(entries: &mut Vec<HistoryEntry>) {
         }
     });
 
-    if entries.len() > HISTORY_MAX_ENTRIES {
-        entries.truncate(HISTORY_MAX_ENTRIES);
-    }
-}
-
-async fn load_rate_limits(path: &Path) -> Result<RateLimitMap, ApiError> {
-    match tokio::fs::read_to_string(path).await {
-        Ok(contents) => {
-            let mut map: RateLimitMap = serde_json::from_str(&contents).map_err(|error| {
-                ApiError::internal(format!("No se pudo leer limites de descarga: {error}"))
-            })?;
-
-            let now = Utc::now();
-            let window_start = now - chrono::Duration::hours(DOWNLOAD_WINDOW_HOURS);
-            map.retain(|_, timestamps| {
-                timestamps.sort();
-                timestamps.retain(|timestamp| *timestamp > window_start);
-                !timestamps.is_empty()
-            });
-
-            Ok(map)
-        }
-        Err(error) if error.kind() == ErrorKind::NotFound => Ok(HashMap::new()),
-        Err(error) => Err(ApiError::internal(format!(
-            "No se pudo abrir archivo de limites de descarga: {error}"
-        ))),
-    }
-}
-
-async fn persist_rate_limits(path: &Path, rate_limits: &RateLimitMap) -> Result<(), ApiError> {
-    let payload = serde_json::to_string_pretty(rate_limits).map_err(|error| {
-        ApiError::internal(format!(
-            "No se pudo serializar limites de descarga: {error}"
-        ))
-    })?;
-
-    tokio::fs::write(path, payload).await.map_err(|error| {
-        ApiError::internal(format!("No se pudo guardar limites de descarga: {error}"))
-    })
+    entries.truncate(config.history_max_entries);
 }
 
-fn build_video_options(formats: &[YtDlpFormat]) -> Vec<FormatOption> {
+fn build_video_options(
+    formats: &[YtDlpFormat],
+    best_audio_bytes: Option<f64>,
+) -> Vec<FormatOption> {
     let mut options: Vec<(u32, f32, f32, FormatOption)> = formats
         .iter()
         .filter(|item| has_video(item))
@@ -1156,11 +4452,16 @@ fn build_video_options(formats: &[YtDlpFormat]) -> Vec<FormatOption> {
                 .unwrap_or_else(|| "Video".to_string());
 
             let has_audio = has_audio(item);
-            let size_label = item
-                .filesize
-                .or(item.filesize_approx)
-                .map(format_filesize_mb)
-                .unwrap_or_else(|| "tamano variable".to_string());
+            let stream_bytes = item.filesize.or(item.filesize_approx);
+            let merged_bytes = stream_bytes
+                .zip(best_audio_bytes)
+                .filter(|_| !has_audio)
+                .map(|(video, audio)| video + audio);
+            let size_label = match (merged_bytes, stream_bytes) {
+                (Some(merged), _) => format!("~{} tras unir audio", format_filesize_mb(merged)),
+                (None, Some(bytes)) => format_filesize_mb(bytes),
+                (None, None) => "tamano variable".to_string(),
+            };
             let fps_label = item
                 .fps
                 .filter(|fps| *fps > 0.0)
@@ -1179,6 +4480,10 @@ fn build_video_options(formats: &[YtDlpFormat]) -> Vec<FormatOption> {
                 resolution: Some(resolution),
                 ext,
                 has_audio,
+                details: FormatDetails::from_format(item, item.tbr),
+                size_bytes: merged_bytes.or(stream_bytes),
+                merged_size_bytes: merged_bytes.map(|bytes| bytes.round() as u64),
+                estimated_seconds: None,
             };
 
             (
@@ -1226,10 +4531,17 @@ fn build_audio_options(formats: &[YtDlpFormat]) -> Vec<FormatOption> {
                 .map(format_filesize_mb)
                 .unwrap_or_else(|| "tamano variable".to_string());
 
-            let label = format!(
+            let mut label = format!(
                 "Audio · {} · {bitrate_label} · {size_label}",
                 ext.to_uppercase()
             );
+            if let Some(language) = item
+                .language
+                .as_deref()
+                .filter(|language| *language != "none")
+            {
+                label.push_str(&format!(" · {language}"));
+            }
 
             (
                 item.abr.unwrap_or_default(),
@@ -1240,6 +4552,10 @@ fn build_audio_options(formats: &[YtDlpFormat]) -> Vec<FormatOption> {
                     resolution: None,
                     ext,
                     has_audio: true,
+                    details: FormatDetails::from_format(item, item.abr.or(item.tbr)),
+                    size_bytes: item.filesize.or(item.filesize_approx),
+                    merged_size_bytes: None,
+                    estimated_seconds: None,
                 },
             )
         })
@@ -1267,8 +4583,7 @@ fn run_error_message(stderr: &[u8]) -> String {
     let message = String::from_utf8_lossy(stderr)
         .lines()
         .map(str::trim)
-        .filter(|line| !line.is_empty())
-        .next_back()
+        .rfind(|line| !line.is_empty())
         .unwrap_or("yt-dlp no pudo completar la operacion")
         .to_string();
     let lower = message.to_ascii_lowercase();
@@ -1284,38 +4599,160 @@ fn run_error_message(stderr: &[u8]) -> String {
     }
 }
 
-async fn run_yt_dlp(args: Vec<String>) -> Result<std::process::Output, ApiError> {
-    let command_future = Command::new("yt-dlp").args(args).output();
-    let output = timeout(Duration::from_secs(YT_DLP_TIMEOUT_SECONDS), command_future)
+async fn run_extractor(
+    program: &str,
+    args: Vec<String>,
+    time_limit: Duration,
+) -> Result<std::process::Output, ApiError> {
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    process_group::isolate(&mut command);
+    let child = command.spawn().map_err(extractor_spawn_error)?;
+    let mut group = ProcessGroup::track(&child);
+    let output = timeout(time_limit, child.wait_with_output())
         .await
         .map_err(|_| {
             ApiError::bad_request(
-                "La descarga excedio el tiempo limite. Intenta con otra URL o formato.",
+                "La consulta a yt-dlp excedio el tiempo limite. Intenta con otra URL o formato.",
             )
         })?
-        .map_err(|error| {
-            if error.kind() == ErrorKind::NotFound {
-                ApiError::internal(
-                    "yt-dlp no esta instalado en el sistema. Instala yt-dlp y reinicia el backend.",
-                )
-            } else {
-                ApiError::internal(format!("No se pudo ejecutar yt-dlp: {error}"))
+        .map_err(extractor_spawn_error)?;
+    group.disarm();
+
+    if !output.status.success() {
+        log_extractor_failure(program, &output);
+        return Err(ApiError::bad_request(run_error_message(&output.stderr)));
+    }
+
+    Ok(output)
+}
+
+async fn run_extractor_streaming(
+    program: &str,
+    args: Vec<String>,
+    time_limit: Duration,
+    on_line: &mut (dyn FnMut(&str) + Send),
+) -> Result<std::process::Output, ApiError> {
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    process_group::isolate(&mut command);
+    priority::child_priority().apply(&mut command);
+    let mut child = command.spawn().map_err(extractor_spawn_error)?;
+    let mut group = ProcessGroup::track(&child);
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| ApiError::internal("No se pudo leer la salida de yt-dlp."))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| ApiError::internal("No se pudo leer la salida de yt-dlp."))?;
+
+    let execution = async {
+        let mut stdout_lines = BufReader::new(stdout).lines();
+        let mut stderr_lines = BufReader::new(stderr).lines();
+        let mut collected_stdout = Vec::new();
+        let mut collected_stderr = Vec::new();
+        let (mut stdout_done, mut stderr_done) = (false, false);
+
+        while !(stdout_done && stderr_done) {
+            tokio::select! {
+                line = stdout_lines.next_line(), if !stdout_done => match line {
+                    Ok(Some(line)) => {
+                        on_line(&line);
+                        if !postprocess::is_progress_line(&line) {
+                            lowmem::collect_line(&mut collected_stdout, &line);
+                        }
+                    }
+                    _ => stdout_done = true,
+                },
+                line = stderr_lines.next_line(), if !stderr_done => match line {
+                    Ok(Some(line)) => {
+                        on_line(&line);
+                        if !postprocess::is_progress_line(&line) {
+                            lowmem::collect_line(&mut collected_stderr, &line);
+                        }
+                    }
+                    _ => stderr_done = true,
+                },
             }
-        })?;
+        }
+
+        child.wait().await.map(|status| std::process::Output {
+            status,
+            stdout: collected_stdout,
+            stderr: collected_stderr,
+        })
+    };
+
+    let output = timeout(time_limit, execution)
+        .await
+        .map_err(|_| {
+            ApiError::bad_request(
+                "La descarga excedio el tiempo limite. Intenta con otra URL o formato.",
+            )
+        })?
+        .map_err(extractor_spawn_error)?;
+    group.disarm();
 
     if !output.status.success() {
+        log_extractor_failure(program, &output);
         return Err(ApiError::bad_request(run_error_message(&output.stderr)));
     }
 
     Ok(output)
 }
 
+fn log_extractor_failure(program: &str, output: &std::process::Output) {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines = stderr.lines().collect::<Vec<_>>();
+    warn!(
+        "{program} termino con {}; stderr:\n{}",
+        output.status,
+        lines[lines.len().saturating_sub(MAX_LOGGED_STDERR_LINES)..].join("\n")
+    );
+}
+
+fn extractor_spawn_error(error: std::io::Error) -> ApiError {
+    if error.kind() == ErrorKind::NotFound {
+        ApiError::internal(
+            "yt-dlp no esta instalado en el sistema. Instala yt-dlp y reinicia el backend.",
+        )
+    } else {
+        ApiError::internal(format!("No se pudo ejecutar yt-dlp: {error}"))
+    }
+}
+
+fn job_metadata_tags(
+    url: &str,
+    job_id: Uuid,
+    downloaded_at: DateTime<Utc>,
+) -> Vec<(&'static str, String)> {
+    let date = downloaded_at.to_rfc3339_opts(SecondsFormat::Secs, true);
+    vec![
+        ("source_url", url.to_string()),
+        ("download_date", date.clone()),
+        ("job_id", job_id.to_string()),
+        (
+            "comment",
+            format!("Total Downloader job {job_id} | {url} | {date}"),
+        ),
+    ]
+}
+
 fn extract_printed_path(stdout: &[u8]) -> Option<String> {
     String::from_utf8_lossy(stdout)
         .lines()
         .map(str::trim)
-        .filter(|line| !line.is_empty())
-        .next_back()
+        .rfind(|line| !line.is_empty() && !compat::is_codecs_line(line))
         .map(ToString::to_string)
 }
 
@@ -1397,20 +4834,86 @@ async fn resolve_download_candidate(
 }
 
 async fn cleanup_download_job(job_dir: &Path) {
-    if let Err(error) = tokio::fs::remove_dir_all(job_dir).await {
-        if error.kind() != ErrorKind::NotFound {
-            info!("No se pudo limpiar carpeta temporal: {error}");
+    if let Err(error) = tokio::fs::remove_dir_all(job_dir).await
+        && error.kind() != ErrorKind::NotFound
+    {
+        info!("No se pudo limpiar carpeta temporal: {error}");
+    }
+}
+
+#[derive(Debug)]
+struct JobDir {
+    path: Option<PathBuf>,
+}
+
+impl JobDir {
+    fn new(path: PathBuf) -> Self {
+        Self { path: Some(path) }
+    }
+
+    fn path(&self) -> &Path {
+        self.path.as_deref().unwrap_or(Path::new(""))
+    }
+
+    async fn remove(mut self) {
+        if let Some(path) = self.path.take() {
+            cleanup_download_job(&path).await;
+        }
+    }
+}
+
+impl Drop for JobDir {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            info!("Descarga abandonada; limpiando {}.", path.to_string_lossy());
+            tokio::spawn(async move { cleanup_download_job(&path).await });
         }
     }
 }
 
-fn schedule_cleanup_download_job(job_dir: PathBuf) {
+fn schedule_artifact_release(
+    state: &AppState,
+    artifact_hash: String,
+    job_id: Uuid,
+    after_seconds: u64,
+) {
+    let artifacts = Arc::clone(&state.artifacts);
     tokio::spawn(async move {
-        tokio::time::sleep(Duration::from_secs(DOWNLOAD_JOB_RETENTION_SECONDS)).await;
-        cleanup_download_job(&job_dir).await;
+        tokio::time::sleep(Duration::from_secs(after_seconds)).await;
+        artifacts.release(&artifact_hash, job_id).await;
     });
 }
 
+async fn shutdown_signal() {
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        () = terminate => {}
+    }
+    info!("Senal de apagado recibida; se dejan de aceptar conexiones.");
+}
+
+async fn run_periodic_cleanup(state: AppState) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(PERIODIC_CLEANUP_SECONDS));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        cleanup_stale_download_jobs(&state.transfer_dir, STALE_DOWNLOAD_JOB_SECONDS).await;
+        state.artifacts.release_expired().await;
+        if let Some(registry) = &state.registry {
+            registry.prune_expired().await;
+        }
+    }
+}
+
 async fn cleanup_stale_download_jobs(transfer_dir: &Path, older_than_secs: u64) {
     if older_than_secs == 0 {
         return;
@@ -1487,29 +4990,27 @@ async fn cleanup_stale_download_jobs(transfer_dir: &Path, older_than_secs: u64)
 }
 
 fn prune_antibot_challenges(challenges: &mut AntiBotChallengeMap, now: DateTime<Utc>) {
-    challenges.retain(|_, challenge| {
-        (now - challenge.created_at).num_seconds() <= ANTIBOT_CHALLENGE_TTL_SECONDS
-    });
+    challenges.retain(|_, challenge| challenge.expires_at >= now);
 }
 
-fn trim_antibot_challenges(challenges: &mut AntiBotChallengeMap) {
-    if challenges.len() <= MAX_ANTIBOT_CHALLENGES {
-        return;
-    }
-
-    let overflow = challenges.len() - MAX_ANTIBOT_CHALLENGES;
-    let mut oldest = challenges
-        .iter()
-        .map(|(id, challenge)| (id.clone(), challenge.created_at))
-        .collect::<Vec<_>>();
-    oldest.sort_by_key(|(_, created_at)| *created_at);
-
-    for (id, _) in oldest.into_iter().take(overflow) {
-        challenges.remove(&id);
+async fn find_antibot_challenge(
+    state: &AppState,
+    challenge_id: &str,
+    consume: bool,
+) -> Result<Option<AntiBotChallenge>, ApiError> {
+    if let Some(redis) = &state.redis {
+        return redis.get_json("challenge", challenge_id, consume).await;
     }
+    let mut challenges = state.anti_bot_challenges.lock().await;
+    prune_antibot_challenges(&mut challenges, Utc::now());
+    Ok(if consume {
+        challenges.remove(challenge_id)
+    } else {
+        challenges.get(challenge_id).cloned()
+    })
 }
 
-fn is_supported_download_url(input: &str) -> bool {
+fn is_supported_download_url(input: &str, extra_domains: &[String]) -> bool {
     let parsed = match Url::parse(input) {
         Ok(url) => url,
         Err(_) => return false,
@@ -1524,26 +5025,62 @@ fn is_supported_download_url(input: &str) -> bool {
         None => return false,
     };
 
-    const SUPPORTED_DOMAINS: [&str; 14] = [
-        "youtube.com",
-        "youtu.be",
-        "x.com",
-        "twitter.com",
-        "facebook.com",
-        "fb.watch",
-        "instagram.com",
-        "bsky.app",
-        "tiktok.com",
-        "vm.tiktok.com",
-        "vt.tiktok.com",
-        "m.youtube.com",
-        "music.youtube.com",
-        "m.facebook.com",
-    ];
-
     SUPPORTED_DOMAINS
         .iter()
-        .any(|domain| host == *domain || host.ends_with(&format!(".{domain}")))
+        .copied()
+        .chain(extra_domains.iter().map(String::as_str))
+        .any(|domain| host == domain || host.ends_with(&format!(".{domain}")))
+}
+
+fn url_domain(input: &str) -> String {
+    Url::parse(input)
+        .ok()
+        .and_then(|parsed| parsed.host_str().map(|host| host.to_ascii_lowercase()))
+        .map(|host| host.trim_start_matches("www.").to_string())
+        .unwrap_or_default()
+}
+
+async fn downloads_in_window(state: &AppState, client: &str) -> usize {
+    let window_start = Utc::now() - chrono::Duration::hours(state.config.download_window_hours);
+    if let Some(redis) = &state.redis {
+        return redis
+            .count_attempts(client, window_start)
+            .await
+            .unwrap_or_default();
+    }
+    state
+        .rate_limits
+        .lock()
+        .await
+        .get(client)
+        .map(|timestamps| {
+            timestamps
+                .iter()
+                .filter(|timestamp| **timestamp > window_start)
+                .count()
+        })
+        .unwrap_or_default()
+}
+
+async fn client_reputation(state: &AppState, client_ip: &str) -> ClientReputation {
+    let window_start = Utc::now() - chrono::Duration::hours(state.config.download_window_hours);
+    let downloads_last_24h = downloads_in_window(state, client_ip).await;
+    let failed_downloads_recent = state
+        .history
+        .lock()
+        .await
+        .iter()
+        .filter(|entry| {
+            entry.remote_ip == client_ip
+                && entry.created_at > window_start
+                && matches!(entry.status, DownloadStatus::Failed)
+        })
+        .count();
+
+    ClientReputation {
+        downloads_last_24h,
+        failed_downloads_recent,
+    }
 }
 
 fn is_domain_match(input: &str, domain: &str) -> bool {
@@ -1584,13 +5121,22 @@ fn build_automatic_formats_response(url: &str) -> FormatsResponse {
 
     FormatsResponse {
         title: format!("Modo automatico ({source})"),
+        source_title: None,
         thumbnail: None,
+        duration_seconds: None,
+        uploader: None,
+        upload_date: None,
+        view_count: None,
         video_options: vec![FormatOption {
             format_id: "bestvideo+bestaudio/best".to_string(),
             label: "Mejor calidad automatica".to_string(),
             resolution: Some("Auto".to_string()),
             ext: "mp4".to_string(),
             has_audio: true,
+            details: FormatDetails::default(),
+            size_bytes: None,
+            merged_size_bytes: None,
+            estimated_seconds: None,
         }],
         audio_options: vec![FormatOption {
             format_id: "bestaudio".to_string(),
@@ -1598,11 +5144,20 @@ fn build_automatic_formats_response(url: &str) -> FormatsResponse {
             resolution: None,
             ext: "mp3".to_string(),
             has_audio: true,
+            details: FormatDetails::default(),
+            size_bytes: None,
+            merged_size_bytes: None,
+            estimated_seconds: None,
         }],
     }
 }
 
-fn is_pow_solution_valid(challenge_id: &str, nonce: &str, solution: u64) -> bool {
+fn is_pow_solution_valid(
+    challenge_id: &str,
+    nonce: &str,
+    solution: u64,
+    difficulty: usize,
+) -> bool {
     let mut hasher = Sha256::new();
     hasher.update(challenge_id.as_bytes());
     hasher.update(b":");
@@ -1611,10 +5166,225 @@ fn is_pow_solution_valid(challenge_id: &str, nonce: &str, solution: u64) -> bool
     hasher.update(solution.to_string().as_bytes());
     let digest = hasher.finalize();
     let hex = format!("{digest:x}");
-    let prefix = "0".repeat(ANTIBOT_DIFFICULTY_HEX_PREFIX);
+    let prefix = "0".repeat(difficulty);
     hex.starts_with(&prefix)
 }
 
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), message);
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(tag.as_ref());
+    digest
+}
+
+fn verify_hmac(key: &[u8], message: &[u8], signature: &str) -> bool {
+    decode_hex(signature.trim()).is_some_and(|tag| {
+        hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, key), message, &tag).is_ok()
+    })
+}
+
+fn sign_value(secret: &[u8], value: &str) -> String {
+    encode_hex(&hmac_sha256(secret, value.as_bytes()))
+}
+
+fn verify_signature(secret: &[u8], value: &str, signature: &str) -> bool {
+    verify_hmac(secret, value.as_bytes(), signature)
+}
+
+// Comparing MACs of both sides keeps the timing independent of their contents and lengths.
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, b"constant-time-eq");
+    hmac::verify(&key, left, hmac::sign(&key, right).as_ref()).is_ok()
+}
+
+fn check_signed_timestamp(timestamp: &str, max_skew_seconds: i64) -> Result<(), &'static str> {
+    let issued_at = timestamp
+        .trim()
+        .parse::<i64>()
+        .map_err(|_| "marca de tiempo invalida")?;
+    if (Utc::now().timestamp() - issued_at).abs() > max_skew_seconds {
+        return Err("marca de tiempo fuera de rango");
+    }
+    Ok(())
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(value.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+fn file_signature_payload(artifact_hash: &str, expires_at: i64) -> String {
+    format!("file:{artifact_hash}:{expires_at}")
+}
+
+fn build_signed_file_path(
+    secret: &[u8],
+    artifact_hash: &str,
+    job_id: Uuid,
+    expires_at: i64,
+) -> String {
+    let signature = sign_value(
+        secret,
+        &file_signature_payload(&format!("{artifact_hash}:{job_id}"), expires_at),
+    );
+    format!("/api/files/{artifact_hash}?job={job_id}&expires={expires_at}&sig={signature}")
+}
+
+fn build_signed_job_file_path(secret: &[u8], job_id: Uuid, expires_at: i64) -> String {
+    let signature = sign_value(
+        secret,
+        &file_signature_payload(&job_id.to_string(), expires_at),
+    );
+    format!("/api/files/{job_id}?expires={expires_at}&sig={signature}")
+}
+
+fn build_feed_token(secret: &[u8], client_ip: &str) -> String {
+    let signature = sign_value(secret, &format!("feed:{client_ip}"));
+    format!("{}.{signature}", encode_hex(client_ip.as_bytes()))
+}
+
+fn verify_feed_token(secret: &[u8], token: &str) -> Option<String> {
+    let (encoded_ip, signature) = token.trim().split_once('.')?;
+    let client_ip = String::from_utf8(decode_hex(encoded_ip)?).ok()?;
+    verify_signature(secret, &format!("feed:{client_ip}"), signature).then_some(client_ip)
+}
+
+fn public_base_url(state: &AppState, headers: &HeaderMap) -> String {
+    if let Some(configured) = &state.public_base_url {
+        return configured.clone();
+    }
+
+    let header_value = |key: &str| {
+        headers
+            .get(key)
+            .and_then(|value| value.to_str().ok())
+            .and_then(non_empty)
+            .map(ToString::to_string)
+    };
+    let host = header_value("host").unwrap_or_else(resolve_bind_addr);
+    let scheme = if state.trust_proxy_headers {
+        header_value("x-forwarded-proto").unwrap_or_else(|| "http".to_string())
+    } else {
+        "http".to_string()
+    };
+
+    format!("{scheme}://{host}")
+}
+
+fn build_history_feed(
+    state: &AppState,
+    entries: &[HistoryEntry],
+    base_url: &str,
+    feed_url: &str,
+    now: DateTime<Utc>,
+) -> String {
+    let updated = entries
+        .first()
+        .map(|entry| entry.created_at)
+        .unwrap_or(now)
+        .to_rfc3339_opts(SecondsFormat::Secs, true);
+    let feed_id = Sha256::digest(feed_url.as_bytes());
+
+    let mut feed = String::new();
+    feed.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    feed.push_str("  <title>Total Downloader - Historial</title>\n");
+    feed.push_str(&format!(
+        "  <id>urn:total-downloader:feed:{}</id>\n",
+        encode_hex(&feed_id[..16])
+    ));
+    feed.push_str(&format!("  <updated>{updated}</updated>\n"));
+    feed.push_str(&format!(
+        "  <link rel=\"self\" href=\"{}\"/>\n",
+        escape_xml(feed_url)
+    ));
+
+    for entry in entries {
+        let title = entry
+            .title
+            .clone()
+            .unwrap_or_else(|| "Sin titulo".to_string());
+        let summary = match entry.status {
+            DownloadStatus::Success => format!("Descarga completada · {}", entry.format),
+            DownloadStatus::Failed => format!(
+                "Descarga fallida · {}",
+                entry.error.as_deref().unwrap_or("error desconocido")
+            ),
+        };
+        let status = match entry.status {
+            DownloadStatus::Success => "success",
+            DownloadStatus::Failed => "failed",
+        };
+
+        feed.push_str("  <entry>\n");
+        feed.push_str(&format!("    <id>urn:uuid:{}</id>\n", entry.id));
+        feed.push_str(&format!("    <title>{}</title>\n", escape_xml(&title)));
+        feed.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            entry.created_at.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ));
+        feed.push_str(&format!(
+            "    <link rel=\"alternate\" href=\"{}\"/>\n",
+            escape_xml(&entry.url)
+        ));
+
+        let retention_ends_at =
+            entry.created_at + chrono::Duration::seconds(DOWNLOAD_JOB_RETENTION_SECONDS as i64);
+        if let (DownloadStatus::Success, Some(artifact_hash), Some(job_id)) =
+            (&entry.status, entry.artifact_hash.as_deref(), entry.job_id)
+            && retention_ends_at > now
+        {
+            let filename = entry.saved_path.as_deref().unwrap_or("download.bin");
+            let file_path = build_signed_file_path(
+                &state.signing_secret,
+                artifact_hash,
+                job_id,
+                retention_ends_at.timestamp(),
+            );
+            feed.push_str(&format!(
+                "    <link rel=\"enclosure\" type=\"{}\" href=\"{}\"/>\n",
+                content_type_for_filename(filename),
+                escape_xml(&format!("{base_url}{file_path}"))
+            ));
+        }
+
+        feed.push_str(&format!("    <category term=\"{status}\"/>\n"));
+        feed.push_str(&format!(
+            "    <summary>{}</summary>\n",
+            escape_xml(&summary)
+        ));
+        feed.push_str("  </entry>\n");
+    }
+
+    feed.push_str("</feed>\n");
+    feed
+}
+
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for character in value.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
 fn content_type_for_filename(filename: &str) -> &'static str {
     let extension = Path::new(filename)
         .extension()
@@ -1632,12 +5402,102 @@ fn content_type_for_filename(filename: &str) -> &'static str {
         "aac" => "audio/aac",
         "wav" => "audio/wav",
         "ogg" => "audio/ogg",
-        "opus" => "audio/ogg",
+        "opus" => "audio/opus",
         "flac" => "audio/flac",
+        "3gp" => "video/3gpp",
+        "3g2" => "video/3gpp2",
+        "ts" | "m2ts" => "video/mp2t",
+        "flv" => "video/x-flv",
+        "avi" => "video/x-msvideo",
+        "m4v" => "video/x-m4v",
+        "avif" => "image/avif",
+        "webp" => "image/webp",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "zip" => "application/zip",
         _ => "application/octet-stream",
     }
 }
 
+fn conditional_json_response<T: Serialize>(
+    request_headers: &HeaderMap,
+    value: &T,
+    last_modified: Option<DateTime<Utc>>,
+) -> Result<Response, ApiError> {
+    let body = serde_json::to_vec(value)
+        .map_err(|error| ApiError::internal(format!("No se pudo serializar respuesta: {error}")))?;
+    let etag = format!("\"{}\"", encode_hex(&Sha256::digest(&body)[..16]));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("private, no-cache"));
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        headers.insert(ETAG, value);
+    }
+    if let Some(value) = last_modified
+        .map(format_http_date)
+        .and_then(|date| HeaderValue::from_str(&date).ok())
+    {
+        headers.insert(LAST_MODIFIED, value);
+    }
+
+    let if_none_match = request_headers
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok());
+    let not_modified = match if_none_match {
+        Some(candidates) => candidates
+            .split(',')
+            .map(str::trim)
+            .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag),
+        None => request_headers
+            .get(IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .zip(last_modified)
+            .is_some_and(|(since, modified)| modified.timestamp() <= since.timestamp()),
+    };
+
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok((headers, body).into_response())
+}
+
+fn format_http_date(value: DateTime<Utc>) -> String {
+    value.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn build_attachment_headers(
+    filename: &str,
+    content_type: &'static str,
+    content_length: u64,
+) -> Result<HeaderMap, ApiError> {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(
+        CONTENT_LENGTH,
+        HeaderValue::from_str(&content_length.to_string())
+            .map_err(|_| ApiError::internal("No se pudo crear el tamano de descarga."))?,
+    );
+
+    let content_disposition = build_content_disposition(filename);
+    headers.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_str(&content_disposition)
+            .map_err(|_| ApiError::internal("No se pudo crear la cabecera de descarga."))?,
+    );
+
+    let safe_header_filename = sanitize_ascii_filename(filename);
+    headers.insert(
+        HeaderName::from_static("x-download-filename"),
+        HeaderValue::from_str(&safe_header_filename)
+            .map_err(|_| ApiError::internal("No se pudo crear el nombre del archivo."))?,
+    );
+
+    Ok(headers)
+}
+
 fn build_content_disposition(filename: &str) -> String {
     let safe_ascii = sanitize_ascii_filename(filename);
     format!(
@@ -1679,6 +5539,71 @@ fn has_audio_only(format: &YtDlpFormat) -> bool {
     !has_video(format) && has_audio(format)
 }
 
+impl FormatDetails {
+    fn from_format(format: &YtDlpFormat, bitrate: Option<f32>) -> Self {
+        let known_codec = |codec: &Option<String>| {
+            codec
+                .clone()
+                .filter(|codec| !codec.is_empty() && codec != "none")
+        };
+        Self {
+            height: format.height.filter(|height| *height > 0),
+            fps: format.fps.filter(|fps| *fps > 0.0),
+            filesize_bytes: format
+                .filesize
+                .or(format.filesize_approx)
+                .filter(|bytes| *bytes > 0.0)
+                .map(|bytes| bytes.round() as u64),
+            bitrate_kbps: bitrate.filter(|bitrate| *bitrate > 0.0),
+            vcodec: known_codec(&format.vcodec),
+            acodec: known_codec(&format.acodec),
+            language: format
+                .language
+                .clone()
+                .filter(|language| !language.is_empty() && language != "none"),
+            format_note: format.format_note.clone().and_then(normalize_optional_text),
+        }
+    }
+}
+
+impl FormatOption {
+    fn v2(&self, automatic: &str) -> FormatOptionV2<'_> {
+        FormatOptionV2 {
+            format_id: &self.format_id,
+            automatic: self.format_id == automatic,
+            ext: &self.ext,
+            has_audio: self.has_audio,
+            details: &self.details,
+            merged_size_bytes: self.merged_size_bytes,
+            estimated_seconds: self.estimated_seconds,
+        }
+    }
+}
+
+impl FormatsResponse {
+    fn v2(&self) -> FormatsResponseV2<'_> {
+        FormatsResponseV2 {
+            api_version: 2,
+            title: self.source_title.as_deref(),
+            thumbnail: self.thumbnail.as_deref(),
+            duration_seconds: self.duration_seconds,
+            uploader: self.uploader.as_deref(),
+            upload_date: self.upload_date.as_deref(),
+            view_count: self.view_count,
+            video_options: self
+                .video_options
+                .iter()
+                .map(|option| option.v2(AUTOMATIC_VIDEO_SELECTOR))
+                .collect(),
+            audio_options: self
+                .audio_options
+                .iter()
+                .map(|option| option.v2(AUTOMATIC_AUDIO_SELECTOR))
+                .collect(),
+        }
+    }
+}
+
 fn format_filesize_mb(bytes: f64) -> String {
     let mb = bytes / 1_048_576.0;
     if mb > 1024.0 {
@@ -1688,6 +5613,18 @@ fn format_filesize_mb(bytes: f64) -> String {
     }
 }
 
+// An arch-suffixed variable (FFMPEG_PATH_AARCH64, FFMPEG_PATH_ARM) wins, so one .env can serve x86 and ARM hosts.
+fn binary_path_env(name: &str) -> Option<String> {
+    let arch = std::env::consts::ARCH.to_ascii_uppercase();
+    [format!("{name}_{arch}"), name.to_string()]
+        .iter()
+        .find_map(|variable| {
+            std::env::var(variable)
+                .ok()
+                .and_then(|value| non_empty(&value).map(ToString::to_string))
+        })
+}
+
 fn non_empty(value: &str) -> Option<&str> {
     let trimmed = value.trim();
     if trimmed.is_empty() {
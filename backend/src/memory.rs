@@ -0,0 +1,212 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use axum::{Json, extract::State};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::info;
+
+use crate::{AntiBotChallenge, ApiError, AppState, CachedFormats, read_usize_env};
+
+const DEFAULT_MAX_CHALLENGES: usize = 20_000;
+const DEFAULT_MAX_RATE_LIMIT_CLIENTS: usize = 50_000;
+const DEFAULT_MAX_FORMATS_CACHE: usize = 500;
+const DEFAULT_MAX_FINISHED_JOBS: usize = 5_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TrackedMap {
+    Challenges,
+    RateLimits,
+    FormatsCache,
+    Jobs,
+}
+
+const TRACKED_MAPS: [(TrackedMap, &str, usize); 4] = [
+    (
+        TrackedMap::Challenges,
+        "MEMORY_MAX_CHALLENGES",
+        DEFAULT_MAX_CHALLENGES,
+    ),
+    (
+        TrackedMap::RateLimits,
+        "MEMORY_MAX_RATE_LIMIT_CLIENTS",
+        DEFAULT_MAX_RATE_LIMIT_CLIENTS,
+    ),
+    (
+        TrackedMap::FormatsCache,
+        "MEMORY_MAX_FORMATS_CACHE",
+        DEFAULT_MAX_FORMATS_CACHE,
+    ),
+    (
+        TrackedMap::Jobs,
+        "MEMORY_MAX_FINISHED_JOBS",
+        DEFAULT_MAX_FINISHED_JOBS,
+    ),
+];
+
+#[derive(Debug)]
+pub(crate) struct MemoryBudget {
+    ceilings: [usize; TRACKED_MAPS.len()],
+    evictions: [AtomicU64; TRACKED_MAPS.len()],
+}
+
+#[derive(Debug, Serialize)]
+struct MapReport {
+    map: TrackedMap,
+    entries: usize,
+    ceiling: usize,
+    approx_bytes: usize,
+    evictions: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct MemoryReport {
+    rss_bytes: Option<u64>,
+    tracked_bytes: usize,
+    maps: Vec<MapReport>,
+}
+
+impl MemoryBudget {
+    pub(crate) fn from_env() -> Self {
+        let ceilings = TRACKED_MAPS.map(|(_, variable, default)| {
+            read_usize_env(variable)
+                .filter(|ceiling| *ceiling > 0)
+                .unwrap_or_else(|| crate::lowmem::tracked_entries(default))
+        });
+        info!(
+            "Limites de memoria: {} challenges, {} clientes con cuota, {} formatos en cache, {} jobs terminados.",
+            ceilings[0], ceilings[1], ceilings[2], ceilings[3]
+        );
+        Self {
+            ceilings,
+            evictions: Default::default(),
+        }
+    }
+
+    fn index(map: TrackedMap) -> usize {
+        TRACKED_MAPS
+            .iter()
+            .position(|(tracked, _, _)| *tracked == map)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn ceiling(&self, map: TrackedMap) -> usize {
+        self.ceilings[Self::index(map)]
+    }
+
+    pub(crate) fn evict_lru<K, V, S>(
+        &self,
+        map: TrackedMap,
+        entries: &mut HashMap<K, V>,
+        last_used: impl Fn(&V) -> Option<S>,
+    ) -> usize
+    where
+        K: Clone + Eq + Hash,
+        S: Ord,
+    {
+        let ceiling = self.ceiling(map);
+        if entries.len() <= ceiling {
+            return 0;
+        }
+        let mut candidates = entries
+            .iter()
+            .filter_map(|(key, value)| last_used(value).map(|stamp| (stamp, key.clone())))
+            .collect::<Vec<_>>();
+        if candidates.len() <= ceiling {
+            return 0;
+        }
+        let overflow = candidates.len() - ceiling;
+        candidates.select_nth_unstable_by(overflow - 1, |left, right| left.0.cmp(&right.0));
+        for (_, key) in candidates.into_iter().take(overflow) {
+            entries.remove(&key);
+        }
+        self.evictions[Self::index(map)].fetch_add(overflow as u64, Ordering::Relaxed);
+        overflow
+    }
+
+    fn map_report(&self, map: TrackedMap, entries: usize, approx_bytes: usize) -> MapReport {
+        MapReport {
+            map,
+            entries,
+            ceiling: self.ceiling(map),
+            approx_bytes,
+            evictions: self.evictions[Self::index(map)].load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub(crate) fn resident_set_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages = statm.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    // SAFETY: sysconf only reads a system constant.
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    u64::try_from(page_size)
+        .ok()
+        .map(|page_size| pages * page_size)
+}
+
+fn challenge_bytes(id: &str, challenge: &AntiBotChallenge) -> usize {
+    size_of::<(String, AntiBotChallenge)>() + id.len() + challenge.nonce.len() + challenge.ip.len()
+}
+
+fn formats_bytes(url: &str, cached: &CachedFormats) -> usize {
+    size_of::<(String, CachedFormats)>()
+        + url.len()
+        + serde_json::to_vec(cached.response.as_ref()).map_or(0, |encoded| encoded.len())
+}
+
+pub(crate) async fn get_memory_report(
+    State(state): State<AppState>,
+) -> Result<Json<MemoryReport>, ApiError> {
+    let budget = &state.memory;
+    let challenges = {
+        let challenges = state.anti_bot_challenges.lock().await;
+        budget.map_report(
+            TrackedMap::Challenges,
+            challenges.len(),
+            challenges
+                .iter()
+                .map(|(id, challenge)| challenge_bytes(id, challenge))
+                .sum(),
+        )
+    };
+    let rate_limits = {
+        let rate_limits = state.rate_limits.lock().await;
+        budget.map_report(
+            TrackedMap::RateLimits,
+            rate_limits.len(),
+            rate_limits
+                .iter()
+                .map(|(ip, timestamps)| {
+                    size_of::<(String, Vec<DateTime<Utc>>)>()
+                        + ip.len()
+                        + timestamps.capacity() * size_of::<DateTime<Utc>>()
+                })
+                .sum(),
+        )
+    };
+    let formats = {
+        let cache = state.formats_cache.lock().await;
+        budget.map_report(
+            TrackedMap::FormatsCache,
+            cache.len(),
+            cache
+                .iter()
+                .map(|(url, cached)| formats_bytes(url, cached))
+                .sum(),
+        )
+    };
+    let (job_entries, job_bytes) = state.jobs.memory_usage().await;
+    let jobs = budget.map_report(TrackedMap::Jobs, job_entries, job_bytes);
+
+    let maps = vec![challenges, rate_limits, formats, jobs];
+    Ok(Json(MemoryReport {
+        rss_bytes: resident_set_bytes(),
+        tracked_bytes: maps.iter().map(|map| map.approx_bytes).sum(),
+        maps,
+    }))
+}
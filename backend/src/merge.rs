@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+
+use crate::{ApiError, DownloadMode, ffmpeg, postprocess};
+
+const REENCODE_FALLBACK_SIZE: (u32, u32) = (1280, 720);
+const REENCODE_FPS: u32 = 30;
+
+pub(crate) struct MergeSource {
+    pub(crate) path: PathBuf,
+    pub(crate) extension: String,
+}
+
+fn concat_list_line(path: &Path) -> String {
+    format!("file '{}'\n", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+fn even(value: u32) -> u32 {
+    value.max(2) & !1
+}
+
+fn describe(probe: &ffmpeg::MediaProbe) -> String {
+    [probe.video.as_deref(), probe.audio.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+// Stream copy needs every piece to share container, codecs and geometry; otherwise the caller must opt into re-encoding.
+pub(crate) async fn concat(
+    sources: &[MergeSource],
+    dir: &Path,
+    stem: &str,
+    mode: &DownloadMode,
+    reencode: bool,
+    on_progress: &mut (dyn FnMut(f64) + Send),
+) -> Result<PathBuf, ApiError> {
+    let Some(first) = sources.first() else {
+        return Err(ApiError::internal("No hay elementos para unir."));
+    };
+    let mut probes = Vec::with_capacity(sources.len());
+    for source in sources {
+        probes.push(ffmpeg::probe(&source.path).await?);
+    }
+    let audio_only = matches!(mode, DownloadMode::Audio);
+    let mismatch = sources.iter().zip(&probes).position(|(source, probe)| {
+        source.extension != first.extension
+            || probe.video != probes[0].video
+            || probe.audio != probes[0].audio
+    });
+
+    if !reencode {
+        if let Some(index) = mismatch {
+            return Err(ApiError::bad_request(format!(
+                "Los elementos no se pueden unir sin recodificar: el 1 es {} ({}) y el {} es {} ({}). Envia merge_reencode: true para recodificarlos.",
+                first.extension,
+                describe(&probes[0]),
+                index + 1,
+                sources[index].extension,
+                describe(&probes[index]),
+            )));
+        }
+        let list = dir.join("concat.txt");
+        let mut content = String::from("ffconcat version 1.0\n");
+        for (source, probe) in sources.iter().zip(&probes) {
+            content.push_str(&concat_list_line(&source.path));
+            // Declared durations let ffmpeg report the combined length for progress.
+            if let Some(duration) = probe.duration_seconds {
+                content.push_str(&format!("duration {duration:.3}\n"));
+            }
+        }
+        tokio::fs::write(&list, content).await.map_err(|error| {
+            ApiError::internal(format!("No se pudo preparar la union: {error}"))
+        })?;
+        let output = dir.join(format!("{stem}.{}", first.extension));
+        let mut args = ["-map", "0", "-c", "copy"]
+            .map(ToString::to_string)
+            .to_vec();
+        if matches!(first.extension.as_str(), "mp4" | "m4a" | "mov") {
+            args.extend(["-movflags".to_string(), "+faststart".to_string()]);
+        }
+        ffmpeg::run_with_input_args(
+            &list,
+            &output,
+            &["-f", "concat", "-safe", "0"].map(ToString::to_string),
+            args,
+            ffmpeg::transcode_timeout(),
+            on_progress,
+        )
+        .await?;
+        return Ok(output);
+    }
+
+    let with_audio = probes.iter().filter(|probe| probe.audio.is_some()).count();
+    if with_audio != 0 && with_audio != probes.len() {
+        return Err(ApiError::bad_request(
+            "Algunos elementos no tienen pista de audio; no se pueden unir en un solo archivo.",
+        ));
+    }
+    if !audio_only && probes.iter().any(|probe| probe.video.is_none()) {
+        return Err(ApiError::bad_request(
+            "Algunos elementos no tienen video; usa el modo audio para unirlos.",
+        ));
+    }
+    let has_audio = with_audio > 0;
+    if audio_only && !has_audio {
+        return Err(ApiError::bad_request(
+            "Los elementos no tienen pista de audio.",
+        ));
+    }
+
+    let (width, height) = probes[0].dimensions.unwrap_or(REENCODE_FALLBACK_SIZE);
+    let (width, height) = (even(width), even(height));
+    let mut filter = String::new();
+    let mut labels = String::new();
+    for index in 0..sources.len() {
+        if !audio_only {
+            filter.push_str(&format!(
+                "[{index}:v:0]scale={width}:{height}:force_original_aspect_ratio=decrease,pad={width}:{height}:(ow-iw)/2:(oh-ih)/2,setsar=1,fps={REENCODE_FPS},format=yuv420p[v{index}];"
+            ));
+            labels.push_str(&format!("[v{index}]"));
+        }
+        if has_audio {
+            filter.push_str(&format!(
+                "[{index}:a:0]aresample=48000,aformat=channel_layouts=stereo[a{index}];"
+            ));
+            labels.push_str(&format!("[a{index}]"));
+        }
+    }
+    filter.push_str(&format!(
+        "{labels}concat=n={}:v={}:a={}",
+        sources.len(),
+        u8::from(!audio_only),
+        u8::from(has_audio)
+    ));
+    if !audio_only {
+        filter.push_str("[v]");
+    }
+    if has_audio {
+        filter.push_str("[a]");
+    }
+
+    let extension = if audio_only {
+        first.extension.as_str()
+    } else {
+        "mp4"
+    };
+    let output = dir.join(format!("{stem}.{extension}"));
+    let mut args = Vec::new();
+    for source in &sources[1..] {
+        args.push("-i".to_string());
+        args.push(source.path.to_string_lossy().into_owned());
+    }
+    args.extend(["-filter_complex".to_string(), filter]);
+    if !audio_only {
+        args.extend(
+            [
+                "-map", "[v]", "-c:v", "libx264", "-preset", "veryfast", "-crf", "23",
+            ]
+            .map(ToString::to_string),
+        );
+    }
+    if has_audio {
+        args.extend(["-map".to_string(), "[a]".to_string()]);
+        let codec: &[&str] = if audio_only {
+            postprocess::audio_codec_args(extension, None)
+        } else {
+            &["-c:a", "aac", "-b:a", "160k"]
+        };
+        args.extend(codec.iter().map(ToString::to_string));
+    }
+    if matches!(extension, "mp4" | "m4a" | "mov") {
+        args.extend(["-movflags".to_string(), "+faststart".to_string()]);
+    }
+    ffmpeg::run(
+        &first.path,
+        &output,
+        args,
+        ffmpeg::transcode_timeout(),
+        on_progress,
+    )
+    .await?;
+    Ok(output)
+}
@@ -0,0 +1,126 @@
+use tracing::{info, warn};
+
+use crate::{ApiError, read_list_env};
+
+const MAX_EXTRA_ARGS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueRule {
+    Target,
+    Range(u32, u32),
+}
+
+const PASSTHROUGH_OPTIONS: [(&str, ValueRule); 3] = [
+    ("impersonate", ValueRule::Target),
+    ("concurrent-fragments", ValueRule::Range(1, 16)),
+    ("retries", ValueRule::Range(0, 20)),
+];
+
+#[derive(Debug)]
+pub(crate) struct ExtraArgsPolicy {
+    allowed: Vec<(&'static str, ValueRule)>,
+}
+
+impl ValueRule {
+    fn accepts(self, value: &str) -> bool {
+        match self {
+            Self::Target => {
+                !value.is_empty()
+                    && value.len() <= 40
+                    && !value.starts_with('-')
+                    && value.chars().all(|character| {
+                        character.is_ascii_alphanumeric()
+                            || matches!(character, '-' | '_' | '.' | ':')
+                    })
+            }
+            Self::Range(min, max) => value
+                .parse::<u32>()
+                .is_ok_and(|number| (min..=max).contains(&number)),
+        }
+    }
+}
+
+fn rejected(argument: &str) -> ApiError {
+    ApiError::bad_request(format!("Argumento de yt-dlp no permitido: {argument}."))
+}
+
+impl ExtraArgsPolicy {
+    pub(crate) fn from_env() -> Self {
+        let mut allowed = Vec::new();
+        for name in read_list_env("EXTRA_ARGS_ALLOWED") {
+            let name = name.trim_start_matches('-');
+            match PASSTHROUGH_OPTIONS
+                .iter()
+                .find(|(option, _)| *option == name)
+            {
+                Some(option) if !allowed.contains(option) => allowed.push(*option),
+                Some(_) => {}
+                None => warn!("EXTRA_ARGS_ALLOWED incluye una opcion no soportada: {name}"),
+            }
+        }
+        if !allowed.is_empty() {
+            info!(
+                "Argumentos extra de yt-dlp habilitados: {}",
+                allowed
+                    .iter()
+                    .map(|(option, _)| format!("--{option}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        Self { allowed }
+    }
+
+    pub(crate) fn options(&self) -> Vec<String> {
+        self.allowed
+            .iter()
+            .map(|(option, _)| format!("--{option}"))
+            .collect()
+    }
+
+    pub(crate) fn validate(&self, raw: Option<Vec<String>>) -> Result<Vec<String>, ApiError> {
+        let Some(raw) = raw.filter(|raw| !raw.is_empty()) else {
+            return Ok(Vec::new());
+        };
+        if self.allowed.is_empty() {
+            return Err(ApiError::bad_request(
+                "Este servidor no admite argumentos extra de yt-dlp.",
+            ));
+        }
+        if raw.len() > MAX_EXTRA_ARGS {
+            return Err(ApiError::bad_request(format!(
+                "Se admiten como maximo {MAX_EXTRA_ARGS} argumentos extra."
+            )));
+        }
+
+        let mut normalized: Vec<String> = Vec::new();
+        let mut arguments = raw.iter().map(|argument| argument.trim());
+        while let Some(argument) = arguments.next() {
+            let Some(option) = argument.strip_prefix("--") else {
+                return Err(rejected(argument));
+            };
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, value),
+                None => (option, arguments.next().unwrap_or_default()),
+            };
+            let Some((name, rule)) = self.allowed.iter().find(|(allowed, _)| *allowed == name)
+            else {
+                return Err(rejected(argument));
+            };
+            if !rule.accepts(value) {
+                return Err(ApiError::bad_request(format!(
+                    "Valor invalido para --{name}: {value}."
+                )));
+            }
+            let flag = format!("--{name}");
+            if normalized.contains(&flag) {
+                return Err(ApiError::bad_request(format!(
+                    "El argumento --{name} esta repetido."
+                )));
+            }
+            normalized.push(flag);
+            normalized.push(value.to_string());
+        }
+        Ok(normalized)
+    }
+}
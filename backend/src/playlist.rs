@@ -0,0 +1,667 @@
+use axum::{
+    body::Body,
+    http::{HeaderName, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use futures_util::{StreamExt, stream};
+use serde::Deserialize;
+use tokio::{
+    sync::mpsc,
+    time::{Duration, timeout},
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::archive::{ArchiveEntry, archive_len, write_archive};
+use crate::artifacts::StoredArtifact;
+use crate::billing;
+use crate::extractor::RequestClass;
+use crate::jobs::{JobHandle, JobPhase, PhasePlan};
+use crate::merge::{self, MergeSource};
+use crate::{
+    ApiError, AppState, ArtifactSpec, ClipRange, DOWNLOAD_JOB_RETENTION_SECONDS, DownloadRequest,
+    DownloadStatus, HistoryEntry, JobDir, METADATA_QUEUE_WAIT_MS, METADATA_RETRY_AFTER_SECONDS,
+    STREAM_RETRY_AFTER_SECONDS, admit_download, build_attachment_headers,
+    content_type_for_filename, deliver_artifact, ffmpeg, is_supported_download_url,
+    mark_history_interrupted, normalize_clip, normalize_optional_text, produce_artifact,
+    push_history, read_usize_env,
+};
+
+const DEFAULT_PLAYLIST_MAX_ENTRIES: usize = 20;
+const DEFAULT_PLAYLIST_MAX_ENTRY_MB: usize = 100;
+const DEFAULT_PLAYLIST_CONCURRENCY: usize = 2;
+const STREAM_CHANNEL_CHUNKS: usize = 8;
+const PLAYLIST_PHASES: PhasePlan = &[(JobPhase::Extraction, 0.05), (JobPhase::Download, 0.95)];
+const MERGED_PHASES: PhasePlan = &[
+    (JobPhase::Extraction, 0.05),
+    (JobPhase::Download, 0.75),
+    (JobPhase::Merge, 0.20),
+];
+
+#[derive(Debug)]
+pub(crate) struct PlaylistLimits {
+    max_entries: usize,
+    max_entry_bytes: u64,
+    concurrency: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct FlatPlaylist {
+    title: Option<String>,
+    #[serde(default)]
+    entries: Vec<FlatEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FlatEntry {
+    url: Option<String>,
+    webpage_url: Option<String>,
+}
+
+struct PlaylistItem {
+    index: usize,
+    job_id: Uuid,
+    artifact: StoredArtifact,
+}
+
+struct Batch {
+    title: Option<String>,
+    total: usize,
+    skipped: usize,
+    items: Vec<PlaylistItem>,
+}
+
+// Clip ranges turn a single video into a batch handled exactly like a playlist.
+pub(crate) fn apply_batch(
+    payload: &mut DownloadRequest,
+    limits: &PlaylistLimits,
+) -> Result<(), ApiError> {
+    let clips = payload.clips.take().unwrap_or_default();
+    if !clips.is_empty() {
+        if payload.playlist {
+            return Err(ApiError::bad_request(
+                "clips no se combina con playlist; recorta un solo video.",
+            ));
+        }
+        if payload.clip.is_some() {
+            return Err(ApiError::bad_request(
+                "Usa clips o start_time/end_time, no ambos.",
+            ));
+        }
+        if clips.len() > limits.max_entries {
+            return Err(ApiError::bad_request(format!(
+                "Puedes pedir como maximo {} recortes.",
+                limits.max_entries
+            )));
+        }
+        for clip in clips {
+            let range = normalize_clip(clip.start_time, clip.end_time)?.ok_or_else(|| {
+                ApiError::bad_request("Cada elemento de clips necesita start_time o end_time.")
+            })?;
+            payload.clip_ranges.push(range);
+        }
+    }
+    payload.merge |= payload.merge_reencode;
+    if payload.merge && !payload.playlist && payload.clip_ranges.is_empty() {
+        return Err(ApiError::bad_request(
+            "merge solo aplica a listas (playlist) o a varios recortes (clips).",
+        ));
+    }
+    if payload.merge && payload.format_label.is_none() {
+        payload.format_label = Some(if payload.playlist {
+            "Lista unida".to_string()
+        } else {
+            format!("{} recortes unidos", payload.clip_ranges.len())
+        });
+    }
+    Ok(())
+}
+
+impl PlaylistLimits {
+    pub(crate) fn from_env() -> Self {
+        let max_entry_mb = read_usize_env("PLAYLIST_MAX_ENTRY_MB")
+            .filter(|value| *value > 0)
+            .unwrap_or(DEFAULT_PLAYLIST_MAX_ENTRY_MB);
+        Self {
+            max_entries: read_usize_env("PLAYLIST_MAX_ENTRIES")
+                .filter(|value| *value > 0)
+                .unwrap_or(DEFAULT_PLAYLIST_MAX_ENTRIES),
+            max_entry_bytes: max_entry_mb as u64 * 1024 * 1024,
+            concurrency: crate::lowmem::concurrency(
+                read_usize_env("PLAYLIST_CONCURRENCY")
+                    .filter(|value| *value > 0)
+                    .unwrap_or(DEFAULT_PLAYLIST_CONCURRENCY),
+            ),
+        }
+    }
+}
+
+async fn enumerate_entries(
+    state: &AppState,
+    url: &str,
+) -> Result<(Option<String>, Vec<String>), ApiError> {
+    let args = vec![
+        "-J".to_string(),
+        "--flat-playlist".to_string(),
+        "--yes-playlist".to_string(),
+        "--playlist-end".to_string(),
+        state.playlist.max_entries.to_string(),
+        "--no-warnings".to_string(),
+        url.to_string(),
+    ];
+    let _metadata_permit = timeout(
+        Duration::from_millis(METADATA_QUEUE_WAIT_MS),
+        state.metadata_semaphore.clone().acquire_owned(),
+    )
+    .await
+    .map_err(|_| ApiError::metadata_saturated(METADATA_RETRY_AFTER_SECONDS))?
+    .map_err(|_| ApiError::internal("No se pudo reservar capacidad para metadatos."))?;
+    let output = state
+        .extractor
+        .run(RequestClass::Metadata, url, args, state.metadata_timeout)
+        .await?;
+    let playlist: FlatPlaylist = serde_json::from_slice(&output.stdout).map_err(|error| {
+        ApiError::internal(format!(
+            "No se pudo interpretar la lista de yt-dlp: {error}"
+        ))
+    })?;
+
+    let entries: Vec<String> = playlist
+        .entries
+        .into_iter()
+        .filter_map(|entry| entry.webpage_url.or(entry.url))
+        .filter(|entry_url| is_supported_download_url(entry_url, &state.extra_supported_domains))
+        .take(state.playlist.max_entries)
+        .collect();
+    if entries.is_empty() {
+        return Err(ApiError::bad_request(
+            "La URL no contiene una lista con elementos descargables.",
+        ));
+    }
+    Ok((playlist.title.and_then(normalize_optional_text), entries))
+}
+
+async fn download_entry(
+    state: &AppState,
+    parent: &JobHandle,
+    spec: ArtifactSpec<'_>,
+    on_progress: &(dyn Fn(f64) + Sync),
+) -> Result<(Uuid, StoredArtifact), ApiError> {
+    let entry_job = state.jobs.create(Uuid::new_v4(), parent.owner()).await?;
+    if parent.is_prioritized() {
+        entry_job.prioritize();
+    }
+    let abandon = entry_job.abandon_on_drop();
+    let mut updates = entry_job.watch();
+    let produce = produce_artifact(state, &entry_job, &spec);
+    tokio::pin!(produce);
+    let result = loop {
+        tokio::select! {
+            result = &mut produce => break result,
+            changed = updates.changed() => {
+                if changed.is_err() {
+                    break (&mut produce).await;
+                }
+                on_progress(updates.borrow().progress() / 100.0);
+            }
+        }
+    };
+    abandon.disarm();
+    match &result {
+        Ok(artifact) => entry_job.complete(&artifact.filename),
+        Err(error) => entry_job.fail(&error.message),
+    }
+    result.map(|artifact| (entry_job.job_id(), artifact))
+}
+
+async fn release_items(state: &AppState, items: &[PlaylistItem]) {
+    for item in items {
+        state
+            .artifacts
+            .release(&item.artifact.hash, item.job_id)
+            .await;
+    }
+}
+
+fn archive_filename(title: Option<&str>) -> String {
+    format!("{}.zip", batch_stem(title, "playlist"))
+}
+
+fn batch_stem(title: Option<&str>, fallback: &str) -> String {
+    let stem: String = title
+        .unwrap_or(fallback)
+        .chars()
+        .map(|character| {
+            if character.is_control() || matches!(character, '/' | '\\') {
+                '_'
+            } else {
+                character
+            }
+        })
+        .take(120)
+        .collect();
+    match stem.trim().trim_start_matches('.') {
+        "" => fallback.to_string(),
+        stem => stem.to_string(),
+    }
+}
+
+// Downloads every entry (or clip range) as its own job; the parent reports their combined progress.
+async fn collect_batch(
+    state: &AppState,
+    url: &str,
+    payload: &DownloadRequest,
+    job: &JobHandle,
+    max_download_bytes: u64,
+) -> Result<Batch, ApiError> {
+    let job_id = job.job_id();
+    let (title, targets): (Option<String>, Vec<(String, Option<ClipRange>)>) =
+        if payload.clip_ranges.is_empty() {
+            let (title, entry_urls) = tokio::select! {
+                listed = enumerate_entries(state, url) => listed?,
+                () = job.cancelled() => return Err(ApiError::job_cancelled()),
+            };
+            (
+                title,
+                entry_urls
+                    .into_iter()
+                    .map(|entry_url| (entry_url, None))
+                    .collect(),
+            )
+        } else {
+            (
+                None,
+                payload
+                    .clip_ranges
+                    .iter()
+                    .map(|clip| (url.to_string(), Some(*clip)))
+                    .collect(),
+            )
+        };
+    let total = targets.len();
+    // Clips are pieces of one video the user already chose, so they keep its format and full size limit.
+    let clipped = !payload.clip_ranges.is_empty();
+    let max_entry_bytes = if clipped {
+        max_download_bytes
+    } else {
+        max_download_bytes.min(state.playlist.max_entry_bytes)
+    };
+    let embed_metadata = payload.embed_metadata.unwrap_or(state.embed_job_metadata);
+    info!(
+        "{} {url} con {total} elementos para el job {job_id}.",
+        if clipped { "Recortes de" } else { "Lista" }
+    );
+
+    let fractions = std::sync::Mutex::new(vec![0.0_f64; total]);
+    let report = |index: usize, fraction: f64| {
+        let mut fractions = fractions.lock().unwrap_or_else(|error| error.into_inner());
+        fractions[index] = fraction.clamp(0.0, 1.0);
+        job.progress(
+            JobPhase::Download,
+            fractions.iter().sum::<f64>() / total as f64,
+        );
+    };
+    let mut items = Vec::with_capacity(total);
+    let mut skipped = 0_usize;
+    let downloads = stream::iter(targets.into_iter().enumerate())
+        .map(|(index, (entry_url, clip))| {
+            let report = &report;
+            async move {
+                let spec = ArtifactSpec {
+                    url: &entry_url,
+                    mode: payload.mode.clone(),
+                    format_id: payload
+                        .format_id
+                        .as_deref()
+                        .and_then(crate::non_empty)
+                        .filter(|_| clipped),
+                    has_audio: clipped && payload.has_audio.unwrap_or(false),
+                    hints: payload.hints,
+                    container: payload.container.as_deref(),
+                    subtitle_languages: payload.embed_subtitles.as_deref().unwrap_or_default(),
+                    codec_profile: payload.codec_profile.as_ref(),
+                    clip,
+                    split_chapters: false,
+                    extra_args: payload.extra_args.as_deref().unwrap_or_default(),
+                    sponsorblock_remove: payload.sponsorblock_categories(),
+                    embed_metadata,
+                    embed_thumbnail: payload.embed_thumbnail.unwrap_or(true),
+                    audio_tags: None,
+                    sidecars: None,
+                    ip_family: payload.ip_family.unwrap_or(state.ip_family),
+                    max_download_bytes: max_entry_bytes,
+                    retention_seconds: DOWNLOAD_JOB_RETENTION_SECONDS,
+                };
+                let on_progress = |fraction: f64| report(index, fraction);
+                let result = download_entry(state, job, spec, &on_progress).await;
+                (index, result)
+            }
+        })
+        .buffer_unordered(state.playlist.concurrency);
+    let collect = async {
+        tokio::pin!(downloads);
+        while let Some((index, result)) = downloads.next().await {
+            match result {
+                Ok((entry_job_id, artifact)) => items.push(PlaylistItem {
+                    index,
+                    job_id: entry_job_id,
+                    artifact,
+                }),
+                Err(error) => {
+                    skipped += 1;
+                    warn!("Elemento {} de {url} omitido: {}", index + 1, error.message);
+                }
+            }
+            report(index, 1.0);
+        }
+    };
+    let cancelled = tokio::select! {
+        () = collect => false,
+        () = job.cancelled() => true,
+    };
+    if cancelled {
+        release_items(state, &items).await;
+        return Err(ApiError::job_cancelled());
+    }
+    if items.is_empty() {
+        return Err(ApiError::job_failed(if clipped {
+            "No se pudo descargar ningun recorte."
+        } else {
+            "No se pudo descargar ningun elemento de la lista."
+        }));
+    }
+    items.sort_by_key(|item| item.index);
+    Ok(Batch {
+        title,
+        total,
+        skipped,
+        items,
+    })
+}
+
+async fn merge_batch(
+    state: &AppState,
+    payload: &DownloadRequest,
+    job: &JobHandle,
+    batch: &Batch,
+    max_download_bytes: u64,
+) -> Result<StoredArtifact, ApiError> {
+    // A merged clip set missing a piece would silently change the video, so it fails instead.
+    if batch.skipped > 0 && !payload.clip_ranges.is_empty() {
+        return Err(ApiError::job_failed(format!(
+            "No se pudieron descargar {} de {} recortes; no se unen incompletos.",
+            batch.skipped, batch.total
+        )));
+    }
+    let job_id = job.job_id();
+    let _permit = tokio::select! {
+        permit = state.download_semaphore.clone().acquire_owned() => permit
+            .map_err(|_| ApiError::internal("No se pudo reservar capacidad para unir."))?,
+        () = job.cancelled() => return Err(ApiError::job_cancelled()),
+    };
+    let job_dir = JobDir::new(state.transfer_dir.join(job_id.to_string()));
+    tokio::fs::create_dir_all(job_dir.path())
+        .await
+        .map_err(|error| {
+            ApiError::internal(format!("No se pudo preparar la union temporal: {error}"))
+        })?;
+    let sources = batch
+        .items
+        .iter()
+        .map(|item| MergeSource {
+            path: item.artifact.path.clone(),
+            extension: std::path::Path::new(&item.artifact.filename)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .unwrap_or("bin")
+                .to_ascii_lowercase(),
+        })
+        .collect::<Vec<_>>();
+    let title = payload.title.clone().and_then(normalize_optional_text);
+    let stem = batch_stem(
+        batch.title.as_deref().or(title.as_deref()),
+        if payload.playlist {
+            "lista"
+        } else {
+            "recortes"
+        },
+    );
+
+    let result = async {
+        job.progress(JobPhase::Merge, 0.0);
+        let mut on_progress = |fraction| job.progress(JobPhase::Merge, fraction);
+        let work = merge::concat(
+            &sources,
+            job_dir.path(),
+            &stem,
+            &payload.mode,
+            payload.merge_reencode,
+            &mut on_progress,
+        );
+        let (merged, cpu_seconds) = tokio::select! {
+            (merged, cpu_seconds) = ffmpeg::metered(work) => (merged?, cpu_seconds),
+            () = job.cancelled() => return Err(ApiError::job_cancelled()),
+        };
+        let size = tokio::fs::metadata(&merged)
+            .await
+            .map_err(|error| {
+                ApiError::internal(format!("No se pudo leer el archivo unido: {error}"))
+            })?
+            .len();
+        if size > max_download_bytes {
+            return Err(ApiError::file_too_large(max_download_bytes));
+        }
+        let filename = merged
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("union.bin")
+            .to_string();
+        let expires_at =
+            Utc::now() + chrono::Duration::seconds(DOWNLOAD_JOB_RETENTION_SECONDS as i64);
+        let artifact = state
+            .artifacts
+            .ingest(&merged, job_id, &filename, expires_at, None)
+            .await?;
+        info!(
+            "Job {job_id}: {} elementos unidos en {filename}.",
+            batch.items.len()
+        );
+        if let Some(usage) = &state.usage {
+            usage
+                .record_job(
+                    job.owner(),
+                    job_id,
+                    &artifact,
+                    cpu_seconds,
+                    DOWNLOAD_JOB_RETENTION_SECONDS,
+                )
+                .await;
+        }
+        Ok(artifact)
+    }
+    .await;
+    job_dir.remove().await;
+    result
+}
+
+async fn run_merged_download(
+    state: &AppState,
+    client_identity: &str,
+    url: &str,
+    payload: &DownloadRequest,
+    job: &JobHandle,
+) -> Result<Response, ApiError> {
+    let requested_at = Utc::now();
+    let limits = admit_download(state, client_identity, url, payload).await?;
+    job.running(MERGED_PHASES);
+    let produced = async {
+        let batch = collect_batch(state, url, payload, job, limits.max_download_bytes).await?;
+        let merged = merge_batch(state, payload, job, &batch, limits.max_download_bytes).await;
+        release_items(state, &batch.items).await;
+        merged
+    }
+    .await;
+    deliver_artifact(
+        state,
+        client_identity,
+        url,
+        payload,
+        job,
+        requested_at,
+        produced,
+    )
+    .await
+}
+
+pub(crate) async fn run_playlist_download(
+    state: &AppState,
+    client_identity: &str,
+    url: &str,
+    payload: &DownloadRequest,
+    job: &JobHandle,
+) -> Result<Response, ApiError> {
+    if payload.merge {
+        return run_merged_download(state, client_identity, url, payload, job).await;
+    }
+    let history_id = Uuid::new_v4();
+    let batch_label = if payload.clip_ranges.is_empty() {
+        "Lista"
+    } else {
+        "Recortes"
+    };
+    let job_id = job.job_id();
+    let selected_title = payload.title.clone().and_then(normalize_optional_text);
+
+    let result: Result<(Response, String, usize), ApiError> = async {
+        let limits = admit_download(state, client_identity, url, payload).await?;
+        job.running(PLAYLIST_PHASES);
+        let Batch {
+            title: playlist_title,
+            total,
+            skipped,
+            items,
+        } = collect_batch(state, url, payload, job, limits.max_download_bytes).await?;
+        let width = total.to_string().len();
+        let entries: Vec<ArchiveEntry> = items
+            .iter()
+            .map(|item| ArchiveEntry {
+                name: format!(
+                    "{:0width$}-{}",
+                    item.index + 1,
+                    item.artifact.filename.replace(['/', '\\'], "_")
+                ),
+                path: item.artifact.path.clone(),
+                size: item.artifact.size,
+            })
+            .collect();
+        let Some(content_length) = archive_len(&entries) else {
+            release_items(state, &items).await;
+            return Err(ApiError::bad_request(
+                "La lista supera el tamano maximo de un ZIP (4 GiB).",
+            ));
+        };
+        let Some(stream_permit) = state.delivery.try_reserve_stream() else {
+            release_items(state, &items).await;
+            return Err(ApiError::streams_saturated(STREAM_RETRY_AFTER_SECONDS));
+        };
+
+        let filename = archive_filename(playlist_title.as_deref().or(selected_title.as_deref()));
+        let mut headers = match build_attachment_headers(
+            &filename,
+            content_type_for_filename(&filename),
+            content_length,
+        ) {
+            Ok(headers) => headers,
+            Err(error) => {
+                release_items(state, &items).await;
+                return Err(error);
+            }
+        };
+        for (name, value) in [
+            ("x-job-id", job_id.to_string()),
+            ("x-playlist-entries", items.len().to_string()),
+            ("x-playlist-skipped", skipped.to_string()),
+        ] {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(HeaderName::from_static(name), value);
+            }
+        }
+
+        let entry_count = items.len();
+        let (sender, receiver) =
+            mpsc::channel(crate::lowmem::channel_chunks(STREAM_CHANNEL_CHUNKS));
+        let stream_state = state.clone();
+        let on_served = state.usage.as_ref().and_then(|usage| {
+            billing::account_for(job.owner())
+                .map(|account| usage.served_handler(account, Some(job_id)))
+        });
+        tokio::spawn(async move {
+            let result = write_archive(entries, &sender).await;
+            if result.is_ok()
+                && let Some(on_served) = on_served
+            {
+                on_served(content_length);
+            }
+            if let Err(error) = result {
+                if error.kind() == std::io::ErrorKind::BrokenPipe {
+                    info!("Cliente cerro la conexion durante el ZIP del job {job_id}.");
+                } else {
+                    warn!("No se pudo generar el ZIP del job {job_id}: {error}");
+                    let _ = sender.send(Err(error)).await;
+                }
+                mark_history_interrupted(&stream_state, history_id).await;
+            }
+            drop(sender);
+            drop(stream_permit);
+            release_items(&stream_state, &items).await;
+        });
+        let body = Body::from_stream(stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|chunk| (chunk, receiver))
+        }));
+        Ok(((headers, body).into_response(), filename, entry_count))
+    }
+    .await;
+
+    let (status, saved_path, format, error) = match &result {
+        Ok((_, filename, entry_count)) => (
+            DownloadStatus::Success,
+            Some(filename.clone()),
+            format!("{batch_label} ({entry_count} elementos)"),
+            None,
+        ),
+        Err(error) => (
+            DownloadStatus::Failed,
+            None,
+            batch_label.to_string(),
+            Some(error.message.clone()),
+        ),
+    };
+    push_history(
+        state,
+        HistoryEntry {
+            id: history_id,
+            created_at: Utc::now(),
+            requester_ip: client_identity.to_string(),
+            remote_ip: payload.remote_ip.clone(),
+            url: url.to_string(),
+            title: selected_title,
+            thumbnail: payload.thumbnail.clone().and_then(normalize_optional_text),
+            mode: payload.mode.clone(),
+            format,
+            status,
+            saved_path,
+            error,
+            job_id: result.is_ok().then_some(job_id),
+            artifact_hash: None,
+            clip: None,
+            duration_seconds: None,
+            request_id: crate::clienterrors::current_request_id(),
+        },
+    )
+    .await?;
+
+    let (response, filename, _) = result?;
+    job.complete(&filename);
+    Ok(response)
+}
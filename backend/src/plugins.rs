@@ -0,0 +1,164 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use axum::{Json, extract::State};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{ApiError, AppState};
+
+const MAX_PLUGIN_FILE_BYTES: u64 = 512 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ExtractorPlugin {
+    plugin_dir: String,
+    package: Option<String>,
+    module: String,
+    kind: String,
+    classes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PluginsResponse {
+    plugin_dirs: Vec<String>,
+    extra_domains: Vec<String>,
+    plugins: Vec<ExtractorPlugin>,
+}
+
+pub(crate) fn plugin_dirs_from_env() -> Vec<PathBuf> {
+    crate::read_list_env_raw("YT_DLP_PLUGIN_DIRS")
+        .into_iter()
+        .map(PathBuf::from)
+        .collect()
+}
+
+pub(crate) fn plugin_args(plugin_dirs: &[PathBuf]) -> Vec<String> {
+    plugin_dirs
+        .iter()
+        .flat_map(|dir| {
+            [
+                "--plugin-dirs".to_string(),
+                dir.to_string_lossy().to_string(),
+            ]
+        })
+        .collect()
+}
+
+pub(crate) async fn discover_plugins(plugin_dirs: &[PathBuf]) -> Vec<ExtractorPlugin> {
+    let mut plugins = Vec::new();
+
+    for plugin_dir in plugin_dirs {
+        let mut roots = vec![(None, plugin_dir.clone())];
+        match tokio::fs::read_dir(plugin_dir).await {
+            Ok(mut entries) => {
+                while let Ok(Some(entry)) = entries.next_entry().await {
+                    let path = entry.path();
+                    if path.join("yt_dlp_plugins").is_dir() {
+                        roots.push((entry.file_name().to_str().map(ToString::to_string), path));
+                    }
+                }
+            }
+            Err(error) => {
+                if error.kind() != ErrorKind::NotFound {
+                    warn!(
+                        "No se pudo leer carpeta de plugins {:?}: {error}",
+                        plugin_dir
+                    );
+                } else {
+                    warn!("Carpeta de plugins inexistente: {:?}", plugin_dir);
+                }
+                continue;
+            }
+        }
+
+        for (package, root) in roots {
+            for kind in ["extractor", "postprocessor"] {
+                let namespace_dir = root.join("yt_dlp_plugins").join(kind);
+                collect_plugin_modules(
+                    plugin_dir,
+                    package.as_deref(),
+                    kind,
+                    &namespace_dir,
+                    &mut plugins,
+                )
+                .await;
+            }
+        }
+    }
+
+    plugins
+}
+
+async fn collect_plugin_modules(
+    plugin_dir: &Path,
+    package: Option<&str>,
+    kind: &str,
+    namespace_dir: &Path,
+    plugins: &mut Vec<ExtractorPlugin>,
+) {
+    let Ok(mut entries) = tokio::fs::read_dir(namespace_dir).await else {
+        return;
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        let Some(module) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".py"))
+        else {
+            continue;
+        };
+        if module.starts_with('_') {
+            continue;
+        }
+
+        let classes = match entry.metadata().await {
+            Ok(metadata) if metadata.len() <= MAX_PLUGIN_FILE_BYTES => {
+                tokio::fs::read_to_string(&path)
+                    .await
+                    .map(|source| plugin_class_names(&source, kind))
+                    .unwrap_or_default()
+            }
+            _ => Vec::new(),
+        };
+
+        plugins.push(ExtractorPlugin {
+            plugin_dir: plugin_dir.to_string_lossy().to_string(),
+            package: package.map(ToString::to_string),
+            module: module.to_string(),
+            kind: kind.to_string(),
+            classes,
+        });
+    }
+}
+
+fn plugin_class_names(source: &str, kind: &str) -> Vec<String> {
+    let suffix = if kind == "extractor" { "IE" } else { "PP" };
+    source
+        .lines()
+        .filter_map(|line| line.strip_prefix("class "))
+        .filter_map(|rest| {
+            let name = rest
+                .split(|character: char| !(character.is_alphanumeric() || character == '_'))
+                .next()?;
+            name.ends_with(suffix).then(|| name.to_string())
+        })
+        .collect()
+}
+
+pub(crate) async fn list_plugins(
+    State(state): State<AppState>,
+) -> Result<Json<PluginsResponse>, ApiError> {
+    Ok(Json(PluginsResponse {
+        plugin_dirs: state
+            .plugin_dirs
+            .iter()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .collect(),
+        extra_domains: state.extra_supported_domains.as_ref().clone(),
+        plugins: discover_plugins(&state.plugin_dirs).await,
+    }))
+}
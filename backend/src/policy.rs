@@ -0,0 +1,187 @@
+use std::{path::PathBuf, process::Stdio};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    process::Command,
+    time::{Duration, timeout},
+};
+use tracing::warn;
+
+use crate::ApiError;
+
+const DEFAULT_POLICY_HOOK_TIMEOUT_MS: u64 = 500;
+const DEFAULT_POLICY_HOOK_MEMORY_MB: u64 = 64;
+const MAX_POLICY_HOOK_OUTPUT_BYTES: u64 = 16 * 1024;
+
+#[derive(Debug, Clone)]
+pub(crate) struct PolicyHook {
+    command: PathBuf,
+    timeout: Duration,
+    memory_limit_bytes: u64,
+    fail_open: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PolicyInput<'a> {
+    pub(crate) endpoint: &'a str,
+    pub(crate) url: &'a str,
+    pub(crate) domain: String,
+    pub(crate) client_ip: &'a str,
+    pub(crate) reputation: ClientReputation,
+    pub(crate) mode: Option<&'a str>,
+    pub(crate) format_id: Option<&'a str>,
+    pub(crate) limits: PolicyLimits,
+}
+
+#[derive(Debug, Serialize, Clone, Copy)]
+pub(crate) struct ClientReputation {
+    pub(crate) downloads_last_24h: usize,
+    pub(crate) failed_downloads_recent: usize,
+}
+
+#[derive(Debug, Serialize, Clone, Copy)]
+pub(crate) struct PolicyLimits {
+    pub(crate) daily_limit: usize,
+    pub(crate) max_download_bytes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PolicyAction {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Deserialize)]
+struct PolicyOutput {
+    decision: PolicyAction,
+    message: Option<String>,
+    daily_limit: Option<usize>,
+    max_download_bytes: Option<u64>,
+}
+
+impl PolicyHook {
+    pub(crate) fn from_env() -> Option<Self> {
+        let command = std::env::var("POLICY_HOOK_COMMAND")
+            .ok()
+            .and_then(|value| crate::non_empty(&value).map(PathBuf::from))?;
+        let timeout_ms = crate::read_usize_env("POLICY_HOOK_TIMEOUT_MS")
+            .filter(|value| *value > 0)
+            .map_or(DEFAULT_POLICY_HOOK_TIMEOUT_MS, |value| value as u64);
+        let memory_mb = crate::read_usize_env("POLICY_HOOK_MEMORY_MB")
+            .filter(|value| *value > 0)
+            .map_or(DEFAULT_POLICY_HOOK_MEMORY_MB, |value| value as u64);
+
+        Some(Self {
+            command,
+            timeout: Duration::from_millis(timeout_ms),
+            memory_limit_bytes: memory_mb * 1024 * 1024,
+            fail_open: crate::read_bool_env("POLICY_HOOK_FAIL_OPEN").unwrap_or(false),
+        })
+    }
+
+    pub(crate) fn command(&self) -> &PathBuf {
+        &self.command
+    }
+
+    pub(crate) async fn evaluate(&self, input: &PolicyInput<'_>) -> Result<PolicyLimits, ApiError> {
+        match self.run(input).await {
+            // The hook may only tighten the configured limits, never raise them.
+            Ok(output) => match output.decision {
+                PolicyAction::Allow => Ok(PolicyLimits {
+                    daily_limit: output
+                        .daily_limit
+                        .map_or(input.limits.daily_limit, |limit| {
+                            limit.min(input.limits.daily_limit)
+                        }),
+                    max_download_bytes: output
+                        .max_download_bytes
+                        .map_or(input.limits.max_download_bytes, |bytes| {
+                            bytes.min(input.limits.max_download_bytes)
+                        }),
+                }),
+                PolicyAction::Deny => Err(ApiError::policy_denied(
+                    output
+                        .message
+                        .and_then(crate::normalize_optional_text)
+                        .unwrap_or_else(|| {
+                            "La solicitud fue rechazada por la politica del servidor.".to_string()
+                        }),
+                )),
+            },
+            Err(message) => {
+                warn!("Hook de politica fallo: {message}");
+                if self.fail_open {
+                    Ok(input.limits)
+                } else {
+                    Err(ApiError::policy_denied(
+                        "No se pudo evaluar la politica del servidor. Intenta mas tarde.",
+                    ))
+                }
+            }
+        }
+    }
+
+    async fn run(&self, input: &PolicyInput<'_>) -> Result<PolicyOutput, String> {
+        let payload = serde_json::to_vec(input).map_err(|error| error.to_string())?;
+
+        let mut command = Command::new(&self.command);
+        command
+            .env_clear()
+            .env("PATH", "/usr/local/bin:/usr/bin:/bin")
+            .current_dir(std::env::temp_dir())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+
+        #[cfg(unix)]
+        {
+            let memory_limit = self.memory_limit_bytes as libc::rlim_t;
+            // SAFETY: setrlimit is async-signal-safe and only touches the forked child.
+            unsafe {
+                command.pre_exec(move || {
+                    let limit = libc::rlimit {
+                        rlim_cur: memory_limit,
+                        rlim_max: memory_limit,
+                    };
+                    if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        let mut child = command.spawn().map_err(|error| error.to_string())?;
+        let mut stdin = child.stdin.take().ok_or("sin stdin")?;
+        let stdout = child.stdout.take().ok_or("sin stdout")?;
+
+        let execution = async {
+            stdin
+                .write_all(&payload)
+                .await
+                .map_err(|error| error.to_string())?;
+            drop(stdin);
+
+            let mut output = Vec::new();
+            stdout
+                .take(MAX_POLICY_HOOK_OUTPUT_BYTES)
+                .read_to_end(&mut output)
+                .await
+                .map_err(|error| error.to_string())?;
+            let status = child.wait().await.map_err(|error| error.to_string())?;
+            if !status.success() {
+                return Err(format!("el hook termino con estado {status}"));
+            }
+
+            serde_json::from_slice::<PolicyOutput>(&output)
+                .map_err(|error| format!("respuesta invalida: {error}"))
+        };
+
+        timeout(self.timeout, execution)
+            .await
+            .map_err(|_| "tiempo limite excedido".to_string())?
+    }
+}
@@ -0,0 +1,447 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    ApiError, ffmpeg,
+    jobs::{JobPhase, TransferRate},
+};
+
+pub(crate) const PROGRESS_MARKER: &str = "__progress__";
+pub(crate) const POSTPROCESS_MARKER: &str = "__postprocess__";
+pub(crate) const COVER_STEM: &str = "cover";
+const COVER_FORMATS: [&str; 3] = ["mp3", "m4a", "flac"];
+const MAX_TAG_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct AudioTags {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) album: Option<String>,
+}
+
+fn normalize_tag(field: &str, value: Option<String>) -> Result<Option<String>, ApiError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let cleaned = value
+        .chars()
+        .map(|character| {
+            if character.is_control() {
+                ' '
+            } else {
+                character
+            }
+        })
+        .collect::<String>()
+        .trim()
+        .to_string();
+    if cleaned.chars().count() > MAX_TAG_CHARS {
+        return Err(ApiError::bad_request(format!(
+            "La etiqueta {field} supera {MAX_TAG_CHARS} caracteres."
+        )));
+    }
+    Ok((!cleaned.is_empty()).then_some(cleaned))
+}
+
+impl AudioTags {
+    pub(crate) fn normalize(self) -> Result<Option<Self>, ApiError> {
+        let tags = Self {
+            title: normalize_tag("title", self.title)?,
+            artist: normalize_tag("artist", self.artist)?,
+            album: normalize_tag("album", self.album)?,
+        };
+        Ok((tags != Self::default()).then_some(tags))
+    }
+
+    pub(crate) fn pairs(&self) -> Vec<(&'static str, String)> {
+        [
+            ("title", &self.title),
+            ("artist", &self.artist),
+            ("album", &self.album),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.clone().map(|value| (key, value)))
+        .collect()
+    }
+
+    pub(crate) fn cache_key(&self) -> String {
+        self.pairs()
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+}
+
+pub(crate) fn thumbnail_args(dir: &Path) -> Vec<String> {
+    vec![
+        "--write-thumbnail".to_string(),
+        "--convert-thumbnails".to_string(),
+        "jpg".to_string(),
+        "-o".to_string(),
+        format!("thumbnail:{}/{COVER_STEM}.%(ext)s", dir.to_string_lossy()),
+    ]
+}
+
+pub(crate) async fn find_cover(dir: &Path) -> Option<PathBuf> {
+    let cover = dir.join(format!("{COVER_STEM}.jpg"));
+    tokio::fs::metadata(&cover)
+        .await
+        .is_ok_and(|metadata| metadata.is_file() && metadata.len() > 0)
+        .then_some(cover)
+}
+
+fn cover_args(cover: &Path, output: &Path) -> Vec<String> {
+    let mut args = vec![
+        "-i".to_string(),
+        cover.to_string_lossy().into_owned(),
+        "-map".to_string(),
+        "0:a".to_string(),
+        "-map".to_string(),
+        "1:v:0".to_string(),
+        "-c:v".to_string(),
+        "mjpeg".to_string(),
+        "-disposition:v:0".to_string(),
+        "attached_pic".to_string(),
+        "-metadata:s:v".to_string(),
+        "comment=Cover (front)".to_string(),
+    ];
+    if output
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("mp3"))
+    {
+        args.push("-id3v2_version".to_string());
+        args.push("3".to_string());
+    }
+    args
+}
+
+fn supported_cover<'a>(cover: Option<&'a Path>, output: &Path) -> Option<&'a Path> {
+    let extension = output
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    cover.filter(|_| COVER_FORMATS.contains(&extension.as_str()))
+}
+
+pub(crate) fn progress_args() -> Vec<String> {
+    vec![
+        "--progress".to_string(),
+        "--progress-template".to_string(),
+        format!(
+            "download:{PROGRESS_MARKER} %(progress._percent_str)s %(progress.speed)s %(progress.eta)s"
+        ),
+        "--progress-template".to_string(),
+        format!("postprocess:{POSTPROCESS_MARKER} %(progress.postprocessor)s %(progress.status)s"),
+    ]
+}
+
+pub(crate) fn is_progress_line(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with(PROGRESS_MARKER) || line.starts_with(POSTPROCESS_MARKER)
+}
+
+pub(crate) fn parse_progress_line(line: &str) -> Option<(JobPhase, f64)> {
+    let line = line.trim();
+    if let Some(rest) = line.strip_prefix(PROGRESS_MARKER) {
+        let percent = rest
+            .split_whitespace()
+            .next()?
+            .trim_end_matches('%')
+            .trim()
+            .parse::<f64>()
+            .ok()?;
+        return Some((JobPhase::Download, (percent / 100.0).clamp(0.0, 1.0)));
+    }
+
+    let rest = line.strip_prefix(POSTPROCESS_MARKER)?;
+    let mut parts = rest.split_whitespace();
+    let postprocessor = parts.next()?;
+    let status = parts.next()?;
+    if postprocessor != "Merger" {
+        return None;
+    }
+    Some((
+        JobPhase::Merge,
+        if status == "finished" { 1.0 } else { 0.0 },
+    ))
+}
+
+pub(crate) fn parse_transfer_rate(line: &str) -> Option<TransferRate> {
+    let mut parts = line
+        .trim()
+        .strip_prefix(PROGRESS_MARKER)?
+        .split_whitespace()
+        .skip(1);
+    let speed_bytes_per_second = parts
+        .next()
+        .and_then(|value| value.parse::<f64>().ok())
+        .filter(|speed| speed.is_finite() && *speed > 0.0);
+    let eta_seconds = parts
+        .next()
+        .and_then(|value| value.parse::<f64>().ok())
+        .filter(|eta| eta.is_finite() && *eta >= 0.0)
+        .map(|eta| eta.round() as u64);
+    Some(TransferRate {
+        speed_bytes_per_second,
+        eta_seconds,
+    })
+}
+
+fn metadata_args(tags: &[(&str, String)], output: &Path) -> Vec<String> {
+    let mut args = Vec::new();
+    for (key, value) in tags {
+        args.push("-metadata".to_string());
+        args.push(format!("{key}={value}"));
+    }
+    let extension = output
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if !tags.is_empty() && matches!(extension.as_str(), "mp4" | "m4a" | "mov") {
+        args.push("-movflags".to_string());
+        args.push("use_metadata_tags".to_string());
+    }
+    args
+}
+
+pub(crate) fn audio_codec_args(
+    audio_format: &str,
+    source_codec: Option<&str>,
+) -> &'static [&'static str] {
+    let source = source_codec.unwrap_or_default().to_ascii_lowercase();
+    let same_codec = match audio_format {
+        "mp3" => source == "mp3",
+        "m4a" => source.starts_with("mp4a") || source == "aac",
+        "opus" => source == "opus",
+        "ogg" => source == "vorbis",
+        "flac" => source == "flac",
+        _ => false,
+    };
+    if same_codec {
+        return &["-c:a", "copy"];
+    }
+    match audio_format {
+        "mp3" => &["-c:a", "libmp3lame", "-q:a", "0"],
+        "m4a" => &["-c:a", "aac", "-b:a", "192k"],
+        "opus" => &["-c:a", "libopus", "-b:a", "160k"],
+        "ogg" => &["-c:a", "libvorbis", "-q:a", "6"],
+        "flac" => &["-c:a", "flac"],
+        "wav" => &["-c:a", "pcm_s16le"],
+        _ => &["-q:a", "0"],
+    }
+}
+
+pub(crate) async fn convert_audio(
+    input: &Path,
+    audio_format: &str,
+    source_codec: Option<&str>,
+    tags: &[(&str, String)],
+    cover: Option<&Path>,
+    on_progress: &mut (dyn FnMut(f64) + Send),
+) -> Result<PathBuf, ApiError> {
+    let output = input.with_extension(audio_format);
+    if output == input {
+        return embed_metadata(input, tags, cover).await;
+    }
+
+    let streams = match supported_cover(cover, &output) {
+        Some(cover) => cover_args(cover, &output),
+        None => vec!["-vn".to_string()],
+    };
+    let args = streams
+        .into_iter()
+        .chain(
+            ["-map_metadata", "0"]
+                .iter()
+                .chain(audio_codec_args(audio_format, source_codec))
+                .map(ToString::to_string),
+        )
+        .chain(metadata_args(tags, &output))
+        .collect::<Vec<_>>();
+    ffmpeg::run(
+        input,
+        &output,
+        args,
+        ffmpeg::postprocess_timeout(),
+        on_progress,
+    )
+    .await?;
+    let _ = tokio::fs::remove_file(input).await;
+    Ok(output)
+}
+
+pub(crate) async fn embed_metadata(
+    input: &Path,
+    tags: &[(&str, String)],
+    cover: Option<&Path>,
+) -> Result<PathBuf, ApiError> {
+    let cover = supported_cover(cover, input);
+    if tags.is_empty() && cover.is_none() {
+        return Ok(input.to_path_buf());
+    }
+
+    let extension = input
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("bin");
+    let staged = input.with_extension(format!("tagged.{extension}"));
+    let streams = match cover {
+        Some(cover) => cover_args(cover, input)
+            .into_iter()
+            .chain(["-c:a".to_string(), "copy".to_string()])
+            .collect(),
+        None => ["-map", "0", "-c", "copy"]
+            .into_iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+    };
+    let args = streams
+        .into_iter()
+        .chain(["-map_metadata".to_string(), "0".to_string()])
+        .chain(metadata_args(tags, input))
+        .collect::<Vec<_>>();
+    ffmpeg::run(
+        input,
+        &staged,
+        args,
+        ffmpeg::postprocess_timeout(),
+        &mut |_| {},
+    )
+    .await?;
+    tokio::fs::rename(&staged, input).await.map_err(|error| {
+        ApiError::internal(format!(
+            "No se pudo reemplazar el archivo etiquetado: {error}"
+        ))
+    })?;
+    Ok(input.to_path_buf())
+}
+
+pub(crate) async fn transcode_compatible(
+    input: &Path,
+    video: bool,
+    audio: bool,
+    on_progress: &mut (dyn FnMut(f64) + Send),
+) -> Result<PathBuf, ApiError> {
+    let staged = input.with_extension("compat.mp4");
+    let software_video: &[&str] = if video {
+        &[
+            "-c:v", "libx264", "-preset", "veryfast", "-crf", "23", "-pix_fmt", "yuv420p",
+        ]
+    } else {
+        &["-c:v", "copy"]
+    };
+    let audio_args: &[&str] = if audio {
+        &["-c:a", "aac", "-b:a", "160k"]
+    } else {
+        &["-c:a", "copy"]
+    };
+    let args = |video_args: Vec<String>| {
+        ["-map", "0:v:0?", "-map", "0:a:0?", "-map_metadata", "0"]
+            .iter()
+            .map(ToString::to_string)
+            .chain(video_args)
+            .chain(
+                audio_args
+                    .iter()
+                    .chain(&["-movflags", "+faststart"])
+                    .map(ToString::to_string),
+            )
+            .collect::<Vec<_>>()
+    };
+    let software_args = args(software_video.iter().map(ToString::to_string).collect());
+
+    let hardware = ffmpeg::hw_encoder().filter(|_| video);
+    let accelerated = match hardware {
+        Some(encoder) => {
+            let result = ffmpeg::run_with_input_args(
+                input,
+                &staged,
+                &encoder.input_args(),
+                args(encoder.video_args()),
+                ffmpeg::transcode_timeout(),
+                on_progress,
+            )
+            .await;
+            if let Err(error) = &result {
+                warn!(
+                    "{} no pudo recodificar {}: {}; se reintenta por software.",
+                    encoder.accel().encoder_name(),
+                    input.display(),
+                    error.message
+                );
+            }
+            result.is_ok()
+        }
+        None => false,
+    };
+    if !accelerated {
+        ffmpeg::run(
+            input,
+            &staged,
+            software_args,
+            ffmpeg::transcode_timeout(),
+            on_progress,
+        )
+        .await?;
+    }
+    let _ = tokio::fs::remove_file(input).await;
+    let output = input.with_extension("mp4");
+    tokio::fs::rename(&staged, &output).await.map_err(|error| {
+        ApiError::internal(format!(
+            "No se pudo reemplazar el archivo convertido: {error}"
+        ))
+    })?;
+    Ok(output)
+}
+
+// Copies the streams into another container; WebM only carries VP8/VP9/AV1 and Opus/Vorbis, so it re-encodes.
+pub(crate) async fn remux(
+    input: &Path,
+    container: &str,
+    on_progress: &mut (dyn FnMut(f64) + Send),
+) -> Result<PathBuf, ApiError> {
+    let staged = input.with_extension(format!("remux.{container}"));
+    let codecs: &[&str] = match container {
+        "webm" => &[
+            "-c:v",
+            "libvpx-vp9",
+            "-b:v",
+            "0",
+            "-crf",
+            "32",
+            "-c:a",
+            "libopus",
+            "-b:a",
+            "160k",
+        ],
+        "mp4" | "mov" => &["-c", "copy", "-movflags", "+faststart"],
+        _ => &["-c", "copy"],
+    };
+    let args = ["-map", "0", "-map_metadata", "0"]
+        .iter()
+        .chain(codecs)
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    let timeout = if container == "webm" {
+        ffmpeg::transcode_timeout()
+    } else {
+        ffmpeg::postprocess_timeout()
+    };
+    ffmpeg::run(input, &staged, args, timeout, on_progress).await?;
+    let _ = tokio::fs::remove_file(input).await;
+    let output = input.with_extension(container);
+    tokio::fs::rename(&staged, &output).await.map_err(|error| {
+        ApiError::internal(format!(
+            "No se pudo reemplazar el archivo convertido: {error}"
+        ))
+    })?;
+    Ok(output)
+}
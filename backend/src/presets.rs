@@ -0,0 +1,167 @@
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{ApiError, DownloadMode, DownloadRequest, FormatHints, read_list_env_raw};
+
+pub(crate) const VIDEO_CONTAINERS: [&str; 4] = ["mp4", "mkv", "webm", "mov"];
+pub(crate) const AUDIO_CONTAINERS: [&str; 6] = ["mp3", "m4a", "opus", "ogg", "flac", "wav"];
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DownloadPreset {
+    name: String,
+    mode: DownloadMode,
+    #[serde(flatten)]
+    hints: FormatHints,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    container: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embed_metadata: Option<bool>,
+}
+
+#[derive(Debug)]
+pub(crate) struct PresetCatalog {
+    presets: Vec<DownloadPreset>,
+}
+
+fn preset(
+    name: &str,
+    mode: DownloadMode,
+    max_height: Option<u32>,
+    container: &str,
+    embed_metadata: Option<bool>,
+) -> DownloadPreset {
+    DownloadPreset {
+        name: name.to_string(),
+        mode,
+        hints: FormatHints {
+            max_height,
+            max_bytes: None,
+            audio_language: None,
+        },
+        container: Some(container.to_string()),
+        embed_metadata,
+    }
+}
+
+fn default_presets() -> Vec<DownloadPreset> {
+    vec![
+        preset("phone", DownloadMode::Video, Some(720), "mp4", None),
+        preset("tablet", DownloadMode::Video, Some(1080), "mp4", None),
+        preset("tv", DownloadMode::Video, Some(2160), "mkv", None),
+        preset(
+            "audio-podcast",
+            DownloadMode::Audio,
+            None,
+            "mp3",
+            Some(true),
+        ),
+    ]
+}
+
+fn optional<T: std::str::FromStr>(value: Option<&str>) -> Result<Option<T>, ()> {
+    match value.map(str::trim).filter(|value| !value.is_empty()) {
+        Some(value) => value.parse().map(Some).map_err(|_| ()),
+        None => Ok(None),
+    }
+}
+
+fn parse_preset(raw: &str) -> Option<DownloadPreset> {
+    let mut fields = raw.split('|');
+    let name = fields.next()?.trim().to_ascii_lowercase();
+    if name.is_empty() {
+        return None;
+    }
+    let mode = match fields.next()?.trim().to_ascii_lowercase().as_str() {
+        "video" => DownloadMode::Video,
+        "audio" => DownloadMode::Audio,
+        _ => return None,
+    };
+    let max_height = optional::<u32>(fields.next()).ok()?;
+    let container = fields
+        .next()
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| !value.is_empty());
+    let allowed: &[&str] = match mode {
+        DownloadMode::Video => &VIDEO_CONTAINERS,
+        DownloadMode::Audio => &AUDIO_CONTAINERS,
+    };
+    if container
+        .as_deref()
+        .is_some_and(|container| !allowed.contains(&container))
+    {
+        return None;
+    }
+    let max_bytes = optional::<u64>(fields.next())
+        .ok()?
+        .map(|megabytes| megabytes * 1024 * 1024);
+    let embed_metadata = optional::<bool>(fields.next()).ok()?;
+
+    Some(DownloadPreset {
+        name,
+        mode,
+        hints: FormatHints {
+            max_height,
+            max_bytes,
+            audio_language: None,
+        },
+        container,
+        embed_metadata,
+    })
+}
+
+impl PresetCatalog {
+    pub(crate) fn from_env() -> Self {
+        let mut presets = default_presets();
+        for raw in read_list_env_raw("DOWNLOAD_PRESETS") {
+            let Some(parsed) = parse_preset(&raw) else {
+                warn!("Preset invalido en DOWNLOAD_PRESETS: {raw:?}");
+                continue;
+            };
+            match presets.iter_mut().find(|preset| preset.name == parsed.name) {
+                Some(existing) => *existing = parsed,
+                None => presets.push(parsed),
+            }
+        }
+        info!(
+            "Presets de descarga: {}",
+            presets
+                .iter()
+                .map(|preset| preset.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        Self { presets }
+    }
+
+    pub(crate) fn list(&self) -> Vec<DownloadPreset> {
+        self.presets.clone()
+    }
+
+    pub(crate) fn apply(&self, payload: &mut DownloadRequest) -> Result<(), ApiError> {
+        let Some(name) = payload
+            .preset
+            .as_deref()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        else {
+            return Ok(());
+        };
+        let preset = self
+            .presets
+            .iter()
+            .find(|preset| preset.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                ApiError::bad_request(format!("Preset de descarga desconocido: {name}."))
+            })?;
+
+        payload.mode = preset.mode.clone();
+        payload.hints.max_height = payload.hints.max_height.or(preset.hints.max_height);
+        payload.hints.max_bytes = payload.hints.max_bytes.or(preset.hints.max_bytes);
+        payload.embed_metadata = payload.embed_metadata.or(preset.embed_metadata);
+        payload.container = preset.container.clone();
+        if payload.format_label.is_none() {
+            payload.format_label = Some(format!("Preset {}", preset.name));
+        }
+        Ok(())
+    }
+}
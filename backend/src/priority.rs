@@ -0,0 +1,96 @@
+use std::sync::OnceLock;
+
+use tokio::process::Command;
+
+const MAX_NICENESS: i32 = 19;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IoClass {
+    BestEffort(u8),
+    Idle,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ChildPriority {
+    niceness: Option<i32>,
+    io_class: Option<IoClass>,
+}
+
+impl ChildPriority {
+    fn from_env() -> Self {
+        let niceness = std::env::var("CHILD_NICENESS")
+            .ok()
+            .and_then(|value| value.trim().parse::<i32>().ok())
+            .filter(|value| *value > 0)
+            .map(|value| value.min(MAX_NICENESS));
+        let io_class = std::env::var("CHILD_IONICE_CLASS").ok().and_then(|value| {
+            match value.trim().to_ascii_lowercase().as_str() {
+                "idle" => Some(IoClass::Idle),
+                "best-effort" | "best_effort" | "besteffort" => {
+                    let level = crate::read_usize_env("CHILD_IONICE_LEVEL")
+                        .map_or(7, |level| level.min(7) as u8);
+                    Some(IoClass::BestEffort(level))
+                }
+                _ => None,
+            }
+        });
+
+        Self { niceness, io_class }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.niceness.is_some() || self.io_class.is_some()
+    }
+
+    pub(crate) fn apply(&self, command: &mut Command) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        #[cfg(unix)]
+        {
+            let niceness = self.niceness;
+            let io_class = self.io_class;
+            // SAFETY: setpriority and ioprio_set are async-signal-safe and only touch the
+            // forked child. Failures are ignored so a restricted host never blocks a download.
+            unsafe {
+                command.pre_exec(move || {
+                    if let Some(niceness) = niceness {
+                        libc::setpriority(libc::PRIO_PROCESS, 0, niceness);
+                    }
+                    #[cfg(target_os = "linux")]
+                    if let Some(io_class) = io_class {
+                        set_io_priority(io_class);
+                    }
+                    #[cfg(not(target_os = "linux"))]
+                    let _ = io_class;
+                    Ok(())
+                });
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_io_priority(io_class: IoClass) {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+
+    let ioprio = match io_class {
+        IoClass::BestEffort(level) => {
+            (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | libc::c_int::from(level)
+        }
+        IoClass::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+    };
+    // SAFETY: ioprio_set only reads its integer arguments and affects the calling process.
+    unsafe {
+        libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio);
+    }
+}
+
+pub(crate) fn child_priority() -> &'static ChildPriority {
+    static PRIORITY: OnceLock<ChildPriority> = OnceLock::new();
+    PRIORITY.get_or_init(ChildPriority::from_env)
+}
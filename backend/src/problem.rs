@@ -0,0 +1,206 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{
+        HeaderValue, StatusCode,
+        header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, VARY},
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+const PROBLEM_TYPE_PREFIX: &str = "urn:total-downloader:problem:";
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+const PROBLEM_TITLES: &[(&str, &str, &str)] = &[
+    (
+        "BOT_CHECK_FAILED",
+        "Bot check failed",
+        "Verificacion anti-bot fallida",
+    ),
+    (
+        "DAILY_LIMIT_EXCEEDED",
+        "Daily download limit exceeded",
+        "Limite diario de descargas superado",
+    ),
+    ("FORBIDDEN", "Forbidden", "Acceso denegado"),
+    ("INVALID_SIGNATURE", "Invalid signature", "Firma invalida"),
+    ("JOB_CANCELLED", "Job cancelled", "Descarga cancelada"),
+    ("JOB_FAILED", "Job failed", "La descarga fallo"),
+    ("JOB_PENDING", "Job still processing", "Descarga en proceso"),
+    (
+        "METADATA_SATURATED",
+        "Metadata lookups saturated",
+        "Consultas de formatos saturadas",
+    ),
+    ("NOT_FOUND", "Not found", "No encontrado"),
+    ("POLICY_DENIED", "Denied by policy", "Denegado por politica"),
+    (
+        "STREAMS_SATURATED",
+        "Transfers saturated",
+        "Transferencias saturadas",
+    ),
+    ("UNAUTHORIZED", "Unauthorized", "No autorizado"),
+    (
+        "UPLOAD_OFFSET_MISMATCH",
+        "Upload offset mismatch",
+        "Fragmento fuera de orden",
+    ),
+    (
+        "VERIFICATION_COOLDOWN",
+        "Verification cooldown",
+        "Espera entre verificaciones",
+    ),
+    (
+        "WORKERS_UNAVAILABLE",
+        "Workers unavailable",
+        "Workers no disponibles",
+    ),
+];
+
+#[derive(Debug, Deserialize)]
+struct LegacyErrorBody {
+    error: String,
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    retry_after_seconds: Option<u64>,
+    #[serde(default)]
+    request_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProblemDocument {
+    #[serde(rename = "type")]
+    kind: String,
+    title: String,
+    title_es: String,
+    status: u16,
+    detail: String,
+    instance: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+fn wants_problem_json(request: &Request) -> bool {
+    request
+        .headers()
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_range| {
+            let mut parts = media_range.split(';').map(str::trim);
+            let matches = parts
+                .next()
+                .is_some_and(|media| media.eq_ignore_ascii_case(PROBLEM_CONTENT_TYPE));
+            let rejected = parts.any(|param| param.replace(' ', "") == "q=0");
+            matches && !rejected
+        })
+}
+
+fn titles(code: Option<&str>, status: StatusCode) -> (String, String) {
+    code.and_then(|code| {
+        PROBLEM_TITLES
+            .iter()
+            .find(|(known, _, _)| *known == code)
+            .map(|(_, en, es)| (en.to_string(), es.to_string()))
+    })
+    .unwrap_or_else(|| {
+        let en = status.canonical_reason().unwrap_or("Error").to_string();
+        let es = match status {
+            StatusCode::BAD_REQUEST => "Solicitud invalida",
+            StatusCode::PAYLOAD_TOO_LARGE => "Solicitud demasiado grande",
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => "Tipo de contenido no soportado",
+            StatusCode::UNPROCESSABLE_ENTITY => "Cuerpo de solicitud invalido",
+            StatusCode::TOO_MANY_REQUESTS => "Demasiadas solicitudes",
+            StatusCode::SERVICE_UNAVAILABLE => "Servicio no disponible",
+            status if status.is_server_error() => "Error interno",
+            _ => "Error",
+        };
+        (en, es.to_string())
+    })
+}
+
+pub(crate) async fn negotiate_problem_json(request: Request, next: Next) -> Response {
+    if !wants_problem_json(&request) {
+        return next.run(request).await;
+    }
+
+    let instance = request.uri().path().to_string();
+    let response = next.run(request).await;
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error()) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_ERROR_BODY_BYTES).await else {
+        return (status, "").into_response();
+    };
+    let is_json = parts
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let legacy = is_json
+        .then(|| serde_json::from_slice::<LegacyErrorBody>(&bytes).ok())
+        .flatten();
+    let (detail, code, retry_after_seconds, request_id) = match legacy {
+        Some(body) => (
+            body.error,
+            body.code,
+            body.retry_after_seconds,
+            body.request_id,
+        ),
+        None => (
+            String::from_utf8_lossy(&bytes).trim().to_string(),
+            None,
+            None,
+            crate::clienterrors::current_request_id(),
+        ),
+    };
+    let (title, title_es) = titles(code.as_deref(), status);
+
+    let document = ProblemDocument {
+        kind: code.as_deref().map_or_else(
+            || "about:blank".to_string(),
+            |code| {
+                format!(
+                    "{PROBLEM_TYPE_PREFIX}{}",
+                    code.to_ascii_lowercase().replace('_', "-")
+                )
+            },
+        ),
+        detail: if detail.is_empty() {
+            title_es.clone()
+        } else {
+            detail
+        },
+        title,
+        title_es,
+        status: status.as_u16(),
+        instance,
+        code,
+        retry_after_seconds,
+        request_id,
+    };
+    let Ok(payload) = serde_json::to_vec(&document) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+
+    parts
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_CONTENT_TYPE));
+    parts.headers.remove(CONTENT_LENGTH);
+    parts
+        .headers
+        .append(VARY, HeaderValue::from_static("accept"));
+    Response::from_parts(parts, Body::from(payload))
+}
@@ -0,0 +1,38 @@
+use tokio::process::{Child, Command};
+
+pub(crate) fn isolate(command: &mut Command) {
+    command.kill_on_drop(true);
+    #[cfg(unix)]
+    command.process_group(0);
+}
+
+#[derive(Debug)]
+pub(crate) struct ProcessGroup {
+    leader: Option<u32>,
+}
+
+impl ProcessGroup {
+    pub(crate) fn track(child: &Child) -> Self {
+        Self { leader: child.id() }
+    }
+
+    // Once the leader is reaped its pid can be reused, so the group must not be signalled again.
+    pub(crate) fn disarm(&mut self) {
+        self.leader = None;
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(leader) = self.leader.and_then(|pid| libc::pid_t::try_from(pid).ok()) {
+            // SAFETY: killpg only sends a signal; the group was created by `isolate` for this
+            // child, so it reaches yt-dlp/ffmpeg and any helpers they forked, never the backend.
+            unsafe {
+                libc::killpg(leader, libc::SIGKILL);
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = self.leader;
+    }
+}
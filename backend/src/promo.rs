@@ -0,0 +1,357 @@
+use std::{collections::HashMap, net::SocketAddr, path::Path};
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, State},
+    http::HeaderMap,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    ApiError, AppState, client_identity_for_request, non_empty,
+    storage::{read_snapshot, write_snapshot},
+    stored_identity,
+};
+
+const MAX_PROMO_CODE_LENGTH: usize = 64;
+const DEFAULT_BOOST_HOURS: i64 = 24;
+const PROMO_LABEL: &str = "codigos promocionales";
+const MAX_BOOST_HOURS: i64 = 24 * 365;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct PromoCode {
+    code: String,
+    extra_downloads: usize,
+    max_download_bytes: Option<u64>,
+    boost_hours: i64,
+    max_redemptions: usize,
+    #[serde(default)]
+    redeemed_by: Vec<String>,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct QuotaBoost {
+    code: String,
+    extra_downloads: usize,
+    max_download_bytes: Option<u64>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct PromoStore {
+    #[serde(default)]
+    codes: HashMap<String, PromoCode>,
+    #[serde(default)]
+    boosts: HashMap<String, Vec<QuotaBoost>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ActiveBoost {
+    pub(crate) extra_downloads: usize,
+    pub(crate) max_download_bytes: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CreatePromoCodeRequest {
+    code: Option<String>,
+    extra_downloads: Option<usize>,
+    max_download_bytes: Option<u64>,
+    boost_hours: Option<i64>,
+    max_redemptions: Option<usize>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RedeemPromoCodeRequest {
+    code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct RedeemPromoCodeResponse {
+    code: String,
+    extra_downloads: usize,
+    max_download_bytes: Option<u64>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+struct PromoAuditEvent<'a> {
+    at: DateTime<Utc>,
+    event: &'a str,
+    code: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<&'a str>,
+}
+
+impl PromoStore {
+    pub(crate) fn active_boost(&self, ip: &str, now: DateTime<Utc>) -> ActiveBoost {
+        self.boosts
+            .get(ip)
+            .into_iter()
+            .flatten()
+            .filter(|boost| boost.expires_at > now)
+            .fold(ActiveBoost::default(), |acc, boost| ActiveBoost {
+                extra_downloads: acc.extra_downloads + boost.extra_downloads,
+                max_download_bytes: match (acc.max_download_bytes, boost.max_download_bytes) {
+                    (Some(a), Some(b)) => Some(a.max(b)),
+                    (a, b) => a.or(b),
+                },
+            })
+    }
+
+    fn prune(&mut self, now: DateTime<Utc>) {
+        self.boosts.retain(|_, boosts| {
+            boosts.retain(|boost| boost.expires_at > now);
+            !boosts.is_empty()
+        });
+    }
+
+    fn redeem(
+        &mut self,
+        code: &str,
+        ip: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(QuotaBoost, bool), String> {
+        let promo = self
+            .codes
+            .get_mut(code)
+            .ok_or_else(|| "Codigo promocional invalido.".to_string())?;
+
+        if promo.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err("El codigo promocional expiro.".to_string());
+        }
+        if promo.redeemed_by.iter().any(|redeemed| redeemed == ip) {
+            // Downloads resend the code; while its boost lasts that is not a second redemption.
+            return self
+                .boosts
+                .get(ip)
+                .into_iter()
+                .flatten()
+                .find(|boost| boost.code == promo.code && boost.expires_at > now)
+                .map(|boost| (boost.clone(), false))
+                .ok_or_else(|| "Este codigo ya fue canjeado desde tu conexion.".to_string());
+        }
+        if promo.redeemed_by.len() >= promo.max_redemptions {
+            return Err("El codigo promocional alcanzo su limite de canjes.".to_string());
+        }
+
+        let expires_at = chrono::Duration::try_hours(promo.boost_hours)
+            .and_then(|duration| now.checked_add_signed(duration))
+            .ok_or_else(|| "El codigo promocional tiene una duracion invalida.".to_string())?;
+
+        promo.redeemed_by.push(ip.to_string());
+        let boost = QuotaBoost {
+            code: promo.code.clone(),
+            extra_downloads: promo.extra_downloads,
+            max_download_bytes: promo.max_download_bytes,
+            expires_at,
+        };
+        self.boosts
+            .entry(ip.to_string())
+            .or_default()
+            .push(boost.clone());
+        Ok((boost, true))
+    }
+}
+
+pub(crate) fn normalize_promo_code(value: &str) -> Option<String> {
+    let trimmed = non_empty(value)?;
+    if trimmed.len() > MAX_PROMO_CODE_LENGTH
+        || !trimmed
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || character == '-')
+    {
+        return None;
+    }
+    Some(trimmed.to_ascii_uppercase())
+}
+
+fn generate_promo_code() -> String {
+    let raw = Uuid::new_v4().simple().to_string().to_ascii_uppercase();
+    format!("{}-{}-{}", &raw[0..4], &raw[4..8], &raw[8..12])
+}
+
+pub(crate) async fn load_promo_store(path: &Path) -> Result<PromoStore, ApiError> {
+    let mut store: PromoStore = read_snapshot(path, PROMO_LABEL).await?;
+    store.prune(Utc::now());
+    Ok(store)
+}
+
+// Callers hold the store lock while this runs so an older snapshot never lands after a newer one.
+async fn persist_promo_store(path: &Path, store: &PromoStore) -> Result<(), ApiError> {
+    write_snapshot(path, store, PROMO_LABEL).await
+}
+
+async fn append_promo_audit(
+    path: &Path,
+    event: &str,
+    code: &str,
+    ip: Option<&str>,
+    detail: Option<&str>,
+) {
+    let record = PromoAuditEvent {
+        at: Utc::now(),
+        event,
+        code,
+        ip,
+        detail,
+    };
+    let Ok(mut line) = serde_json::to_string(&record) else {
+        return;
+    };
+    line.push('\n');
+
+    let result = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(line.as_bytes()).await
+    }
+    .await;
+
+    if let Err(error) = result {
+        warn!("No se pudo escribir auditoria de codigos promocionales: {error}");
+    }
+}
+
+pub(crate) async fn active_boost_for(state: &AppState, ip: &str) -> ActiveBoost {
+    state
+        .promo
+        .lock()
+        .await
+        .active_boost(&stored_identity(state, ip), Utc::now())
+}
+
+pub(crate) async fn redeem_promo_code(
+    state: &AppState,
+    raw_code: &str,
+    ip: &str,
+) -> Result<QuotaBoost, ApiError> {
+    let code = normalize_promo_code(raw_code)
+        .ok_or_else(|| ApiError::bad_request("Codigo promocional invalido."))?;
+    let now = Utc::now();
+    let key = stored_identity(state, ip);
+
+    let result = {
+        let mut store = state.promo.lock().await;
+        store.prune(now);
+        let result = store.redeem(&code, &key, now);
+        if matches!(result, Ok((_, true))) {
+            persist_promo_store(&state.promo_path, &store).await?;
+        }
+        result
+    };
+
+    match result {
+        Ok((boost, false)) => Ok(boost),
+        Ok((boost, true)) => {
+            info!("Codigo promocional {code} canjeado por IP {ip}");
+            append_promo_audit(&state.promo_audit_path, "redeemed", &code, Some(&key), None).await;
+            Ok(boost)
+        }
+        Err(message) => {
+            append_promo_audit(
+                &state.promo_audit_path,
+                "rejected",
+                &code,
+                Some(&key),
+                Some(&message),
+            )
+            .await;
+            Err(ApiError::bad_request(message))
+        }
+    }
+}
+
+pub(crate) async fn redeem_promo(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<RedeemPromoCodeRequest>,
+) -> Result<Json<RedeemPromoCodeResponse>, ApiError> {
+    let client_ip = client_identity_for_request(&state, &headers, addr);
+    let boost = redeem_promo_code(&state, &payload.code, &client_ip).await?;
+
+    Ok(Json(RedeemPromoCodeResponse {
+        code: boost.code,
+        extra_downloads: boost.extra_downloads,
+        max_download_bytes: boost.max_download_bytes,
+        expires_at: boost.expires_at,
+    }))
+}
+
+pub(crate) async fn list_promo_codes(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<PromoCode>>, ApiError> {
+    let mut codes = state
+        .promo
+        .lock()
+        .await
+        .codes
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    codes.sort_by_key(|code| std::cmp::Reverse(code.created_at));
+    Ok(Json(codes))
+}
+
+pub(crate) async fn create_promo_code(
+    State(state): State<AppState>,
+    Json(payload): Json<CreatePromoCodeRequest>,
+) -> Result<Json<PromoCode>, ApiError> {
+    let code = match payload.code.as_deref() {
+        Some(value) => normalize_promo_code(value).ok_or_else(|| {
+            ApiError::bad_request("El codigo solo admite letras, numeros y guiones.")
+        })?,
+        None => generate_promo_code(),
+    };
+    let extra_downloads = payload.extra_downloads.unwrap_or_default();
+    if extra_downloads == 0 && payload.max_download_bytes.is_none() {
+        return Err(ApiError::bad_request(
+            "El codigo debe otorgar descargas extra o un limite de tamano mayor.",
+        ));
+    }
+    let boost_hours = payload.boost_hours.unwrap_or(DEFAULT_BOOST_HOURS);
+    if !(1..=MAX_BOOST_HOURS).contains(&boost_hours) {
+        return Err(ApiError::bad_request(format!(
+            "La duracion del beneficio debe estar entre 1 y {MAX_BOOST_HOURS} horas."
+        )));
+    }
+
+    let promo = PromoCode {
+        code: code.clone(),
+        extra_downloads,
+        max_download_bytes: payload.max_download_bytes,
+        boost_hours,
+        max_redemptions: payload
+            .max_redemptions
+            .filter(|value| *value > 0)
+            .unwrap_or(1),
+        redeemed_by: Vec::new(),
+        created_at: Utc::now(),
+        expires_at: payload.expires_at,
+    };
+
+    {
+        let mut store = state.promo.lock().await;
+        if store.codes.contains_key(&code) {
+            return Err(ApiError::bad_request("Ese codigo promocional ya existe."));
+        }
+        store.codes.insert(code.clone(), promo.clone());
+        persist_promo_store(&state.promo_path, &store).await?;
+    }
+
+    info!("Codigo promocional {code} creado");
+    append_promo_audit(&state.promo_audit_path, "created", &code, None, None).await;
+    Ok(Json(promo))
+}
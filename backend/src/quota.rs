@@ -0,0 +1,173 @@
+use chrono::{DateTime, Duration, Timelike, Utc};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::read_list_env_raw;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+struct QuotaWindow {
+    start_hour: u32,
+    end_hour: u32,
+    daily_limit: usize,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+struct LoadRule {
+    min_active_jobs: usize,
+    factor: f64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct QuotaSchedule {
+    default_daily_limit: usize,
+    utc_offset_hours: i64,
+    windows: Vec<QuotaWindow>,
+    load_rules: Vec<LoadRule>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct QuotaPolicy {
+    default_daily_limit: usize,
+    utc_offset_hours: i64,
+    local_hour: u32,
+    active_window: Option<QuotaWindow>,
+    active_jobs: usize,
+    load_factor: f64,
+    effective_daily_limit: usize,
+    schedule: Vec<QuotaWindow>,
+    load_rules: Vec<LoadRule>,
+}
+
+impl QuotaWindow {
+    fn contains(&self, hour: u32) -> bool {
+        if self.start_hour <= self.end_hour {
+            (self.start_hour..self.end_hour).contains(&hour)
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+}
+
+fn parse_window(entry: &str) -> Option<QuotaWindow> {
+    let (hours, limit) = entry.split_once(':')?;
+    let (start, end) = hours.split_once('-')?;
+    let start_hour = start.trim().parse::<u32>().ok().filter(|hour| *hour < 24)?;
+    let end_hour = end.trim().parse::<u32>().ok().filter(|hour| *hour <= 24)?;
+    let daily_limit = limit
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|limit| *limit > 0)?;
+    (start_hour != end_hour).then_some(QuotaWindow {
+        start_hour,
+        end_hour: end_hour % 24,
+        daily_limit,
+    })
+}
+
+fn parse_load_rule(entry: &str) -> Option<LoadRule> {
+    let (threshold, factor) = entry.split_once(':')?;
+    let min_active_jobs = threshold
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|jobs| *jobs > 0)?;
+    let factor = factor
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|factor| *factor > 0.0 && *factor <= 1.0)?;
+    Some(LoadRule {
+        min_active_jobs,
+        factor,
+    })
+}
+
+impl QuotaSchedule {
+    pub(crate) fn from_env(default_daily_limit: usize) -> Self {
+        let windows = read_list_env_raw("QUOTA_SCHEDULE")
+            .into_iter()
+            .filter_map(|entry| {
+                let window = parse_window(&entry);
+                if window.is_none() {
+                    warn!("Entrada invalida en QUOTA_SCHEDULE (usa inicio-fin:limite): {entry:?}");
+                }
+                window
+            })
+            .collect();
+        let mut load_rules = read_list_env_raw("QUOTA_LOAD_RULES")
+            .into_iter()
+            .filter_map(|entry| {
+                let rule = parse_load_rule(&entry);
+                if rule.is_none() {
+                    warn!("Entrada invalida en QUOTA_LOAD_RULES (usa jobs:factor): {entry:?}");
+                }
+                rule
+            })
+            .collect::<Vec<_>>();
+        load_rules.sort_by_key(|rule| rule.min_active_jobs);
+        let utc_offset_hours = std::env::var("QUOTA_UTC_OFFSET_HOURS")
+            .ok()
+            .and_then(|value| value.trim().parse::<i64>().ok())
+            .filter(|offset| (-12..=14).contains(offset))
+            .unwrap_or(0);
+
+        Self {
+            default_daily_limit,
+            utc_offset_hours,
+            windows,
+            load_rules,
+        }
+    }
+
+    pub(crate) fn is_dynamic(&self) -> bool {
+        !self.windows.is_empty() || !self.load_rules.is_empty()
+    }
+
+    fn local_hour(&self, now: DateTime<Utc>) -> u32 {
+        (now + Duration::hours(self.utc_offset_hours)).hour()
+    }
+
+    fn active_window(&self, now: DateTime<Utc>) -> Option<QuotaWindow> {
+        let hour = self.local_hour(now);
+        self.windows
+            .iter()
+            .find(|window| window.contains(hour))
+            .copied()
+    }
+
+    fn load_factor(&self, active_jobs: usize) -> f64 {
+        self.load_rules
+            .iter()
+            .rev()
+            .find(|rule| active_jobs >= rule.min_active_jobs)
+            .map_or(1.0, |rule| rule.factor)
+    }
+
+    pub(crate) fn base_limit(&self, now: DateTime<Utc>) -> usize {
+        self.active_window(now)
+            .map_or(self.default_daily_limit, |window| window.daily_limit)
+    }
+
+    pub(crate) fn scale(&self, limit: usize, active_jobs: usize) -> usize {
+        ((limit as f64 * self.load_factor(active_jobs)).floor() as usize).max(1)
+    }
+
+    pub(crate) fn effective_limit(&self, now: DateTime<Utc>, active_jobs: usize) -> usize {
+        self.scale(self.base_limit(now), active_jobs)
+    }
+
+    pub(crate) fn describe(&self, now: DateTime<Utc>, active_jobs: usize) -> QuotaPolicy {
+        QuotaPolicy {
+            default_daily_limit: self.default_daily_limit,
+            utc_offset_hours: self.utc_offset_hours,
+            local_hour: self.local_hour(now),
+            active_window: self.active_window(now),
+            active_jobs,
+            load_factor: self.load_factor(active_jobs),
+            effective_daily_limit: self.effective_limit(now, active_jobs),
+            schedule: self.windows.clone(),
+            load_rules: self.load_rules.clone(),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use axum::{
+    extract::{Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{ApiError, AppState, auth::session_role, bearer_matches};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Role {
+    Anonymous,
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "user" => Some(Self::User),
+            "moderator" => Some(Self::Moderator),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RoleTokens {
+    tokens: Vec<(String, Role)>,
+}
+
+impl RoleTokens {
+    pub(crate) fn from_env() -> Self {
+        let mut tokens = Vec::new();
+        if let Some(admin_token) = std::env::var("ADMIN_TOKEN")
+            .ok()
+            .and_then(|value| crate::non_empty(&value).map(ToString::to_string))
+        {
+            tokens.push((admin_token, Role::Admin));
+        }
+
+        for entry in crate::read_list_env_raw("ROLE_TOKENS") {
+            let parsed = entry.split_once(':').and_then(|(role, token)| {
+                Some((crate::non_empty(token)?.to_string(), Role::parse(role)?))
+            });
+            match parsed {
+                Some(binding) => tokens.push(binding),
+                None => warn!("Entrada invalida en ROLE_TOKENS (usa rol:token): {entry:?}"),
+            }
+        }
+
+        Self { tokens }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub(crate) fn resolve(&self, headers: &HeaderMap) -> Role {
+        self.tokens
+            .iter()
+            .filter(|(token, _)| bearer_matches(token, headers))
+            .map(|(_, role)| *role)
+            .max()
+            .unwrap_or(Role::Anonymous)
+    }
+}
+
+pub(crate) async fn require_role(
+    State((state, required)): State<(AppState, Role)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.roles.is_empty() && state.auth.is_none() {
+        return ApiError::not_found("Endpoints de administracion deshabilitados.").into_response();
+    }
+
+    let role = state
+        .roles
+        .resolve(request.headers())
+        .max(session_role(&state, request.headers()));
+    if role == Role::Anonymous {
+        return ApiError::unauthorized("Token de administracion invalido.").into_response();
+    }
+    if role < required {
+        return ApiError::forbidden("Tu rol no permite esta operacion.").into_response();
+    }
+
+    next.run(request).await
+}
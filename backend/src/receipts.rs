@@ -0,0 +1,250 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use axum::{
+    Json,
+    extract::{Path as RoutePath, State},
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chrono::{DateTime, Utc};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{ApiError, AppState, DownloadMode, encode_hex, non_empty, read_bool_env};
+
+const SIGNATURE_ALGORITHM: &str = "Ed25519";
+
+#[derive(Debug)]
+pub(crate) struct ReceiptSigner {
+    key_pair: Ed25519KeyPair,
+    key_id: String,
+    dir: PathBuf,
+    keep_url: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Receipt {
+    receipt_id: Uuid,
+    job_id: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url_sha256: Option<String>,
+    mode: DownloadMode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format_id: Option<String>,
+    format: String,
+    filename: String,
+    size_bytes: u64,
+    sha256: String,
+    requested_at: DateTime<Utc>,
+    completed_at: DateTime<Utc>,
+    issuer: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SignedReceipt {
+    receipt: Receipt,
+    payload: String,
+    algorithm: String,
+    key_id: String,
+    signature: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PublicKeyResponse {
+    algorithm: &'static str,
+    key_id: String,
+    public_key: String,
+}
+
+pub(crate) struct ReceiptDetails<'a> {
+    pub(crate) job_id: Uuid,
+    pub(crate) url: &'a str,
+    pub(crate) mode: DownloadMode,
+    pub(crate) format_id: Option<&'a str>,
+    pub(crate) format: &'a str,
+    pub(crate) filename: &'a str,
+    pub(crate) size_bytes: u64,
+    pub(crate) sha256: &'a str,
+    pub(crate) requested_at: DateTime<Utc>,
+}
+
+impl ReceiptSigner {
+    pub(crate) async fn from_env(
+        data_dir: &std::path::Path,
+        keep_url: bool,
+    ) -> Result<Option<Self>, ApiError> {
+        if !read_bool_env("DOWNLOAD_RECEIPTS").unwrap_or(false) {
+            return Ok(None);
+        }
+
+        let key_path = data_dir.join("receipt_key.pk8");
+        let pkcs8 = match std::env::var("RECEIPT_SIGNING_KEY")
+            .ok()
+            .and_then(|value| non_empty(&value).map(ToString::to_string))
+        {
+            Some(encoded) => BASE64.decode(encoded.trim()).map_err(|_| {
+                ApiError::internal("RECEIPT_SIGNING_KEY debe ser una clave PKCS#8 en base64.")
+            })?,
+            None => match tokio::fs::read(&key_path).await {
+                Ok(pkcs8) => pkcs8,
+                Err(error) if error.kind() == ErrorKind::NotFound => {
+                    let pkcs8 =
+                        Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).map_err(|_| {
+                            ApiError::internal("No se pudo generar la clave de recibos.")
+                        })?;
+                    tokio::fs::write(&key_path, pkcs8.as_ref())
+                        .await
+                        .map_err(|error| {
+                            ApiError::internal(format!(
+                                "No se pudo guardar la clave de recibos: {error}"
+                            ))
+                        })?;
+                    warn!(
+                        "RECEIPT_SIGNING_KEY no configurado. Se genero una clave en {}.",
+                        key_path.display()
+                    );
+                    pkcs8.as_ref().to_vec()
+                }
+                Err(error) => {
+                    return Err(ApiError::internal(format!(
+                        "No se pudo leer la clave de recibos: {error}"
+                    )));
+                }
+            },
+        };
+        let key_pair = Ed25519KeyPair::from_pkcs8(&pkcs8)
+            .map_err(|_| ApiError::internal("La clave de recibos no es Ed25519 PKCS#8 valida."))?;
+        let key_id = encode_hex(&Sha256::digest(key_pair.public_key().as_ref()))[..16].to_string();
+
+        let dir = data_dir.join("receipts");
+        tokio::fs::create_dir_all(&dir).await.map_err(|error| {
+            ApiError::internal(format!("No se pudo crear la carpeta de recibos: {error}"))
+        })?;
+        info!("Recibos de descarga firmados habilitados (clave {key_id}).");
+
+        Ok(Some(Self {
+            key_pair,
+            key_id,
+            dir,
+            keep_url,
+        }))
+    }
+
+    fn receipt_path(&self, receipt_id: Uuid) -> PathBuf {
+        self.dir.join(format!("{receipt_id}.json"))
+    }
+
+    async fn issue(&self, receipt: Receipt) -> Result<SignedReceipt, ApiError> {
+        let payload = serde_json::to_string(&receipt).map_err(|error| {
+            ApiError::internal(format!("No se pudo serializar el recibo: {error}"))
+        })?;
+        let signature = BASE64.encode(self.key_pair.sign(payload.as_bytes()));
+        let signed = SignedReceipt {
+            receipt,
+            payload,
+            algorithm: SIGNATURE_ALGORITHM.to_string(),
+            key_id: self.key_id.clone(),
+            signature,
+        };
+
+        let document = serde_json::to_string_pretty(&signed).map_err(|error| {
+            ApiError::internal(format!("No se pudo serializar el recibo: {error}"))
+        })?;
+        tokio::fs::write(self.receipt_path(signed.receipt.receipt_id), document)
+            .await
+            .map_err(|error| {
+                ApiError::internal(format!("No se pudo guardar el recibo: {error}"))
+            })?;
+        Ok(signed)
+    }
+}
+
+pub(crate) async fn issue_receipt(state: &AppState, details: ReceiptDetails<'_>) -> Option<String> {
+    let signer = state.receipts.as_deref()?;
+    // Without history the receipt still proves the source: the holder hashes the URL they know.
+    let (url, url_sha256) = if signer.keep_url {
+        (Some(details.url.to_string()), None)
+    } else {
+        (
+            None,
+            Some(encode_hex(&Sha256::digest(details.url.as_bytes()))),
+        )
+    };
+    let receipt = Receipt {
+        receipt_id: details.job_id,
+        job_id: details.job_id,
+        url,
+        url_sha256,
+        mode: details.mode,
+        format_id: details.format_id.map(ToString::to_string),
+        format: details.format.to_string(),
+        filename: details.filename.to_string(),
+        size_bytes: details.size_bytes,
+        sha256: details.sha256.to_string(),
+        requested_at: details.requested_at,
+        completed_at: Utc::now(),
+        issuer: state
+            .public_base_url
+            .clone()
+            .unwrap_or_else(|| "total-downloader".to_string()),
+    };
+
+    match signer.issue(receipt).await {
+        Ok(signed) => Some(format!("/api/receipts/{}", signed.receipt.receipt_id)),
+        Err(error) => {
+            warn!(
+                "No se pudo emitir el recibo del job {}: {}",
+                details.job_id, error.message
+            );
+            None
+        }
+    }
+}
+
+fn receipt_signer(state: &AppState) -> Result<&ReceiptSigner, ApiError> {
+    state
+        .receipts
+        .as_deref()
+        .ok_or_else(|| ApiError::not_found("Los recibos de descarga no estan habilitados."))
+}
+
+pub(crate) async fn get_receipt(
+    State(state): State<AppState>,
+    RoutePath(receipt_id): RoutePath<Uuid>,
+) -> Result<Json<SignedReceipt>, ApiError> {
+    let signer = receipt_signer(&state)?;
+    let content = match tokio::fs::read_to_string(signer.receipt_path(receipt_id)).await {
+        Ok(content) => content,
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            return Err(ApiError::not_found(
+                "No existe un recibo con ese identificador.",
+            ));
+        }
+        Err(error) => {
+            return Err(ApiError::internal(format!(
+                "No se pudo leer el recibo: {error}"
+            )));
+        }
+    };
+    let signed = serde_json::from_str(&content)
+        .map_err(|error| ApiError::internal(format!("Recibo invalido: {error}")))?;
+    Ok(Json(signed))
+}
+
+pub(crate) async fn get_receipt_public_key(
+    State(state): State<AppState>,
+) -> Result<Json<PublicKeyResponse>, ApiError> {
+    let signer = receipt_signer(&state)?;
+    Ok(Json(PublicKeyResponse {
+        algorithm: SIGNATURE_ALGORITHM,
+        key_id: signer.key_id.clone(),
+        public_key: BASE64.encode(signer.key_pair.public_key().as_ref()),
+    }))
+}
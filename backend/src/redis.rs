@@ -0,0 +1,417 @@
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream},
+    net::TcpStream,
+    sync::Mutex,
+    time::{Duration, timeout},
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{ApiError, non_empty};
+
+const REDIS_TIMEOUT_SECONDS: u64 = 3;
+const DEFAULT_KEY_PREFIX: &str = "total-downloader";
+const MAX_BULK_BYTES: usize = 1024 * 1024;
+const SCAN_BATCH: &str = "100";
+
+// Drops entries outside the window and only records the attempt while under the limit,
+// so concurrent replicas can never exceed it. Returns {allowed, oldest_ms}.
+// Every key is checked before any is recorded, so a rejected attempt never counts against the others.
+const RATE_LIMIT_SCRIPT: &str = r"
+local now = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+for i, key in ipairs(KEYS) do
+  redis.call('ZREMRANGEBYSCORE', key, '-inf', now - window)
+  if redis.call('ZCARD', key) >= tonumber(ARGV[3 + i]) then
+    local oldest = redis.call('ZRANGE', key, 0, 0, 'WITHSCORES')
+    return {0, tonumber(oldest[2]) or now, i}
+  end
+end
+for _, key in ipairs(KEYS) do
+  redis.call('ZADD', key, now, ARGV[3])
+  redis.call('PEXPIRE', key, window)
+end
+return {1, 0}
+";
+
+#[derive(Debug)]
+enum Reply {
+    Status(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+#[derive(Debug)]
+pub(crate) struct RedisStore {
+    address: String,
+    username: Option<String>,
+    password: Option<String>,
+    database: u32,
+    prefix: String,
+    connection: Mutex<Option<BufStream<TcpStream>>>,
+}
+
+fn encode_command(args: &[&[u8]]) -> Vec<u8> {
+    let mut command = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        command.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        command.extend_from_slice(arg);
+        command.extend_from_slice(b"\r\n");
+    }
+    command
+}
+
+async fn read_reply(stream: &mut BufStream<TcpStream>) -> Result<Reply, String> {
+    let mut line = String::new();
+    stream
+        .read_line(&mut line)
+        .await
+        .map_err(|error| error.to_string())?;
+    let line = line.trim_end_matches(['\r', '\n']);
+    let (kind, rest) = line.split_at_checked(1).ok_or("conexion cerrada")?;
+    let number = || {
+        rest.parse::<i64>()
+            .map_err(|_| format!("respuesta invalida: {line}"))
+    };
+    match kind {
+        "+" => Ok(Reply::Status(rest.to_string())),
+        "-" => Ok(Reply::Error(rest.to_string())),
+        ":" => Ok(Reply::Integer(number()?)),
+        "$" => {
+            let length = number()?;
+            if length < 0 {
+                return Ok(Reply::Bulk(None));
+            }
+            let length = length as usize;
+            if length > MAX_BULK_BYTES {
+                return Err(format!("respuesta de {length} bytes demasiado grande"));
+            }
+            let mut data = vec![0_u8; length + 2];
+            stream
+                .read_exact(&mut data)
+                .await
+                .map_err(|error| error.to_string())?;
+            data.truncate(length);
+            Ok(Reply::Bulk(Some(data)))
+        }
+        "*" => {
+            let length = number()?;
+            let mut items = Vec::new();
+            for _ in 0..length.max(0) {
+                items.push(Box::pin(read_reply(stream)).await?);
+            }
+            Ok(Reply::Array(items))
+        }
+        _ => Err(format!("respuesta invalida: {line}")),
+    }
+}
+
+fn reply_integer(reply: &Reply) -> Option<i64> {
+    match reply {
+        Reply::Integer(value) => Some(*value),
+        Reply::Bulk(Some(data)) => std::str::from_utf8(data).ok()?.parse().ok(),
+        _ => None,
+    }
+}
+
+impl RedisStore {
+    pub(crate) fn from_env() -> Result<Option<Self>, ApiError> {
+        let Some(raw) = std::env::var("REDIS_URL")
+            .ok()
+            .and_then(|value| non_empty(&value).map(ToString::to_string))
+        else {
+            return Ok(None);
+        };
+        let invalid = |reason: &str| ApiError::internal(format!("REDIS_URL invalida: {reason}"));
+        let url = Url::parse(&raw).map_err(|error| invalid(&error.to_string()))?;
+        if url.scheme() != "redis" {
+            return Err(invalid(
+                "solo se admite redis:// (para TLS usa un tunel como stunnel)",
+            ));
+        }
+        let host = url.host_str().ok_or_else(|| invalid("falta el host"))?;
+        let database = match url.path().trim_matches('/') {
+            "" => 0,
+            path => path
+                .parse::<u32>()
+                .map_err(|_| invalid("la base de datos debe ser un numero"))?,
+        };
+        let prefix = std::env::var("REDIS_KEY_PREFIX")
+            .ok()
+            .and_then(|value| non_empty(&value).map(ToString::to_string))
+            .unwrap_or_else(|| DEFAULT_KEY_PREFIX.to_string());
+
+        Ok(Some(Self {
+            address: format!("{host}:{}", url.port().unwrap_or(6379)),
+            username: non_empty(url.username()).map(ToString::to_string),
+            password: url.password().map(ToString::to_string),
+            database,
+            prefix,
+            connection: Mutex::new(None),
+        }))
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        format!(
+            "{}/{} (prefijo {})",
+            self.address, self.database, self.prefix
+        )
+    }
+
+    fn key(&self, kind: &str, id: &str) -> String {
+        format!("{}:{kind}:{id}", self.prefix)
+    }
+
+    async fn connect(&self) -> Result<BufStream<TcpStream>, String> {
+        let stream = TcpStream::connect(&self.address)
+            .await
+            .map_err(|error| error.to_string())?;
+        let mut stream = BufStream::new(stream);
+        if let Some(password) = &self.password {
+            let mut args = vec![b"AUTH".as_slice()];
+            if let Some(username) = &self.username {
+                args.push(username.as_bytes());
+            }
+            args.push(password.as_bytes());
+            if let Reply::Error(error) = Self::send(&mut stream, &args).await? {
+                return Err(error);
+            }
+        }
+        if self.database != 0 {
+            let database = self.database.to_string();
+            if let Reply::Error(error) =
+                Self::send(&mut stream, &[b"SELECT", database.as_bytes()]).await?
+            {
+                return Err(error);
+            }
+        }
+        Ok(stream)
+    }
+
+    // Replies are read in full, so any readable byte or EOF on an idle connection means it is unusable.
+    fn is_open(stream: &BufStream<TcpStream>) -> bool {
+        matches!(
+            stream.get_ref().try_read(&mut [0; 1]),
+            Err(error) if error.kind() == std::io::ErrorKind::WouldBlock
+        )
+    }
+
+    async fn send(stream: &mut BufStream<TcpStream>, args: &[&[u8]]) -> Result<Reply, String> {
+        stream
+            .write_all(&encode_command(args))
+            .await
+            .map_err(|error| error.to_string())?;
+        stream.flush().await.map_err(|error| error.to_string())?;
+        read_reply(stream).await
+    }
+
+    async fn command(&self, args: &[&[u8]]) -> Result<Reply, ApiError> {
+        let result = timeout(Duration::from_secs(REDIS_TIMEOUT_SECONDS), async {
+            let mut connection = self.connection.lock().await;
+            // A command that reached the server is never resent (EVAL would count twice), so only
+            // connecting is retried; a cached connection the server already closed is replaced first.
+            let mut stream = match connection.take().filter(Self::is_open) {
+                Some(stream) => stream,
+                None => match self.connect().await {
+                    Ok(stream) => stream,
+                    Err(error) => {
+                        warn!("No se pudo conectar con Redis, reintentando: {error}");
+                        self.connect().await?
+                    }
+                },
+            };
+            match Self::send(&mut stream, args).await? {
+                Reply::Error(error) => {
+                    *connection = Some(stream);
+                    Err(error)
+                }
+                reply => {
+                    *connection = Some(stream);
+                    Ok(reply)
+                }
+            }
+        })
+        .await
+        .unwrap_or_else(|_| Err(format!("sin respuesta en {REDIS_TIMEOUT_SECONDS} s")));
+        result.map_err(|error| {
+            warn!("Redis fallo: {error}");
+            ApiError::internal("El almacenamiento compartido no responde. Intenta nuevamente.")
+        })
+    }
+
+    pub(crate) async fn ping(&self) -> Result<(), ApiError> {
+        match self.command(&[b"PING"]).await? {
+            Reply::Status(status) if status == "PONG" => {
+                info!("Redis conectado en {}", self.describe());
+                Ok(())
+            }
+            other => Err(ApiError::internal(format!(
+                "Respuesta inesperada de Redis a PING: {other:?}"
+            ))),
+        }
+    }
+
+    // Returns the index of the first exhausted limit and its retry delay in seconds.
+    pub(crate) async fn register_attempts(
+        &self,
+        attempts: &[(&str, usize)],
+        now: DateTime<Utc>,
+        window_hours: i64,
+    ) -> Result<Option<(usize, u64)>, ApiError> {
+        let now_ms = now.timestamp_millis();
+        let window_ms = window_hours * 3_600_000;
+        let keys = attempts
+            .iter()
+            .map(|(client, _)| self.key("rate", client))
+            .collect::<Vec<_>>();
+        let mut args = vec![
+            now_ms.to_string(),
+            window_ms.to_string(),
+            format!("{now_ms}-{}", Uuid::new_v4().simple()),
+        ];
+        args.extend(attempts.iter().map(|(_, limit)| limit.to_string()));
+        let key_count = keys.len().to_string();
+        let mut command: Vec<&[u8]> =
+            vec![b"EVAL", RATE_LIMIT_SCRIPT.as_bytes(), key_count.as_bytes()];
+        command.extend(keys.iter().map(|key| key.as_bytes()));
+        command.extend(args.iter().map(|arg| arg.as_bytes()));
+
+        let reply = self.command(&command).await?;
+        let Reply::Array(items) = reply else {
+            return Err(ApiError::internal("Respuesta inesperada de Redis."));
+        };
+        match items.as_slice() {
+            [allowed, _] if reply_integer(allowed) == Some(1) => Ok(None),
+            [_, oldest, index] => {
+                let oldest = reply_integer(oldest).unwrap_or(now_ms);
+                let index = reply_integer(index).map_or(0, |index| (index - 1).max(0) as usize);
+                Ok(Some((
+                    index,
+                    ((oldest + window_ms - now_ms) / 1000).max(1) as u64,
+                )))
+            }
+            _ => Err(ApiError::internal("Respuesta inesperada de Redis.")),
+        }
+    }
+
+    pub(crate) async fn count_attempts(
+        &self,
+        client: &str,
+        window_start: DateTime<Utc>,
+    ) -> Result<usize, ApiError> {
+        let key = self.key("rate", client);
+        let min = format!("({}", window_start.timestamp_millis());
+        let reply = self
+            .command(&[b"ZCOUNT", key.as_bytes(), min.as_bytes(), b"+inf"])
+            .await?;
+        Ok(reply_integer(&reply).unwrap_or_default().max(0) as usize)
+    }
+
+    pub(crate) async fn put_json<T: Serialize>(
+        &self,
+        kind: &str,
+        id: &str,
+        value: &T,
+        ttl: Option<Duration>,
+    ) -> Result<(), ApiError> {
+        let key = self.key(kind, id);
+        let payload = serde_json::to_vec(value)
+            .map_err(|error| ApiError::internal(format!("No se pudo serializar: {error}")))?;
+        let result = match ttl {
+            Some(ttl) => {
+                let ttl_ms = ttl.as_millis().max(1).to_string();
+                self.command(&[b"SET", key.as_bytes(), &payload, b"PX", ttl_ms.as_bytes()])
+                    .await
+            }
+            None => self.command(&[b"SET", key.as_bytes(), &payload]).await,
+        };
+        result.map(|_| ())
+    }
+
+    pub(crate) async fn delete(&self, kind: &str, id: &str) -> Result<bool, ApiError> {
+        let key = self.key(kind, id);
+        let reply = self.command(&[b"DEL", key.as_bytes()]).await?;
+        Ok(reply_integer(&reply).unwrap_or_default() > 0)
+    }
+
+    pub(crate) async fn get_json<T: DeserializeOwned>(
+        &self,
+        kind: &str,
+        id: &str,
+        remove: bool,
+    ) -> Result<Option<T>, ApiError> {
+        let key = self.key(kind, id);
+        let command: &[u8] = if remove { b"GETDEL" } else { b"GET" };
+        match self.command(&[command, key.as_bytes()]).await? {
+            Reply::Bulk(Some(data)) => serde_json::from_slice(&data).map(Some).map_err(|error| {
+                ApiError::internal(format!("Valor invalido en Redis para {key}: {error}"))
+            }),
+            _ => Ok(None),
+        }
+    }
+
+    pub(crate) async fn list_json<T: DeserializeOwned>(
+        &self,
+        kind: &str,
+    ) -> Result<Vec<T>, ApiError> {
+        let unexpected = || ApiError::internal("Respuesta inesperada de Redis.");
+        let pattern = self.key(kind, "*");
+        let mut cursor = b"0".to_vec();
+        let mut values = Vec::new();
+        loop {
+            let reply = self
+                .command(&[
+                    b"SCAN",
+                    &cursor,
+                    b"MATCH",
+                    pattern.as_bytes(),
+                    b"COUNT",
+                    SCAN_BATCH.as_bytes(),
+                ])
+                .await?;
+            let Reply::Array(mut items) = reply else {
+                return Err(unexpected());
+            };
+            let (Some(Reply::Array(keys)), Some(Reply::Bulk(Some(next)))) =
+                (items.pop(), items.pop())
+            else {
+                return Err(unexpected());
+            };
+            let keys = keys
+                .into_iter()
+                .filter_map(|key| match key {
+                    Reply::Bulk(Some(key)) => Some(key),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            if !keys.is_empty() {
+                let mut command = vec![b"MGET".as_slice()];
+                command.extend(keys.iter().map(Vec::as_slice));
+                let Reply::Array(found) = self.command(&command).await? else {
+                    return Err(unexpected());
+                };
+                // A key that expired between SCAN and MGET comes back empty and is skipped.
+                for (key, item) in keys.iter().zip(found) {
+                    if let Reply::Bulk(Some(data)) = item {
+                        match serde_json::from_slice(&data) {
+                            Ok(value) => values.push(value),
+                            Err(error) => warn!(
+                                "Valor invalido en Redis para {}: {error}",
+                                String::from_utf8_lossy(key)
+                            ),
+                        }
+                    }
+                }
+            }
+            if next == b"0" {
+                return Ok(values);
+            }
+            cursor = next;
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri, header::LOCATION},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, time::Duration};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    ApiError,
+    artifacts::{ArtifactStore, StoredArtifact, is_artifact_hash},
+    non_empty,
+};
+
+const FORWARDED_HEADER: &str = "x-node-forwarded";
+const PROXY_CONNECT_TIMEOUT_SECONDS: u64 = 5;
+const PROXY_CHANNEL_CHUNKS: usize = 4;
+const PROXIED_REQUEST_HEADERS: &[&str] = &["range", "if-range", "last-event-id"];
+const PROXIED_RESPONSE_HEADERS: &[&str] = &[
+    "content-type",
+    "content-length",
+    "content-range",
+    "accept-ranges",
+    "content-disposition",
+    "cache-control",
+    "etag",
+    "last-modified",
+    "retry-after",
+    "x-job-id",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ForwardMode {
+    Redirect,
+    Proxy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct LocationRecord {
+    node_id: String,
+    node_url: Option<String>,
+    expires_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    filename: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+}
+
+pub(crate) enum ArtifactRoute {
+    Local(StoredArtifact),
+    Remote(LocationRecord),
+}
+
+#[derive(Debug)]
+pub(crate) struct NodeRegistry {
+    dir: PathBuf,
+    node_id: String,
+    node_url: Option<String>,
+    shared_storage: bool,
+    forward_mode: ForwardMode,
+    client: reqwest::Client,
+}
+
+impl NodeRegistry {
+    pub(crate) async fn from_env(public_base_url: Option<&str>) -> Result<Option<Self>, ApiError> {
+        let Some(dir) = std::env::var("NODE_REGISTRY_DIR")
+            .ok()
+            .and_then(|value| non_empty(&value).map(PathBuf::from))
+        else {
+            return Ok(None);
+        };
+
+        for subdir in ["jobs", "artifacts"] {
+            tokio::fs::create_dir_all(dir.join(subdir))
+                .await
+                .map_err(|error| {
+                    ApiError::internal(format!("No se pudo crear el registro de nodos: {error}"))
+                })?;
+        }
+
+        let node_id = std::env::var("NODE_ID")
+            .ok()
+            .and_then(|value| non_empty(&value).map(ToString::to_string))
+            .unwrap_or_else(|| Uuid::new_v4().simple().to_string());
+        let node_url = std::env::var("NODE_PUBLIC_URL")
+            .ok()
+            .and_then(|value| non_empty(&value).map(ToString::to_string))
+            .or_else(|| public_base_url.map(ToString::to_string))
+            .map(|url| url.trim_end_matches('/').to_string());
+        if node_url.is_none() {
+            warn!("NODE_PUBLIC_URL no configurado: otros nodos no podran redirigir a este.");
+        }
+        let forward_mode = match std::env::var("NODE_FORWARD_MODE")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "proxy" => ForwardMode::Proxy,
+            _ => ForwardMode::Redirect,
+        };
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(PROXY_CONNECT_TIMEOUT_SECONDS))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|error| {
+                ApiError::internal(format!("No se pudo crear cliente HTTP de nodos: {error}"))
+            })?;
+
+        Ok(Some(Self {
+            dir,
+            node_id,
+            node_url,
+            shared_storage: crate::read_bool_env("ARTIFACTS_SHARED").unwrap_or(false),
+            forward_mode,
+            client,
+        }))
+    }
+
+    pub(crate) fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    pub(crate) async fn record_job(&self, job_id: Uuid, expires_at: DateTime<Utc>) {
+        let record = self.location(expires_at, None, None);
+        self.write(
+            self.dir.join("jobs").join(format!("{job_id}.json")),
+            &record,
+        )
+        .await;
+    }
+
+    pub(crate) async fn record_artifact(
+        &self,
+        job_id: Uuid,
+        artifact: &StoredArtifact,
+        expires_at: DateTime<Utc>,
+    ) {
+        let record = self.location(
+            expires_at,
+            Some(artifact.filename.clone()),
+            Some(artifact.size),
+        );
+        self.write(
+            self.dir.join("jobs").join(format!("{job_id}.json")),
+            &record,
+        )
+        .await;
+        self.write(
+            self.dir
+                .join("artifacts")
+                .join(format!("{}.json", artifact.hash)),
+            &record,
+        )
+        .await;
+    }
+
+    pub(crate) async fn locate_job(
+        &self,
+        job_id: Uuid,
+        headers: &HeaderMap,
+    ) -> Option<LocationRecord> {
+        if headers.contains_key(FORWARDED_HEADER) {
+            return None;
+        }
+        self.read(self.dir.join("jobs").join(format!("{job_id}.json")))
+            .await
+            .filter(|record| record.node_id != self.node_id && record.node_url.is_some())
+    }
+
+    pub(crate) async fn route_artifact(
+        &self,
+        artifacts: &ArtifactStore,
+        hash: &str,
+        job_id: Option<Uuid>,
+        headers: &HeaderMap,
+    ) -> Option<ArtifactRoute> {
+        if !is_artifact_hash(hash) {
+            return None;
+        }
+        let record = self
+            .read(self.dir.join("artifacts").join(format!("{hash}.json")))
+            .await
+            .filter(|record| record.node_id != self.node_id)?;
+
+        if self.shared_storage
+            && let (Some(filename), Some(size)) = (record.filename.clone(), record.size)
+            && let Some(path) = artifacts.blob_at(hash, size).await
+        {
+            // The hash record keeps the last job's name; the job's own record has the one it asked for.
+            let filename = match job_id {
+                Some(job_id) => self
+                    .read(self.dir.join("jobs").join(format!("{job_id}.json")))
+                    .await
+                    .and_then(|job| job.filename)
+                    .unwrap_or(filename),
+                None => filename,
+            };
+            return Some(ArtifactRoute::Local(StoredArtifact {
+                hash: hash.to_string(),
+                path,
+                filename,
+                size,
+                reused: true,
+            }));
+        }
+
+        (record.node_url.is_some() && !headers.contains_key(FORWARDED_HEADER))
+            .then_some(ArtifactRoute::Remote(record))
+    }
+
+    pub(crate) async fn forward(
+        &self,
+        location: &LocationRecord,
+        method: Method,
+        uri: &Uri,
+        request_headers: &HeaderMap,
+        client_ip: &str,
+    ) -> Result<Response, ApiError> {
+        let node_url = location
+            .node_url
+            .as_deref()
+            .ok_or_else(|| ApiError::not_found("El recurso pertenece a otro nodo."))?;
+        let path = uri
+            .path_and_query()
+            .map(|value| value.as_str())
+            .unwrap_or_else(|| uri.path());
+        let target = format!("{node_url}{path}");
+
+        if self.forward_mode == ForwardMode::Redirect {
+            let value = HeaderValue::from_str(&target)
+                .map_err(|_| ApiError::internal("URL de nodo invalida."))?;
+            return Ok((StatusCode::TEMPORARY_REDIRECT, [(LOCATION, value)]).into_response());
+        }
+
+        info!("Reenviando {path} al nodo {}", location.node_id);
+        let mut request = self
+            .client
+            .request(method, &target)
+            .header("x-forwarded-for", client_ip)
+            .header(FORWARDED_HEADER, &self.node_id);
+        // Resumed downloads and reconnecting event streams only work if these reach the owner.
+        for name in PROXIED_REQUEST_HEADERS {
+            if let Some(value) = request_headers.get(*name) {
+                request = request.header(*name, value.clone());
+            }
+        }
+        let mut upstream = request.send().await.map_err(|error| {
+            ApiError::internal(format!(
+                "No se pudo contactar al nodo {}: {error}",
+                location.node_id
+            ))
+        })?;
+
+        let mut headers = HeaderMap::new();
+        for name in PROXIED_RESPONSE_HEADERS {
+            if let Some(value) = upstream.headers().get(*name) {
+                headers.insert(HeaderName::from_static(name), value.clone());
+            }
+        }
+        let status = upstream.status();
+
+        let (sender, receiver) = mpsc::channel(PROXY_CHANNEL_CHUNKS);
+        tokio::spawn(async move {
+            loop {
+                let chunk = match upstream.chunk().await {
+                    Ok(Some(chunk)) => Ok(chunk),
+                    Ok(None) => break,
+                    Err(error) => Err(std::io::Error::other(error)),
+                };
+                let failed = chunk.is_err();
+                if sender.send(chunk).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        let body = Body::from_stream(stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|chunk| (chunk, receiver))
+        }));
+
+        Ok((status, headers, body).into_response())
+    }
+
+    pub(crate) async fn prune_expired(&self) {
+        let now = Utc::now();
+        for subdir in ["jobs", "artifacts"] {
+            let Ok(mut entries) = tokio::fs::read_dir(self.dir.join(subdir)).await else {
+                continue;
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path();
+                if path.extension().is_none_or(|extension| extension != "json") {
+                    continue;
+                }
+                let expired = tokio::fs::read(&path)
+                    .await
+                    .ok()
+                    .and_then(|content| serde_json::from_slice::<LocationRecord>(&content).ok())
+                    .is_none_or(|record| record.expires_at <= now);
+                if expired
+                    && let Err(error) = tokio::fs::remove_file(&path).await
+                    && error.kind() != ErrorKind::NotFound
+                {
+                    warn!("No se pudo limpiar el registro {:?}: {error}", path);
+                }
+            }
+        }
+    }
+
+    fn location(
+        &self,
+        expires_at: DateTime<Utc>,
+        filename: Option<String>,
+        size: Option<u64>,
+    ) -> LocationRecord {
+        LocationRecord {
+            node_id: self.node_id.clone(),
+            node_url: self.node_url.clone(),
+            expires_at,
+            filename,
+            size,
+        }
+    }
+
+    async fn read(&self, path: PathBuf) -> Option<LocationRecord> {
+        let content = tokio::fs::read(&path).await.ok()?;
+        serde_json::from_slice::<LocationRecord>(&content)
+            .ok()
+            .filter(|record| record.expires_at > Utc::now())
+    }
+
+    async fn write(&self, path: PathBuf, record: &LocationRecord) {
+        let staged = path.with_extension(format!("{}.tmp", Uuid::new_v4().simple()));
+        let result = async {
+            let content = serde_json::to_vec(record).map_err(|error| error.to_string())?;
+            tokio::fs::write(&staged, content)
+                .await
+                .map_err(|error| error.to_string())?;
+            tokio::fs::rename(&staged, &path)
+                .await
+                .map_err(|error| error.to_string())
+        }
+        .await;
+        if let Err(error) = result {
+            let _ = tokio::fs::remove_file(&staged).await;
+            warn!("No se pudo escribir el registro {:?}: {error}", path);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::{ApiError, AppState, check_signed_timestamp, encode_hex, verify_hmac};
+
+pub(crate) const TIMESTAMP_HEADER: &str = "x-td-timestamp";
+pub(crate) const SIGNATURE_HEADER: &str = "x-td-signature";
+pub(crate) const DEFAULT_MAX_SKEW_SECONDS: i64 = 300;
+const MAX_SIGNED_BODY_BYTES: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub(crate) struct RequestSigner {
+    secret: Vec<u8>,
+    max_skew_seconds: i64,
+}
+
+impl RequestSigner {
+    pub(crate) fn from_env() -> Option<Self> {
+        let secret = std::env::var("REQUEST_SIGNING_SECRET")
+            .ok()
+            .and_then(|value| crate::non_empty(&value).map(|secret| secret.as_bytes().to_vec()))?;
+        let max_skew_seconds = crate::read_usize_env("REQUEST_SIGNING_MAX_SKEW_SECONDS")
+            .filter(|value| *value > 0)
+            .map_or(DEFAULT_MAX_SKEW_SECONDS, |value| value as i64);
+
+        Some(Self::new(secret, max_skew_seconds))
+    }
+
+    pub(crate) fn new(secret: Vec<u8>, max_skew_seconds: i64) -> Self {
+        Self {
+            secret,
+            max_skew_seconds,
+        }
+    }
+
+    pub(crate) fn verify(
+        &self,
+        method: &str,
+        path: &str,
+        timestamp: &str,
+        body: &[u8],
+        signature: &str,
+    ) -> Result<(), &'static str> {
+        check_signed_timestamp(timestamp, self.max_skew_seconds)?;
+
+        let payload = format!(
+            "{}\n{method}\n{path}\n{}",
+            timestamp.trim(),
+            encode_hex(&Sha256::digest(body))
+        );
+        if verify_hmac(&self.secret, payload.as_bytes(), signature) {
+            Ok(())
+        } else {
+            Err("firma incorrecta")
+        }
+    }
+}
+
+pub(crate) async fn require_signed_request(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(signer) = state.request_signer.as_deref() else {
+        return next.run(request).await;
+    };
+    // The signature proves the request came from our frontend; integrations prove it with their key.
+    if matches!(
+        state
+            .api_keys
+            .resolve(&state.roles, request.headers())
+            .await,
+        Ok(Some(_))
+    ) {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    let header = |name: &str| {
+        parts
+            .headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(ToString::to_string)
+    };
+    let (Some(timestamp), Some(signature)) = (header(TIMESTAMP_HEADER), header(SIGNATURE_HEADER))
+    else {
+        return ApiError::bot_check_failed("Falta la firma de la solicitud.").into_response();
+    };
+
+    let Ok(body) = axum::body::to_bytes(body, MAX_SIGNED_BODY_BYTES).await else {
+        return ApiError::bad_request("El cuerpo de la solicitud es demasiado grande.")
+            .into_response();
+    };
+    let path = parts
+        .uri
+        .path_and_query()
+        .map_or_else(|| parts.uri.path(), |value| value.as_str());
+    if let Err(reason) = signer.verify(parts.method.as_str(), path, &timestamp, &body, &signature) {
+        debug!("Firma de solicitud rechazada para {path}: {reason}");
+        return ApiError::bot_check_failed("Firma de solicitud invalida.").into_response();
+    }
+
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
@@ -0,0 +1,124 @@
+use axum::{
+    extract::{Request, State},
+    http::{
+        HeaderMap, HeaderValue,
+        header::{COOKIE, SET_COOKIE},
+    },
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    AppState, non_empty, public_base_url, read_bool_env, read_usize_env, sign_value,
+    verify_signature,
+};
+
+const SESSION_COOKIE: &str = "td_anon";
+pub(crate) const SESSION_HEADER: &str = "x-session-token";
+const DEFAULT_SESSION_DAYS: usize = 30;
+const DEFAULT_IP_LIMIT_FACTOR: usize = 5;
+
+#[derive(Debug)]
+pub(crate) struct AnonSessions {
+    days: i64,
+    pub(crate) ip_limit_factor: usize,
+}
+
+impl AnonSessions {
+    pub(crate) fn from_env() -> Option<Self> {
+        if !read_bool_env("ANON_SESSIONS_ENABLED").unwrap_or(false) {
+            return None;
+        }
+        let sessions = Self {
+            days: read_usize_env("ANON_SESSION_DAYS")
+                .filter(|days| *days > 0)
+                .unwrap_or(DEFAULT_SESSION_DAYS) as i64,
+            ip_limit_factor: read_usize_env("ANON_SESSION_IP_LIMIT_FACTOR")
+                .filter(|factor| *factor > 0)
+                .unwrap_or(DEFAULT_IP_LIMIT_FACTOR),
+        };
+        info!(
+            "Sesiones anonimas habilitadas: duran {} dias y cada IP admite {}x la cuota diaria.",
+            sessions.days, sessions.ip_limit_factor
+        );
+        Some(sessions)
+    }
+}
+
+fn token_payload(id: &str, expires: i64) -> String {
+    format!("anon-session:{id}:{expires}")
+}
+
+fn presented_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(SESSION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(non_empty)
+        .or_else(|| {
+            headers
+                .get_all(COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(';'))
+                .filter_map(|pair| pair.trim().split_once('='))
+                .find(|(key, _)| *key == SESSION_COOKIE)
+                .map(|(_, value)| value)
+        })
+}
+
+// Quota, history and anti-bot key off this subject; requests without a valid token stay on their IP.
+pub(crate) fn subject(state: &AppState, headers: &HeaderMap) -> Option<String> {
+    state.sessions.as_ref()?;
+    let mut parts = presented_token(headers)?.splitn(3, '.');
+    let (id, expires, signature) = (parts.next()?, parts.next()?, parts.next()?);
+    let id = Uuid::try_parse(id).ok()?.simple().to_string();
+    let expires = expires.parse::<i64>().ok()?;
+    if expires < Utc::now().timestamp()
+        || !verify_signature(
+            &state.signing_secret,
+            &token_payload(&id, expires),
+            signature,
+        )
+    {
+        return None;
+    }
+    Some(format!("session:{id}"))
+}
+
+pub(crate) async fn issue_session(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(sessions) = state.sessions.as_ref() else {
+        return next.run(request).await;
+    };
+    if subject(&state, request.headers()).is_some() {
+        return next.run(request).await;
+    }
+    let secure = public_base_url(&state, request.headers()).starts_with("https://");
+    let id = Uuid::new_v4().simple().to_string();
+    let max_age = sessions.days * 24 * 60 * 60;
+    let expires = Utc::now().timestamp() + max_age;
+    let token = format!(
+        "{id}.{expires}.{}",
+        sign_value(&state.signing_secret, &token_payload(&id, expires))
+    );
+
+    let mut response = next.run(request).await;
+    let cookie = format!(
+        "{SESSION_COOKIE}={token}; Path=/; Max-Age={max_age}; HttpOnly; SameSite=Lax{}",
+        if secure { "; Secure" } else { "" }
+    );
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&cookie) {
+        headers.append(SET_COOKIE, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&token) {
+        headers.insert(SESSION_HEADER, value);
+    }
+    response
+}
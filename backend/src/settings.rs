@@ -0,0 +1,348 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{ApiError, non_empty};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ServerSettings {
+    bind: Option<String>,
+    port: Option<u16>,
+    cors_origins: Option<Vec<String>>,
+    data_dir: Option<String>,
+    transfer_dir: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LimitSettings {
+    download_limit_per_day: Option<u64>,
+    download_window_hours: Option<u64>,
+    max_download_mb: Option<u64>,
+    snapshot_max_download_mb: Option<u64>,
+    yt_dlp_timeout_seconds: Option<u64>,
+    history_per_ip_limit: Option<u64>,
+    history_max_entries: Option<u64>,
+    formats_cache_ttl_seconds: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct YtDlpSettings {
+    path: Option<String>,
+    candidate_path: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FfmpegSettings {
+    path: Option<String>,
+    hwaccel: Option<String>,
+    hwaccel_device: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TurnstileSettings {
+    secret_key: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Settings {
+    server: ServerSettings,
+    limits: LimitSettings,
+    yt_dlp: YtDlpSettings,
+    ffmpeg: FfmpegSettings,
+    turnstile: TurnstileSettings,
+    env: BTreeMap<String, Value>,
+}
+
+#[derive(Debug)]
+pub(crate) struct LoadedSettings {
+    pub(crate) path: PathBuf,
+    pub(crate) applied: Vec<String>,
+    pub(crate) overridden: Vec<String>,
+}
+
+impl Settings {
+    pub(crate) fn from_file(path: &Path) -> Result<Self, ApiError> {
+        let content = std::fs::read_to_string(path).map_err(|error| {
+            ApiError::internal(format!(
+                "No se pudo leer el archivo de configuracion {}: {error}",
+                path.display()
+            ))
+        })?;
+        let document = parse_document(&content)
+            .map_err(|error| ApiError::internal(format!("{}: {error}", path.display())))?;
+        serde_json::from_value(document).map_err(|error| {
+            ApiError::internal(format!(
+                "Configuracion invalida en {}: {error}",
+                path.display()
+            ))
+        })
+    }
+
+    fn env_pairs(self) -> Vec<(String, String)> {
+        let mut pairs = Vec::new();
+        let mut push = |name: &str, value: Option<String>| {
+            if let Some(value) = value {
+                pairs.push((name.to_string(), value));
+            }
+        };
+        let number = |value: Option<u64>| value.map(|value| value.to_string());
+
+        push("APP_ADDR", self.server.bind);
+        push("PORT", self.server.port.map(|port| port.to_string()));
+        push(
+            "ALLOWED_ORIGINS",
+            self.server.cors_origins.map(|origins| origins.join(",")),
+        );
+        push("DATA_DIR", self.server.data_dir);
+        push("TRANSFER_DIR", self.server.transfer_dir);
+
+        let limits = self.limits;
+        push(
+            "DOWNLOAD_LIMIT_PER_DAY",
+            number(limits.download_limit_per_day),
+        );
+        push(
+            "DOWNLOAD_WINDOW_HOURS",
+            number(limits.download_window_hours),
+        );
+        push("MAX_DOWNLOAD_MB", number(limits.max_download_mb));
+        push(
+            "SNAPSHOT_MAX_DOWNLOAD_MB",
+            number(limits.snapshot_max_download_mb),
+        );
+        push(
+            "YT_DLP_TIMEOUT_SECONDS",
+            number(limits.yt_dlp_timeout_seconds),
+        );
+        push("HISTORY_PER_IP_LIMIT", number(limits.history_per_ip_limit));
+        push("HISTORY_MAX_ENTRIES", number(limits.history_max_entries));
+        push(
+            "FORMATS_CACHE_TTL_SECONDS",
+            number(limits.formats_cache_ttl_seconds),
+        );
+
+        push("YT_DLP_STABLE_PATH", self.yt_dlp.path);
+        push("YT_DLP_CANDIDATE_PATH", self.yt_dlp.candidate_path);
+        push("FFMPEG_PATH", self.ffmpeg.path);
+        push("FFMPEG_HWACCEL", self.ffmpeg.hwaccel);
+        push("FFMPEG_HWACCEL_DEVICE", self.ffmpeg.hwaccel_device);
+        push("TURNSTILE_SECRET_KEY", self.turnstile.secret_key);
+
+        for (name, value) in self.env {
+            push(&name.to_ascii_uppercase(), Some(env_string(&value)));
+        }
+        pairs
+    }
+}
+
+pub(crate) fn apply_config_file(path: Option<PathBuf>) -> Result<Option<LoadedSettings>, ApiError> {
+    let Some(path) = path.or_else(|| {
+        std::env::var("CONFIG_PATH")
+            .ok()
+            .and_then(|value| non_empty(&value).map(PathBuf::from))
+    }) else {
+        return Ok(None);
+    };
+    let settings = Settings::from_file(&path)?;
+    let mut loaded = LoadedSettings {
+        path,
+        applied: Vec::new(),
+        overridden: Vec::new(),
+    };
+    for (name, value) in settings.env_pairs() {
+        if std::env::var_os(&name).is_some() {
+            loaded.overridden.push(name);
+            continue;
+        }
+        // SAFETY: called from main before the tokio runtime or any other thread starts.
+        unsafe { std::env::set_var(&name, value) };
+        loaded.applied.push(name);
+    }
+    Ok(Some(loaded))
+}
+
+fn env_string(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items.iter().map(env_string).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+fn parse_document(content: &str) -> Result<Value, String> {
+    let mut root = Map::new();
+    let mut section: Option<String> = None;
+    let mut lines = content.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let line_number = index + 1;
+        let mut line = strip_comment(line).trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            let name = name.trim();
+            if name.is_empty() || !name.chars().all(is_key_char) {
+                return Err(format!("linea {line_number}: seccion invalida [{name}]"));
+            }
+            if root.contains_key(name) {
+                return Err(format!("linea {line_number}: seccion [{name}] repetida"));
+            }
+            root.insert(name.to_string(), Value::Object(Map::new()));
+            section = Some(name.to_string());
+            continue;
+        }
+
+        while line.contains('[') && bracket_depth(&line) > 0 {
+            let Some((_, next)) = lines.next() else {
+                return Err(format!("linea {line_number}: lista sin cerrar"));
+            };
+            line.push(' ');
+            line.push_str(strip_comment(next).trim());
+        }
+        let (key, raw_value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("linea {line_number}: se esperaba clave = valor"))?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(is_key_char) {
+            return Err(format!("linea {line_number}: clave invalida {key:?}"));
+        }
+        let (value, rest) = parse_value(raw_value.trim())
+            .map_err(|error| format!("linea {line_number}: {error}"))?;
+        if !rest.trim().is_empty() {
+            return Err(format!(
+                "linea {line_number}: texto inesperado despues del valor"
+            ));
+        }
+
+        let table = match &section {
+            Some(name) => root
+                .get_mut(name)
+                .and_then(Value::as_object_mut)
+                .ok_or_else(|| format!("linea {line_number}: seccion desconocida"))?,
+            None => &mut root,
+        };
+        if table.insert(key.to_string(), value).is_some() {
+            return Err(format!("linea {line_number}: clave {key} repetida"));
+        }
+    }
+    Ok(Value::Object(root))
+}
+
+fn is_key_char(character: char) -> bool {
+    character.is_ascii_alphanumeric() || character == '_' || character == '-'
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, character) in line.char_indices() {
+        match (quote, character) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(open), _) if character == open && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(character),
+            (None, '#') => return &line[..index],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+fn bracket_depth(line: &str) -> i32 {
+    let mut depth = 0;
+    let mut quote = None;
+    for character in line.chars() {
+        match (quote, character) {
+            (Some(open), _) if character == open => quote = None,
+            (None, '"' | '\'') => quote = Some(character),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+fn parse_value(input: &str) -> Result<(Value, &str), String> {
+    if let Some(rest) = input.strip_prefix('"') {
+        let mut text = String::new();
+        let mut characters = rest.char_indices();
+        while let Some((index, character)) = characters.next() {
+            match character {
+                '"' => return Ok((Value::String(text), &rest[index + 1..])),
+                '\\' => match characters.next().map(|(_, escaped)| escaped) {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    other => {
+                        return Err(format!("escape no soportado: \\{}", other.unwrap_or(' ')));
+                    }
+                },
+                _ => text.push(character),
+            }
+        }
+        return Err("texto sin cerrar".to_string());
+    }
+    if let Some(rest) = input.strip_prefix('\'') {
+        let end = rest.find('\'').ok_or("texto sin cerrar")?;
+        return Ok((Value::String(rest[..end].to_string()), &rest[end + 1..]));
+    }
+    if let Some(mut rest) = input.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(items), after));
+            }
+            let (item, after) = parse_value(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err("se esperaba , o ] en la lista".to_string());
+            }
+        }
+    }
+
+    let end = input
+        .find(|character: char| character == ',' || character == ']' || character.is_whitespace())
+        .unwrap_or(input.len());
+    let (token, rest) = input.split_at(end);
+    let value = match token {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => {
+            let digits = token.replace('_', "");
+            if let Ok(integer) = digits.parse::<i64>() {
+                Value::from(integer)
+            } else if let Some(float) = digits
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+            {
+                Value::Number(float)
+            } else {
+                return Err(format!("valor no soportado: {token:?}"));
+            }
+        }
+    };
+    Ok((value, rest))
+}
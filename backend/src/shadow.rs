@@ -0,0 +1,251 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use axum::{Json, extract::State};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::{
+    sync::{Mutex, Semaphore},
+    time::{Duration, Instant},
+};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::{ApiError, AppState, YtDlpVideoInfo, has_audio_only, has_video, run_extractor};
+
+const MAX_SHADOW_DIFFERENCES: usize = 25;
+const DEFAULT_SHADOW_MAX_CONCURRENT: usize = 1;
+
+#[derive(Debug)]
+pub(crate) struct ShadowExtractor {
+    command: String,
+    sample_percent: u8,
+    slots: Arc<Semaphore>,
+    stats: Mutex<ShadowStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ExtractionSummary {
+    ok: bool,
+    title: Option<String>,
+    format_count: usize,
+    video_count: usize,
+    audio_count: usize,
+    max_height: Option<u32>,
+    error: Option<String>,
+    #[serde(skip)]
+    duration_ms: u128,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ShadowDifference {
+    at: DateTime<Utc>,
+    domain: String,
+    fields: Vec<&'static str>,
+    primary: ExtractionSummary,
+    shadow: ExtractionSummary,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct ShadowStats {
+    sampled: u64,
+    skipped_busy: u64,
+    both_ok: u64,
+    primary_only_ok: u64,
+    shadow_only_ok: u64,
+    both_failed: u64,
+    title_mismatches: u64,
+    format_count_mismatches: u64,
+    max_height_mismatches: u64,
+    primary_total_ms: u128,
+    shadow_total_ms: u128,
+    recent_differences: VecDeque<ShadowDifference>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ShadowReport {
+    command: String,
+    sample_percent: u8,
+    primary_avg_ms: Option<u128>,
+    shadow_avg_ms: Option<u128>,
+    #[serde(flatten)]
+    stats: ShadowStats,
+}
+
+impl ExtractionSummary {
+    pub(crate) fn from_output(
+        result: &Result<std::process::Output, ApiError>,
+        duration_ms: u128,
+    ) -> Self {
+        let parsed = result
+            .as_ref()
+            .map_err(|error| error.message.clone())
+            .and_then(|output| {
+                serde_json::from_slice::<YtDlpVideoInfo>(&output.stdout)
+                    .map_err(|error| format!("JSON invalido: {error}"))
+            });
+
+        match parsed {
+            Ok(info) => Self {
+                ok: true,
+                title: info.title.clone(),
+                format_count: info.formats.len(),
+                video_count: info.formats.iter().filter(|item| has_video(item)).count(),
+                audio_count: info
+                    .formats
+                    .iter()
+                    .filter(|item| has_audio_only(item))
+                    .count(),
+                max_height: info.formats.iter().filter_map(|item| item.height).max(),
+                error: None,
+                duration_ms,
+            },
+            Err(error) => Self {
+                ok: false,
+                title: None,
+                format_count: 0,
+                video_count: 0,
+                audio_count: 0,
+                max_height: None,
+                error: Some(error),
+                duration_ms,
+            },
+        }
+    }
+}
+
+impl ShadowExtractor {
+    pub(crate) fn from_env() -> Option<Self> {
+        let command = std::env::var("SHADOW_EXTRACTOR_COMMAND")
+            .ok()
+            .and_then(|value| crate::non_empty(&value).map(ToString::to_string))?;
+        let sample_percent = crate::read_usize_env("SHADOW_SAMPLE_PERCENT")
+            .unwrap_or_default()
+            .min(100) as u8;
+        if sample_percent == 0 {
+            return None;
+        }
+        let max_concurrent = crate::read_usize_env("SHADOW_MAX_CONCURRENT")
+            .filter(|value| *value > 0)
+            .unwrap_or(DEFAULT_SHADOW_MAX_CONCURRENT);
+
+        Some(Self {
+            command,
+            sample_percent,
+            slots: Arc::new(Semaphore::new(max_concurrent)),
+            stats: Mutex::new(ShadowStats::default()),
+        })
+    }
+
+    pub(crate) fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub(crate) fn sample_percent(&self) -> u8 {
+        self.sample_percent
+    }
+
+    pub(crate) fn should_sample(&self) -> bool {
+        (Uuid::new_v4().as_u128() % 100) < u128::from(self.sample_percent)
+    }
+
+    pub(crate) fn spawn(
+        self: &Arc<Self>,
+        domain: String,
+        args: Vec<String>,
+        primary: ExtractionSummary,
+        time_limit: Duration,
+    ) {
+        let Ok(permit) = Arc::clone(&self.slots).try_acquire_owned() else {
+            let shadow = Arc::clone(self);
+            tokio::spawn(async move {
+                shadow.stats.lock().await.skipped_busy += 1;
+            });
+            return;
+        };
+
+        let shadow = Arc::clone(self);
+        tokio::spawn(async move {
+            let started_at = Instant::now();
+            let result = run_extractor(&shadow.command, args, time_limit).await;
+            let summary = ExtractionSummary::from_output(&result, started_at.elapsed().as_millis());
+            drop(permit);
+            shadow.record(domain, primary, summary).await;
+        });
+    }
+
+    async fn record(&self, domain: String, primary: ExtractionSummary, shadow: ExtractionSummary) {
+        let mut fields = Vec::new();
+        if primary.ok != shadow.ok {
+            fields.push("ok");
+        }
+        if primary.ok && shadow.ok {
+            if primary.title != shadow.title {
+                fields.push("title");
+            }
+            if primary.format_count != shadow.format_count {
+                fields.push("format_count");
+            }
+            if primary.max_height != shadow.max_height {
+                fields.push("max_height");
+            }
+        }
+
+        let mut stats = self.stats.lock().await;
+        stats.sampled += 1;
+        stats.primary_total_ms += primary.duration_ms;
+        stats.shadow_total_ms += shadow.duration_ms;
+        match (primary.ok, shadow.ok) {
+            (true, true) => stats.both_ok += 1,
+            (true, false) => stats.primary_only_ok += 1,
+            (false, true) => stats.shadow_only_ok += 1,
+            (false, false) => stats.both_failed += 1,
+        }
+        if fields.contains(&"title") {
+            stats.title_mismatches += 1;
+        }
+        if fields.contains(&"format_count") {
+            stats.format_count_mismatches += 1;
+        }
+        if fields.contains(&"max_height") {
+            stats.max_height_mismatches += 1;
+        }
+
+        if fields.is_empty() {
+            debug!("Shadow sin diferencias para {domain}");
+            return;
+        }
+
+        info!("Shadow detecto diferencias para {domain}: {:?}", fields);
+        stats.recent_differences.push_front(ShadowDifference {
+            at: Utc::now(),
+            domain,
+            fields,
+            primary,
+            shadow,
+        });
+        stats.recent_differences.truncate(MAX_SHADOW_DIFFERENCES);
+    }
+
+    async fn report(&self) -> ShadowReport {
+        let stats = self.stats.lock().await.clone();
+        let average = |total: u128| (stats.sampled > 0).then(|| total / u128::from(stats.sampled));
+
+        ShadowReport {
+            command: self.command.clone(),
+            sample_percent: self.sample_percent,
+            primary_avg_ms: average(stats.primary_total_ms),
+            shadow_avg_ms: average(stats.shadow_total_ms),
+            stats,
+        }
+    }
+}
+
+pub(crate) async fn get_shadow_report(
+    State(state): State<AppState>,
+) -> Result<Json<ShadowReport>, ApiError> {
+    let shadow = state
+        .shadow_extractor
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("El modo shadow no esta habilitado."))?;
+    Ok(Json(shadow.report().await))
+}
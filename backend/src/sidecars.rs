@@ -0,0 +1,393 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::archive::{self, ArchiveEntry, write_archive_file};
+use crate::artifacts::hash_file;
+use crate::warc::{WarcFile, write_snapshot_warc};
+use crate::{ApiError, DownloadMode, DownloadRequest};
+
+const SIDECAR_DIR: &str = "sidecars";
+const MAX_COMMENTS: usize = 500;
+const MAX_DESCRIPTION_BYTES: usize = 256 * 1024;
+const MAX_COMMENTS_BYTES: usize = 2 * 1024 * 1024;
+const SNAPSHOT_SUBTITLE_LANGUAGES: &str = "all,-live_chat";
+const PRIVATE_INFO_KEYS: [&str; 9] = [
+    "formats",
+    "requested_formats",
+    "requested_downloads",
+    "http_headers",
+    "url",
+    "manifest_url",
+    "fragments",
+    "automatic_captions",
+    "comments",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SidecarRequest {
+    #[serde(default)]
+    pub(crate) description: bool,
+    #[serde(default)]
+    pub(crate) comments: usize,
+    #[serde(default)]
+    pub(crate) snapshot: bool,
+    #[serde(default)]
+    pub(crate) warc: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct CommentRecord<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    like_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestFile {
+    name: String,
+    size: u64,
+    sha256: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SnapshotManifest<'a> {
+    source_url: &'a str,
+    archived_at: DateTime<Utc>,
+    files: Vec<ManifestFile>,
+}
+
+pub(crate) fn apply_sidecars(
+    payload: &mut DownloadRequest,
+    warc_enabled: bool,
+) -> Result<(), ApiError> {
+    let mut request = payload.sidecars.take().unwrap_or_default();
+    request.snapshot = payload.snapshot;
+    request.warc = payload.warc;
+    if request.warc && !request.snapshot {
+        return Err(ApiError::bad_request(
+            "warc solo aplica junto con snapshot.",
+        ));
+    }
+    if request.warc && !warc_enabled {
+        return Err(ApiError::forbidden(
+            "La salida WARC esta deshabilitada en este servidor.",
+        ));
+    }
+    if !request.description && request.comments == 0 && !request.snapshot {
+        return Ok(());
+    }
+    let label = if request.snapshot {
+        "snapshot"
+    } else {
+        "sidecars"
+    };
+    if request.comments > MAX_COMMENTS {
+        return Err(ApiError::bad_request(format!(
+            "sidecars.comments admite como maximo {MAX_COMMENTS} comentarios."
+        )));
+    }
+    if payload.playlist {
+        return Err(ApiError::bad_request(format!(
+            "{label} no se aplica a listas."
+        )));
+    }
+    if payload.split_chapters {
+        return Err(ApiError::bad_request(format!(
+            "{label} no se combina con split_chapters."
+        )));
+    }
+    if request.snapshot {
+        if !matches!(payload.mode, DownloadMode::Video) {
+            return Err(ApiError::bad_request("snapshot solo aplica al modo video."));
+        }
+        if payload
+            .embed_subtitles
+            .as_ref()
+            .is_some_and(|languages| !languages.is_empty())
+        {
+            return Err(ApiError::bad_request(
+                "snapshot ya guarda todos los subtitulos; no envies embed_subtitles.",
+            ));
+        }
+        request.description = true;
+    }
+    payload.sidecars = Some(request);
+    Ok(())
+}
+
+impl SidecarRequest {
+    pub(crate) fn cache_key(&self) -> String {
+        format!(
+            "{}{}{}c{}",
+            if self.snapshot { "s" } else { "" },
+            if self.warc { "w" } else { "" },
+            if self.description { "d" } else { "" },
+            self.comments
+        )
+    }
+
+    pub(crate) fn args(&self, job_dir: &Path) -> Vec<String> {
+        let dir = job_dir.join(SIDECAR_DIR);
+        let dir = dir.to_string_lossy();
+        let mut args = Vec::new();
+        if self.description {
+            args.extend([
+                "--write-description".to_string(),
+                "-o".to_string(),
+                format!("description:{dir}/description.%(ext)s"),
+            ]);
+        }
+        if self.comments > 0 {
+            args.extend([
+                "--write-comments".to_string(),
+                "--extractor-args".to_string(),
+                format!("youtube:max_comments={0},{0},0,0", self.comments),
+            ]);
+        }
+        if self.comments > 0 || self.snapshot {
+            args.extend([
+                "--write-info-json".to_string(),
+                "-o".to_string(),
+                format!("infojson:{dir}/info.%(ext)s"),
+            ]);
+        }
+        if self.snapshot {
+            args.extend([
+                "--write-thumbnail".to_string(),
+                "-o".to_string(),
+                format!("thumbnail:{dir}/thumbnail.%(ext)s"),
+                "--write-subs".to_string(),
+                "--sub-langs".to_string(),
+                SNAPSHOT_SUBTITLE_LANGUAGES.to_string(),
+                "-o".to_string(),
+                format!("subtitle:{dir}/subtitles.%(ext)s"),
+            ]);
+        }
+        args
+    }
+
+    pub(crate) async fn package(
+        &self,
+        media: &Path,
+        job_dir: &Path,
+        source_url: &str,
+    ) -> Result<PathBuf, ApiError> {
+        let dir = job_dir.join(SIDECAR_DIR);
+        let stem = media
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("download")
+            .to_string();
+        let media_name = media
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("download")
+            .to_string();
+        let mut entries = vec![archive_entry(media, media_name).await?];
+
+        if self.description
+            && let Some(source) = find_sidecar(&dir, |name| name.ends_with(".description")).await
+        {
+            let mut text = tokio::fs::read_to_string(&source).await.unwrap_or_default();
+            truncate_at_char(&mut text, MAX_DESCRIPTION_BYTES);
+            let path = dir.join("description.txt");
+            write_sidecar(&path, text.as_bytes()).await?;
+            entries.push(archive_entry(&path, format!("{stem}.description.txt")).await?);
+        }
+        let mut info = match find_sidecar(&dir, |name| name.ends_with(".info.json")).await {
+            Some(source) => tokio::fs::read(&source)
+                .await
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok()),
+            None => None,
+        };
+        if self.comments > 0 {
+            let comments = info
+                .as_ref()
+                .and_then(|info| info.get("comments"))
+                .and_then(Value::as_array)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let path = dir.join("comments.json");
+            write_sidecar(&path, &encode_comments(comments, self.comments)).await?;
+            entries.push(archive_entry(&path, format!("{stem}.comments.json")).await?);
+        }
+
+        if self.snapshot {
+            if let Some(source) = find_sidecar(&dir, |name| name.starts_with("thumbnail.")).await {
+                let extension = source
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .unwrap_or("jpg")
+                    .to_string();
+                entries
+                    .push(archive_entry(&source, format!("{stem}.thumbnail.{extension}")).await?);
+            }
+            for source in list_sidecars(&dir, |name| name.starts_with("subtitles.")).await {
+                let suffix = source
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.strip_prefix("subtitles."))
+                    .unwrap_or("vtt")
+                    .to_string();
+                entries.push(archive_entry(&source, format!("{stem}.{suffix}")).await?);
+            }
+            if let Some(Value::Object(fields)) = info.as_mut() {
+                for key in PRIVATE_INFO_KEYS {
+                    fields.remove(key);
+                }
+                let metadata = serde_json::to_vec_pretty(fields).unwrap_or_default();
+                let path = dir.join("metadata.json");
+                write_sidecar(&path, &metadata).await?;
+                entries.push(archive_entry(&path, format!("{stem}.metadata.json")).await?);
+            }
+
+            let mut files = Vec::with_capacity(entries.len());
+            for entry in &entries {
+                let (sha256, size) = hash_file(&entry.path).await?;
+                files.push(ManifestFile {
+                    name: entry.name.clone(),
+                    size,
+                    sha256,
+                });
+            }
+            if self.warc {
+                let mut recorded = entries.iter().zip(&files).map(|(entry, file)| WarcFile {
+                    name: entry.name.clone(),
+                    path: entry.path.clone(),
+                    size: file.size,
+                    sha256_hex: file.sha256.clone(),
+                });
+                if let Some(media) = recorded.next() {
+                    let attachments = recorded.collect::<Vec<_>>();
+                    let path = dir.join("snapshot.warc");
+                    write_snapshot_warc(&path, source_url, &media, &attachments).await?;
+                    let name = format!("{stem}.warc");
+                    let (sha256, size) = hash_file(&path).await?;
+                    files.push(ManifestFile {
+                        name: name.clone(),
+                        size,
+                        sha256,
+                    });
+                    entries.push(archive_entry(&path, name).await?);
+                }
+            }
+            let manifest = SnapshotManifest {
+                source_url,
+                archived_at: Utc::now(),
+                files,
+            };
+            let path = dir.join("manifest.json");
+            write_sidecar(
+                &path,
+                &serde_json::to_vec_pretty(&manifest).unwrap_or_default(),
+            )
+            .await?;
+            entries.push(archive_entry(&path, "manifest.json".to_string()).await?);
+        }
+
+        if archive::archive_len(&entries).is_none() {
+            return Err(ApiError::bad_request(
+                "El archivo y sus anexos superan el tamano maximo de un ZIP (4 GiB).",
+            ));
+        }
+        let output = media.with_extension("zip");
+        write_archive_file(entries, &output)
+            .await
+            .map_err(|error| {
+                ApiError::internal(format!("No se pudo empaquetar los anexos: {error}"))
+            })?;
+        let _ = tokio::fs::remove_file(media).await;
+        Ok(output)
+    }
+}
+
+fn truncate_at_char(text: &mut String, max_bytes: usize) {
+    if text.len() <= max_bytes {
+        return;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+}
+
+fn text<'a>(comment: &'a Value, key: &str) -> Option<&'a str> {
+    comment.get(key).and_then(Value::as_str)
+}
+
+fn encode_comments(comments: &[Value], limit: usize) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    let mut total = 4;
+    for comment in comments.iter().take(limit) {
+        let record = CommentRecord {
+            id: text(comment, "id"),
+            parent: text(comment, "parent").filter(|parent| *parent != "root"),
+            author: text(comment, "author"),
+            text: text(comment, "text"),
+            like_count: comment.get("like_count").and_then(Value::as_u64),
+            timestamp: comment.get("timestamp").and_then(Value::as_i64),
+        };
+        let Ok(line) = serde_json::to_string(&record) else {
+            continue;
+        };
+        total += line.len() + 2;
+        if total > MAX_COMMENTS_BYTES {
+            break;
+        }
+        encoded.push(line);
+    }
+    if encoded.is_empty() {
+        return b"[]\n".to_vec();
+    }
+    format!("[\n{}\n]\n", encoded.join(",\n")).into_bytes()
+}
+
+async fn list_sidecars(dir: &Path, matches: impl Fn(&str) -> bool) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+        return found;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.file_name().to_str().is_some_and(&matches) {
+            found.push(entry.path());
+        }
+    }
+    found.sort();
+    found
+}
+
+async fn find_sidecar(dir: &Path, matches: impl Fn(&str) -> bool) -> Option<PathBuf> {
+    list_sidecars(dir, matches).await.into_iter().next()
+}
+
+async fn write_sidecar(path: &Path, contents: &[u8]) -> Result<(), ApiError> {
+    tokio::fs::write(path, contents)
+        .await
+        .map_err(|error| ApiError::internal(format!("No se pudo guardar un anexo: {error}")))
+}
+
+async fn archive_entry(path: &Path, name: String) -> Result<ArchiveEntry, ApiError> {
+    let size = tokio::fs::metadata(path)
+        .await
+        .map_err(|error| ApiError::internal(format!("No se pudo leer un anexo: {error}")))?
+        .len();
+    Ok(ArchiveEntry {
+        name,
+        path: path.to_path_buf(),
+        size,
+    })
+}
@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use tokio::io::AsyncReadExt;
+use tracing::debug;
+
+use crate::content_type_for_filename;
+
+const SNIFF_BYTES: usize = 4096;
+const TS_PACKET_BYTES: usize = 188;
+
+const GENERIC_ISO_MEDIA: &str = "video/mp4";
+const ISO_MEDIA_TYPES: [&str; 6] = [
+    "video/mp4",
+    "audio/mp4",
+    "video/quicktime",
+    "video/x-m4v",
+    "video/3gpp",
+    "video/3gpp2",
+];
+
+fn ftyp_content_type(brand: &[u8]) -> &'static str {
+    match brand {
+        b"avif" | b"avis" => "image/avif",
+        b"heic" | b"heix" | b"mif1" => "image/heic",
+        b"3gp4" | b"3gp5" | b"3gp6" | b"3ge6" | b"3gs7" => "video/3gpp",
+        b"3g2a" | b"3g2b" | b"3g2c" => "video/3gpp2",
+        b"M4A " | b"M4B " => "audio/mp4",
+        b"qt  " => "video/quicktime",
+        b"M4V " | b"M4VH" | b"M4VP" => "video/x-m4v",
+        _ => GENERIC_ISO_MEDIA,
+    }
+}
+
+fn ebml_content_type(header: &[u8]) -> &'static str {
+    if header.windows(4).any(|window| window == b"webm") {
+        "video/webm"
+    } else {
+        "video/x-matroska"
+    }
+}
+
+fn is_mpeg_ts(header: &[u8]) -> bool {
+    header.len() > TS_PACKET_BYTES * 2
+        && [0, TS_PACKET_BYTES, TS_PACKET_BYTES * 2]
+            .iter()
+            .all(|offset| header[*offset] == 0x47)
+}
+
+fn detect(header: &[u8]) -> Option<&'static str> {
+    if header.len() >= 12 && &header[4..8] == b"ftyp" {
+        return Some(ftyp_content_type(&header[8..12]));
+    }
+    if header.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        return Some(ebml_content_type(header));
+    }
+    if header.len() >= 12 && header.starts_with(b"RIFF") {
+        return match &header[8..12] {
+            b"WAVE" => Some("audio/wav"),
+            b"WEBP" => Some("image/webp"),
+            b"AVI " => Some("video/x-msvideo"),
+            _ => None,
+        };
+    }
+    if is_mpeg_ts(header) {
+        return Some("video/mp2t");
+    }
+    if header.starts_with(b"OggS") {
+        if header.windows(8).any(|window| window == b"OpusHead") {
+            return Some("audio/opus");
+        }
+        return Some("audio/ogg");
+    }
+    if header.starts_with(b"fLaC") {
+        return Some("audio/flac");
+    }
+    if header.starts_with(b"ID3") {
+        return Some("audio/mpeg");
+    }
+    if header.starts_with(b"FLV") {
+        return Some("video/x-flv");
+    }
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some("image/jpeg");
+    }
+    if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some("image/png");
+    }
+    if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        return Some("image/gif");
+    }
+    if header.starts_with(b"PK\x03\x04") {
+        return Some("application/zip");
+    }
+    match header {
+        [0xFF, second, ..] if second & 0xF6 == 0xF0 => Some("audio/aac"),
+        [0xFF, second, ..] if second & 0xE0 == 0xE0 => Some("audio/mpeg"),
+        _ => None,
+    }
+}
+
+pub(crate) async fn content_type_for_file(path: &Path, filename: &str) -> &'static str {
+    let declared = content_type_for_filename(filename);
+    let mut header = vec![0_u8; SNIFF_BYTES];
+    let read = match tokio::fs::File::open(path).await {
+        Ok(mut file) => {
+            let mut filled = 0;
+            while filled < header.len() {
+                match file.read(&mut header[filled..]).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => filled += read,
+                }
+            }
+            filled
+        }
+        Err(_) => return declared,
+    };
+
+    match detect(&header[..read]) {
+        // Generic brands (isom, mp42, dash) say nothing beyond "ISO media"; keep the extension's pick.
+        Some(GENERIC_ISO_MEDIA) if ISO_MEDIA_TYPES.contains(&declared) => declared,
+        Some(detected) if detected != declared => {
+            debug!("{filename}: la extension indica {declared} pero el contenido es {detected}.");
+            detected
+        }
+        _ => declared,
+    }
+}
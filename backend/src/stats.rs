@@ -0,0 +1,237 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    io::ErrorKind,
+    path::PathBuf,
+};
+
+use axum::{Json, extract::State};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::{ApiError, AppState, system::DirectoryUsage, url_domain};
+
+const BUCKET_SECONDS: i64 = 3600;
+const RETENTION_HOURS: i64 = 7 * 24;
+const MAX_DOMAINS_PER_BUCKET: usize = 200;
+const TOP_DOMAINS: usize = 10;
+const SAVE_INTERVAL_SECONDS: i64 = 60;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct HourBucket {
+    succeeded: u64,
+    failed: u64,
+    bytes_served: u64,
+    domains: HashMap<String, u64>,
+}
+
+#[derive(Debug)]
+struct StatsState {
+    buckets: BTreeMap<i64, HourBucket>,
+    saved_at: i64,
+}
+
+#[derive(Debug)]
+pub(crate) struct DownloadStats {
+    path: PathBuf,
+    state: Mutex<StatsState>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct WindowStats {
+    downloads: u64,
+    succeeded: u64,
+    failed: u64,
+    bytes_served: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct DomainCount {
+    domain: String,
+    downloads: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct QueueStats {
+    queued: usize,
+    running: usize,
+    download_slots_available: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    download_slots_limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct AdminStats {
+    generated_at: DateTime<Utc>,
+    last_24h: WindowStats,
+    last_7d: WindowStats,
+    top_domains: Vec<DomainCount>,
+    queue: QueueStats,
+    temp_dir: Option<DirectoryUsage>,
+}
+
+fn bucket_key(at: DateTime<Utc>) -> i64 {
+    at.timestamp().div_euclid(BUCKET_SECONDS) * BUCKET_SECONDS
+}
+
+impl StatsState {
+    fn current(&mut self) -> &mut HourBucket {
+        let now = bucket_key(Utc::now());
+        let oldest = now - RETENTION_HOURS * BUCKET_SECONDS;
+        self.buckets.retain(|key, _| *key > oldest);
+        self.buckets.entry(now).or_default()
+    }
+
+    fn recent(&self, hours: i64) -> impl Iterator<Item = &HourBucket> {
+        let since = bucket_key(Utc::now()) - (hours - 1) * BUCKET_SECONDS;
+        self.buckets.range(since..).map(|(_, bucket)| bucket)
+    }
+
+    fn window(&self, hours: i64) -> WindowStats {
+        self.recent(hours)
+            .fold(WindowStats::default(), |mut window, bucket| {
+                window.downloads += bucket.succeeded + bucket.failed;
+                window.succeeded += bucket.succeeded;
+                window.failed += bucket.failed;
+                window.bytes_served += bucket.bytes_served;
+                window
+            })
+    }
+
+    fn top_domains(&self) -> Vec<DomainCount> {
+        let mut totals = HashMap::<&str, u64>::new();
+        for bucket in self.recent(RETENTION_HOURS) {
+            for (domain, count) in &bucket.domains {
+                *totals.entry(domain.as_str()).or_default() += count;
+            }
+        }
+        let mut domains = totals
+            .into_iter()
+            .map(|(domain, downloads)| DomainCount {
+                domain: domain.to_string(),
+                downloads,
+            })
+            .collect::<Vec<_>>();
+        domains.sort_by_key(|entry| (Reverse(entry.downloads), entry.domain.clone()));
+        domains.truncate(TOP_DOMAINS);
+        domains
+    }
+}
+
+impl DownloadStats {
+    pub(crate) async fn load(path: PathBuf) -> Result<Self, ApiError> {
+        let buckets = match tokio::fs::read_to_string(&path).await {
+            Ok(content) if content.trim().is_empty() => BTreeMap::new(),
+            Ok(content) => serde_json::from_str(&content).map_err(|error| {
+                ApiError::internal(format!("Estadisticas de descargas invalidas: {error}"))
+            })?,
+            Err(error) if error.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => {
+                return Err(ApiError::internal(format!(
+                    "No se pudieron leer las estadisticas de descargas: {error}"
+                )));
+            }
+        };
+
+        Ok(Self {
+            path,
+            state: Mutex::new(StatsState {
+                buckets,
+                saved_at: 0,
+            }),
+        })
+    }
+
+    pub(crate) async fn record_download(&self, url: &str, succeeded: bool) {
+        let content = {
+            let mut state = self.state.lock().await;
+            let bucket = state.current();
+            if succeeded {
+                bucket.succeeded += 1;
+            } else {
+                bucket.failed += 1;
+            }
+            let domain = url_domain(url);
+            if !domain.is_empty()
+                && (bucket.domains.len() < MAX_DOMAINS_PER_BUCKET
+                    || bucket.domains.contains_key(&domain))
+            {
+                *bucket.domains.entry(domain).or_default() += 1;
+            }
+            state.saved_at = Utc::now().timestamp();
+            serde_json::to_vec(&state.buckets)
+        };
+        self.save(content).await;
+    }
+
+    // Served bytes arrive per stream, so they are flushed at most once a minute.
+    pub(crate) async fn record_served(&self, bytes: u64) {
+        if bytes == 0 {
+            return;
+        }
+        let content = {
+            let mut state = self.state.lock().await;
+            state.current().bytes_served += bytes;
+            let now = Utc::now().timestamp();
+            if now - state.saved_at < SAVE_INTERVAL_SECONDS {
+                return;
+            }
+            state.saved_at = now;
+            serde_json::to_vec(&state.buckets)
+        };
+        self.save(content).await;
+    }
+
+    pub(crate) async fn flush(&self) {
+        let content = serde_json::to_vec(&self.state.lock().await.buckets);
+        self.save(content).await;
+    }
+
+    async fn save(&self, content: serde_json::Result<Vec<u8>>) {
+        let result = match content {
+            Ok(content) => tokio::fs::write(&self.path, content)
+                .await
+                .map_err(|error| error.to_string()),
+            Err(error) => Err(error.to_string()),
+        };
+        if let Err(error) = result {
+            warn!("No se pudieron guardar las estadisticas de descargas: {error}");
+        }
+    }
+}
+
+pub(crate) async fn get_admin_stats(
+    State(state): State<AppState>,
+) -> Result<Json<AdminStats>, ApiError> {
+    let (last_24h, last_7d, top_domains) = {
+        let stats = state.stats.state.lock().await;
+        (
+            stats.window(24),
+            stats.window(RETENTION_HOURS),
+            stats.top_domains(),
+        )
+    };
+    let (queued, running) = state.jobs.state_counts().await;
+    let monitor = state.system.clone();
+    let temp_dir = tokio::task::spawn_blocking(move || monitor.directory_report("transfer"))
+        .await
+        .map_err(|error| {
+            ApiError::internal(format!("No se pudo medir la carpeta temporal: {error}"))
+        })?;
+
+    Ok(Json(AdminStats {
+        generated_at: Utc::now(),
+        last_24h,
+        last_7d,
+        top_domains,
+        queue: QueueStats {
+            queued,
+            running,
+            download_slots_available: state.download_semaphore.available_permits(),
+            download_slots_limit: state.concurrency.as_ref().map(|tuner| tuner.limit()),
+        },
+        temp_dir,
+    }))
+}
@@ -0,0 +1,430 @@
+use std::{
+    fmt::Debug,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    ApiError, HistoryEntry, RateLimitMap, bans::BanMap, config::Config, trim_history_limits,
+};
+
+const JOURNAL_COMPACT_OPERATIONS: usize = 500;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum HistoryOperation {
+    Push { entry: HistoryEntry },
+    Update { entry: HistoryEntry },
+    Remove { ids: Vec<Uuid> },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RateLimitOperation {
+    ip: String,
+    at: DateTime<Utc>,
+}
+
+pub(crate) type StorageFuture<'a, T> =
+    Pin<Box<dyn Future<Output = Result<T, ApiError>> + Send + 'a>>;
+
+// History, per-IP quota and bans. Loading is shared; implementations differ in how changes are written.
+pub(crate) trait Storage: Debug + Send + Sync {
+    fn files(&self) -> &StorageFiles;
+
+    fn record_history<'a>(
+        &'a self,
+        operation: HistoryOperation,
+        history: &'a [HistoryEntry],
+    ) -> StorageFuture<'a, ()>;
+
+    fn rate_limit_recorded<'a>(
+        &'a self,
+        ip: &'a str,
+        at: DateTime<Utc>,
+        rate_limits: &'a RateLimitMap,
+    ) -> StorageFuture<'a, ()>;
+
+    fn load_history<'a>(&'a self, config: &'a Config) -> StorageFuture<'a, Vec<HistoryEntry>> {
+        Box::pin(self.files().load_history(config))
+    }
+
+    fn load_rate_limits(&self, window_hours: i64) -> StorageFuture<'_, RateLimitMap> {
+        Box::pin(self.files().load_rate_limits(window_hours))
+    }
+
+    // Bans change rarely, so every backend rewrites the whole snapshot.
+    fn load_bans(&self) -> StorageFuture<'_, BanMap> {
+        Box::pin(read_snapshot(&self.files().bans, "las IPs bloqueadas"))
+    }
+
+    fn bans_changed<'a>(&'a self, bans: &'a BanMap) -> StorageFuture<'a, ()> {
+        Box::pin(write_snapshot(
+            &self.files().bans,
+            bans,
+            "las IPs bloqueadas",
+        ))
+    }
+
+    fn history_pushed<'a>(
+        &'a self,
+        entry: &HistoryEntry,
+        history: &'a [HistoryEntry],
+    ) -> StorageFuture<'a, ()> {
+        self.record_history(
+            HistoryOperation::Push {
+                entry: entry.clone(),
+            },
+            history,
+        )
+    }
+
+    fn history_updated<'a>(
+        &'a self,
+        entry: &HistoryEntry,
+        history: &'a [HistoryEntry],
+    ) -> StorageFuture<'a, ()> {
+        self.record_history(
+            HistoryOperation::Update {
+                entry: entry.clone(),
+            },
+            history,
+        )
+    }
+
+    fn history_removed<'a>(
+        &'a self,
+        ids: Vec<Uuid>,
+        history: &'a [HistoryEntry],
+    ) -> StorageFuture<'a, ()> {
+        self.record_history(HistoryOperation::Remove { ids }, history)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct StorageFiles {
+    history: PathBuf,
+    rate_limits: PathBuf,
+    bans: PathBuf,
+}
+
+// Rewrites the whole JSON file on every change.
+#[derive(Debug)]
+pub(crate) struct JsonStorage {
+    files: StorageFiles,
+}
+
+// Appends each change to a journal and folds it into the JSON snapshot every few hundred operations.
+#[derive(Debug)]
+pub(crate) struct JournalStorage {
+    files: StorageFiles,
+    history_operations: AtomicUsize,
+    rate_limit_operations: AtomicUsize,
+}
+
+pub(crate) fn from_env(data_dir: &Path) -> Result<Arc<dyn Storage>, ApiError> {
+    let files = StorageFiles {
+        history: data_dir.join("history.json"),
+        rate_limits: data_dir.join("rate_limits.json"),
+        bans: data_dir.join("bans.json"),
+    };
+    match std::env::var("STORAGE_BACKEND")
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "" | "journal" => Ok(Arc::new(JournalStorage {
+            files,
+            history_operations: AtomicUsize::new(0),
+            rate_limit_operations: AtomicUsize::new(0),
+        })),
+        "json" => Ok(Arc::new(JsonStorage { files })),
+        other => Err(ApiError::internal(format!(
+            "STORAGE_BACKEND={other:?} no es valido. Usa journal o json."
+        ))),
+    }
+}
+
+fn journal_path(path: &Path) -> PathBuf {
+    path.with_extension("journal.jsonl")
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    path.with_extension("json.bak")
+}
+
+async fn parse_snapshot<T: DeserializeOwned + Default>(path: &Path) -> Result<T, String> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(contents) if contents.trim().is_empty() => Ok(T::default()),
+        Ok(contents) => serde_json::from_str(&contents).map_err(|error| error.to_string()),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(T::default()),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+pub(crate) async fn read_snapshot<T: DeserializeOwned + Default>(
+    path: &Path,
+    label: &str,
+) -> Result<T, ApiError> {
+    let error = match parse_snapshot(path).await {
+        Ok(value) => return Ok(value),
+        Err(error) => error,
+    };
+    let backup = backup_path(path);
+    if !tokio::fs::try_exists(&backup).await.unwrap_or(false) {
+        return Err(ApiError::internal(format!(
+            "No se pudo leer {label}: {error}"
+        )));
+    }
+    match parse_snapshot(&backup).await {
+        Ok(value) => {
+            warn!(
+                "{} esta danado ({error}); se usa la copia {}.",
+                path.display(),
+                backup.display()
+            );
+            let damaged = path.with_extension("json.corrupt");
+            if let Err(error) = tokio::fs::rename(path, &damaged).await {
+                warn!("No se pudo apartar {}: {error}", path.display());
+            }
+            Ok(value)
+        }
+        Err(backup_error) => Err(ApiError::internal(format!(
+            "No se pudo leer {label}: {error}; la copia {} tampoco es valida: {backup_error}",
+            backup.display()
+        ))),
+    }
+}
+
+async fn read_journal<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, ApiError> {
+    let contents = match tokio::fs::read_to_string(path).await {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(ApiError::internal(format!(
+                "No se pudo abrir {}: {error}",
+                path.display()
+            )));
+        }
+    };
+    let mut operations = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(operation) => operations.push(operation),
+            Err(error) => {
+                warn!(
+                    "{} linea {}: operacion incompleta ignorada ({error}).",
+                    path.display(),
+                    index + 1
+                );
+                break;
+            }
+        }
+    }
+    Ok(operations)
+}
+
+pub(crate) async fn write_snapshot<T: Serialize + ?Sized>(
+    path: &Path,
+    value: &T,
+    label: &str,
+) -> Result<(), ApiError> {
+    let payload = crate::lowmem::to_json_vec(value)
+        .map_err(|error| ApiError::internal(format!("No se pudo serializar {label}: {error}")))?;
+    let temp_path = path.with_extension("json.tmp");
+    let result = async {
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        file.write_all(&payload).await?;
+        file.sync_all().await?;
+        drop(file);
+        // Keep the previous snapshot as .bak; a hard link avoids copying the whole file.
+        if tokio::fs::try_exists(path).await? {
+            let backup = backup_path(path);
+            let _ = tokio::fs::remove_file(&backup).await;
+            if tokio::fs::hard_link(path, &backup).await.is_err() {
+                tokio::fs::copy(path, &backup).await?;
+            }
+        }
+        tokio::fs::rename(&temp_path, path).await
+    }
+    .await;
+    result.map_err(|error| ApiError::internal(format!("No se pudo guardar {label}: {error}")))
+}
+
+pub(crate) async fn append_line<T: Serialize>(path: &Path, operation: &T) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(operation).map_err(std::io::Error::other)?;
+    line.push(b'\n');
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(&line).await?;
+    file.sync_data().await
+}
+
+fn apply_history_operation(history: &mut Vec<HistoryEntry>, operation: HistoryOperation) {
+    match operation {
+        HistoryOperation::Push { entry } => {
+            history.retain(|existing| existing.id != entry.id);
+            history.insert(0, entry);
+        }
+        HistoryOperation::Update { entry } => {
+            if let Some(existing) = history.iter_mut().find(|existing| existing.id == entry.id) {
+                *existing = entry;
+            }
+        }
+        HistoryOperation::Remove { ids } => history.retain(|entry| !ids.contains(&entry.id)),
+    }
+}
+
+impl StorageFiles {
+    // Both backends replay a leftover journal, so switching STORAGE_BACKEND keeps every change.
+    async fn load_history(&self, config: &Config) -> Result<Vec<HistoryEntry>, ApiError> {
+        let mut history: Vec<HistoryEntry> =
+            read_snapshot(&self.history, "el historial local").await?;
+        let operations = read_journal(&journal_path(&self.history)).await?;
+        let replayed = operations.len();
+        for operation in operations {
+            apply_history_operation(&mut history, operation);
+        }
+        trim_history_limits(&mut history, config);
+        if replayed > 0 {
+            info!("Historial: {replayed} operaciones del journal aplicadas.");
+            self.compact_history(&history).await?;
+        }
+        Ok(history)
+    }
+
+    async fn load_rate_limits(&self, window_hours: i64) -> Result<RateLimitMap, ApiError> {
+        let mut map: RateLimitMap = read_snapshot(&self.rate_limits, "limites de descarga").await?;
+        let operations: Vec<RateLimitOperation> =
+            read_journal(&journal_path(&self.rate_limits)).await?;
+        let replayed = operations.len();
+        for operation in operations {
+            map.entry(operation.ip).or_default().push(operation.at);
+        }
+
+        let window_start = Utc::now() - chrono::Duration::hours(window_hours);
+        map.retain(|_, timestamps| {
+            timestamps.sort();
+            timestamps.dedup();
+            timestamps.retain(|timestamp| *timestamp > window_start);
+            !timestamps.is_empty()
+        });
+        if replayed > 0 {
+            self.compact_rate_limits(&map).await?;
+        }
+        Ok(map)
+    }
+
+    async fn compact_history(&self, history: &[HistoryEntry]) -> Result<(), ApiError> {
+        write_snapshot(&self.history, history, "el historial").await?;
+        remove_journal(&journal_path(&self.history)).await;
+        Ok(())
+    }
+
+    async fn compact_rate_limits(&self, rate_limits: &RateLimitMap) -> Result<(), ApiError> {
+        write_snapshot(&self.rate_limits, rate_limits, "limites de descarga").await?;
+        remove_journal(&journal_path(&self.rate_limits)).await;
+        Ok(())
+    }
+}
+
+impl Storage for JsonStorage {
+    fn files(&self) -> &StorageFiles {
+        &self.files
+    }
+
+    fn record_history<'a>(
+        &'a self,
+        _operation: HistoryOperation,
+        history: &'a [HistoryEntry],
+    ) -> StorageFuture<'a, ()> {
+        Box::pin(self.files.compact_history(history))
+    }
+
+    fn rate_limit_recorded<'a>(
+        &'a self,
+        _ip: &'a str,
+        _at: DateTime<Utc>,
+        rate_limits: &'a RateLimitMap,
+    ) -> StorageFuture<'a, ()> {
+        Box::pin(self.files.compact_rate_limits(rate_limits))
+    }
+}
+
+impl Storage for JournalStorage {
+    fn files(&self) -> &StorageFiles {
+        &self.files
+    }
+
+    fn record_history<'a>(
+        &'a self,
+        operation: HistoryOperation,
+        history: &'a [HistoryEntry],
+    ) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            append_line(&journal_path(&self.files.history), &operation)
+                .await
+                .map_err(|error| {
+                    ApiError::internal(format!("No se pudo guardar el historial: {error}"))
+                })?;
+            if self.history_operations.fetch_add(1, Ordering::Relaxed) + 1
+                >= JOURNAL_COMPACT_OPERATIONS
+            {
+                self.files.compact_history(history).await?;
+                self.history_operations.store(0, Ordering::Relaxed);
+            }
+            Ok(())
+        })
+    }
+
+    fn rate_limit_recorded<'a>(
+        &'a self,
+        ip: &'a str,
+        at: DateTime<Utc>,
+        rate_limits: &'a RateLimitMap,
+    ) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            let operation = RateLimitOperation {
+                ip: ip.to_string(),
+                at,
+            };
+            append_line(&journal_path(&self.files.rate_limits), &operation)
+                .await
+                .map_err(|error| {
+                    ApiError::internal(format!("No se pudo guardar limites de descarga: {error}"))
+                })?;
+            if self.rate_limit_operations.fetch_add(1, Ordering::Relaxed) + 1
+                >= JOURNAL_COMPACT_OPERATIONS
+            {
+                self.files.compact_rate_limits(rate_limits).await?;
+                self.rate_limit_operations.store(0, Ordering::Relaxed);
+            }
+            Ok(())
+        })
+    }
+}
+
+async fn remove_journal(path: &Path) {
+    if let Err(error) = tokio::fs::remove_file(path).await
+        && error.kind() != ErrorKind::NotFound
+    {
+        warn!("No se pudo vaciar {}: {error}", path.display());
+    }
+}
@@ -0,0 +1,111 @@
+use serde::Deserialize;
+
+use crate::{AUTOMATIC_VIDEO_SELECTOR, ApiError, DownloadMode, DownloadRequest, non_empty};
+
+const MAX_TRACKS_PER_KIND: usize = 4;
+const MULTISTREAM_CONTAINER: &str = "mkv";
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct StreamSelection {
+    #[serde(default)]
+    video: Vec<String>,
+    #[serde(default)]
+    audio: Vec<String>,
+}
+
+fn normalize_track_ids(kind: &str, ids: Vec<String>) -> Result<Vec<String>, ApiError> {
+    let mut normalized: Vec<String> = Vec::new();
+    for id in ids {
+        let Some(id) = non_empty(&id) else {
+            continue;
+        };
+        if !id
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || "-_.".contains(character))
+        {
+            return Err(ApiError::bad_request(format!(
+                "Identificador de pista de {kind} invalido: {id:?}. Usa los format_id de /api/formats."
+            )));
+        }
+        if !normalized.iter().any(|existing| existing == id) {
+            normalized.push(id.to_string());
+        }
+    }
+    if normalized.len() > MAX_TRACKS_PER_KIND {
+        return Err(ApiError::bad_request(format!(
+            "Se admiten como maximo {MAX_TRACKS_PER_KIND} pistas de {kind}."
+        )));
+    }
+    Ok(normalized)
+}
+
+pub(crate) fn apply_stream_selection(payload: &mut DownloadRequest) -> Result<(), ApiError> {
+    let Some(selection) = payload.streams.take() else {
+        return Ok(());
+    };
+    let video = normalize_track_ids("video", selection.video)?;
+    let audio = normalize_track_ids("audio", selection.audio)?;
+    if video.is_empty() && audio.is_empty() {
+        return Ok(());
+    }
+    if !matches!(payload.mode, DownloadMode::Video) {
+        return Err(ApiError::bad_request("streams solo aplica al modo video."));
+    }
+    if payload.playlist {
+        return Err(ApiError::bad_request("streams no se aplica a listas."));
+    }
+    if payload
+        .format_id
+        .as_deref()
+        .and_then(non_empty)
+        .is_some_and(|format_id| format_id != AUTOMATIC_VIDEO_SELECTOR)
+    {
+        return Err(ApiError::bad_request(
+            "Envia format_id o streams, no ambos.",
+        ));
+    }
+    if payload.compatibility {
+        return Err(ApiError::bad_request(
+            "streams no se combina con compatibility.",
+        ));
+    }
+
+    let multistream = video.len() > 1 || audio.len() > 1;
+    let video = if video.is_empty() {
+        vec!["bv*".to_string()]
+    } else {
+        video
+    };
+    let audio = if audio.is_empty() {
+        vec!["ba".to_string()]
+    } else {
+        audio
+    };
+    let selector = video
+        .iter()
+        .chain(&audio)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("+");
+    if payload.format_label.is_none() {
+        payload.format_label = Some(format!("Pistas {selector}"));
+    }
+    payload.format_id = Some(selector);
+    payload.has_audio = Some(true);
+    payload.codec_profile = None;
+    if multistream && payload.container.is_none() {
+        payload.container = Some(MULTISTREAM_CONTAINER.to_string());
+    }
+    Ok(())
+}
+
+pub(crate) fn multistream_args(format_id: Option<&str>) -> Vec<String> {
+    if format_id.is_some_and(|format_id| format_id.split('+').count() > 2) {
+        vec![
+            "--video-multistreams".to_string(),
+            "--audio-multistreams".to_string(),
+        ]
+    } else {
+        Vec::new()
+    }
+}
@@ -0,0 +1,229 @@
+use std::{any::Any, future::Future, sync::Arc};
+
+use axum::{Json, extract::State};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::{
+    sync::{Mutex, watch},
+    task::JoinHandle,
+    time::{Duration, timeout},
+};
+use tracing::{error, info, warn};
+
+use crate::{ApiError, AppState};
+
+const MAX_TASK_RESTARTS: u32 = 10;
+const MAX_RESTART_BACKOFF_SECONDS: u64 = 60;
+const SHUTDOWN_GRACE_SECONDS: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RestartPolicy {
+    OnPanic,
+    Always,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum TaskState {
+    Running,
+    Restarting,
+    Completed,
+    Failed,
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TaskStatus {
+    name: &'static str,
+    policy: RestartPolicy,
+    state: TaskState,
+    restarts: u32,
+    started_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_exit_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_panic: Option<String>,
+}
+
+struct SupervisedTask {
+    status: Arc<std::sync::Mutex<TaskStatus>>,
+    stop: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub(crate) struct TaskSupervisor {
+    tasks: Mutex<Vec<SupervisedTask>>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TaskReport {
+    healthy: bool,
+    tasks: Vec<TaskStatus>,
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panic sin mensaje".to_string())
+}
+
+fn update(status: &std::sync::Mutex<TaskStatus>, apply: impl FnOnce(&mut TaskStatus)) {
+    if let Ok(mut status) = status.lock() {
+        apply(&mut status);
+    }
+}
+
+async fn stop_requested(stop: &mut watch::Receiver<bool>) {
+    let _ = stop.wait_for(|stopped| *stopped).await;
+}
+
+async fn supervise<F, Fut>(
+    status: Arc<std::sync::Mutex<TaskStatus>>,
+    mut stop: watch::Receiver<bool>,
+    policy: RestartPolicy,
+    factory: F,
+) where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let name = status.lock().map(|status| status.name).unwrap_or("tarea");
+    let mut restarts = 0;
+    loop {
+        update(&status, |status| {
+            status.state = TaskState::Running;
+            status.started_at = Utc::now();
+        });
+        let mut run = tokio::spawn(factory());
+        let outcome = tokio::select! {
+            outcome = &mut run => outcome,
+            () = stop_requested(&mut stop) => {
+                run.abort();
+                let _ = run.await;
+                update(&status, |status| status.state = TaskState::Stopped);
+                return;
+            }
+        };
+
+        let panicked = match outcome {
+            Ok(()) => None,
+            Err(error) if error.is_panic() => Some(panic_message(error.into_panic())),
+            Err(_) => {
+                update(&status, |status| status.state = TaskState::Stopped);
+                return;
+            }
+        };
+        if let Some(message) = &panicked {
+            error!("La tarea {name} fallo con panic: {message}");
+        }
+        let restart = match policy {
+            RestartPolicy::OnPanic => panicked.is_some(),
+            RestartPolicy::Always => true,
+        };
+        let exhausted = restart && restarts >= MAX_TASK_RESTARTS;
+        update(&status, |status| {
+            status.last_exit_at = Some(Utc::now());
+            if panicked.is_some() {
+                status.last_panic = panicked.clone();
+            }
+            status.state = match (restart && !exhausted, panicked.is_some() || exhausted) {
+                (true, _) => TaskState::Restarting,
+                (false, true) => TaskState::Failed,
+                (false, false) => TaskState::Completed,
+            };
+        });
+        if !restart {
+            return;
+        }
+        if exhausted {
+            error!("La tarea {name} supero {MAX_TASK_RESTARTS} reinicios; se deja detenida.");
+            return;
+        }
+
+        restarts += 1;
+        update(&status, |status| status.restarts = restarts);
+        let backoff =
+            Duration::from_secs((1_u64 << restarts.min(6)).min(MAX_RESTART_BACKOFF_SECONDS));
+        warn!("Reiniciando la tarea {name} en {backoff:?} (reinicio {restarts}).");
+        tokio::select! {
+            () = tokio::time::sleep(backoff) => {}
+            () = stop_requested(&mut stop) => {
+                update(&status, |status| status.state = TaskState::Stopped);
+                return;
+            }
+        }
+    }
+}
+
+impl TaskSupervisor {
+    pub(crate) async fn spawn<F, Fut>(&self, name: &'static str, policy: RestartPolicy, factory: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let status = Arc::new(std::sync::Mutex::new(TaskStatus {
+            name,
+            policy,
+            state: TaskState::Running,
+            restarts: 0,
+            started_at: Utc::now(),
+            last_exit_at: None,
+            last_panic: None,
+        }));
+        let (stop, stop_receiver) = watch::channel(false);
+        let handle = tokio::spawn(supervise(
+            Arc::clone(&status),
+            stop_receiver,
+            policy,
+            factory,
+        ));
+        self.tasks.lock().await.push(SupervisedTask {
+            status,
+            stop,
+            handle,
+        });
+    }
+
+    pub(crate) async fn shutdown(&self) {
+        let tasks = std::mem::take(&mut *self.tasks.lock().await);
+        for task in tasks.into_iter().rev() {
+            let name = task
+                .status
+                .lock()
+                .map(|status| status.name)
+                .unwrap_or("tarea");
+            let _ = task.stop.send(true);
+            if timeout(Duration::from_secs(SHUTDOWN_GRACE_SECONDS), task.handle)
+                .await
+                .is_err()
+            {
+                warn!("La tarea {name} no se detuvo en {SHUTDOWN_GRACE_SECONDS} s.");
+            } else {
+                info!("Tarea {name} detenida.");
+            }
+        }
+    }
+
+    async fn report(&self) -> TaskReport {
+        let tasks = self
+            .tasks
+            .lock()
+            .await
+            .iter()
+            .filter_map(|task| task.status.lock().ok().map(|status| status.clone()))
+            .collect::<Vec<_>>();
+        TaskReport {
+            healthy: tasks.iter().all(|task| task.state != TaskState::Failed),
+            tasks,
+        }
+    }
+}
+
+pub(crate) async fn get_task_report(
+    State(state): State<AppState>,
+) -> Result<Json<TaskReport>, ApiError> {
+    Ok(Json(state.supervisor.report().await))
+}
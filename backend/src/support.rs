@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    ApiError, AppState, DownloadStatus, build_attachment_headers,
+    config::Config,
+    extractor::ExtractorReport,
+    health::{ReadinessReport, readiness_report},
+    system::SystemReport,
+    url_domain,
+};
+
+const MAX_BUNDLED_FAILURES: usize = 50;
+const REDACTED: &str = "[redactado]";
+const SENSITIVE_MARKERS: [&str; 8] = [
+    "SECRET",
+    "TOKEN",
+    "PASSWORD",
+    "KEY",
+    "COOKIE",
+    "CREDENTIAL",
+    "SALT",
+    "DSN",
+];
+// Only the backend's own settings end up in the bundle, never the rest of the host environment.
+const CONFIG_PREFIXES: [&str; 62] = [
+    "ADMIN_",
+    "ALLOWED_",
+    "ANON_SESSION",
+    "API_",
+    "APP_",
+    "ARIA2C_",
+    "ARTIFACTS_",
+    "AUTH_",
+    "AUTO_",
+    "BILLING_",
+    "CHILD_",
+    "CLIENT_",
+    "CODEC_",
+    "CONFIG_",
+    "DATA_",
+    "DEMO_",
+    "DNS_",
+    "DOWNLOAD_",
+    "EMAIL_",
+    "EMBED_",
+    "ESCALATION_",
+    "EXTRA_",
+    "FFMPEG_",
+    "FORMATS_",
+    "HISTORY_",
+    "IMPERSONATE_",
+    "IP_",
+    "LEAK_",
+    "LOW_",
+    "MAX_",
+    "MEMORY_",
+    "METADATA_",
+    "NODE_",
+    "OIDC_",
+    "PLAYLIST_",
+    "POLICY_",
+    "PREMIUM_",
+    "PUBLIC_",
+    "QUOTA_",
+    "RECEIPT_",
+    "REDIS_",
+    "REQUEST_",
+    "ROLE_",
+    "SHADOW_",
+    "SIGNED_",
+    "SIGNING_",
+    "SLOW_",
+    "SMTP_",
+    "SNAPSHOT_",
+    "SOURCE_",
+    "SPONSORBLOCK_",
+    "STORAGE_",
+    "STRIPE_",
+    "TELEMETRY_",
+    "TRANSFER_",
+    "TRUST_",
+    "TURNSTILE_",
+    "USAGE_",
+    "VERIFIED_",
+    "WORKER_",
+    "YT_DLP_",
+    "XDG_",
+];
+const CONFIG_VARIABLES: [&str; 2] = ["PORT", "RUST_LOG"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum FailureClass {
+    UnsupportedUrl,
+    Unavailable,
+    Blocked,
+    Timeout,
+    TooLarge,
+    Interrupted,
+    Postprocess,
+    Other,
+}
+
+#[derive(Debug, Serialize)]
+struct BundledFailure {
+    created_at: DateTime<Utc>,
+    domain: String,
+    class: FailureClass,
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BuildInfo {
+    version: &'static str,
+    os: &'static str,
+    arch: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct SupportBundle {
+    bundle_id: Uuid,
+    generated_at: DateTime<Utc>,
+    build: BuildInfo,
+    config: Config,
+    environment: BTreeMap<String, String>,
+    readiness: ReadinessReport,
+    extractor: ExtractorReport,
+    system: SystemReport,
+    failure_classes: BTreeMap<FailureClass, usize>,
+    recent_failures: Vec<BundledFailure>,
+}
+
+fn is_sensitive(name: &str) -> bool {
+    SENSITIVE_MARKERS.iter().any(|marker| name.contains(marker))
+}
+
+// URLs may carry user:password or signed query strings; keep only scheme and host.
+fn redact_value(name: &str, value: &str) -> String {
+    if is_sensitive(name) {
+        return REDACTED.to_string();
+    }
+    value
+        .split(',')
+        .map(|part| match url::Url::parse(part.trim()) {
+            Ok(parsed)
+                if parsed.has_host()
+                    && (!parsed.username().is_empty()
+                        || parsed.password().is_some()
+                        || parsed.query().is_some()) =>
+            {
+                format!(
+                    "{}://{}/{REDACTED}",
+                    parsed.scheme(),
+                    parsed.host_str().unwrap_or_default()
+                )
+            }
+            _ => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn redacted_environment() -> BTreeMap<String, String> {
+    std::env::vars()
+        .filter(|(name, _)| {
+            CONFIG_VARIABLES.contains(&name.as_str())
+                || CONFIG_PREFIXES
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+        })
+        .map(|(name, value)| {
+            let value = redact_value(&name, &value);
+            (name, value)
+        })
+        .collect()
+}
+
+fn redact_urls(message: &str) -> String {
+    message
+        .split(' ')
+        .map(|word| {
+            if word.starts_with("http://") || word.starts_with("https://") {
+                format!("<{}>", url_domain(word))
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn classify_failure(message: &str) -> FailureClass {
+    let lower = message.to_lowercase();
+    let has = |needles: &[&str]| needles.iter().any(|needle| lower.contains(needle));
+    if has(&["no soportada", "unsupported url"]) {
+        FailureClass::UnsupportedUrl
+    } else if has(&["interrump", "cerro la conexion"]) {
+        FailureClass::Interrupted
+    } else if has(&["tiempo limite", "tardo demasiado", "timed out", "timeout"]) {
+        FailureClass::Timeout
+    } else if has(&[
+        "tamano maximo",
+        "supera el limite",
+        "demasiado grande",
+        "too large",
+    ]) {
+        FailureClass::TooLarge
+    } else if has(&[
+        "sign in",
+        "confirm you",
+        "bot",
+        "http error 403",
+        "http error 429",
+        "rate-limit",
+    ]) {
+        FailureClass::Blocked
+    } else if has(&[
+        "unavailable",
+        "private",
+        "removed",
+        "no disponible",
+        "http error 404",
+        "geo",
+    ]) {
+        FailureClass::Unavailable
+    } else if has(&["ffmpeg", "convers", "transcod"]) {
+        FailureClass::Postprocess
+    } else {
+        FailureClass::Other
+    }
+}
+
+async fn recent_failures(state: &AppState) -> Vec<BundledFailure> {
+    state
+        .history
+        .lock()
+        .await
+        .iter()
+        .filter(|entry| matches!(entry.status, DownloadStatus::Failed))
+        .take(MAX_BUNDLED_FAILURES)
+        .map(|entry| {
+            let error = entry.error.clone().unwrap_or_default();
+            BundledFailure {
+                created_at: entry.created_at,
+                domain: url_domain(&entry.url),
+                class: classify_failure(&error),
+                error: redact_urls(&error),
+                request_id: entry.request_id.clone(),
+            }
+        })
+        .collect()
+}
+
+pub(crate) async fn get_support_bundle(
+    State(state): State<AppState>,
+) -> Result<Response, ApiError> {
+    let monitor = state.system.clone();
+    let system = tokio::task::spawn_blocking(move || monitor.report())
+        .await
+        .map_err(|error| {
+            ApiError::internal(format!("No se pudo leer el estado del sistema: {error}"))
+        })?;
+    let readiness = readiness_report(&state).await;
+    let recent_failures = recent_failures(&state).await;
+    let failure_classes = recent_failures
+        .iter()
+        .fold(BTreeMap::new(), |mut classes, failure| {
+            *classes.entry(failure.class).or_default() += 1;
+            classes
+        });
+    let generated_at = Utc::now();
+
+    let bundle = SupportBundle {
+        bundle_id: Uuid::new_v4(),
+        generated_at,
+        build: BuildInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+        },
+        config: state.config.as_ref().clone(),
+        environment: redacted_environment(),
+        readiness,
+        extractor: state.extractor.report().await,
+        system,
+        failure_classes,
+        recent_failures,
+    };
+    let body = serde_json::to_vec_pretty(&bundle).map_err(|error| {
+        ApiError::internal(format!("No se pudo generar el paquete de soporte: {error}"))
+    })?;
+    let headers = build_attachment_headers(
+        &format!(
+            "total-downloader-soporte-{}.json",
+            generated_at.format("%Y%m%d-%H%M%S")
+        ),
+        "application/json",
+        body.len() as u64,
+    )?;
+    Ok((headers, body).into_response())
+}
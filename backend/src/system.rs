@@ -0,0 +1,277 @@
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use axum::{Json, extract::State};
+use serde::Serialize;
+
+use crate::{ApiError, AppState, memory};
+
+#[derive(Debug)]
+pub(crate) struct SystemMonitor {
+    started_at: Instant,
+    directories: Vec<(&'static str, PathBuf)>,
+}
+
+#[derive(Debug, Serialize)]
+struct CpuReport {
+    cores: usize,
+    load_1m: Option<f64>,
+    load_5m: Option<f64>,
+    load_15m: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+struct MemoryUsage {
+    total_bytes: Option<u64>,
+    available_bytes: Option<u64>,
+    process_rss_bytes: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ProcessCounts {
+    yt_dlp: usize,
+    ffmpeg: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct DirectoryUsage {
+    name: &'static str,
+    path: String,
+    used_bytes: u64,
+    files: u64,
+    filesystem_total_bytes: Option<u64>,
+    filesystem_available_bytes: Option<u64>,
+}
+
+// Ratios in 0..1 (CPU is 1-minute load per core, so it can exceed 1).
+#[derive(Debug, Clone, Copy, Serialize)]
+pub(crate) struct PressureSample {
+    pub(crate) cpu_load_per_core: Option<f64>,
+    pub(crate) memory_available_ratio: Option<f64>,
+    pub(crate) disk_available_ratio: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SystemReport {
+    uptime_seconds: u64,
+    system_uptime_seconds: Option<u64>,
+    cpu: CpuReport,
+    memory: MemoryUsage,
+    processes: ProcessCounts,
+    directories: Vec<DirectoryUsage>,
+}
+
+impl SystemMonitor {
+    pub(crate) fn new(directories: Vec<(&'static str, PathBuf)>) -> Self {
+        Self {
+            started_at: Instant::now(),
+            directories,
+        }
+    }
+
+    pub(crate) fn directories(&self) -> &[(&'static str, PathBuf)] {
+        &self.directories
+    }
+
+    pub(crate) fn directory_report(&self, name: &str) -> Option<DirectoryUsage> {
+        self.directories
+            .iter()
+            .find(|(candidate, _)| *candidate == name)
+            .map(|(name, path)| directory_usage(name, path))
+    }
+
+    pub(crate) fn pressure(&self, directory: &str) -> PressureSample {
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+        let meminfo = read_meminfo();
+        let ratio =
+            |available: u64, total: u64| (total > 0).then(|| available as f64 / total as f64);
+        PressureSample {
+            cpu_load_per_core: std::fs::read_to_string("/proc/loadavg")
+                .ok()
+                .and_then(|content| content.split_whitespace().next()?.parse::<f64>().ok())
+                .map(|load| load / cores as f64),
+            memory_available_ratio: meminfo
+                .get("MemAvailable")
+                .zip(meminfo.get("MemTotal"))
+                .and_then(|(available, total)| ratio(*available, *total)),
+            disk_available_ratio: self
+                .directories
+                .iter()
+                .find(|(name, _)| *name == directory)
+                .and_then(|(_, path)| filesystem_space(path))
+                .and_then(|(total, available)| ratio(available, total)),
+        }
+    }
+
+    pub(crate) fn report(&self) -> SystemReport {
+        let loads = std::fs::read_to_string("/proc/loadavg")
+            .map(|content| {
+                content
+                    .split_whitespace()
+                    .take(3)
+                    .map(|value| value.parse::<f64>().ok())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let meminfo = read_meminfo();
+        SystemReport {
+            uptime_seconds: self.started_at.elapsed().as_secs(),
+            system_uptime_seconds: std::fs::read_to_string("/proc/uptime")
+                .ok()
+                .and_then(|content| content.split_whitespace().next()?.parse::<f64>().ok())
+                .map(|seconds| seconds as u64),
+            cpu: CpuReport {
+                cores: std::thread::available_parallelism().map_or(1, |cores| cores.get()),
+                load_1m: loads.first().copied().flatten(),
+                load_5m: loads.get(1).copied().flatten(),
+                load_15m: loads.get(2).copied().flatten(),
+            },
+            memory: MemoryUsage {
+                total_bytes: meminfo.get("MemTotal").copied(),
+                available_bytes: meminfo.get("MemAvailable").copied(),
+                process_rss_bytes: memory::resident_set_bytes(),
+            },
+            processes: count_child_processes(),
+            directories: self
+                .directories
+                .iter()
+                .map(|(name, path)| directory_usage(name, path))
+                .collect(),
+        }
+    }
+}
+
+fn read_meminfo() -> HashMap<String, u64> {
+    std::fs::read_to_string("/proc/meminfo")
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|line| {
+                    let (key, value) = line.split_once(':')?;
+                    let kilobytes = value.split_whitespace().next()?.parse::<u64>().ok()?;
+                    Some((key.to_string(), kilobytes * 1024))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn program_kind(pid: u32) -> Option<&'static str> {
+    let cmdline = std::fs::read(format!("/proc/{pid}/cmdline")).ok()?;
+    cmdline
+        .split(|byte| *byte == 0)
+        .take(2)
+        .filter_map(|arg| {
+            let arg = String::from_utf8_lossy(arg);
+            let name = arg.rsplit('/').next()?.to_ascii_lowercase();
+            if name.contains("yt-dlp") || name.contains("yt_dlp") {
+                Some("yt-dlp")
+            } else if name.contains("ffmpeg") || name.contains("ffprobe") {
+                Some("ffmpeg")
+            } else {
+                None
+            }
+        })
+        .next()
+}
+
+fn count_child_processes() -> ProcessCounts {
+    let own_pid = std::process::id();
+    let parents = std::fs::read_dir("/proc")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+                .filter_map(|pid| parent_pid(pid).map(|parent| (pid, parent)))
+                .collect::<HashMap<_, _>>()
+        })
+        .unwrap_or_default();
+    let descends_from_backend = |mut pid: u32| {
+        for _ in 0..32 {
+            match parents.get(&pid) {
+                Some(parent) if *parent == own_pid => return true,
+                Some(parent) if *parent > 1 => pid = *parent,
+                _ => return false,
+            }
+        }
+        false
+    };
+
+    let mut counts = ProcessCounts::default();
+    for pid in parents
+        .keys()
+        .copied()
+        .filter(|pid| descends_from_backend(*pid))
+    {
+        match program_kind(pid) {
+            Some("yt-dlp") => counts.yt_dlp += 1,
+            Some("ffmpeg") => counts.ffmpeg += 1,
+            _ => {}
+        }
+    }
+    counts
+}
+
+fn filesystem_space(path: &Path) -> Option<(u64, u64)> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs only fills the zeroed struct we pass for a valid C string.
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    let block = stats.f_frsize as u64;
+    Some((stats.f_blocks as u64 * block, stats.f_bavail as u64 * block))
+}
+
+fn directory_usage(name: &'static str, path: &Path) -> DirectoryUsage {
+    let mut used_bytes = 0;
+    let mut files = 0;
+    let mut pending = vec![path.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                used_bytes += metadata.len();
+                files += 1;
+            }
+        }
+    }
+    let space = filesystem_space(path);
+    DirectoryUsage {
+        name,
+        path: path.to_string_lossy().into_owned(),
+        used_bytes,
+        files,
+        filesystem_total_bytes: space.map(|(total, _)| total),
+        filesystem_available_bytes: space.map(|(_, available)| available),
+    }
+}
+
+pub(crate) async fn get_system_report(
+    State(state): State<AppState>,
+) -> Result<Json<SystemReport>, ApiError> {
+    let monitor = state.system.clone();
+    tokio::task::spawn_blocking(move || monitor.report())
+        .await
+        .map(Json)
+        .map_err(|error| {
+            ApiError::internal(format!("No se pudo leer el estado del sistema: {error}"))
+        })
+}
@@ -0,0 +1,181 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{Json, extract::State};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::{
+    sync::Mutex,
+    time::{Duration, MissedTickBehavior},
+};
+use tracing::{debug, info, warn};
+
+use crate::{ApiError, AppState, non_empty, read_bool_env, read_usize_env, throughput};
+
+const DEFAULT_TELEMETRY_INTERVAL_MINUTES: usize = 60;
+const TELEMETRY_TIMEOUT_SECONDS: u64 = 10;
+
+#[derive(Debug)]
+pub(crate) struct Telemetry {
+    endpoint: String,
+    interval: Duration,
+    client: reqwest::Client,
+    counters: Mutex<TelemetryCounters>,
+}
+
+#[derive(Debug)]
+struct TelemetryCounters {
+    since: DateTime<Utc>,
+    platforms: BTreeMap<String, PlatformOutcomes>,
+    error_codes: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+struct PlatformOutcomes {
+    succeeded: u64,
+    failed: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TelemetryReport {
+    version: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    period_start: DateTime<Utc>,
+    period_end: DateTime<Utc>,
+    platforms: BTreeMap<String, PlatformOutcomes>,
+    error_codes: BTreeMap<String, u64>,
+}
+
+impl TelemetryCounters {
+    fn new() -> Self {
+        Self {
+            since: Utc::now(),
+            platforms: BTreeMap::new(),
+            error_codes: BTreeMap::new(),
+        }
+    }
+
+    fn report(&self) -> TelemetryReport {
+        TelemetryReport {
+            version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            period_start: self.since,
+            period_end: Utc::now(),
+            platforms: self.platforms.clone(),
+            error_codes: self.error_codes.clone(),
+        }
+    }
+}
+
+impl Telemetry {
+    pub(crate) fn from_env(client: reqwest::Client) -> Option<Self> {
+        if !read_bool_env("TELEMETRY_ENABLED").unwrap_or(false) {
+            return None;
+        }
+        let Some(endpoint) = std::env::var("TELEMETRY_ENDPOINT")
+            .ok()
+            .and_then(|value| non_empty(&value).map(ToString::to_string))
+        else {
+            warn!(
+                "TELEMETRY_ENABLED=true sin TELEMETRY_ENDPOINT. La telemetria queda desactivada."
+            );
+            return None;
+        };
+        let minutes = read_usize_env("TELEMETRY_INTERVAL_MINUTES")
+            .filter(|minutes| *minutes > 0)
+            .unwrap_or(DEFAULT_TELEMETRY_INTERVAL_MINUTES);
+        info!("Telemetria anonima habilitada hacia {endpoint} cada {minutes} min.");
+
+        Some(Self {
+            endpoint,
+            interval: Duration::from_secs(minutes as u64 * 60),
+            client,
+            counters: Mutex::new(TelemetryCounters::new()),
+        })
+    }
+
+    pub(crate) async fn record(&self, url: &str, result: Result<(), &ApiError>) {
+        let mut counters = self.counters.lock().await;
+        let outcomes = counters
+            .platforms
+            .entry(throughput::platform_key(url))
+            .or_default();
+        match result {
+            Ok(()) => outcomes.succeeded += 1,
+            Err(error) => {
+                outcomes.failed += 1;
+                let code = error.code.map_or_else(
+                    || format!("HTTP_{}", error.status.as_u16()),
+                    ToString::to_string,
+                );
+                *counters.error_codes.entry(code).or_default() += 1;
+            }
+        }
+    }
+
+    pub(crate) async fn run_reporter(self: Arc<Self>) {
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            self.flush().await;
+        }
+    }
+
+    async fn flush(&self) {
+        let report = {
+            let counters = self.counters.lock().await;
+            if counters.platforms.is_empty() {
+                return;
+            }
+            counters.report()
+        };
+
+        let sent = self
+            .client
+            .post(&self.endpoint)
+            .timeout(Duration::from_secs(TELEMETRY_TIMEOUT_SECONDS))
+            .json(&report)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match sent {
+            Ok(_) => {
+                let mut counters = self.counters.lock().await;
+                for (platform, sent) in &report.platforms {
+                    if let Some(outcomes) = counters.platforms.get_mut(platform) {
+                        outcomes.succeeded = outcomes.succeeded.saturating_sub(sent.succeeded);
+                        outcomes.failed = outcomes.failed.saturating_sub(sent.failed);
+                    }
+                }
+                counters
+                    .platforms
+                    .retain(|_, outcomes| outcomes.succeeded + outcomes.failed > 0);
+                for (code, sent) in &report.error_codes {
+                    if let Some(count) = counters.error_codes.get_mut(code) {
+                        *count = count.saturating_sub(*sent);
+                    }
+                }
+                counters.error_codes.retain(|_, count| *count > 0);
+                counters.since = report.period_end;
+                debug!("Telemetria enviada a {}", self.endpoint);
+            }
+            Err(error) => {
+                warn!("No se pudo enviar la telemetria: {error}");
+            }
+        }
+    }
+}
+
+pub(crate) async fn get_telemetry_preview(
+    State(state): State<AppState>,
+) -> Result<Json<TelemetryReport>, ApiError> {
+    let telemetry = state
+        .telemetry
+        .as_deref()
+        .ok_or_else(|| ApiError::not_found("La telemetria no esta habilitada."))?;
+    let report = telemetry.counters.lock().await.report();
+    Ok(Json(report))
+}
@@ -0,0 +1,221 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    io::ErrorKind,
+    path::PathBuf,
+};
+
+use axum::{Json, extract::State};
+use chrono::{DateTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::Mutex, time::Duration};
+use tracing::warn;
+use url::Url;
+
+use crate::{ApiError, AppState};
+
+const THROUGHPUT_SMOOTHING: f64 = 0.3;
+const MIN_SAMPLE_SECONDS: f64 = 0.5;
+const MIN_HOUR_BUCKET_SAMPLES: u64 = 3;
+const ADAPTIVE_TIMEOUT_FACTOR: f64 = 3.0;
+const MAX_ADAPTIVE_TIMEOUT_SECONDS: u64 = 30 * 60;
+const GLOBAL_KEY: &str = "*";
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct ThroughputBucket {
+    bytes_per_second: f64,
+    samples: u64,
+    total_bytes: u64,
+    total_seconds: f64,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PlatformModel {
+    overall: ThroughputBucket,
+    by_hour: BTreeMap<u32, ThroughputBucket>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ThroughputStats {
+    path: PathBuf,
+    platforms: Mutex<HashMap<String, PlatformModel>>,
+}
+
+#[derive(Debug, Serialize)]
+struct PlatformReport {
+    platform: String,
+    #[serde(flatten)]
+    model: PlatformModel,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ThroughputReport {
+    current_hour_utc: u32,
+    min_hour_bucket_samples: u64,
+    platforms: Vec<PlatformReport>,
+}
+
+pub(crate) fn platform_key(url: &str) -> String {
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|parsed| parsed.host_str().map(|host| host.to_ascii_lowercase()))
+        .unwrap_or_default();
+    let matches = |domain: &str| host == domain || host.ends_with(&format!(".{domain}"));
+
+    let platform = if matches("youtube.com") || matches("youtu.be") {
+        "youtube"
+    } else if matches("x.com") || matches("twitter.com") {
+        "x"
+    } else if matches("facebook.com") || matches("fb.watch") {
+        "facebook"
+    } else if matches("instagram.com") {
+        "instagram"
+    } else if matches("tiktok.com") {
+        "tiktok"
+    } else if matches("bsky.app") {
+        "bluesky"
+    } else {
+        return host.trim_start_matches("www.").to_string();
+    };
+    platform.to_string()
+}
+
+impl ThroughputBucket {
+    fn observe(&mut self, bytes: u64, seconds: f64, now: DateTime<Utc>) {
+        let bytes_per_second = bytes as f64 / seconds;
+        self.bytes_per_second = if self.samples == 0 {
+            bytes_per_second
+        } else {
+            self.bytes_per_second * (1.0 - THROUGHPUT_SMOOTHING)
+                + bytes_per_second * THROUGHPUT_SMOOTHING
+        };
+        self.samples += 1;
+        self.total_bytes = self.total_bytes.saturating_add(bytes);
+        self.total_seconds += seconds;
+        self.updated_at = Some(now);
+    }
+}
+
+impl PlatformModel {
+    fn observe(&mut self, bytes: u64, seconds: f64, now: DateTime<Utc>) {
+        self.overall.observe(bytes, seconds, now);
+        self.by_hour
+            .entry(now.hour())
+            .or_default()
+            .observe(bytes, seconds, now);
+    }
+
+    fn rate_at(&self, hour: u32) -> Option<f64> {
+        self.by_hour
+            .get(&hour)
+            .filter(|bucket| bucket.samples >= MIN_HOUR_BUCKET_SAMPLES)
+            .or_else(|| (self.overall.samples > 0).then_some(&self.overall))
+            .map(|bucket| bucket.bytes_per_second)
+            .filter(|rate| *rate > 0.0)
+    }
+}
+
+impl ThroughputStats {
+    pub(crate) async fn load(path: PathBuf) -> Result<Self, ApiError> {
+        let platforms = match tokio::fs::read_to_string(&path).await {
+            Ok(content) if content.trim().is_empty() => HashMap::new(),
+            Ok(content) => serde_json::from_str(&content).map_err(|error| {
+                ApiError::internal(format!("Estadisticas de rendimiento invalidas: {error}"))
+            })?,
+            Err(error) if error.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(error) => {
+                return Err(ApiError::internal(format!(
+                    "No se pudieron leer las estadisticas de rendimiento: {error}"
+                )));
+            }
+        };
+
+        Ok(Self {
+            path,
+            platforms: Mutex::new(platforms),
+        })
+    }
+
+    pub(crate) async fn record(&self, url: &str, bytes: u64, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if bytes == 0 || seconds < MIN_SAMPLE_SECONDS {
+            return;
+        }
+
+        let now = Utc::now();
+        let content = {
+            let mut platforms = self.platforms.lock().await;
+            platforms
+                .entry(platform_key(url))
+                .or_default()
+                .observe(bytes, seconds, now);
+            platforms
+                .entry(GLOBAL_KEY.to_string())
+                .or_default()
+                .observe(bytes, seconds, now);
+            crate::lowmem::to_json_vec(&*platforms)
+        };
+
+        let result = match content {
+            Ok(content) => tokio::fs::write(&self.path, content)
+                .await
+                .map_err(|error| error.to_string()),
+            Err(error) => Err(error.to_string()),
+        };
+        if let Err(error) = result {
+            warn!("No se pudieron guardar las estadisticas de rendimiento: {error}");
+        }
+    }
+
+    pub(crate) async fn estimator(&self, url: &str) -> impl Fn(Option<f64>) -> Option<u64> {
+        let hour = Utc::now().hour();
+        let platforms = self.platforms.lock().await;
+        let rate = platforms
+            .get(&platform_key(url))
+            .and_then(|model| model.rate_at(hour))
+            .or_else(|| {
+                platforms
+                    .get(GLOBAL_KEY)
+                    .and_then(|model| model.rate_at(hour))
+            });
+
+        move |size_bytes| {
+            let rate = rate?;
+            size_bytes
+                .filter(|size| *size > 0.0)
+                .map(|size| ((size / rate).ceil() as u64).max(1))
+        }
+    }
+
+    pub(crate) async fn download_timeout(
+        &self,
+        url: &str,
+        size_bytes: Option<f64>,
+        base: Duration,
+    ) -> Duration {
+        let estimate = self.estimator(url).await;
+        let Some(estimated_seconds) = estimate(size_bytes) else {
+            return base;
+        };
+        let adaptive = (estimated_seconds as f64 * ADAPTIVE_TIMEOUT_FACTOR).ceil() as u64;
+        Duration::from_secs(adaptive.min(MAX_ADAPTIVE_TIMEOUT_SECONDS)).max(base)
+    }
+}
+
+pub(crate) async fn get_throughput_report(
+    State(state): State<AppState>,
+) -> Result<Json<ThroughputReport>, ApiError> {
+    let platforms = state.throughput.platforms.lock().await.clone();
+    let mut platforms = platforms
+        .into_iter()
+        .map(|(platform, model)| PlatformReport { platform, model })
+        .collect::<Vec<_>>();
+    platforms.sort_by_key(|report| Reverse(report.model.overall.samples));
+
+    Ok(Json(ThroughputReport {
+        current_hour_utc: Utc::now().hour(),
+        min_hour_bucket_samples: MIN_HOUR_BUCKET_SAMPLES,
+        platforms,
+    }))
+}
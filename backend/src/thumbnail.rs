@@ -0,0 +1,159 @@
+use std::net::SocketAddr;
+
+use axum::{
+    Json,
+    body::Body,
+    extract::{ConnectInfo, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use tokio::time::{Duration, timeout};
+use uuid::Uuid;
+
+use crate::extractor::RequestClass;
+use crate::{
+    ApiError, AppState, JobDir, METADATA_QUEUE_WAIT_MS, METADATA_RETRY_AFTER_SECONDS, bans,
+    client_ip_for_request, evaluate_lookup_policy, is_supported_download_url, sniff,
+};
+
+const THUMBNAIL_FORMATS: [&str; 3] = ["jpg", "webp", "png"];
+const THUMBNAIL_STEM: &str = "thumbnail";
+const MAX_THUMBNAIL_BYTES: u64 = 10 * 1024 * 1024;
+const THUMBNAIL_CACHE_SECONDS: u64 = 60 * 60;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ThumbnailRequest {
+    url: String,
+    format: Option<String>,
+}
+
+pub(crate) async fn fetch_thumbnail(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<ThumbnailRequest>,
+) -> Result<Response, ApiError> {
+    thumbnail_response(
+        &state,
+        &client_ip_for_request(&state, &headers, addr),
+        payload,
+    )
+    .await
+}
+
+pub(crate) async fn fetch_thumbnail_by_query(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(payload): Query<ThumbnailRequest>,
+) -> Result<Response, ApiError> {
+    thumbnail_response(
+        &state,
+        &client_ip_for_request(&state, &headers, addr),
+        payload,
+    )
+    .await
+}
+
+async fn thumbnail_response(
+    state: &AppState,
+    client_ip: &str,
+    payload: ThumbnailRequest,
+) -> Result<Response, ApiError> {
+    bans::ensure_not_banned(state, client_ip).await?;
+    let url = payload.url.trim();
+    if url.is_empty() {
+        return Err(ApiError::bad_request("Ingresa una URL valida."));
+    }
+    if !is_supported_download_url(url, &state.extra_supported_domains) {
+        return Err(ApiError::bad_request(
+            "URL no soportada. Usa una URL de X, Facebook, TikTok, YouTube, Instagram o Bluesky.",
+        ));
+    }
+    let format = payload
+        .format
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "jpg".to_string());
+    if !THUMBNAIL_FORMATS.contains(&format.as_str()) {
+        return Err(ApiError::bad_request(format!(
+            "Formato de miniatura no soportado: {format}. Usa {}.",
+            THUMBNAIL_FORMATS.join(", ")
+        )));
+    }
+    evaluate_lookup_policy(state, client_ip, "thumbnail", url).await?;
+
+    let _metadata_permit = timeout(
+        Duration::from_millis(METADATA_QUEUE_WAIT_MS),
+        state.metadata_semaphore.clone().acquire_owned(),
+    )
+    .await
+    .map_err(|_| ApiError::metadata_saturated(METADATA_RETRY_AFTER_SECONDS))?
+    .map_err(|_| ApiError::internal("No se pudo reservar capacidad para metadatos."))?;
+
+    let work_dir = JobDir::new(state.transfer_dir.join(Uuid::new_v4().to_string()));
+    tokio::fs::create_dir_all(work_dir.path())
+        .await
+        .map_err(|error| {
+            ApiError::internal(format!("No se pudo preparar la carpeta temporal: {error}"))
+        })?;
+    let args = vec![
+        "--skip-download".to_string(),
+        "--no-playlist".to_string(),
+        "--no-warnings".to_string(),
+        "--write-thumbnail".to_string(),
+        "--convert-thumbnails".to_string(),
+        format.clone(),
+        "-o".to_string(),
+        format!(
+            "thumbnail:{}/{THUMBNAIL_STEM}.%(ext)s",
+            work_dir.path().to_string_lossy()
+        ),
+        url.to_string(),
+    ];
+    let result = async {
+        state
+            .extractor
+            .run(RequestClass::Metadata, url, args, state.metadata_timeout)
+            .await?;
+        let path = work_dir.path().join(format!("{THUMBNAIL_STEM}.{format}"));
+        let size = tokio::fs::metadata(&path)
+            .await
+            .map_err(|_| ApiError::not_found("Este contenido no tiene miniatura disponible."))?
+            .len();
+        if size == 0 || size > MAX_THUMBNAIL_BYTES {
+            return Err(ApiError::not_found(
+                "Este contenido no tiene miniatura disponible.",
+            ));
+        }
+        let filename = format!("{THUMBNAIL_STEM}.{format}");
+        let content_type = sniff::content_type_for_file(&path, &filename).await;
+        if !content_type.starts_with("image/") {
+            return Err(ApiError::internal(
+                "yt-dlp devolvio una miniatura que no es una imagen.",
+            ));
+        }
+        let bytes = tokio::fs::read(&path).await.map_err(|error| {
+            ApiError::internal(format!("No se pudo leer la miniatura: {error}"))
+        })?;
+        Ok((content_type, bytes))
+    }
+    .await;
+    work_dir.remove().await;
+    let (content_type, bytes) = result?;
+
+    let mut response = (StatusCode::OK, Body::from(bytes)).into_response();
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response_headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_str(&format!("private, max-age={THUMBNAIL_CACHE_SECONDS}"))
+            .unwrap_or_else(|_| HeaderValue::from_static("private")),
+    );
+    response_headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    Ok(response)
+}
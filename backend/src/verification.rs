@@ -0,0 +1,374 @@
+use std::{collections::HashMap, io::ErrorKind, net::SocketAddr, path::PathBuf};
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header::LOCATION},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    ApiError, AppState, client_identity_for_request, encode_hex, hmac_sha256,
+    mailer::{SmtpMailer, normalize_email},
+    public_base_url, stored_identity,
+};
+
+const VERIFY_TOKEN_MINUTES: i64 = 30;
+const VERIFY_COOLDOWN_SECONDS: i64 = 60;
+const DEFAULT_VERIFIED_TIER_DAYS: i64 = 30;
+const MAX_IPS_PER_IDENTITY: usize = 3;
+const MAX_PENDING_VERIFICATIONS: usize = 10_000;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct VerifiedGrant {
+    email_hash: String,
+    granted_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct VerificationStore {
+    #[serde(default)]
+    identities: HashMap<String, DateTime<Utc>>,
+    #[serde(default)]
+    grants: HashMap<String, VerifiedGrant>,
+}
+
+#[derive(Debug)]
+struct PendingVerification {
+    email_hash: String,
+    ip: String,
+    issued_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub(crate) struct EmailVerification {
+    mailer: SmtpMailer,
+    verified_daily_limit: usize,
+    tier_days: i64,
+    redirect_url: Option<String>,
+    path: PathBuf,
+    store: Mutex<VerificationStore>,
+    pending: Mutex<HashMap<String, PendingVerification>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EmailVerificationRequest {
+    email: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct EmailVerificationSent {
+    status: &'static str,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EmailConfirmQuery {
+    token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct VerificationStatus {
+    verified: bool,
+    daily_limit: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl VerificationStore {
+    fn prune(&mut self, now: DateTime<Utc>) {
+        self.grants.retain(|_, grant| grant.expires_at > now);
+    }
+
+    fn grant(
+        &mut self,
+        email_hash: &str,
+        ip: &str,
+        now: DateTime<Utc>,
+        days: i64,
+    ) -> DateTime<Utc> {
+        self.identities.entry(email_hash.to_string()).or_insert(now);
+
+        let mut bound = self
+            .grants
+            .iter()
+            .filter(|(bound_ip, grant)| grant.email_hash == email_hash && *bound_ip != ip)
+            .map(|(bound_ip, grant)| (bound_ip.clone(), grant.granted_at))
+            .collect::<Vec<_>>();
+        bound.sort_by_key(|(_, granted_at)| *granted_at);
+        let excess = (bound.len() + 1).saturating_sub(MAX_IPS_PER_IDENTITY);
+        for (bound_ip, _) in bound.into_iter().take(excess) {
+            self.grants.remove(&bound_ip);
+        }
+
+        let expires_at = now + chrono::Duration::days(days);
+        self.grants.insert(
+            ip.to_string(),
+            VerifiedGrant {
+                email_hash: email_hash.to_string(),
+                granted_at: now,
+                expires_at,
+            },
+        );
+        expires_at
+    }
+}
+
+impl EmailVerification {
+    pub(crate) async fn from_env(
+        path: PathBuf,
+        default_daily_limit: usize,
+    ) -> Result<Option<Self>, ApiError> {
+        let Some(mailer) = SmtpMailer::from_env() else {
+            return Ok(None);
+        };
+
+        let mut store = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => {
+                serde_json::from_str::<VerificationStore>(&contents).map_err(|error| {
+                    ApiError::internal(format!("No se pudo leer verificaciones de email: {error}"))
+                })?
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => VerificationStore::default(),
+            Err(error) => {
+                return Err(ApiError::internal(format!(
+                    "No se pudo abrir archivo de verificaciones de email: {error}"
+                )));
+            }
+        };
+        store.prune(Utc::now());
+
+        let verified_daily_limit = crate::read_usize_env("VERIFIED_DAILY_LIMIT")
+            .filter(|value| *value > 0)
+            .unwrap_or(default_daily_limit * 3);
+        let tier_days = crate::read_usize_env("VERIFIED_TIER_DAYS")
+            .filter(|value| *value > 0)
+            .map_or(DEFAULT_VERIFIED_TIER_DAYS, |value| value as i64);
+        let redirect_url = std::env::var("EMAIL_VERIFY_REDIRECT_URL")
+            .ok()
+            .and_then(|value| crate::non_empty(&value).map(ToString::to_string));
+
+        Ok(Some(Self {
+            mailer,
+            verified_daily_limit,
+            tier_days,
+            redirect_url,
+            path,
+            store: Mutex::new(store),
+            pending: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    pub(crate) async fn verified_until(&self, ip: &str) -> Option<DateTime<Utc>> {
+        self.store
+            .lock()
+            .await
+            .grants
+            .get(ip)
+            .map(|grant| grant.expires_at)
+            .filter(|expires_at| *expires_at > Utc::now())
+    }
+
+    async fn persist(&self, store: &VerificationStore) -> Result<(), ApiError> {
+        let payload = crate::lowmem::to_json_vec(store).map_err(|error| {
+            ApiError::internal(format!(
+                "No se pudo serializar verificaciones de email: {error}"
+            ))
+        })?;
+        tokio::fs::write(&self.path, payload)
+            .await
+            .map_err(|error| {
+                ApiError::internal(format!(
+                    "No se pudo guardar verificaciones de email: {error}"
+                ))
+            })
+    }
+}
+
+pub(crate) async fn verified_daily_limit_for(state: &AppState, ip: &str) -> Option<usize> {
+    let verification = state.email_verification.as_deref()?;
+    verification
+        .verified_until(&stored_identity(state, ip))
+        .await
+        .map(|_| verification.verified_daily_limit)
+}
+
+fn email_hash(state: &AppState, email: &str) -> String {
+    encode_hex(&hmac_sha256(&state.signing_secret, email.as_bytes()))
+}
+
+fn token_hash(token: &str) -> String {
+    encode_hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn enabled(state: &AppState) -> Result<&EmailVerification, ApiError> {
+    state
+        .email_verification
+        .as_deref()
+        .ok_or_else(|| ApiError::not_found("Verificacion por email deshabilitada."))
+}
+
+pub(crate) async fn request_email_verification(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<EmailVerificationRequest>,
+) -> Result<Json<EmailVerificationSent>, ApiError> {
+    let verification = enabled(&state)?;
+    let email = normalize_email(&payload.email)
+        .ok_or_else(|| ApiError::bad_request("Ingresa un email valido."))?;
+    let client_ip = stored_identity(&state, &client_identity_for_request(&state, &headers, addr));
+    let hashed_email = email_hash(&state, &email);
+    let now = Utc::now();
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let expires_at = now + chrono::Duration::minutes(VERIFY_TOKEN_MINUTES);
+
+    {
+        let mut pending = verification.pending.lock().await;
+        pending.retain(|_, entry| entry.expires_at > now);
+        let cooling_down = pending.values().any(|entry| {
+            (entry.ip == client_ip || entry.email_hash == hashed_email)
+                && (now - entry.issued_at).num_seconds() < VERIFY_COOLDOWN_SECONDS
+        });
+        if cooling_down {
+            return Err(ApiError::verification_cooldown(
+                "Ya enviamos un enlace hace poco. Espera un minuto antes de pedir otro.",
+                VERIFY_COOLDOWN_SECONDS as u64,
+            ));
+        }
+        if pending.len() >= MAX_PENDING_VERIFICATIONS {
+            return Err(ApiError::verification_cooldown(
+                "Hay demasiadas verificaciones pendientes. Intenta mas tarde.",
+                VERIFY_COOLDOWN_SECONDS as u64,
+            ));
+        }
+        pending.insert(
+            token_hash(&token),
+            PendingVerification {
+                email_hash: hashed_email,
+                ip: client_ip.clone(),
+                issued_at: now,
+                expires_at,
+            },
+        );
+    }
+
+    let link = format!(
+        "{}/api/verify/email/confirm?token={token}",
+        public_base_url(&state, &headers)
+    );
+    let body = format!(
+        "Hola,\n\nConfirma tu email para ampliar tu limite diario a {} descargas:\n\n{link}\n\nEl enlace caduca en {VERIFY_TOKEN_MINUTES} minutos. Si no lo pediste, ignora este mensaje.\n",
+        verification.verified_daily_limit
+    );
+    if let Err(error) = verification
+        .mailer
+        .send(&email, "Confirma tu email en Total Downloader", &body)
+        .await
+    {
+        verification
+            .pending
+            .lock()
+            .await
+            .remove(&token_hash(&token));
+        warn!("No se pudo enviar email de verificacion: {error}");
+        return Err(ApiError::internal(
+            "No se pudo enviar el email de verificacion. Intenta mas tarde.",
+        ));
+    }
+
+    info!("Email de verificacion enviado para {client_ip}");
+    Ok(Json(EmailVerificationSent {
+        status: "sent",
+        expires_at,
+    }))
+}
+
+pub(crate) async fn confirm_email_verification(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<EmailConfirmQuery>,
+) -> Result<Response, ApiError> {
+    let verification = enabled(&state)?;
+    let client_ip = stored_identity(&state, &client_identity_for_request(&state, &headers, addr));
+    let now = Utc::now();
+
+    let pending = verification
+        .pending
+        .lock()
+        .await
+        .remove(&token_hash(query.token.trim()))
+        .filter(|entry| entry.expires_at > now);
+    let Some(pending) = pending else {
+        return match &verification.redirect_url {
+            Some(url) => redirect(url, false),
+            None => Err(ApiError::bad_request(
+                "El enlace de verificacion es invalido o expiro.",
+            )),
+        };
+    };
+
+    let (expires_at, snapshot) = {
+        let mut store = verification.store.lock().await;
+        store.prune(now);
+        let expires_at = store.grant(
+            &pending.email_hash,
+            &pending.ip,
+            now,
+            verification.tier_days,
+        );
+        if client_ip != pending.ip {
+            store.grant(&pending.email_hash, &client_ip, now, verification.tier_days);
+        }
+        (expires_at, store.clone())
+    };
+    verification.persist(&snapshot).await?;
+    info!("Email verificado para IP {}", pending.ip);
+
+    match &verification.redirect_url {
+        Some(url) => redirect(url, true),
+        None => Ok(Json(VerificationStatus {
+            verified: true,
+            daily_limit: verification.verified_daily_limit,
+            expires_at: Some(expires_at),
+        })
+        .into_response()),
+    }
+}
+
+pub(crate) async fn get_verification_status(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Json<VerificationStatus>, ApiError> {
+    let verification = enabled(&state)?;
+    let client_ip = stored_identity(&state, &client_identity_for_request(&state, &headers, addr));
+    let expires_at = verification.verified_until(&client_ip).await;
+
+    Ok(Json(VerificationStatus {
+        verified: expires_at.is_some(),
+        daily_limit: if expires_at.is_some() {
+            verification.verified_daily_limit
+        } else {
+            state.config.download_limit_per_day
+        },
+        expires_at,
+    }))
+}
+
+fn redirect(url: &str, verified: bool) -> Result<Response, ApiError> {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    let location = format!("{url}{separator}email_verified={}", u8::from(verified));
+    let value = HeaderValue::from_str(&location)
+        .map_err(|_| ApiError::internal("EMAIL_VERIFY_REDIRECT_URL invalida."))?;
+    Ok((StatusCode::SEE_OTHER, [(LOCATION, value)]).into_response())
+}
@@ -0,0 +1,158 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{SecondsFormat, Utc};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt, BufWriter},
+};
+use uuid::Uuid;
+
+use crate::ApiError;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const WARC_COPY_BUFFER_BYTES: usize = 64 * 1024;
+
+pub(crate) struct WarcFile {
+    pub(crate) name: String,
+    pub(crate) path: PathBuf,
+    pub(crate) size: u64,
+    pub(crate) sha256_hex: String,
+}
+
+fn base32(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let mut buffer = 0_u64;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | u64::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    while !encoded.len().is_multiple_of(8) {
+        encoded.push('=');
+    }
+    encoded
+}
+
+fn labelled_digest(sha256_hex: &str) -> String {
+    let bytes = (0..sha256_hex.len())
+        .step_by(2)
+        .filter_map(|index| u8::from_str_radix(sha256_hex.get(index..index + 2)?, 16).ok())
+        .collect::<Vec<_>>();
+    format!("sha256:{}", base32(&bytes))
+}
+
+fn content_type_for(name: &str) -> &'static str {
+    let extension = name.rsplit('.').next().unwrap_or_default();
+    match extension.to_ascii_lowercase().as_str() {
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "vtt" => "text/vtt",
+        "srt" => "application/x-subrip",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "mkv" => "video/x-matroska",
+        "mov" => "video/quicktime",
+        _ => "application/octet-stream",
+    }
+}
+
+fn record_id() -> String {
+    format!("<urn:uuid:{}>", Uuid::new_v4())
+}
+
+fn record_header(fields: &[(&str, &str)], content_length: u64) -> String {
+    let mut header = String::from("WARC/1.1\r\n");
+    for (name, value) in fields {
+        header.push_str(&format!("{name}: {value}\r\n"));
+    }
+    header.push_str(&format!("Content-Length: {content_length}\r\n\r\n"));
+    header
+}
+
+// yt-dlp talks TLS to the platforms, so the fetched payloads are stored as resource and
+// metadata records instead of raw request/response pairs.
+pub(crate) async fn write_snapshot_warc(
+    output: &Path,
+    source_url: &str,
+    media: &WarcFile,
+    attachments: &[WarcFile],
+) -> Result<(), ApiError> {
+    let result = async {
+        let mut writer = BufWriter::new(File::create(output).await?);
+        let date = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let filename = output
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("snapshot.warc");
+
+        let warcinfo_id = record_id();
+        let info = format!(
+            "software: Total_Downloader/{}\r\nformat: WARC File Format 1.1\r\nconformsTo: https://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/\r\ndescription: Snapshot de {source_url}\r\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        writer.write_all(record_header(
+                &[
+                    ("WARC-Type", "warcinfo"),
+                    ("WARC-Record-ID", &warcinfo_id),
+                    ("WARC-Date", &date),
+                    ("WARC-Filename", filename),
+                    ("Content-Type", "application/warc-fields"),
+                ],
+                info.len() as u64,
+            )
+            .as_bytes(),
+        )
+        .await?;
+        writer.write_all(info.as_bytes()).await?;
+        writer.write_all(b"\r\n\r\n").await?;
+
+        let media_id = record_id();
+        let mut records = vec![(media, "resource", media_id.clone())];
+        records.extend(
+            attachments
+                .iter()
+                .map(|attachment| (attachment, "metadata", record_id())),
+        );
+        let mut buffer = vec![0_u8; WARC_COPY_BUFFER_BYTES];
+        for (file, kind, id) in records {
+            let digest = labelled_digest(&file.sha256_hex);
+            let mut fields = vec![
+                ("WARC-Type", kind),
+                ("WARC-Record-ID", id.as_str()),
+                ("WARC-Date", date.as_str()),
+                ("WARC-Target-URI", source_url),
+                ("WARC-Warcinfo-ID", warcinfo_id.as_str()),
+                ("WARC-Block-Digest", digest.as_str()),
+                ("WARC-Payload-Digest", digest.as_str()),
+                ("Content-Type", content_type_for(&file.name)),
+                ("X-File-Name", file.name.as_str()),
+            ];
+            if kind == "metadata" {
+                fields.push(("WARC-Refers-To", media_id.as_str()));
+            }
+            writer.write_all(record_header(&fields, file.size).as_bytes()).await?;
+            let mut source = File::open(&file.path).await?;
+            loop {
+                let read = source.read(&mut buffer).await?;
+                if read == 0 {
+                    break;
+                }
+                writer.write_all(&buffer[..read]).await?;
+            }
+            writer.write_all(b"\r\n\r\n").await?;
+        }
+        writer.flush().await
+    }
+    .await;
+    result.map_err(|error| ApiError::internal(format!("No se pudo generar el WARC: {error}")))
+}
@@ -0,0 +1,378 @@
+use std::{collections::HashSet, io, net::SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{CONNECTION, ORIGIN, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, UPGRADE},
+    },
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use hyper_util::rt::TokioIo;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::{broadcast, mpsc, watch},
+    time::{Duration, interval},
+};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    ApiError, AppState, client_ip_for_request,
+    joblog::{JobLogLine, LogFollower},
+    jobs::{JobSnapshot, JobState},
+    normalize_origin,
+};
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+const OUTGOING_BUFFER: usize = 64;
+const PING_INTERVAL_SECONDS: u64 = 30;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+enum Incoming {
+    Text(String),
+    Ping(Vec<u8>),
+    Close,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ClientCommand {
+    Subscribe { job_id: Uuid },
+    SubscribeLogs { job_id: Uuid },
+    Cancel { job_id: Uuid },
+    Ping,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Queued { job: JobSnapshot },
+    Started { job: JobSnapshot },
+    Progress { job: JobSnapshot },
+    Completed { job: JobSnapshot },
+    Failed { job: JobSnapshot },
+    Cancelled { job: JobSnapshot },
+    Subscribed { job_id: Uuid },
+    Log { job_id: Uuid, log: JobLogLine },
+    Pong,
+    Error { message: String },
+}
+
+fn header_has_token(
+    headers: &HeaderMap,
+    name: impl axum::http::header::AsHeaderName,
+    token: &str,
+) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Frame> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head).await?;
+    let mut length = u64::from(head[1] & 0x7F);
+    if length == 126 {
+        let mut extended = [0u8; 2];
+        reader.read_exact(&mut extended).await?;
+        length = u64::from(u16::from_be_bytes(extended));
+    } else if length == 127 {
+        let mut extended = [0u8; 8];
+        reader.read_exact(&mut extended).await?;
+        length = u64::from_be_bytes(extended);
+    }
+    if head[1] & 0x80 == 0 || length > MAX_MESSAGE_BYTES as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "trama sin mascara o demasiado grande",
+        ));
+    }
+
+    let mut mask = [0u8; 4];
+    reader.read_exact(&mut mask).await?;
+    let mut payload = vec![0u8; length as usize];
+    reader.read_exact(&mut payload).await?;
+    for (index, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[index % 4];
+    }
+
+    Ok(Frame {
+        fin: head[0] & 0x80 != 0,
+        opcode: head[0] & 0x0F,
+        payload,
+    })
+}
+
+async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    opcode: u8,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= usize::from(u16::MAX) => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame).await?;
+    writer.flush().await
+}
+
+async fn read_messages<R: AsyncRead + Unpin>(mut reader: R, sender: mpsc::Sender<Incoming>) {
+    let mut message = Vec::new();
+    loop {
+        let incoming = match read_frame(&mut reader).await {
+            Ok(frame) if frame.opcode == OPCODE_PING => Incoming::Ping(frame.payload),
+            Ok(frame) if frame.opcode == OPCODE_PONG => continue,
+            Ok(frame) if matches!(frame.opcode, OPCODE_TEXT | OPCODE_CONTINUATION) => {
+                message.extend_from_slice(&frame.payload);
+                if message.len() > MAX_MESSAGE_BYTES {
+                    Incoming::Close
+                } else if !frame.fin {
+                    continue;
+                } else {
+                    match String::from_utf8(std::mem::take(&mut message)) {
+                        Ok(text) => Incoming::Text(text),
+                        Err(_) => Incoming::Close,
+                    }
+                }
+            }
+            _ => Incoming::Close,
+        };
+        let closing = matches!(incoming, Incoming::Close);
+        if sender.send(incoming).await.is_err() || closing {
+            return;
+        }
+    }
+}
+
+async fn forward_job(
+    mut receiver: watch::Receiver<JobSnapshot>,
+    sender: mpsc::Sender<ServerMessage>,
+) {
+    let mut previous = None;
+    loop {
+        let job = receiver.borrow_and_update().clone();
+        let state = job.state();
+        let message = match state {
+            JobState::Queued => ServerMessage::Queued { job },
+            JobState::Running if previous == Some(JobState::Running) => {
+                ServerMessage::Progress { job }
+            }
+            JobState::Running => ServerMessage::Started { job },
+            JobState::Completed => ServerMessage::Completed { job },
+            JobState::Failed => ServerMessage::Failed { job },
+            JobState::Cancelled => ServerMessage::Cancelled { job },
+        };
+        if sender.send(message).await.is_err() || state.is_terminal() {
+            return;
+        }
+        previous = Some(state);
+        if receiver.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn forward_logs(
+    job_id: Uuid,
+    mut follower: LogFollower,
+    sender: mpsc::Sender<ServerMessage>,
+) {
+    while let Some(log) = follower.next().await {
+        if sender
+            .send(ServerMessage::Log { job_id, log })
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+pub(crate) async fn job_updates_socket(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut request: Request,
+) -> Result<Response, ApiError> {
+    let headers = request.headers();
+    let key = headers
+        .get(SEC_WEBSOCKET_KEY)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|_| {
+            header_has_token(headers, UPGRADE, "websocket")
+                && header_has_token(headers, CONNECTION, "upgrade")
+                && header_has_token(headers, "sec-websocket-version", "13")
+        })
+        .ok_or_else(|| ApiError::bad_request("Se requiere una solicitud WebSocket."))?;
+    if let Some(origin) = headers.get(ORIGIN).and_then(|value| value.to_str().ok())
+        && !normalize_origin(origin).is_some_and(|origin| state.allowed_origins.contains(&origin))
+    {
+        return Err(ApiError::forbidden("Origen no autorizado."));
+    }
+
+    let accept = BASE64.encode(digest::digest(
+        &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        format!("{key}{WEBSOCKET_GUID}").as_bytes(),
+    ));
+    let accept = HeaderValue::from_str(&accept)
+        .map_err(|_| ApiError::bad_request("Sec-WebSocket-Key invalida."))?;
+    let client_ip = client_ip_for_request(&state, headers, addr);
+    let upgrade = hyper::upgrade::on(&mut request);
+
+    tokio::spawn(async move {
+        match upgrade.await {
+            Ok(upgraded) => run_session(state, TokioIo::new(upgraded), client_ip).await,
+            Err(error) => debug!("No se pudo completar el upgrade WebSocket: {error}"),
+        }
+    });
+
+    Ok((
+        StatusCode::SWITCHING_PROTOCOLS,
+        [
+            (UPGRADE, HeaderValue::from_static("websocket")),
+            (CONNECTION, HeaderValue::from_static("upgrade")),
+            (SEC_WEBSOCKET_ACCEPT, accept),
+        ],
+    )
+        .into_response())
+}
+
+fn unknown_job() -> ServerMessage {
+    ServerMessage::Error {
+        message: "No existe una descarga con ese identificador.".to_string(),
+    }
+}
+
+async fn run_session<S>(state: AppState, stream: S, client_ip: String)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let (incoming_sender, mut incoming) = mpsc::channel(OUTGOING_BUFFER);
+    let (outgoing_sender, mut outgoing) = mpsc::channel(OUTGOING_BUFFER);
+    let reader_task = tokio::spawn(read_messages(reader, incoming_sender));
+
+    let mut created = state.jobs.watch_created();
+    let mut watched = HashSet::new();
+    let mut tailed = HashSet::new();
+    for (job_id, receiver) in state.jobs.active_for(&client_ip).await {
+        watched.insert(job_id);
+        tokio::spawn(forward_job(receiver, outgoing_sender.clone()));
+    }
+    let mut keepalive = interval(Duration::from_secs(PING_INTERVAL_SECONDS));
+    keepalive.tick().await;
+
+    loop {
+        let reply = tokio::select! {
+            message = incoming.recv() => match message {
+                Some(Incoming::Text(text)) => match serde_json::from_str::<ClientCommand>(&text) {
+                    Ok(ClientCommand::Subscribe { job_id }) => {
+                        Some(match state.jobs.subscribe(job_id, &client_ip).await {
+                            Some(receiver) => {
+                                if watched.insert(job_id) {
+                                    tokio::spawn(forward_job(receiver, outgoing_sender.clone()));
+                                }
+                                ServerMessage::Subscribed { job_id }
+                            }
+                            None => unknown_job(),
+                        })
+                    }
+                    Ok(ClientCommand::SubscribeLogs { job_id }) => {
+                        Some(match state.jobs.follow_logs(job_id, &client_ip, 0).await {
+                            Some(follower) => {
+                                if tailed.insert(job_id) {
+                                    tokio::spawn(forward_logs(job_id, follower, outgoing_sender.clone()));
+                                }
+                                ServerMessage::Subscribed { job_id }
+                            }
+                            None => unknown_job(),
+                        })
+                    }
+                    Ok(ClientCommand::Cancel { job_id }) => {
+                        match state.jobs.cancel(job_id, &client_ip).await {
+                            Some(job) if job.state() != JobState::Cancelled => Some(ServerMessage::Error {
+                                message: "La descarga ya habia terminado.".to_string(),
+                            }),
+                            Some(_) if watched.contains(&job_id) => None,
+                            Some(job) => Some(ServerMessage::Cancelled { job }),
+                            None => Some(unknown_job()),
+                        }
+                    }
+                    Ok(ClientCommand::Ping) => Some(ServerMessage::Pong),
+                    Err(error) => Some(ServerMessage::Error {
+                        message: format!("Comando invalido: {error}"),
+                    }),
+                },
+                Some(Incoming::Ping(payload)) => {
+                    if write_frame(&mut writer, OPCODE_PONG, &payload).await.is_err() {
+                        break;
+                    }
+                    None
+                }
+                Some(Incoming::Close) | None => break,
+            },
+            Some(message) = outgoing.recv() => Some(message),
+            job = created.recv() => match job {
+                Ok((job_id, owner)) if owner == client_ip && watched.insert(job_id) => {
+                    if let Some(receiver) = state.jobs.subscribe(job_id, &client_ip).await {
+                        tokio::spawn(forward_job(receiver, outgoing_sender.clone()));
+                    }
+                    None
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+                _ => None,
+            },
+            _ = keepalive.tick() => {
+                if write_frame(&mut writer, OPCODE_PING, &[]).await.is_err() {
+                    break;
+                }
+                None
+            }
+        };
+
+        let Some(reply) = reply else {
+            continue;
+        };
+        let Ok(payload) = serde_json::to_vec(&reply) else {
+            continue;
+        };
+        if write_frame(&mut writer, OPCODE_TEXT, &payload)
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    let _ = write_frame(&mut writer, OPCODE_CLOSE, &[]).await;
+    reader_task.abort();
+}
@@ -0,0 +1,430 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::State,
+    http::{HeaderMap, HeaderValue, header::CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use futures_util::stream;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, time::Duration};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::compat::{CodecDecision, CodecProfile};
+use crate::egress::IpFamily;
+use crate::postprocess::AudioTags;
+use crate::sidecars::SidecarRequest;
+use crate::{
+    ApiError, AppState, ArtifactSpec, ClipRange, DownloadMode, FormatHints, bearer_matches,
+    jobs::{JobHandle, JobPhase, TransferRate},
+    produce_local_file,
+};
+
+const WORKER_CONNECT_TIMEOUT_SECONDS: u64 = 5;
+const WORKER_KEEPALIVE_SECONDS: u64 = 30;
+const WORKER_EVENT_BUFFER: usize = 16;
+const MAX_WORKER_EVENT_BYTES: usize = 16 * 1024;
+pub(crate) const WORKER_JOB_OWNER: &str = "worker";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct WorkerJobRequest {
+    job_id: Uuid,
+    url: String,
+    mode: DownloadMode,
+    format_id: Option<String>,
+    has_audio: bool,
+    #[serde(default)]
+    hints: FormatHints,
+    #[serde(default)]
+    container: Option<String>,
+    #[serde(default)]
+    subtitle_languages: Vec<String>,
+    #[serde(default)]
+    codec_profile: Option<CodecProfile>,
+    #[serde(default)]
+    clip: Option<ClipRange>,
+    #[serde(default)]
+    split_chapters: bool,
+    #[serde(default)]
+    extra_args: Vec<String>,
+    #[serde(default)]
+    sponsorblock_remove: Vec<String>,
+    embed_metadata: bool,
+    #[serde(default)]
+    embed_thumbnail: bool,
+    #[serde(default)]
+    audio_tags: Option<AudioTags>,
+    #[serde(default)]
+    sidecars: Option<SidecarRequest>,
+    #[serde(default)]
+    ip_family: IpFamily,
+    #[serde(default)]
+    leak_tag: Option<String>,
+    max_download_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum WorkerEvent {
+    Progress {
+        phase: JobPhase,
+        progress: f64,
+        #[serde(default)]
+        transfer: TransferRate,
+    },
+    Completed {
+        hash: String,
+        size: u64,
+        filename: String,
+        #[serde(default)]
+        codecs: Option<CodecDecision>,
+        #[serde(default)]
+        cpu_seconds: f64,
+    },
+    Failed {
+        status: u16,
+        message: String,
+    },
+    Log {
+        line: String,
+    },
+}
+
+#[derive(Debug)]
+pub(crate) struct ProducedBlob {
+    pub(crate) hash: String,
+    pub(crate) size: u64,
+    pub(crate) filename: String,
+    pub(crate) codecs: Option<CodecDecision>,
+    pub(crate) cpu_seconds: f64,
+}
+
+#[derive(Debug)]
+pub(crate) enum DispatchError {
+    Unavailable,
+    Job(ApiError),
+}
+
+#[derive(Debug)]
+pub(crate) struct WorkerPool {
+    urls: Vec<String>,
+    secret: String,
+    fallback_local: bool,
+    next: AtomicUsize,
+    client: reqwest::Client,
+}
+
+impl WorkerJobRequest {
+    pub(crate) fn from_spec(job_id: Uuid, spec: &ArtifactSpec<'_>, leak_tag: Option<&str>) -> Self {
+        Self {
+            job_id,
+            url: spec.url.to_string(),
+            mode: spec.mode.clone(),
+            format_id: spec.format_id.map(ToString::to_string),
+            has_audio: spec.has_audio,
+            hints: spec.hints,
+            container: spec.container.map(ToString::to_string),
+            subtitle_languages: spec.subtitle_languages.to_vec(),
+            codec_profile: spec.codec_profile.cloned(),
+            clip: spec.clip,
+            split_chapters: spec.split_chapters,
+            extra_args: spec.extra_args.to_vec(),
+            sponsorblock_remove: spec.sponsorblock_remove.to_vec(),
+            embed_metadata: spec.embed_metadata,
+            embed_thumbnail: spec.embed_thumbnail,
+            audio_tags: spec.audio_tags.cloned(),
+            sidecars: spec.sidecars,
+            ip_family: spec.ip_family,
+            leak_tag: leak_tag.map(ToString::to_string),
+            max_download_bytes: spec.max_download_bytes,
+        }
+    }
+
+    fn to_spec(&self) -> ArtifactSpec<'_> {
+        ArtifactSpec {
+            url: &self.url,
+            mode: self.mode.clone(),
+            format_id: self.format_id.as_deref(),
+            has_audio: self.has_audio,
+            hints: self.hints,
+            container: self.container.as_deref(),
+            subtitle_languages: &self.subtitle_languages,
+            codec_profile: self.codec_profile.as_ref(),
+            clip: self.clip,
+            split_chapters: self.split_chapters,
+            extra_args: &self.extra_args,
+            sponsorblock_remove: &self.sponsorblock_remove,
+            embed_metadata: self.embed_metadata,
+            embed_thumbnail: self.embed_thumbnail,
+            audio_tags: self.audio_tags.as_ref(),
+            sidecars: self.sidecars,
+            ip_family: self.ip_family,
+            max_download_bytes: self.max_download_bytes,
+            retention_seconds: 0,
+        }
+    }
+}
+
+impl WorkerPool {
+    pub(crate) fn from_env(secret: Option<&str>) -> Result<Option<Self>, ApiError> {
+        let urls = crate::read_list_env_raw("WORKER_URLS")
+            .into_iter()
+            .map(|url| url.trim_end_matches('/').to_string())
+            .collect::<Vec<_>>();
+        if urls.is_empty() {
+            return Ok(None);
+        }
+        let Some(secret) = secret else {
+            warn!("WORKER_URLS definido sin WORKER_SHARED_SECRET: se ignoran los workers remotos.");
+            return Ok(None);
+        };
+
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(WORKER_CONNECT_TIMEOUT_SECONDS))
+            .tcp_keepalive(Duration::from_secs(WORKER_KEEPALIVE_SECONDS))
+            .build()
+            .map_err(|error| {
+                ApiError::internal(format!("No se pudo crear cliente HTTP de workers: {error}"))
+            })?;
+
+        Ok(Some(Self {
+            urls,
+            secret: secret.to_string(),
+            fallback_local: crate::read_bool_env("WORKER_FALLBACK_LOCAL").unwrap_or(true),
+            next: AtomicUsize::new(0),
+            client,
+        }))
+    }
+
+    pub(crate) fn urls(&self) -> &[String] {
+        &self.urls
+    }
+
+    pub(crate) fn fallback_local(&self) -> bool {
+        self.fallback_local
+    }
+
+    pub(crate) async fn dispatch(
+        &self,
+        job: &JobHandle,
+        request: &WorkerJobRequest,
+    ) -> Result<ProducedBlob, DispatchError> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..self.urls.len() {
+            let base = &self.urls[(start + offset) % self.urls.len()];
+            let response = match self
+                .client
+                .post(format!("{base}/api/worker/produce"))
+                .bearer_auth(&self.secret)
+                .json(request)
+                .send()
+                .await
+            {
+                Ok(response) => response,
+                Err(error) => {
+                    warn!("Worker {base} no disponible: {error}");
+                    continue;
+                }
+            };
+            if response.status() == StatusCode::SERVICE_UNAVAILABLE {
+                warn!("Worker {base} saturado; se prueba el siguiente.");
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(DispatchError::Job(ApiError::internal(format!(
+                    "El worker {base} rechazo la descarga ({}).",
+                    response.status()
+                ))));
+            }
+
+            info!("Descarga {} enviada al worker {base}", request.job_id);
+            job.running(request.to_spec().phase_plan());
+            return follow_events(job, base, response)
+                .await
+                .map_err(DispatchError::Job);
+        }
+
+        Err(DispatchError::Unavailable)
+    }
+}
+
+async fn follow_events(
+    job: &JobHandle,
+    base: &str,
+    mut response: reqwest::Response,
+) -> Result<ProducedBlob, ApiError> {
+    let mut buffer = Vec::new();
+    loop {
+        let chunk = response.chunk().await.map_err(|error| {
+            ApiError::internal(format!(
+                "Se perdio la conexion con el worker {base}: {error}"
+            ))
+        })?;
+        let Some(chunk) = chunk else {
+            return Err(ApiError::internal(format!(
+                "El worker {base} cerro la conexion sin terminar la descarga."
+            )));
+        };
+        buffer.extend_from_slice(&chunk);
+
+        while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+            let line = buffer.drain(..=end).collect::<Vec<_>>();
+            let Ok(event) = serde_json::from_slice::<WorkerEvent>(&line) else {
+                continue;
+            };
+            match event {
+                WorkerEvent::Progress {
+                    phase,
+                    progress,
+                    transfer,
+                } => {
+                    job.transfer(transfer);
+                    job.mirror_progress(phase, progress);
+                }
+                WorkerEvent::Completed {
+                    hash,
+                    size,
+                    filename,
+                    codecs,
+                    cpu_seconds,
+                } => {
+                    return Ok(ProducedBlob {
+                        hash,
+                        size,
+                        filename,
+                        codecs,
+                        cpu_seconds,
+                    });
+                }
+                WorkerEvent::Failed { status, message } => {
+                    return Err(ApiError::remote(status, message));
+                }
+                WorkerEvent::Log { line } => job.log(&line),
+            }
+        }
+        if buffer.len() > MAX_WORKER_EVENT_BYTES {
+            return Err(ApiError::internal(format!(
+                "El worker {base} envio una respuesta invalida."
+            )));
+        }
+    }
+}
+
+pub(crate) async fn produce_on_worker(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<WorkerJobRequest>,
+) -> Result<Response, ApiError> {
+    let expected = state
+        .worker_secret
+        .as_deref()
+        .ok_or_else(|| ApiError::not_found("Modo worker deshabilitado."))?;
+    if !bearer_matches(expected, &headers) {
+        return Err(ApiError::unauthorized("Credencial de worker invalida."));
+    }
+
+    let job = state.jobs.create(request.job_id, WORKER_JOB_OWNER).await?;
+    let (sender, receiver) = mpsc::channel(WORKER_EVENT_BUFFER);
+    tokio::spawn(run_worker_job(state, job, request, sender));
+
+    let body = Body::from_stream(stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    }));
+    let mut response = body.into_response();
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    Ok(response)
+}
+
+async fn run_worker_job(
+    state: AppState,
+    job: JobHandle,
+    request: WorkerJobRequest,
+    sender: mpsc::Sender<Result<Bytes, std::io::Error>>,
+) {
+    let mut progress = job.watch();
+    let mut logs = job.watch_logs();
+    let production = async {
+        let spec = request.to_spec();
+        let produced = produce_local_file(&state, &job, &spec, request.leak_tag.as_deref()).await?;
+        let stored = state.artifacts.store_blob(&produced.path).await;
+        produced.job_dir.remove().await;
+        let (hash, size) = stored?;
+        Ok::<_, ApiError>((
+            hash,
+            size,
+            produced.filename,
+            produced.codecs,
+            produced.cpu_seconds,
+        ))
+    };
+    tokio::pin!(production);
+
+    let outcome = loop {
+        tokio::select! {
+            result = &mut production => break result,
+            () = sender.closed() => {
+                warn!("El nodo API abandono la descarga {}", request.job_id);
+                return;
+            }
+            Ok(()) = progress.changed() => {
+                let snapshot = progress.borrow_and_update().clone();
+                let Some(phase) = snapshot.phase() else {
+                    continue;
+                };
+                let event = WorkerEvent::Progress {
+                    phase,
+                    progress: snapshot.progress(),
+                    transfer: snapshot.transfer(),
+                };
+                if send_event(&sender, &event).await.is_err() {
+                    warn!("El nodo API abandono la descarga {}", request.job_id);
+                    return;
+                }
+            }
+            Ok(entry) = logs.recv() => {
+                let event = WorkerEvent::Log { line: entry.line };
+                if send_event(&sender, &event).await.is_err() {
+                    warn!("El nodo API abandono la descarga {}", request.job_id);
+                    return;
+                }
+            }
+        }
+    };
+
+    let event = match outcome {
+        Ok((hash, size, filename, codecs, cpu_seconds)) => {
+            job.complete(&filename);
+            WorkerEvent::Completed {
+                hash,
+                size,
+                filename,
+                codecs,
+                cpu_seconds,
+            }
+        }
+        Err(error) => {
+            job.fail(&error.message);
+            WorkerEvent::Failed {
+                status: error.status.as_u16(),
+                message: error.message,
+            }
+        }
+    };
+    let _ = send_event(&sender, &event).await;
+}
+
+async fn send_event(
+    sender: &mpsc::Sender<Result<Bytes, std::io::Error>>,
+    event: &WorkerEvent,
+) -> Result<(), ()> {
+    let mut line = serde_json::to_vec(event).map_err(|_| ())?;
+    line.push(b'\n');
+    sender.send(Ok(Bytes::from(line))).await.map_err(|_| ())
+}
@@ -0,0 +1,174 @@
+#![allow(dead_code)]
+
+use std::{
+    net::TcpListener,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+const FAKE_YT_DLP: &str = r#"#!/bin/sh
+for arg; do
+  if [ "$arg" = "-J" ]; then
+    echo '{"title":"Fake video","duration":12,"formats":[{"format_id":"18","ext":"mp4","vcodec":"avc1","acodec":"mp4a","height":360,"filesize":1000}]}'
+    exit 0
+  fi
+done
+prev=""
+out=""
+for arg; do
+  [ "$prev" = "-o" ] && out="$arg"
+  prev="$arg"
+done
+[ -z "$out" ] && exit 0
+file="$(dirname "$out")/Fake video.mp4"
+head -c "${FAKE_SIZE:-65536}" /dev/zero > "$file"
+echo "$file"
+"#;
+
+pub struct Server {
+    child: Child,
+    pub base: String,
+    pub root: PathBuf,
+    pub client: reqwest::Client,
+}
+
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+impl Server {
+    pub async fn start(envs: &[(&str, &str)]) -> Self {
+        Self::start_on(free_port(), envs).await
+    }
+
+    // For nodes that must know their own URL before starting (NODE_PUBLIC_URL).
+    pub async fn start_on(port: u16, envs: &[(&str, &str)]) -> Self {
+        let root = std::env::temp_dir().join(format!("td-test-{}", Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&root).unwrap();
+        let yt_dlp = root.join("yt-dlp");
+        std::fs::write(&yt_dlp, FAKE_YT_DLP).unwrap();
+        std::fs::set_permissions(&yt_dlp, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_backend"))
+            .env("PORT", port.to_string())
+            .env("DATA_DIR", root.join("data"))
+            .env("TRANSFER_DIR", root.join("transfers"))
+            .env("ARTIFACTS_DIR", root.join("artifacts"))
+            .env("YT_DLP_STABLE_PATH", &yt_dlp)
+            .env("FFMPEG_PATH", "/bin/false")
+            .env("RUST_LOG", "warn")
+            .envs(envs.iter().copied())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let server = Self {
+            child,
+            base: format!("http://127.0.0.1:{port}"),
+            root,
+            client: reqwest::Client::new(),
+        };
+        for _ in 0..100 {
+            if server
+                .client
+                .get(server.url("/api/health"))
+                .send()
+                .await
+                .is_ok()
+            {
+                return server;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("el backend no arranco");
+    }
+
+    // Graceful shutdown, so every pending write lands before the test inspects the data dir.
+    pub fn stop(&mut self) {
+        unsafe {
+            libc::kill(self.child.id() as libc::pid_t, libc::SIGTERM);
+        }
+        let _ = self.child.wait();
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base)
+    }
+
+    pub async fn antibot_fields(&self) -> Value {
+        self.antibot_fields_as(None).await
+    }
+
+    // With TRUST_PROXY_HEADERS the forwarded address stands in for a distinct client.
+    pub async fn antibot_fields_as(&self, forwarded_for: Option<&str>) -> Value {
+        let mut request = self.client.get(self.url("/api/antibot/challenge"));
+        if let Some(ip) = forwarded_for {
+            request = request.header("x-forwarded-for", ip);
+        }
+        let challenge: Value = request.send().await.unwrap().json().await.unwrap();
+        let id = challenge["challenge_id"].as_str().unwrap();
+        let nonce = challenge["nonce"].as_str().unwrap();
+        let zeros = "0".repeat(challenge["difficulty"].as_u64().unwrap() as usize);
+        let solution = (0u64..)
+            .find(|n| {
+                format!("{:x}", Sha256::digest(format!("{id}:{nonce}:{n}"))).starts_with(&zeros)
+            })
+            .unwrap();
+        json!({
+            "antibot_challenge_id": id,
+            "antibot_solution": solution,
+            "antibot_elapsed_ms": 1500,
+        })
+    }
+
+    pub async fn download(&self, url: &str) -> reqwest::Response {
+        self.download_as(None, url).await
+    }
+
+    pub async fn download_as(&self, forwarded_for: Option<&str>, url: &str) -> reqwest::Response {
+        let mut body = self.antibot_fields_as(forwarded_for).await;
+        body["url"] = json!(url);
+        body["mode"] = json!("video");
+        let mut request = self.client.post(self.url("/api/download")).json(&body);
+        if let Some(ip) = forwarded_for {
+            request = request.header("x-forwarded-for", ip);
+        }
+        request.send().await.unwrap()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+pub fn files_under(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .flat_map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                files_under(&path)
+            } else {
+                vec![path]
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    sync::mpsc,
+    time::Duration,
+};
+
+use common::{Server, files_under};
+use serde_json::{Value, json};
+
+const SOURCE_URL: &str = "https://www.youtube.com/watch?v=history-off-secret";
+const CLIENT_IP: &str = "127.0.0.1";
+
+// Accepts one message and hands back its body so the test can follow the confirmation link.
+fn fake_smtp() -> (u16, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut reply = |line: &str| writer.write_all(format!("{line}\r\n").as_bytes()).unwrap();
+        reply("220 fake ESMTP");
+        let (mut in_data, mut message) = (false, String::new());
+        for line in BufReader::new(stream).lines() {
+            let line = line.unwrap();
+            if in_data {
+                if line == "." {
+                    in_data = false;
+                    reply("250 ok");
+                } else {
+                    message.push_str(&line);
+                    message.push('\n');
+                }
+                continue;
+            }
+            match line
+                .to_ascii_uppercase()
+                .split(' ')
+                .next()
+                .unwrap_or_default()
+            {
+                "EHLO" => {
+                    reply("250-fake");
+                    reply("250 OK");
+                }
+                "DATA" => {
+                    in_data = true;
+                    reply("354 go");
+                }
+                "QUIT" => {
+                    reply("221 bye");
+                    break;
+                }
+                _ => reply("250 ok"),
+            }
+        }
+        let _ = sender.send(message);
+    });
+    (port, receiver)
+}
+
+#[tokio::test]
+async fn history_disabled_keeps_urls_and_ips_off_disk() {
+    let (smtp_port, mail) = fake_smtp();
+    let smtp_port = smtp_port.to_string();
+    let mut server = Server::start(&[
+        ("HISTORY_ENABLED", "false"),
+        ("DOWNLOAD_RECEIPTS", "true"),
+        ("ADMIN_TOKEN", "admin-secret"),
+        ("SMTP_HOST", "127.0.0.1"),
+        ("SMTP_PORT", &smtp_port),
+        ("SMTP_SECURITY", "plain"),
+        ("SMTP_FROM", "noreply@example.com"),
+    ])
+    .await;
+    let client = &server.client;
+
+    let created = client
+        .post(server.url("/api/admin/promo-codes"))
+        .bearer_auth("admin-secret")
+        .json(&json!({"code": "HISTORY-OFF", "extra_downloads": 2}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(created.status(), 200);
+    let redeemed = client
+        .post(server.url("/api/promo/redeem"))
+        .json(&json!({"code": "HISTORY-OFF"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(redeemed.status(), 200);
+
+    let requested = client
+        .post(server.url("/api/verify/email"))
+        .json(&json!({"email": "visitor@example.com"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(requested.status(), 200);
+    let message = mail.recv_timeout(Duration::from_secs(5)).unwrap();
+    let token = message
+        .split("token=")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .unwrap();
+    let confirmed = client
+        .get(server.url(&format!("/api/verify/email/confirm?token={token}")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(confirmed.status(), 200);
+    let status: Value = client
+        .get(server.url("/api/verify/email/status"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["verified"], true);
+
+    let download = server.download(SOURCE_URL).await;
+    assert_eq!(download.status(), 200);
+    let receipt_url = download.headers()["x-receipt-url"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    download.bytes().await.unwrap();
+    let receipt: Value = client
+        .get(server.url(&receipt_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(receipt["receipt"].get("url").is_none());
+    assert!(receipt["receipt"]["url_sha256"].is_string());
+    server.stop();
+
+    let data_dir = server.root.join("data");
+    for name in [
+        "promo_codes.json",
+        "promo_audit.jsonl",
+        "verified_emails.json",
+    ] {
+        assert!(data_dir.join(name).is_file(), "{name} no se guardo");
+    }
+    let files = [data_dir, server.root.join("artifacts")]
+        .iter()
+        .flat_map(|dir| files_under(dir))
+        .collect::<Vec<_>>();
+    assert!(
+        files
+            .iter()
+            .any(|path| path.starts_with(server.root.join("data/receipts")))
+    );
+    for path in files {
+        let contents = String::from_utf8_lossy(&std::fs::read(&path).unwrap()).into_owned();
+        assert!(
+            !contents.contains(CLIENT_IP),
+            "{} guarda la IP del cliente",
+            path.display()
+        );
+        assert!(
+            !contents.contains("history-off-secret"),
+            "{} guarda la URL descargada",
+            path.display()
+        );
+    }
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use common::Server;
+use serde_json::{Value, json};
+use tokio::task::JoinSet;
+
+const DEFAULT_REQUESTS: usize = 1_000_000;
+const DEFAULT_MAX_RSS_MB: u64 = 256;
+const CONCURRENCY: usize = 32;
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+// Spreads requests over many forwarded addresses so every map keeps gaining keys.
+fn forwarded_ip(index: usize) -> String {
+    format!(
+        "10.{}.{}.{}",
+        (index >> 16) & 0xff,
+        (index >> 8) & 0xff,
+        index & 0xff
+    )
+}
+
+async fn mixed_request(server: &Server, index: usize) -> u16 {
+    let ip = forwarded_ip(index);
+    let response = match index {
+        _ if index.is_multiple_of(1000) => {
+            let url = format!("https://www.youtube.com/watch?v=soak-{index}");
+            server.download_as(Some(&ip), &url).await
+        }
+        _ if index % 100 == 1 => {
+            let url = format!(
+                "https://www.youtube.com/watch?v=soak-{}",
+                index / 100 % 1500
+            );
+            server
+                .client
+                .get(server.url("/api/formats"))
+                .query(&[("url", url)])
+                .header("x-forwarded-for", &ip)
+                .send()
+                .await
+                .unwrap()
+        }
+        _ if index % 10 == 2 => server
+            .client
+            .post(server.url("/api/download"))
+            .header("x-forwarded-for", &ip)
+            .json(&json!({"url": "https://www.youtube.com/watch?v=soak", "mode": "video"}))
+            .send()
+            .await
+            .unwrap(),
+        _ if matches!(index % 10, 3 | 4) => server
+            .client
+            .get(server.url("/api/health"))
+            .send()
+            .await
+            .unwrap(),
+        _ => server
+            .client
+            .get(server.url("/api/antibot/challenge"))
+            .header("x-forwarded-for", &ip)
+            .send()
+            .await
+            .unwrap(),
+    };
+    let status = response.status().as_u16();
+    response.bytes().await.unwrap();
+    status
+}
+
+// Run with `cargo test --release --test memory_soak -- --ignored`; SOAK_REQUESTS and SOAK_MAX_RSS_MB tune it.
+#[tokio::test(flavor = "multi_thread")]
+#[ignore]
+async fn mixed_traffic_stays_under_memory_ceiling() {
+    let requests = env_or("SOAK_REQUESTS", DEFAULT_REQUESTS);
+    let max_rss = env_or("SOAK_MAX_RSS_MB", DEFAULT_MAX_RSS_MB) * 1024 * 1024;
+    let server = Arc::new(
+        Server::start(&[
+            ("TRUST_PROXY_HEADERS", "true"),
+            ("ADMIN_TOKEN", "soak-admin"),
+            ("MEMORY_MAX_CHALLENGES", "5000"),
+            ("MEMORY_MAX_RATE_LIMIT_CLIENTS", "500"),
+            ("MEMORY_MAX_FORMATS_CACHE", "100"),
+            ("MEMORY_MAX_FINISHED_JOBS", "200"),
+        ])
+        .await,
+    );
+
+    let next = Arc::new(AtomicUsize::new(0));
+    let server_errors = Arc::new(AtomicUsize::new(0));
+    let mut workers = JoinSet::new();
+    for _ in 0..CONCURRENCY {
+        let (server, next, server_errors) = (server.clone(), next.clone(), server_errors.clone());
+        workers.spawn(async move {
+            loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= requests {
+                    break;
+                }
+                if mixed_request(&server, index).await >= 500 {
+                    server_errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+    }
+    while let Some(worker) = workers.join_next().await {
+        worker.unwrap();
+    }
+    assert_eq!(server_errors.load(Ordering::Relaxed), 0);
+
+    let report: Value = server
+        .client
+        .get(server.url("/api/admin/memory"))
+        .bearer_auth("soak-admin")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    for map in report["maps"].as_array().unwrap() {
+        // Jobs still running when the last eviction ran are never evicted.
+        let slack = if map["map"] == "jobs" { CONCURRENCY } else { 0 };
+        assert!(
+            map["entries"].as_u64().unwrap() as usize
+                <= map["ceiling"].as_u64().unwrap() as usize + slack,
+            "{} supera su techo: {map}",
+            map["map"]
+        );
+    }
+    let rss = report["rss_bytes"].as_u64().unwrap();
+    assert!(
+        rss <= max_rss,
+        "RSS de {} MB tras {requests} solicitudes (objetivo {} MB)",
+        rss / 1024 / 1024,
+        max_rss / 1024 / 1024
+    );
+}
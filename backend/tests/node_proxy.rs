@@ -0,0 +1,114 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{Server, free_port};
+use reqwest::{StatusCode, header};
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+const FILE_SIZE: usize = 65536;
+
+async fn wait_for_completion(server: &Server, job_id: &str) {
+    for _ in 0..100 {
+        let status: Value = server
+            .client
+            .get(server.url(&format!("/api/download/{job_id}/status")))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        match status["state"].as_str() {
+            Some("completed") => return,
+            Some("failed" | "cancelled") => panic!("el job no termino bien: {status}"),
+            _ => tokio::time::sleep(Duration::from_millis(50)).await,
+        }
+    }
+    panic!("el job {job_id} no termino a tiempo");
+}
+
+#[tokio::test]
+async fn proxy_node_forwards_ranged_requests_to_the_owner() {
+    let registry = std::env::temp_dir().join(format!("td-registry-{}", Uuid::new_v4().simple()));
+    let registry_dir = registry.to_str().unwrap();
+    let owner_port = free_port();
+    let owner_url = format!("http://127.0.0.1:{owner_port}");
+    let owner = Server::start_on(
+        owner_port,
+        &[
+            ("NODE_REGISTRY_DIR", registry_dir),
+            ("NODE_ID", "owner"),
+            ("NODE_PUBLIC_URL", &owner_url),
+            ("TRUST_PROXY_HEADERS", "true"),
+            ("SIGNING_SECRET", "shared-secret"),
+        ],
+    )
+    .await;
+    let proxy = Server::start(&[
+        ("NODE_REGISTRY_DIR", registry_dir),
+        ("NODE_ID", "proxy"),
+        ("NODE_FORWARD_MODE", "proxy"),
+        ("SIGNING_SECRET", "shared-secret"),
+    ])
+    .await;
+
+    let mut body = owner.antibot_fields().await;
+    body["url"] = json!("https://www.youtube.com/watch?v=ranged-proxy");
+    body["mode"] = json!("video");
+    body["async"] = json!(true);
+    let response = owner
+        .client
+        .post(owner.url("/api/download"))
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let accepted: Value = response.json().await.unwrap();
+    let job_id = accepted["job_id"].as_str().unwrap();
+    wait_for_completion(&owner, job_id).await;
+
+    let file_url = proxy.url(&format!("/api/download/{job_id}/file"));
+    let ranged = proxy
+        .client
+        .get(&file_url)
+        .header(header::RANGE, "bytes=100-199")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(ranged.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        ranged.headers()[header::CONTENT_RANGE],
+        format!("bytes 100-199/{FILE_SIZE}").as_str()
+    );
+    let etag = ranged.headers()[header::ETAG].clone();
+    assert_eq!(ranged.bytes().await.unwrap().len(), 100);
+
+    let resumed = proxy
+        .client
+        .get(&file_url)
+        .header(header::RANGE, "bytes=65000-")
+        .header(header::IF_RANGE, etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resumed.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(resumed.bytes().await.unwrap().len(), FILE_SIZE - 65000);
+
+    // A validator from another file means the client's partial copy is stale.
+    let stale = proxy
+        .client
+        .get(&file_url)
+        .header(header::RANGE, "bytes=100-199")
+        .header(header::IF_RANGE, "\"stale\"")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stale.status(), StatusCode::OK);
+    assert_eq!(stale.bytes().await.unwrap().len(), FILE_SIZE);
+
+    drop((owner, proxy));
+    let _ = std::fs::remove_dir_all(&registry);
+}
@@ -3,14 +3,18 @@ import './App.css'
 import {
   BotCheckError,
   clearHistory,
+  DownloadCancelledError,
   DownloadLimitError,
   fetchAntiBotChallenge,
+  verifyAntiBotChallenge,
   fetchFormats,
   fetchHistory,
+  fetchThumbnail,
   startDownload,
 } from './api'
 import type {
   AntiBotChallenge,
+  DownloadJobStatus,
   DownloadMode,
   FormatOption,
   FormatsResponse,
@@ -54,6 +58,46 @@ function formatCountdown(seconds: number): string {
   return [hours, minutes, secs].map((value) => value.toString().padStart(2, '0')).join(':')
 }
 
+function formatSourceDetails(formats: FormatsResponse): string {
+  const details = []
+  if (formats.uploader) {
+    details.push(formats.uploader)
+  }
+  if (formats.upload_date) {
+    details.push(formats.upload_date)
+  }
+  if (formats.duration_seconds) {
+    details.push(formatCountdown(Math.round(formats.duration_seconds)))
+  }
+  if (formats.view_count !== undefined) {
+    details.push(`${new Intl.NumberFormat('es-ES').format(formats.view_count)} visualizaciones`)
+  }
+  return details.join(' · ')
+}
+
+const SLOW_FORMAT_WARNING_SECONDS = 120
+const MAX_DOWNLOAD_BYTES = 250 * 1024 * 1024
+
+function formatEstimate(seconds: number): string {
+  if (seconds < 60) {
+    return `~${seconds} s`
+  }
+
+  return `~${Math.round(seconds / 60)} min`
+}
+
+function formatJobProgress(status: DownloadJobStatus): string {
+  const parts = [`${Math.round(status.progress)}%`]
+  if (status.speed_bytes_per_second !== undefined) {
+    parts.push(`${(status.speed_bytes_per_second / 1_048_576).toFixed(1)} MB/s`)
+  }
+  if (status.eta_seconds !== undefined) {
+    parts.push(`quedan ${formatEstimate(status.eta_seconds)}`)
+  }
+
+  return parts.join(' · ')
+}
+
 interface BeforeInstallPromptEvent extends Event {
   prompt: () => Promise<void>
   userChoice: Promise<{ outcome: 'accepted' | 'dismissed'; platform: string }>
@@ -115,6 +159,7 @@ function App() {
   const turnstileSiteKey = (import.meta.env.VITE_TURNSTILE_SITE_KEY ?? '').trim()
   const useTurnstile = turnstileSiteKey.length > 0
   const turnstileWidgetIdRef = useRef<string | null>(null)
+  const downloadAbortRef = useRef<AbortController | null>(null)
   const logoSrc = `${import.meta.env.BASE_URL}image.png`
   const [showSplash, setShowSplash] = useState(true)
   const [isMenuOpen, setIsMenuOpen] = useState(false)
@@ -122,12 +167,15 @@ function App() {
   const [url, setUrl] = useState('')
   const [mode, setMode] = useState<DownloadMode>('video')
   const [formats, setFormats] = useState<FormatsResponse | null>(null)
+  const [formatsUrl, setFormatsUrl] = useState('')
+  const [thumbnailSrc, setThumbnailSrc] = useState<string | null>(null)
   const [selectedFormatId, setSelectedFormatId] = useState('')
   const [selectedFormatLabel, setSelectedFormatLabel] = useState('')
   const [selectedFormatHasAudio, setSelectedFormatHasAudio] = useState(false)
   const [history, setHistory] = useState<HistoryEntry[]>([])
   const [isLoadingFormats, setIsLoadingFormats] = useState(false)
   const [isDownloading, setIsDownloading] = useState(false)
+  const [downloadProgress, setDownloadProgress] = useState<DownloadJobStatus | null>(null)
   const [isClearingHistory, setIsClearingHistory] = useState(false)
   const [isPreparingAntiBot, setIsPreparingAntiBot] = useState(false)
   const [antiBotChallenge, setAntiBotChallenge] = useState<AntiBotChallenge | null>(null)
@@ -159,6 +207,9 @@ function App() {
     () => (mode === 'video' ? formats?.video_options ?? [] : formats?.audio_options ?? []),
     [formats, mode],
   )
+  const selectedOption = options.find((item) => item.format_id === selectedFormatId)
+  const selectedEstimate = selectedOption?.estimated_seconds
+  const selectedMergedSize = selectedOption?.merged_size_bytes
 
   const refreshHistory = useCallback(async () => {
     try {
@@ -207,6 +258,34 @@ function App() {
     void prepareAntiBot()
   }, [prepareAntiBot])
 
+  useEffect(() => {
+    const remoteThumbnail = formats?.thumbnail ?? null
+    setThumbnailSrc(null)
+    if (!remoteThumbnail || !formatsUrl) {
+      return
+    }
+
+    const controller = new AbortController()
+    let objectUrl: string | null = null
+    fetchThumbnail(formatsUrl, controller.signal)
+      .then((blob) => {
+        objectUrl = URL.createObjectURL(blob)
+        setThumbnailSrc(objectUrl)
+      })
+      .catch(() => {
+        if (!controller.signal.aborted) {
+          setThumbnailSrc(remoteThumbnail)
+        }
+      })
+
+    return () => {
+      controller.abort()
+      if (objectUrl) {
+        URL.revokeObjectURL(objectUrl)
+      }
+    }
+  }, [formats, formatsUrl])
+
   useEffect(() => {
     if (!useTurnstile) {
       setTurnstileToken('')
@@ -388,6 +467,7 @@ function App() {
     try {
       const payload = await fetchFormats(cleanUrl)
       setFormats(payload)
+      setFormatsUrl(cleanUrl)
       setNotice(`Opciones cargadas para: ${payload.title}`)
     } catch (requestError) {
       setFormats(null)
@@ -425,31 +505,58 @@ function App() {
       return
     }
 
+    if (!useTurnstile && antiBotChallenge && antiBotSolution !== null) {
+      const verification = await verifyAntiBotChallenge(
+        antiBotChallenge.challenge_id,
+        antiBotSolution,
+      ).catch(() => null)
+      if (verification && !verification.valid) {
+        setError(
+          'La verificacion anti-bot expiro. Estamos generando una nueva, intenta de nuevo en unos segundos.',
+        )
+        void prepareAntiBot()
+        return
+      }
+    }
+
+    const abortController = new AbortController()
+    downloadAbortRef.current = abortController
     setIsDownloading(true)
     setError('')
     setNotice('')
 
     try {
       const elapsedMs = Math.max(0, Date.now() - (antiBotReadyAt ?? Date.now()))
-      const result = await startDownload({
-        url: cleanUrl,
-        title: formats?.title ?? undefined,
-        thumbnail: formats?.thumbnail ?? undefined,
-        mode,
-        format_id: selectedFormatId,
-        format_label: selectedFormatLabel,
-        has_audio: selectedFormatHasAudio,
-        antibot_challenge_id: antiBotChallenge?.challenge_id,
-        antibot_solution: antiBotSolution ?? undefined,
-        antibot_honey: antiBotHoneyField,
-        antibot_elapsed_ms: elapsedMs,
-        turnstile_token: useTurnstile ? turnstileToken : undefined,
-      })
+      const result = await startDownload(
+        {
+          url: cleanUrl,
+          title: formats?.title ?? undefined,
+          thumbnail: formats?.thumbnail ?? undefined,
+          mode,
+          format_id: selectedFormatId,
+          format_label: selectedFormatLabel,
+          has_audio: selectedFormatHasAudio,
+          antibot_challenge_id: antiBotChallenge?.challenge_id,
+          antibot_solution: antiBotSolution ?? undefined,
+          antibot_honey: antiBotHoneyField,
+          antibot_elapsed_ms: elapsedMs,
+          turnstile_token: useTurnstile ? turnstileToken : undefined,
+        },
+        setDownloadProgress,
+        abortController.signal,
+      )
 
       const fileLine = result.filename ? `\nArchivo: ${result.filename}` : ''
       setNotice(`Descarga completada en tu dispositivo.${fileLine}`)
       await refreshHistory()
     } catch (requestError) {
+      if (requestError instanceof DownloadCancelledError) {
+        setError('')
+        setNotice('Descarga cancelada.')
+        await refreshHistory()
+        return
+      }
+
       if (requestError instanceof DownloadLimitError) {
         setLimitRemainingSeconds(requestError.retryAfterSeconds)
         setError('')
@@ -476,7 +583,9 @@ function App() {
       )
       await refreshHistory()
     } finally {
+      downloadAbortRef.current = null
       setIsDownloading(false)
+      setDownloadProgress(null)
       if (useTurnstile) {
         const widgetId = turnstileWidgetIdRef.current
         if (widgetId && window.turnstile) {
@@ -528,6 +637,10 @@ function App() {
     setInstallPromptEvent(null)
   }
 
+  const handleCancelDownload = () => {
+    downloadAbortRef.current?.abort()
+  }
+
   const handleClearHistory = async () => {
     if (history.length === 0 || isClearingHistory) {
       return
@@ -739,10 +852,23 @@ function App() {
             >
               {options.map((option: FormatOption) => (
                 <option key={option.format_id} value={option.format_id}>
-                  {option.label}
+                  {option.estimated_seconds !== undefined
+                    ? `${option.label} · ${formatEstimate(option.estimated_seconds)}`
+                    : option.label}
                 </option>
               ))}
             </select>
+            {selectedEstimate !== undefined && selectedEstimate >= SLOW_FORMAT_WARNING_SECONDS && (
+              <p className="feedback warning">
+                Esta opcion tardara {formatEstimate(selectedEstimate)} en descargarse y procesarse.
+              </p>
+            )}
+            {selectedMergedSize !== undefined && selectedMergedSize > MAX_DOWNLOAD_BYTES && (
+              <p className="feedback warning">
+                Con el audio unido, esta opcion podria superar el limite de 250 MB. Elige una
+                resolucion menor si la descarga se rechaza.
+              </p>
+            )}
 
             <button
               type="button"
@@ -760,20 +886,38 @@ function App() {
                       : 'Verificando anti-bot...'
                     : 'Iniciar descarga'}
             </button>
+            {isDownloading && (
+              <div className="download-progress" role="status" aria-live="polite">
+                {downloadProgress && (
+                  <>
+                    <progress max={100} value={downloadProgress.progress} />
+                    <span>{formatJobProgress(downloadProgress)}</span>
+                  </>
+                )}
+                <button
+                  type="button"
+                  className="cancel-download-button"
+                  onClick={handleCancelDownload}
+                >
+                  Cancelar descarga
+                </button>
+              </div>
+            )}
           </form>
 
           {formats && (
             <article className="video-meta">
-              {formats.thumbnail && (
+              {thumbnailSrc && (
                 <img
                   className="thumbnail"
-                  src={formats.thumbnail}
+                  src={thumbnailSrc}
                   alt={`Miniatura de ${formats.title}`}
                   loading="lazy"
                 />
               )}
               <div>
                 <h2>{formats.title}</h2>
+                {formatSourceDetails(formats) && <p>{formatSourceDetails(formats)}</p>}
                 <p>
                   {formats.video_options.length} opciones de video y {formats.audio_options.length}{' '}
                   opciones de audio detectadas.
@@ -839,7 +983,12 @@ function App() {
                   {item.url}
                 </p>
                 {item.saved_path && <code>{item.saved_path}</code>}
-                {item.error && <p className="history-error">{item.error}</p>}
+                {item.error && (
+                  <p className="history-error">
+                    {item.error}
+                    {item.request_id && <> (soporte: {item.request_id})</>}
+                  </p>
+                )}
               </li>
             ))}
           </ul>
@@ -1,5 +1,9 @@
 import type {
   AntiBotChallenge,
+  AntiBotVerifyResult,
+  AsyncDownloadAccepted,
+  ClientErrorReport,
+  DownloadJobStatus,
   DownloadRequest,
   DownloadResult,
   FormatsResponse,
@@ -16,11 +20,20 @@ function normalizeApiBase(value: string | undefined): string {
 }
 
 const API_BASE = normalizeApiBase(import.meta.env.VITE_API_URL)
+const REQUEST_SIGNING_KEY = (import.meta.env.VITE_REQUEST_SIGNING_KEY ?? '').trim()
+const REQUEST_CREDENTIALS: RequestCredentials =
+  import.meta.env.VITE_AUTH_ENABLED === 'true' ? 'include' : 'same-origin'
+const textEncoder = new TextEncoder()
+const JOB_STATUS_WAIT_SECONDS = 30
+const CLIENT_ERRORS_PATH = '/api/client-errors'
+const SESSION_HEADER = 'X-Session-Token'
+const SESSION_STORAGE_KEY = 'td-session-token'
 
 interface ApiError {
   error?: string
   code?: string
   retry_after_seconds?: number
+  request_id?: string
 }
 
 export class DownloadLimitError extends Error {
@@ -40,22 +53,139 @@ export class BotCheckError extends Error {
   }
 }
 
+export class DownloadCancelledError extends Error {
+  constructor(message: string) {
+    super(message)
+    this.name = 'DownloadCancelledError'
+  }
+}
+
+function toHex(buffer: ArrayBuffer): string {
+  return Array.from(new Uint8Array(buffer), (byte) => byte.toString(16).padStart(2, '0')).join('')
+}
+
+function readSessionToken(): string | null {
+  try {
+    return window.localStorage.getItem(SESSION_STORAGE_KEY)
+  } catch {
+    return null
+  }
+}
+
+// El backend asocia cuota e historial a esta sesion; la cookie no viaja si la API esta en otro dominio.
+function sessionHeaders(): Record<string, string> {
+  const token = readSessionToken()
+  return token ? { [SESSION_HEADER]: token } : {}
+}
+
+function rememberSession(response: Response): void {
+  const token = response.headers.get(SESSION_HEADER)
+  if (!token) {
+    return
+  }
+
+  try {
+    window.localStorage.setItem(SESSION_STORAGE_KEY, token)
+  } catch {
+    // Sin almacenamiento local se sigue usando la cookie o la IP.
+  }
+}
+
+async function signatureHeaders(
+  method: string,
+  path: string,
+  body: string,
+): Promise<Record<string, string>> {
+  if (!REQUEST_SIGNING_KEY || !globalThis.crypto?.subtle) {
+    return {}
+  }
+
+  const timestamp = Math.floor(Date.now() / 1000).toString()
+  const bodyHash = toHex(await crypto.subtle.digest('SHA-256', textEncoder.encode(body)))
+  const key = await crypto.subtle.importKey(
+    'raw',
+    textEncoder.encode(REQUEST_SIGNING_KEY),
+    { name: 'HMAC', hash: 'SHA-256' },
+    false,
+    ['sign'],
+  )
+  const signature = await crypto.subtle.sign(
+    'HMAC',
+    key,
+    textEncoder.encode(`${timestamp}\n${method}\n${path}\n${bodyHash}`),
+  )
+
+  return {
+    'X-TD-Timestamp': timestamp,
+    'X-TD-Signature': toHex(signature),
+  }
+}
+
+export async function reportClientError(report: ClientErrorReport): Promise<void> {
+  const body = JSON.stringify({ page: window.location.href, ...report })
+  const signed = await signatureHeaders('POST', CLIENT_ERRORS_PATH, body)
+
+  try {
+    await fetch(`${API_BASE}${CLIENT_ERRORS_PATH}`, {
+      method: 'POST',
+      credentials: REQUEST_CREDENTIALS,
+      headers: {
+        'Content-Type': 'application/json',
+        ...signed,
+      },
+      body,
+      keepalive: true,
+    })
+  } catch {
+    // Los reportes de error no deben romper la interfaz.
+  }
+}
+
+function reportFailedResponse(
+  method: string,
+  path: string,
+  response: Response,
+  message: string | undefined,
+): void {
+  if (response.status < 500) {
+    return
+  }
+
+  void reportClientError({
+    kind: 'request',
+    message: message ?? `HTTP ${response.status}`,
+    endpoint: path,
+    method,
+    status: response.status,
+    request_id: response.headers.get('x-request-id') ?? undefined,
+  })
+}
+
 async function request<T>(path: string, init?: RequestInit): Promise<T> {
+  const method = (init?.method ?? 'GET').toUpperCase()
+  const body = typeof init?.body === 'string' ? init.body : ''
+  const signed = await signatureHeaders(method, path, body)
+
   let response: Response
   try {
     response = await fetch(`${API_BASE}${path}`, {
+      credentials: REQUEST_CREDENTIALS,
+      ...init,
       headers: {
         'Content-Type': 'application/json',
+        ...sessionHeaders(),
+        ...signed,
         ...(init?.headers ?? {}),
       },
-      ...init,
     })
   } catch {
     throw new Error(`No se pudo conectar al backend (${API_BASE}). Verifica que este ejecutandose.`)
   }
+  rememberSession(response)
 
   if (!response.ok) {
     const body = (await response.json().catch(() => ({}))) as ApiError
+    reportFailedResponse(method, path, response, body.error)
     throw new Error(body.error ?? 'No se pudo completar la solicitud.')
   }
 
@@ -107,26 +237,141 @@ export async function fetchFormats(url: string): Promise<FormatsResponse> {
   })
 }
 
+export async function fetchThumbnail(url: string, signal?: AbortSignal): Promise<Blob> {
+  const body = JSON.stringify({ url })
+  const signed = await signatureHeaders('POST', '/api/thumbnail', body)
+  const response = await fetch(`${API_BASE}/api/thumbnail`, {
+    method: 'POST',
+    credentials: REQUEST_CREDENTIALS,
+    headers: {
+      'Content-Type': 'application/json',
+      ...signed,
+    },
+    body,
+    signal,
+  })
+
+  if (!response.ok) {
+    const body = (await response.json().catch(() => ({}))) as ApiError
+    reportFailedResponse('POST', '/api/thumbnail', response, body.error)
+    throw new Error(body.error ?? 'No se pudo cargar la miniatura.')
+  }
+
+  return response.blob()
+}
+
 export async function fetchAntiBotChallenge(): Promise<AntiBotChallenge> {
   return request<AntiBotChallenge>('/api/antibot/challenge')
 }
 
-export async function startDownload(payload: DownloadRequest): Promise<DownloadResult> {
+export async function verifyAntiBotChallenge(
+  challengeId: string,
+  solution: number,
+): Promise<AntiBotVerifyResult> {
+  return request<AntiBotVerifyResult>('/api/antibot/verify', {
+    method: 'POST',
+    body: JSON.stringify({ challenge_id: challengeId, solution }),
+  })
+}
+
+type JobProgressHandler = (status: DownloadJobStatus) => void
+
+function isFinishedJob(status: DownloadJobStatus): boolean {
+  return status.state === 'completed' || status.state === 'failed' || status.state === 'cancelled'
+}
+
+function streamJobProgress(
+  progressUrl: string,
+  onProgress: JobProgressHandler,
+): Promise<DownloadJobStatus> {
+  return new Promise((resolve, reject) => {
+    const source = new EventSource(`${API_BASE}${progressUrl}`, {
+      withCredentials: REQUEST_CREDENTIALS === 'include',
+    })
+    const handleEvent = (event: MessageEvent<string>) => {
+      const status = JSON.parse(event.data) as DownloadJobStatus
+      onProgress(status)
+      if (isFinishedJob(status)) {
+        source.close()
+        resolve(status)
+      }
+    }
+
+    for (const name of ['progress', 'completed', 'failed', 'cancelled']) {
+      source.addEventListener(name, handleEvent)
+    }
+    source.onerror = () => {
+      source.close()
+      reject(new Error('Se perdio la conexion de progreso.'))
+    }
+  })
+}
+
+async function pollJob(jobId: string, onProgress: JobProgressHandler): Promise<DownloadJobStatus> {
+  let since: number | null = null
+  for (;;) {
+    const query = since === null ? '' : `&since=${since}`
+    const status = await request<DownloadJobStatus>(
+      `/api/download/${jobId}/status?wait=${JOB_STATUS_WAIT_SECONDS}${query}`,
+    )
+    onProgress(status)
+    if (isFinishedJob(status)) {
+      return status
+    }
+    since = status.version
+  }
+}
+
+async function waitForJob(
+  accepted: AsyncDownloadAccepted,
+  onProgress: JobProgressHandler,
+): Promise<DownloadJobStatus> {
+  if (typeof EventSource !== 'undefined') {
+    try {
+      return await streamJobProgress(accepted.progress_url, onProgress)
+    } catch {
+      // Si el proxy corta el stream de eventos seguimos con long-polling.
+    }
+  }
+
+  return pollJob(accepted.job_id, onProgress)
+}
+
+export async function cancelDownload(jobId: string): Promise<void> {
+  await request<DownloadJobStatus>(`/api/download/${jobId}`, {
+    method: 'DELETE',
+    keepalive: true,
+  })
+}
+
+export async function startDownload(
+  payload: DownloadRequest,
+  onProgress: JobProgressHandler = () => {},
+  signal?: AbortSignal,
+): Promise<DownloadResult> {
+  const body = JSON.stringify({ ...payload, async: true })
+  const signed = await signatureHeaders('POST', '/api/download', body)
+
   let response: Response
   try {
     response = await fetch(`${API_BASE}/api/download`, {
       method: 'POST',
+      credentials: REQUEST_CREDENTIALS,
       headers: {
         'Content-Type': 'application/json',
+        ...sessionHeaders(),
+        ...signed,
       },
-      body: JSON.stringify(payload),
+      body,
     })
   } catch {
     throw new Error(`No se pudo conectar al backend (${API_BASE}). Verifica que este ejecutandose.`)
   }
+  rememberSession(response)
 
   if (!response.ok) {
     const body = (await response.json().catch(() => ({}))) as ApiError
+    reportFailedResponse('POST', '/api/download', response, body.error)
 
     if (body.code === 'DAILY_LIMIT_EXCEEDED') {
       throw new DownloadLimitError(
@@ -142,8 +387,47 @@ export async function startDownload(payload: DownloadRequest): Promise<DownloadR
     throw new Error(body.error ?? 'No se pudo completar la solicitud.')
   }
 
-  const blob = await response.blob()
-  const filename = extractFilenameFromHeaders(response.headers) ?? 'total-downloader-file'
+  const accepted = (await response.json()) as AsyncDownloadAccepted
+  const cancel = () => {
+    void cancelDownload(accepted.job_id).catch(() => {})
+  }
+  if (signal?.aborted) {
+    cancel()
+  }
+  signal?.addEventListener('abort', cancel, { once: true })
+  window.addEventListener('pagehide', cancel, { once: true })
+
+  let status: DownloadJobStatus
+  try {
+    status = await waitForJob(accepted, onProgress)
+  } finally {
+    signal?.removeEventListener('abort', cancel)
+    window.removeEventListener('pagehide', cancel)
+  }
+  if (status.state === 'cancelled') {
+    throw new DownloadCancelledError(status.error ?? 'La descarga fue cancelada.')
+  }
+  if (status.state === 'failed') {
+    throw new Error(status.error ?? 'No fue posible completar la descarga.')
+  }
+
+  let fileResponse: Response
+  try {
+    fileResponse = await fetch(`${API_BASE}${accepted.file_url}`, {
+      credentials: REQUEST_CREDENTIALS,
+    })
+  } catch {
+    throw new Error(`No se pudo conectar al backend (${API_BASE}). Verifica que este ejecutandose.`)
+  }
+  if (!fileResponse.ok) {
+    const body = (await fileResponse.json().catch(() => ({}))) as ApiError
+    reportFailedResponse('GET', accepted.file_url, fileResponse, body.error)
+    throw new Error(body.error ?? 'No se pudo recuperar el archivo descargado.')
+  }
+
+  const blob = await fileResponse.blob()
+  const filename =
+    extractFilenameFromHeaders(fileResponse.headers) ?? status.filename ?? 'total-downloader-file'
 
   triggerBrowserDownload(blob, filename)
 
@@ -2,7 +2,25 @@ import { createRoot } from 'react-dom/client'
 import { registerSW } from 'virtual:pwa-register'
 import './index.css'
 import App from './App.tsx'
+import { reportClientError } from './api'
 
 registerSW({ immediate: true })
 
+window.addEventListener('error', (event) => {
+  void reportClientError({
+    kind: 'script',
+    message: event.message || 'Error de JavaScript',
+    stack: event.error instanceof Error ? event.error.stack : undefined,
+  })
+})
+
+window.addEventListener('unhandledrejection', (event) => {
+  const reason: unknown = event.reason
+  void reportClientError({
+    kind: 'script',
+    message: reason instanceof Error ? reason.message : String(reason),
+    stack: reason instanceof Error ? reason.stack : undefined,
+  })
+})
+
 createRoot(document.getElementById('root')!).render(<App />)
@@ -7,15 +7,45 @@ export interface FormatOption {
   resolution: string | null
   ext: string
   has_audio: boolean
+  height?: number
+  fps?: number
+  filesize_bytes?: number
+  bitrate_kbps?: number
+  vcodec?: string
+  acodec?: string
+  language?: string
+  format_note?: string
+  merged_size_bytes?: number
+  estimated_seconds?: number
 }
 
 export interface FormatsResponse {
   title: string
   thumbnail: string | null
+  duration_seconds?: number
+  uploader?: string
+  upload_date?: string
+  view_count?: number
   video_options: FormatOption[]
   audio_options: FormatOption[]
 }
 
+export interface FormatOptionV2 extends Omit<FormatOption, 'label' | 'resolution'> {
+  automatic: boolean
+}
+
+export interface FormatsResponseV2 {
+  api_version: 2
+  title: string | null
+  thumbnail: string | null
+  duration_seconds: number | null
+  uploader: string | null
+  upload_date: string | null
+  view_count: number | null
+  video_options: FormatOptionV2[]
+  audio_options: FormatOptionV2[]
+}
+
 export interface HistoryEntry {
   id: string
   created_at: string
@@ -27,6 +57,30 @@ export interface HistoryEntry {
   status: DownloadStatus
   saved_path: string | null
   error: string | null
+  clip?: ClipRange
+  duration_seconds?: number
+  request_id?: string
+}
+
+export interface ClipRange {
+  start_seconds: number
+  end_seconds?: number
+}
+
+export interface StreamSelection {
+  video?: string[]
+  audio?: string[]
+}
+
+export interface SidecarRequest {
+  description?: boolean
+  comments?: number
+}
+
+export interface AudioTags {
+  title?: string
+  artist?: string
+  album?: string
 }
 
 export interface DownloadRequest {
@@ -42,15 +96,100 @@ export interface DownloadRequest {
   antibot_honey?: string
   antibot_elapsed_ms?: number
   turnstile_token?: string
+  promo_code?: string
+  async?: boolean
+  job_id?: string
+  embed_metadata?: boolean
+  playlist?: boolean
+  max_height?: number
+  max_bytes?: number
+  preset?: string
+  embed_subtitles?: string[]
+  start_time?: number | string
+  end_time?: number | string
+  split_chapters?: boolean
+  extra_args?: string[]
+  sponsorblock?: { remove: string[] }
+  audio_format?: 'mp3' | 'm4a' | 'opus' | 'ogg' | 'flac' | 'wav'
+  container?: 'mp4' | 'mkv' | 'webm' | 'mov'
+  compatibility?: boolean
+  embed_thumbnail?: boolean
+  audio_tags?: AudioTags
+  streams?: StreamSelection
+  sidecars?: SidecarRequest
+  snapshot?: boolean
+  warc?: boolean
+  ip_family?: 'any' | 'ipv4' | 'ipv6'
+}
+
+export type JobState = 'queued' | 'running' | 'completed' | 'failed'
+
+export type JobPhase = 'extraction' | 'download' | 'merge' | 'convert' | 'transcode'
+
+export interface JobStatus {
+  job_id: string
+  state: JobState
+  version: number
+  updated_at: string
+  phase?: JobPhase
+  progress: number
+  filename?: string
+  file_url?: string
+  error?: string
 }
 
 export interface DownloadResult {
   filename: string | null
 }
 
+export interface AsyncDownloadAccepted {
+  job_id: string
+  status_url: string
+  progress_url: string
+  file_url: string
+}
+
+export interface DownloadJobStatus {
+  job_id: string
+  state: 'queued' | 'running' | 'completed' | 'failed' | 'cancelled'
+  version: number
+  progress: number
+  phase?: string
+  speed_bytes_per_second?: number
+  eta_seconds?: number
+  filename?: string
+  error?: string
+}
+
+export interface ClientErrorReport {
+  kind: 'script' | 'request'
+  message: string
+  stack?: string
+  page?: string
+  endpoint?: string
+  method?: string
+  status?: number
+  request_id?: string
+  job_id?: string
+}
+
+export interface JobLogLine {
+  seq: number
+  at: string
+  line: string
+}
+
 export interface AntiBotChallenge {
   challenge_id: string
   nonce: string
   difficulty: number
   expires_in_seconds: number
+  expires_at?: string
+  downloads_saturated?: boolean
+}
+
+export interface AntiBotVerifyResult {
+  valid: boolean
+  reason?: 'expired' | 'origin_mismatch' | 'invalid_solution'
+  expires_in_seconds?: number
 }
@@ -4,6 +4,8 @@
 interface ImportMetaEnv {
   readonly VITE_API_URL?: string
   readonly VITE_TURNSTILE_SITE_KEY?: string
+  readonly VITE_REQUEST_SIGNING_KEY?: string
+  readonly VITE_AUTH_ENABLED?: string
 }
 
 interface ImportMeta {